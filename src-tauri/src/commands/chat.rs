@@ -1,20 +1,38 @@
 
 
+pub mod branches;
 mod browser_tools;
+pub mod checkpoints;
+mod context_compaction;
+mod events;
+mod file_diff;
 mod image_tools;
 mod process_tools;
 mod tool_catalog;
 mod tool_executor;
+mod tool_selection;
+mod llm_failover;
 mod llm_provider;
+mod mcp_host;
+mod mcp_tools;
+mod patch;
+mod session_runs;
 pub mod storage;
 pub mod commands;
 pub mod stream;
+mod sub_agents;
 mod web_tools;
 
 
 pub use stream::*;
 pub(crate) use tool_catalog::*;
 pub(crate) use tool_executor::*;
+pub(crate) use tool_selection::*;
+pub(crate) use llm_failover::*;
 pub(crate) use llm_provider::*;
+pub(crate) use mcp_host::*;
 pub(crate) use storage::*;
+pub(crate) use context_compaction::*;
+pub(crate) use events::*;
+pub(crate) use file_diff::*;
 
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tauri::State;
+use uuid::Uuid;
+
+use crate::models::chat::{Conversation, TimelineBranch};
+use crate::AppState;
+
+use super::commands::stream_stop_flags;
+use super::emit_app_event;
+use super::storage::ACTIVE_BRANCH_CTE;
+
+#[derive(Debug, Clone)]
+pub(crate) struct BranchMessage {
+    pub(crate) id: String,
+    pub(crate) parent_id: Option<String>,
+    pub(crate) content: String,
+    pub(crate) attachment_context: Option<String>,
+}
+
+async fn state_pool(state: &State<'_, AppState>) -> SqlitePool {
+    let guard = state.lock().await;
+    guard.db().pool().clone()
+}
+
+/// Branches must not move under a turn that is still streaming into them.
+pub(crate) async fn ensure_conversation_idle(conversation_id: &str) -> Result<(), String> {
+    if stream_stop_flags()
+        .lock()
+        .await
+        .contains_key(conversation_id)
+    {
+        return Err(format!(
+            "Conversation {} is still running a turn; stop it first",
+            conversation_id
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) async fn set_active_leaf(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    leaf_id: Option<&str>,
+) -> Result<(), String> {
+    sqlx::query("UPDATE conversations SET active_leaf_id = ? WHERE id = ?")
+        .bind(leaf_id)
+        .bind(conversation_id)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Finds the user message that opened the turn `message_id` belongs to.
+pub(crate) async fn find_turn_user_message(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    message_id: &str,
+) -> Result<BranchMessage, String> {
+    let row = sqlx::query_as::<_, (String, Option<String>, String, Option<String>)>(
+        "WITH RECURSIVE ancestors(id, depth) AS (
+            SELECT ?, 0
+            UNION ALL
+            SELECT messages.parent_id, ancestors.depth + 1 FROM messages JOIN ancestors ON messages.id = ancestors.id
+            WHERE messages.parent_id IS NOT NULL
+        )
+        SELECT m.id, m.parent_id, m.content, m.attachment_context FROM ancestors JOIN messages m ON m.id = ancestors.id
+        WHERE m.conversation_id = ? AND m.role = 'user'
+        ORDER BY ancestors.depth ASC
+        LIMIT 1",
+    )
+    .bind(message_id)
+    .bind(conversation_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Message not found: {}", message_id))?;
+
+    let (id, parent_id, content, attachment_context) = row;
+    Ok(BranchMessage {
+        id,
+        parent_id,
+        content,
+        attachment_context,
+    })
+}
+
+/// User messages on the active branch with more than one version.
+pub(crate) async fn load_branch_points(
+    pool: &SqlitePool,
+    conversation_id: &str,
+) -> Result<Vec<TimelineBranch>, String> {
+    let rows = sqlx::query_as::<_, (String, String)>(&format!(
+        "{} SELECT m.id, s.id FROM branch
+         JOIN messages m ON m.id = branch.id
+         JOIN messages s ON s.conversation_id = m.conversation_id AND s.role = 'user' AND s.parent_id IS m.parent_id
+         WHERE m.role = 'user'
+         ORDER BY branch.depth DESC, s.created_at ASC, s.rowid ASC",
+        ACTIVE_BRANCH_CTE
+    ))
+    .bind(conversation_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(group_branch_points(rows))
+}
+
+fn group_branch_points(rows: Vec<(String, String)>) -> Vec<TimelineBranch> {
+    let mut branches: Vec<TimelineBranch> = Vec::new();
+    for (message_id, sibling_id) in rows {
+        match branches.last_mut() {
+            Some(branch) if branch.message_id == message_id => branch.sibling_ids.push(sibling_id),
+            _ => branches.push(TimelineBranch {
+                message_id,
+                sibling_ids: vec![sibling_id],
+            }),
+        }
+    }
+    branches.retain(|branch| branch.sibling_ids.len() > 1);
+    branches
+}
+
+/// Makes the branch through `message_id` active, continuing to its most recent leaf.
+#[tauri::command]
+pub async fn switch_conversation_branch(
+    state: State<'_, AppState>,
+    conversation_id: String,
+    message_id: String,
+) -> Result<(), String> {
+    let pool = state_pool(&state).await;
+    ensure_conversation_idle(&conversation_id).await?;
+
+    let leaf_id = sqlx::query_scalar::<_, String>(
+        "WITH RECURSIVE descendants(id) AS (
+            SELECT id FROM messages WHERE id = ? AND conversation_id = ?
+            UNION ALL
+            SELECT messages.id FROM messages JOIN descendants ON messages.parent_id = descendants.id
+        )
+        SELECT m.id FROM descendants JOIN messages m ON m.id = descendants.id
+        ORDER BY m.created_at DESC, m.rowid DESC
+        LIMIT 1",
+    )
+    .bind(&message_id)
+    .bind(&conversation_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Message not found: {}", message_id))?;
+
+    set_active_leaf(&pool, &conversation_id, Some(&leaf_id)).await
+}
+
+/// Copies the active branch up to the end of the turn containing `message_id` into a new
+/// conversation, together with those turns' timeline events.
+#[tauri::command]
+pub async fn fork_conversation(
+    state: State<'_, AppState>,
+    conversation_id: String,
+    message_id: String,
+    title: Option<String>,
+) -> Result<Conversation, String> {
+    let pool = state_pool(&state).await;
+
+    let path = sqlx::query_as::<
+        _,
+        (
+            String,
+            String,
+            String,
+            String,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+        ),
+    >(&format!(
+        "{} SELECT m.id, m.role, m.content, m.created_at, m.tool_calls, m.reasoning, m.attachment_context FROM messages m
+         JOIN branch ON m.id = branch.id
+         ORDER BY branch.depth DESC",
+        ACTIVE_BRANCH_CTE
+    ))
+    .bind(&conversation_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let position = path
+        .iter()
+        .position(|(id, ..)| id == &message_id)
+        .ok_or_else(|| format!("Message {} is not on the active branch", message_id))?;
+    let end = path[position + 1..]
+        .iter()
+        .position(|(_, role, ..)| role == "user")
+        .map(|offset| position + 1 + offset)
+        .unwrap_or(path.len());
+    let path = &path[..end];
+
+    let (source_title, source_model) = sqlx::query_as::<_, (String, String)>(
+        "SELECT title, model FROM conversations WHERE id = ?",
+    )
+    .bind(&conversation_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Conversation not found: {}", conversation_id))?;
+    let title = title
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| format!("{} - 分支", source_title));
+
+    let fork_id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    sqlx::query(
+        "INSERT INTO conversations (id, title, model, fallback_models, tool_profile, sandboxed, created_at, updated_at)
+         SELECT ?, ?, model, fallback_models, tool_profile, sandboxed, ?, ? FROM conversations WHERE id = ?",
+    )
+    .bind(&fork_id)
+    .bind(&title)
+    .bind(now.to_rfc3339())
+    .bind(now.to_rfc3339())
+    .bind(&conversation_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut copied_ids = HashMap::<String, String>::new();
+    let mut parent_id: Option<String> = None;
+    for (id, role, content, created_at, tool_calls, reasoning, attachment_context) in path {
+        let copy_id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO messages (id, conversation_id, parent_id, role, content, created_at, tool_calls, reasoning, attachment_context) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&copy_id)
+        .bind(&fork_id)
+        .bind(parent_id.as_deref())
+        .bind(role)
+        .bind(content)
+        .bind(created_at)
+        .bind(tool_calls.as_deref())
+        .bind(reasoning.as_deref())
+        .bind(attachment_context.as_deref())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+        copied_ids.insert(id.clone(), copy_id.clone());
+        parent_id = Some(copy_id);
+    }
+    sqlx::query("UPDATE conversations SET active_leaf_id = ? WHERE id = ?")
+        .bind(parent_id.as_deref())
+        .bind(&fork_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let events = sqlx::query_as::<_, (String, i64, String, Option<String>, String, String)>(
+        "SELECT turn_id, seq, event_type, tool_call_id, payload, created_at FROM message_events
+         WHERE conversation_id = ?
+         ORDER BY created_at ASC, seq ASC",
+    )
+    .bind(&conversation_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut copied_turns = HashMap::<String, String>::new();
+    for (turn_id, _, event_type, _, payload, _) in &events {
+        if event_type != "user_message" {
+            continue;
+        }
+        let opened_by_copied_message = serde_json::from_str::<Value>(payload)
+            .ok()
+            .and_then(|value| value.get("messageId")?.as_str().map(str::to_string))
+            .is_some_and(|id| copied_ids.contains_key(&id));
+        if opened_by_copied_message {
+            copied_turns.insert(turn_id.clone(), Uuid::new_v4().to_string());
+        }
+    }
+
+    for (turn_id, seq, event_type, tool_call_id, payload, created_at) in events {
+        let Some(copy_turn_id) = copied_turns.get(&turn_id) else {
+            continue;
+        };
+        let mut payload = serde_json::from_str::<Value>(&payload).unwrap_or(Value::Null);
+        if let Some(object) = payload.as_object_mut() {
+            let copy_message_id = object
+                .get("messageId")
+                .and_then(Value::as_str)
+                .and_then(|id| copied_ids.get(id))
+                .cloned();
+            if let Some(copy_message_id) = copy_message_id {
+                object.insert("messageId".to_string(), Value::String(copy_message_id));
+            }
+        }
+        sqlx::query(
+            "INSERT INTO message_events (id, conversation_id, turn_id, seq, event_type, tool_call_id, payload, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&fork_id)
+        .bind(copy_turn_id)
+        .bind(seq)
+        .bind(event_type)
+        .bind(tool_call_id)
+        .bind(payload.to_string())
+        .bind(created_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    let (tool_profile, sandboxed) = sqlx::query_as::<_, (Option<String>, Option<bool>)>(
+        "SELECT tool_profile, sandboxed FROM conversations WHERE id = ?",
+    )
+    .bind(&fork_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    emit_app_event(
+        "conversations-changed",
+        json!({ "conversationId": fork_id }),
+    );
+
+    Ok(Conversation {
+        id: fork_id,
+        title,
+        model: source_model,
+        tool_profile,
+        sandboxed,
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::chat::storage::{
+        insert_message, insert_user_message, load_conversation_context,
+    };
+    use crate::services::database::Database;
+
+    #[tokio::test]
+    async fn regenerated_turns_keep_the_attachment_context() {
+        let root = std::env::temp_dir().join(format!("petool-branches-test-{}", Uuid::new_v4()));
+        let db = Database::new(root.join("petool.db")).await.unwrap();
+        let pool = db.pool();
+        sqlx::query(
+            "INSERT INTO conversations (id, title, model, created_at, updated_at) VALUES ('c1', 'First', 'gpt-4o', '', '')",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        let context = "<resource uri=\"docs://spec\">spec body</resource>".to_string();
+        insert_user_message(pool, "c1", "summarize the spec", Some(context.clone()))
+            .await
+            .unwrap();
+        let reply = insert_message(pool, "c1", "assistant", "first answer", None, None)
+            .await
+            .unwrap();
+
+        // What regenerate_message hands to the new turn.
+        let message = find_turn_user_message(pool, "c1", &reply).await.unwrap();
+        assert_eq!(
+            message.attachment_context.as_deref(),
+            Some(context.as_str())
+        );
+        set_active_leaf(pool, "c1", message.parent_id.as_deref())
+            .await
+            .unwrap();
+        insert_user_message(pool, "c1", &message.content, message.attachment_context)
+            .await
+            .unwrap();
+
+        let contents = load_conversation_context(pool, "c1")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|message| message.content.unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, vec![format!("{}\n\nsummarize the spec", context)]);
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn keeps_only_messages_with_alternatives() {
+        let rows = vec![
+            ("first".to_string(), "first".to_string()),
+            ("second".to_string(), "second-old".to_string()),
+            ("second".to_string(), "second".to_string()),
+            ("third".to_string(), "third".to_string()),
+        ];
+
+        let branches = group_branch_points(rows);
+        assert_eq!(branches.len(), 1);
+        assert_eq!(branches[0].message_id, "second");
+        assert_eq!(branches[0].sibling_ids, vec!["second-old", "second"]);
+    }
+}
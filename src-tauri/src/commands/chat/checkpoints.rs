@@ -0,0 +1,712 @@
+use crate::models::chat::{TimelineEvent, TimelineEventType};
+use crate::AppState;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tauri::State;
+use uuid::Uuid;
+
+use super::file_diff::render_unified_diff;
+use super::patch::{parse_patch, PatchOperation};
+use super::storage::{insert_timeline_event, next_turn_seq, PendingTimelineEvent};
+use super::tool_catalog::*;
+use super::tool_executor::{
+    is_probably_binary, read_bool_argument, read_optional_string_argument, read_path_argument,
+    read_string_argument, resolve_workspace_target, workspace_relative_display_path,
+    write_file_atomic,
+};
+
+/// Files above this size are recorded as touched but not captured, so they cannot be
+/// restored.
+const MAX_CHECKPOINT_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+struct ActiveTurn {
+    turn_id: String,
+    on_timeline: bool,
+}
+
+/// Turns running per conversation, oldest first. Runs can overlap on one conversation
+/// (an interactive turn and a scheduled run) and end in any order.
+static ACTIVE_TURNS: OnceLock<Mutex<HashMap<String, Vec<ActiveTurn>>>> = OnceLock::new();
+
+fn active_turns() -> &'static Mutex<HashMap<String, Vec<ActiveTurn>>> {
+    ACTIVE_TURNS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Keeps a turn registered on its conversation; dropping it removes only that turn.
+pub(crate) struct CheckpointTurnGuard {
+    conversation_id: String,
+    turn_id: String,
+}
+
+impl Drop for CheckpointTurnGuard {
+    fn drop(&mut self) {
+        if let Ok(mut turns) = active_turns().lock() {
+            if let Some(entries) = turns.get_mut(&self.conversation_id) {
+                entries.retain(|entry| entry.turn_id != self.turn_id);
+                if entries.is_empty() {
+                    turns.remove(&self.conversation_id);
+                }
+            }
+        }
+    }
+}
+
+fn enter_turn(conversation_id: &str, turn_id: &str, on_timeline: bool) -> CheckpointTurnGuard {
+    if let Ok(mut turns) = active_turns().lock() {
+        turns
+            .entry(conversation_id.to_string())
+            .or_default()
+            .push(ActiveTurn {
+                turn_id: turn_id.to_string(),
+                on_timeline,
+            });
+    }
+    CheckpointTurnGuard {
+        conversation_id: conversation_id.to_string(),
+        turn_id: turn_id.to_string(),
+    }
+}
+
+/// File-modifying tools run for `conversation_id` snapshot their targets under `turn_id`
+/// until the guard is dropped. For runs that never show on the timeline.
+pub(crate) fn enter_checkpoint_turn(conversation_id: &str, turn_id: &str) -> CheckpointTurnGuard {
+    enter_turn(conversation_id, turn_id, false)
+}
+
+/// Like `enter_checkpoint_turn`, for a turn the timeline shows: interactive turns and live
+/// runs.
+pub(crate) fn enter_timeline_turn(conversation_id: &str, turn_id: &str) -> CheckpointTurnGuard {
+    enter_turn(conversation_id, turn_id, true)
+}
+
+fn latest_turn(conversation_id: &str, timeline_only: bool) -> Option<String> {
+    let turns = active_turns().lock().ok()?;
+    turns
+        .get(conversation_id)?
+        .iter()
+        .rev()
+        .find(|entry| entry.on_timeline || !timeline_only)
+        .map(|entry| entry.turn_id.clone())
+}
+
+fn current_checkpoint_turn(conversation_id: &str) -> Option<String> {
+    latest_turn(conversation_id, false)
+}
+
+/// The latest running turn of `conversation_id` that the timeline shows; scheduled and
+/// other background runs are skipped.
+pub(crate) fn current_timeline_turn(conversation_id: &str) -> Option<String> {
+    latest_turn(conversation_id, true)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointFile {
+    pub path: String,
+    pub existed: bool,
+    /// False when the file was too large to snapshot.
+    pub captured: bool,
+    pub tool_name: String,
+    pub created_at: String,
+    pub restored_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnCheckpoint {
+    pub turn_id: String,
+    pub workspace_root: String,
+    pub created_at: String,
+    pub files: Vec<CheckpointFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointFileDiff {
+    pub path: String,
+    /// Change since the checkpoint: modified, added, deleted, unchanged, binary or not_captured.
+    pub status: String,
+    pub diff: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointSkippedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CheckpointRestoreOutcome {
+    pub turn_id: String,
+    pub restored: Vec<String>,
+    pub removed: Vec<String>,
+    pub skipped: Vec<CheckpointSkippedFile>,
+    /// Restored files that later turns changed again; those later edits are gone now.
+    pub overwritten_later_edits: Vec<String>,
+    /// Checkpoint holding the files as they were before this restore; restoring it undoes
+    /// the restore.
+    pub undo_turn_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointRestoreResponse {
+    pub outcome: CheckpointRestoreOutcome,
+    pub event: TimelineEvent,
+}
+
+struct CheckpointRow {
+    turn_id: String,
+    workspace_root: String,
+    path: String,
+    existed: bool,
+    content: Option<Vec<u8>>,
+    tool_name: String,
+    created_at: String,
+    restored_at: Option<String>,
+}
+
+impl CheckpointRow {
+    fn display_path(&self) -> String {
+        workspace_relative_display_path(Path::new(&self.workspace_root), Path::new(&self.path))
+    }
+}
+
+type CheckpointRowTuple = (
+    String,
+    String,
+    String,
+    i64,
+    Option<Vec<u8>>,
+    String,
+    String,
+    Option<String>,
+);
+
+fn checkpoint_row(
+    (turn_id, workspace_root, path, existed, content, tool_name, created_at, restored_at): CheckpointRowTuple,
+) -> CheckpointRow {
+    CheckpointRow {
+        turn_id,
+        workspace_root,
+        path,
+        existed: existed != 0,
+        content,
+        tool_name,
+        created_at,
+        restored_at,
+    }
+}
+
+/// The tool name and files a write, edit or patch call is about to change. Dry runs and
+/// calls whose arguments do not resolve touch nothing.
+pub(crate) fn checkpoint_targets(
+    runtime_tool: &RuntimeTool,
+    arguments: &Value,
+    workspace_root: &Path,
+) -> Option<(&'static str, Vec<PathBuf>)> {
+    let resolve = |raw_path: &str| resolve_workspace_target(workspace_root, raw_path, true).ok();
+    let path_argument = || {
+        let raw_path = read_path_argument(arguments, "path").ok()?;
+        resolve(&raw_path).map(|path| vec![path])
+    };
+    let (tool_name, paths) = match runtime_tool {
+        RuntimeTool::WorkspaceWriteFile => (WORKSPACE_WRITE_TOOL, path_argument()?),
+        RuntimeTool::WorkspaceEditFile => (WORKSPACE_EDIT_TOOL, path_argument()?),
+        RuntimeTool::WorkspaceApplyPatch => {
+            if read_bool_argument(arguments, "dry_run", false) {
+                return None;
+            }
+            let operations = read_string_argument(arguments, "patch")
+                .and_then(|patch| parse_patch(&patch))
+                .ok()?;
+            let paths = operations
+                .iter()
+                .flat_map(|operation| match operation {
+                    PatchOperation::Add { path, .. } | PatchOperation::Delete { path } => {
+                        vec![path.as_str()]
+                    }
+                    PatchOperation::Update { path, move_to, .. } => std::iter::once(path.as_str())
+                        .chain(move_to.as_deref())
+                        .collect(),
+                })
+                .filter_map(resolve)
+                .collect();
+            (WORKSPACE_APPLY_PATCH_TOOL, paths)
+        }
+        _ => return None,
+    };
+    Some((tool_name, paths))
+}
+
+/// Snapshots `paths` into the conversation's active turn. Only the first snapshot of a
+/// path per turn is kept, so restoring a turn returns files to their state before it.
+pub(crate) async fn record_checkpoint(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    workspace_root: &Path,
+    tool_name: &str,
+    paths: &[PathBuf],
+) -> Result<(), String> {
+    let Some(turn_id) = current_checkpoint_turn(conversation_id) else {
+        return Ok(());
+    };
+    record_checkpoint_in_turn(
+        pool,
+        conversation_id,
+        &turn_id,
+        workspace_root,
+        tool_name,
+        paths,
+    )
+    .await
+}
+
+async fn record_checkpoint_in_turn(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    turn_id: &str,
+    workspace_root: &Path,
+    tool_name: &str,
+    paths: &[PathBuf],
+) -> Result<(), String> {
+    for path in paths {
+        let (existed, content) = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => {
+                if metadata.len() > MAX_CHECKPOINT_FILE_BYTES {
+                    (true, None)
+                } else {
+                    let bytes = fs::read(path)
+                        .map_err(|e| format!("Failed to checkpoint {}: {}", path.display(), e))?;
+                    (true, Some(bytes))
+                }
+            }
+            Ok(_) => continue,
+            Err(_) => (false, None),
+        };
+        sqlx::query(
+            "INSERT OR IGNORE INTO workspace_checkpoints (id, conversation_id, turn_id, workspace_root, path, existed, content, tool_name, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(conversation_id)
+        .bind(turn_id)
+        .bind(workspace_root.to_string_lossy().to_string())
+        .bind(path.to_string_lossy().to_string())
+        .bind(existed as i64)
+        .bind(content)
+        .bind(tool_name)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+async fn load_checkpoint_rows(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    turn_id: Option<&str>,
+) -> Result<Vec<CheckpointRow>, String> {
+    let rows = sqlx::query_as::<_, CheckpointRowTuple>(
+        "SELECT turn_id, workspace_root, path, existed, content, tool_name, created_at, restored_at
+         FROM workspace_checkpoints
+         WHERE conversation_id = ? AND (? IS NULL OR turn_id = ?)
+         ORDER BY created_at ASC, path ASC",
+    )
+    .bind(conversation_id)
+    .bind(turn_id)
+    .bind(turn_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(rows.into_iter().map(checkpoint_row).collect())
+}
+
+pub(crate) async fn list_checkpoints(
+    pool: &SqlitePool,
+    conversation_id: &str,
+) -> Result<Vec<TurnCheckpoint>, String> {
+    let mut turns: Vec<TurnCheckpoint> = Vec::new();
+    for row in load_checkpoint_rows(pool, conversation_id, None).await? {
+        let file = CheckpointFile {
+            path: row.display_path(),
+            existed: row.existed,
+            captured: !row.existed || row.content.is_some(),
+            tool_name: row.tool_name,
+            created_at: row.created_at.clone(),
+            restored_at: row.restored_at,
+        };
+        match turns.iter_mut().find(|turn| turn.turn_id == row.turn_id) {
+            Some(turn) => turn.files.push(file),
+            None => turns.push(TurnCheckpoint {
+                turn_id: row.turn_id,
+                workspace_root: row.workspace_root,
+                created_at: row.created_at,
+                files: vec![file],
+            }),
+        }
+    }
+    Ok(turns)
+}
+
+/// Unified diff from the checkpointed contents to what is on disk now.
+pub(crate) async fn diff_checkpoint(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    turn_id: &str,
+) -> Result<Vec<CheckpointFileDiff>, String> {
+    let rows = load_checkpoint_rows(pool, conversation_id, Some(turn_id)).await?;
+    if rows.is_empty() {
+        return Err(format!("No checkpoint recorded for turn '{}'", turn_id));
+    }
+    Ok(rows.iter().map(diff_checkpoint_row).collect())
+}
+
+fn diff_checkpoint_row(row: &CheckpointRow) -> CheckpointFileDiff {
+    let path = row.display_path();
+    let current = fs::read(&row.path).ok();
+    let (status, diff) = match (row.existed, &row.content, &current) {
+        (true, None, _) => ("not_captured", String::new()),
+        (false, _, None) => ("unchanged", String::new()),
+        (_, before, after) => {
+            let before = before.as_deref().unwrap_or_default();
+            let after = after.as_deref().unwrap_or_default();
+            if before == after {
+                ("unchanged", String::new())
+            } else if is_probably_binary(before) || is_probably_binary(after) {
+                ("binary", String::new())
+            } else {
+                let status = match (row.existed, current.is_some()) {
+                    (false, _) => "added",
+                    (true, false) => "deleted",
+                    (true, true) => "modified",
+                };
+                (
+                    status,
+                    render_unified_diff(
+                        &path,
+                        &String::from_utf8_lossy(before),
+                        &String::from_utf8_lossy(after),
+                    ),
+                )
+            }
+        }
+    };
+    CheckpointFileDiff {
+        path,
+        status: status.to_string(),
+        diff,
+    }
+}
+
+/// Puts the files of `turn_id` back to their state before that turn. `paths` narrows the
+/// restore to some files (workspace-relative or absolute). The current contents are
+/// checkpointed first, under the active turn or, for restores from the UI, a turn of their
+/// own, so the restore can be undone.
+pub(crate) async fn restore_checkpoint(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    turn_id: &str,
+    paths: Option<&[String]>,
+) -> Result<CheckpointRestoreOutcome, String> {
+    let all_rows = load_checkpoint_rows(pool, conversation_id, None).await?;
+    let Some(turn_started_at) = all_rows
+        .iter()
+        .find(|row| row.turn_id == turn_id)
+        .map(|row| row.created_at.clone())
+    else {
+        return Err(format!("No checkpoint recorded for turn '{}'", turn_id));
+    };
+    let rows = all_rows
+        .iter()
+        .filter(|row| row.turn_id == turn_id)
+        .filter(|row| {
+            paths.is_none_or(|paths| {
+                paths.iter().any(|path| {
+                    let path = path.trim().trim_start_matches("./");
+                    path == row.path || path == row.display_path()
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    if rows.is_empty() {
+        return Err("None of the requested paths were checkpointed in this turn".to_string());
+    }
+
+    let undo_turn_id = current_checkpoint_turn(conversation_id)
+        .unwrap_or_else(|| format!("undo-{}", Uuid::new_v4()));
+    if let Some(workspace_root) = rows.first().map(|row| PathBuf::from(&row.workspace_root)) {
+        let targets = rows
+            .iter()
+            .map(|row| PathBuf::from(&row.path))
+            .collect::<Vec<_>>();
+        record_checkpoint_in_turn(
+            pool,
+            conversation_id,
+            &undo_turn_id,
+            &workspace_root,
+            WORKSPACE_CHECKPOINT_TOOL,
+            &targets,
+        )
+        .await?;
+    }
+
+    let later_paths = all_rows
+        .iter()
+        .filter(|row| row.turn_id != turn_id && row.created_at > turn_started_at)
+        .map(|row| row.path.as_str())
+        .collect::<HashSet<_>>();
+    let mut outcome = CheckpointRestoreOutcome {
+        turn_id: turn_id.to_string(),
+        undo_turn_id,
+        ..Default::default()
+    };
+    let mut restored_paths = Vec::new();
+    for row in rows {
+        let display_path = row.display_path();
+        let path = Path::new(&row.path);
+        let result = match (row.existed, &row.content) {
+            (true, None) => Err("file was too large to checkpoint".to_string()),
+            (true, Some(content)) => path
+                .parent()
+                .map_or(Ok(()), |parent| {
+                    fs::create_dir_all(parent).map_err(|e| e.to_string())
+                })
+                .and_then(|_| write_file_atomic(path, content))
+                .map(|_| outcome.restored.push(display_path.clone())),
+            (false, _) if path.is_file() => fs::remove_file(path)
+                .map_err(|e| e.to_string())
+                .map(|_| outcome.removed.push(display_path.clone())),
+            (false, _) => Ok(()),
+        };
+        match result {
+            Ok(()) => {
+                if later_paths.contains(row.path.as_str()) {
+                    outcome.overwritten_later_edits.push(display_path);
+                }
+                restored_paths.push(row.path.clone());
+            }
+            Err(reason) => outcome.skipped.push(CheckpointSkippedFile {
+                path: display_path,
+                reason,
+            }),
+        }
+    }
+
+    let restored_at = Utc::now().to_rfc3339();
+    for path in restored_paths {
+        sqlx::query(
+            "UPDATE workspace_checkpoints SET restored_at = ? WHERE conversation_id = ? AND turn_id = ? AND path = ?",
+        )
+        .bind(&restored_at)
+        .bind(conversation_id)
+        .bind(turn_id)
+        .bind(path)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(outcome)
+}
+
+pub(crate) async fn execute_workspace_checkpoint(
+    arguments: &Value,
+    conversation_id: &str,
+    pool: &SqlitePool,
+) -> Result<Value, String> {
+    let action =
+        read_optional_string_argument(arguments, "action").unwrap_or_else(|| "list".to_string());
+    match action.as_str() {
+        "list" => {
+            let turns = list_checkpoints(pool, conversation_id).await?;
+            Ok(json!({ "turns": turns }))
+        }
+        "diff" => {
+            let turn_id = read_string_argument(arguments, "turn_id")?;
+            let files = diff_checkpoint(pool, conversation_id, &turn_id).await?;
+            Ok(json!({ "turn_id": turn_id, "files": files }))
+        }
+        "restore" => {
+            let turn_id = read_string_argument(arguments, "turn_id")?;
+            let paths = arguments
+                .get("paths")
+                .and_then(Value::as_array)
+                .map(|paths| {
+                    paths
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_string)
+                        .collect::<Vec<_>>()
+                });
+            let outcome =
+                restore_checkpoint(pool, conversation_id, &turn_id, paths.as_deref()).await?;
+            serde_json::to_value(outcome).map_err(|e| e.to_string())
+        }
+        other => Err(format!(
+            "Unsupported action '{}'; use list, diff or restore",
+            other
+        )),
+    }
+}
+
+async fn state_pool(state: &State<'_, AppState>) -> SqlitePool {
+    let guard = state.lock().await;
+    guard.db().pool().clone()
+}
+
+#[tauri::command]
+pub async fn list_workspace_checkpoints(
+    state: State<'_, AppState>,
+    conversation_id: String,
+) -> Result<Vec<TurnCheckpoint>, String> {
+    let pool = state_pool(&state).await;
+    list_checkpoints(&pool, &conversation_id).await
+}
+
+#[tauri::command]
+pub async fn diff_workspace_checkpoint(
+    state: State<'_, AppState>,
+    conversation_id: String,
+    turn_id: String,
+) -> Result<Vec<CheckpointFileDiff>, String> {
+    let pool = state_pool(&state).await;
+    diff_checkpoint(&pool, &conversation_id, &turn_id).await
+}
+
+/// Reverts the files changed in a turn (or only `paths`) and records the revert in that
+/// turn of the timeline, or in `timeline_turn_id` when undoing an earlier restore.
+#[tauri::command]
+pub async fn restore_workspace_checkpoint(
+    state: State<'_, AppState>,
+    conversation_id: String,
+    turn_id: String,
+    paths: Option<Vec<String>>,
+    timeline_turn_id: Option<String>,
+) -> Result<CheckpointRestoreResponse, String> {
+    let pool = state_pool(&state).await;
+    let outcome = restore_checkpoint(&pool, &conversation_id, &turn_id, paths.as_deref()).await?;
+
+    let turn_id = timeline_turn_id.unwrap_or(turn_id);
+    let seq = next_turn_seq(&pool, &conversation_id, &turn_id).await?;
+    let created_at = Utc::now();
+    let event = PendingTimelineEvent {
+        turn_id: turn_id.clone(),
+        seq,
+        event_type: TimelineEventType::CheckpointRestored,
+        tool_call_id: None,
+        payload: serde_json::to_value(&outcome).map_err(|e| e.to_string())?,
+        created_at: created_at.to_rfc3339(),
+    };
+    let id = insert_timeline_event(&pool, &conversation_id, &event).await?;
+
+    Ok(CheckpointRestoreResponse {
+        outcome,
+        event: TimelineEvent {
+            id,
+            conversation_id,
+            turn_id,
+            seq,
+            event_type: event.event_type,
+            tool_call_id: None,
+            payload: event.payload,
+            created_at,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoint_turns_nest_and_diffs_are_unified() {
+        let outer = enter_checkpoint_turn("conversation", "turn-1");
+        {
+            let _inner = enter_checkpoint_turn("conversation", "turn-2");
+            assert_eq!(
+                current_checkpoint_turn("conversation").as_deref(),
+                Some("turn-2")
+            );
+        }
+        assert_eq!(
+            current_checkpoint_turn("conversation").as_deref(),
+            Some("turn-1")
+        );
+        drop(outer);
+        assert_eq!(current_checkpoint_turn("conversation"), None);
+
+        let diff = render_unified_diff("src/main.rs", "a\nb\nc\n", "a\nB\nc\n");
+        assert!(diff.starts_with("--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1,3 +1,3 @@\n"));
+        assert!(diff.contains("\n-b\n+B\n"));
+    }
+
+    #[test]
+    fn overlapping_turns_end_in_any_order() {
+        let interactive = enter_timeline_turn("overlap", "interactive");
+        let scheduled = enter_checkpoint_turn("overlap", "scheduled");
+        assert_eq!(
+            current_checkpoint_turn("overlap").as_deref(),
+            Some("scheduled")
+        );
+        assert_eq!(
+            current_timeline_turn("overlap").as_deref(),
+            Some("interactive")
+        );
+
+        drop(interactive);
+        assert_eq!(
+            current_checkpoint_turn("overlap").as_deref(),
+            Some("scheduled")
+        );
+        assert_eq!(current_timeline_turn("overlap"), None);
+        drop(scheduled);
+        assert_eq!(current_checkpoint_turn("overlap"), None);
+    }
+
+    #[tokio::test]
+    async fn restores_outside_a_turn_can_be_undone() {
+        let root = std::env::temp_dir().join(format!("petool-checkpoint-test-{}", Uuid::new_v4()));
+        let workspace = root.join("workspace");
+        fs::create_dir_all(&workspace).unwrap();
+        let db = crate::services::database::Database::new(root.join("petool.db"))
+            .await
+            .unwrap();
+        let pool = db.pool();
+        sqlx::query(
+            "INSERT INTO conversations (id, title, model, created_at, updated_at) VALUES ('c1', 'First', 'gpt-4o', '', '')",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        let file = workspace.join("notes.md");
+        fs::write(&file, "before").unwrap();
+        {
+            let _turn = enter_checkpoint_turn("c1", "turn-1");
+            record_checkpoint(
+                pool,
+                "c1",
+                &workspace,
+                WORKSPACE_WRITE_TOOL,
+                &[file.clone()],
+            )
+            .await
+            .unwrap();
+        }
+        fs::write(&file, "after").unwrap();
+
+        let outcome = restore_checkpoint(pool, "c1", "turn-1", None)
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "before");
+        assert!(outcome.undo_turn_id.starts_with("undo-"));
+
+        restore_checkpoint(pool, "c1", &outcome.undo_turn_id, None)
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "after");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
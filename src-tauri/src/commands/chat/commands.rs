@@ -3,6 +3,7 @@ use crate::commands::skills::SkillManagerState;
 use crate::models::chat::*;
 use crate::models::config::Config;
 use crate::services::llm::{reasoning_details_from_text, ChatMessage, ChatToolCall};
+use crate::services::usage::{record_usage_logged, UsageSource};
 use serde_json::{json, Value};
 use std::collections::{HashMap, HashSet};
 
@@ -14,8 +15,11 @@ use tauri_plugin_notification::NotificationExt;
 
 use uuid::Uuid;
 use chrono::Utc;
+use futures_util::StreamExt;
 use crate::AppState;
 use super::*;
+use super::branches::{find_turn_user_message, load_branch_points, set_active_leaf};
+use super::checkpoints::enter_timeline_turn;
 use super::storage::*;
 use super::llm_provider::*;
 use super::tool_executor::*;
@@ -33,11 +37,18 @@ pub(crate) fn stream_stop_flags() -> &'static tokio::sync::Mutex<HashMap<String,
     STREAM_STOP_FLAGS.get_or_init(|| tokio::sync::Mutex::new(HashMap::new()))
 }
 
-pub(crate) async fn register_stream_stop_flag(conversation_id: &str) -> Arc<AtomicBool> {
-    let flag = Arc::new(AtomicBool::new(false));
+/// Registers a turn on `conversation_id` unless one is already running there; checking and
+/// registering under one lock keeps two starts from racing.
+pub(crate) async fn try_register_stream_stop_flag(
+    conversation_id: &str,
+) -> Option<Arc<AtomicBool>> {
     let mut flags = stream_stop_flags().lock().await;
+    if flags.contains_key(conversation_id) {
+        return None;
+    }
+    let flag = Arc::new(AtomicBool::new(false));
     flags.insert(conversation_id.to_string(), flag.clone());
-    flag
+    Some(flag)
 }
 
 pub(crate) async fn clear_stream_stop_flag(conversation_id: &str) {
@@ -45,6 +56,52 @@ pub(crate) async fn clear_stream_stop_flag(conversation_id: &str) {
     flags.remove(conversation_id);
 }
 
+fn remove_registered_stop_flag(
+    flags: &mut HashMap<String, Arc<AtomicBool>>,
+    conversation_id: &str,
+    flag: &Arc<AtomicBool>,
+) {
+    if flags
+        .get(conversation_id)
+        .is_some_and(|registered| Arc::ptr_eq(registered, flag))
+    {
+        flags.remove(conversation_id);
+    }
+}
+
+/// Unregisters a turn's stop flag when dropped, so a run whose task panics does not leave
+/// its conversation busy. A newer turn registered on the conversation is left alone.
+pub(crate) struct StreamStopFlagGuard {
+    conversation_id: String,
+    flag: Arc<AtomicBool>,
+}
+
+impl StreamStopFlagGuard {
+    pub(crate) fn new(conversation_id: &str, flag: Arc<AtomicBool>) -> Self {
+        Self {
+            conversation_id: conversation_id.to_string(),
+            flag,
+        }
+    }
+}
+
+impl Drop for StreamStopFlagGuard {
+    fn drop(&mut self) {
+        if let Ok(mut flags) = stream_stop_flags().try_lock() {
+            remove_registered_stop_flag(&mut flags, &self.conversation_id, &self.flag);
+            return;
+        }
+        let conversation_id = std::mem::take(&mut self.conversation_id);
+        let flag = self.flag.clone();
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                let mut flags = stream_stop_flags().lock().await;
+                remove_registered_stop_flag(&mut flags, &conversation_id, &flag);
+            });
+        }
+    }
+}
+
 pub(crate) async fn request_stream_stop(conversation_id: &str) -> bool {
     let flags = stream_stop_flags().lock().await;
     if let Some(flag) = flags.get(conversation_id) {
@@ -58,6 +115,7 @@ pub(crate) async fn request_stream_stop(conversation_id: &str) -> bool {
 pub async fn resolve_tool_approval(
     request_id: String,
     decision: ToolApprovalDecision,
+    response: Option<Value>,
 ) -> Result<(), String> {
     let sender = {
         let mut waiters = tool_approval_waiters().lock().await;
@@ -66,7 +124,7 @@ pub async fn resolve_tool_approval(
 
     let sender = sender.ok_or_else(|| "Tool approval request not found".to_string())?;
     sender
-        .send(decision)
+        .send(ToolApprovalResolution { decision, response })
         .map_err(|_| "Failed to deliver tool approval response".to_string())
 }
 
@@ -105,46 +163,30 @@ pub async fn generate_image(
         guard.db().pool().clone()
     };
 
-    let user_message_id = Uuid::new_v4().to_string();
-    let user_created_at = Utc::now();
-    let user_content = format!("[文生图] {}", trimmed_prompt);
-    sqlx::query(
-        "INSERT INTO messages (id, conversation_id, role, content, created_at, tool_calls, reasoning) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    let user_parent_id = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT active_leaf_id FROM conversations WHERE id = ?",
     )
-    .bind(&user_message_id)
     .bind(&conversation_id)
-    .bind("user")
-    .bind(&user_content)
-    .bind(user_created_at.to_rfc3339())
-    .bind(Option::<String>::None)
-    .bind(Option::<String>::None)
-    .execute(&pool)
+    .fetch_optional(&pool)
     .await
-    .map_err(|e| e.to_string())?;
+    .map_err(|e| e.to_string())?
+    .flatten();
+    let user_created_at = Utc::now();
+    let user_content = format!("[文生图] {}", trimmed_prompt);
+    let user_message_id =
+        insert_message(&pool, &conversation_id, "user", &user_content, None, None).await?;
 
-    let assistant_message_id = Uuid::new_v4().to_string();
     let assistant_created_at = Utc::now();
     let assistant_content = format!("![{}]({})\n\n{}", trimmed_prompt, image_url, image_url);
-    sqlx::query(
-        "INSERT INTO messages (id, conversation_id, role, content, created_at, tool_calls, reasoning) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    let assistant_message_id = insert_message(
+        &pool,
+        &conversation_id,
+        "assistant",
+        &assistant_content,
+        None,
+        None,
     )
-    .bind(&assistant_message_id)
-    .bind(&conversation_id)
-    .bind("assistant")
-    .bind(&assistant_content)
-    .bind(assistant_created_at.to_rfc3339())
-    .bind(Option::<String>::None)
-    .bind(Option::<String>::None)
-    .execute(&pool)
-    .await
-    .map_err(|e| e.to_string())?;
-
-    sqlx::query("UPDATE conversations SET updated_at = ? WHERE id = ?")
-        .bind(assistant_created_at.to_rfc3339())
-        .bind(&conversation_id)
-        .execute(&pool)
-        .await
-        .map_err(|e| e.to_string())?;
+    .await?;
 
     let image_turn_id = Uuid::new_v4().to_string();
     insert_timeline_event(
@@ -155,7 +197,7 @@ pub async fn generate_image(
             seq: 1,
             event_type: TimelineEventType::UserMessage,
             tool_call_id: None,
-            payload: json!({ "content": user_content.clone() }),
+            payload: json!({ "content": user_content.clone(), "messageId": user_message_id }),
             created_at: user_created_at.to_rfc3339(),
         },
     )
@@ -175,8 +217,9 @@ pub async fn generate_image(
     .await?;
 
     let user_message = Message {
-        id: user_message_id,
+        id: user_message_id.clone(),
         conversation_id: conversation_id.clone(),
+        parent_id: user_parent_id,
         role: MessageRole::User,
         content: user_content,
         reasoning: None,
@@ -187,6 +230,7 @@ pub async fn generate_image(
     let assistant_message = Message {
         id: assistant_message_id,
         conversation_id,
+        parent_id: Some(user_message_id),
         role: MessageRole::Assistant,
         content: assistant_content,
         reasoning: None,
@@ -214,11 +258,13 @@ pub async fn send_message(
         guard.db().pool().clone()
     };
     let model_to_use = resolve_conversation_model(&pool, &conversation_id, &config.model).await?;
-    let llm_service = resolve_text_llm_service(&config, &model_to_use)?;
+    let mut llm_routes =
+        LlmRouteChain::for_conversation(&config, &pool, &conversation_id, &model_to_use).await?;
     let turn_id = Uuid::new_v4().to_string();
     let mut seq: i64 = 0;
 
-    insert_message(&pool, &conversation_id, "user", &content, None, None).await?;
+    let user_message_id =
+        insert_message(&pool, &conversation_id, "user", &content, None, None).await?;
     seq += 1;
     insert_timeline_event(
         &pool,
@@ -228,13 +274,20 @@ pub async fn send_message(
             seq,
             event_type: TimelineEventType::UserMessage,
             tool_call_id: None,
-            payload: json!({ "content": content }),
+            payload: json!({ "content": content, "messageId": user_message_id }),
             created_at: Utc::now().to_rfc3339(),
         },
     )
     .await?;
 
-    let mut messages = load_conversation_context(&pool, &conversation_id).await?;
+    let mut messages = prepare_conversation_context(
+        &pool,
+        llm_routes.active_service(),
+        &conversation_id,
+        &model_to_use,
+        &config.context_compaction,
+    )
+    .await?;
     prepend_system_prompt(&mut messages, config.system_prompt.as_deref());
     if let Some(memory_prompt) =
         maybe_prepare_memory_prompt(&pool, &config, &model_to_use, &conversation_id, &content).await
@@ -242,10 +295,20 @@ pub async fn send_message(
         prepend_system_prompt(&mut messages, Some(memory_prompt.as_str()));
     }
 
-    let response = llm_service
-        .chat(&model_to_use, messages)
+    let output = llm_routes
+        .chat_with_usage(messages)
         .await
         .map_err(|e| e.to_string())?;
+    record_usage_logged(
+        &pool,
+        Some(conversation_id.as_str()),
+        Some(turn_id.as_str()),
+        UsageSource::Chat,
+        llm_routes.active_model(),
+        output.usage.as_ref(),
+    )
+    .await;
+    let response = output.content;
 
     insert_message(&pool, &conversation_id, "assistant", &response, None, None).await?;
     seq += 1;
@@ -304,8 +367,128 @@ pub async fn stream_message(
     content: String,
     workspace_directory: Option<String>,
     attachments: Option<Vec<UploadedAttachmentInput>>,
+    mcp_resources: Option<Vec<McpResourceAttachmentInput>>,
 ) -> Result<(), String> {
-    let stop_flag = register_stream_stop_flag(&conversation_id).await;
+    stream_turn(
+        state,
+        mcp_manager,
+        skill_manager,
+        window,
+        conversation_id,
+        content,
+        workspace_directory,
+        attachments,
+        mcp_resources,
+        None,
+        None,
+        None,
+    )
+    .await
+}
+
+/// Replaces a past user message with `content` on a new branch and streams a fresh reply.
+#[tauri::command]
+pub async fn edit_and_resend_message(
+    state: State<'_, AppState>,
+    mcp_manager: State<'_, McpState>,
+    skill_manager: State<'_, SkillManagerState>,
+    window: Window,
+    conversation_id: String,
+    message_id: String,
+    content: String,
+    workspace_directory: Option<String>,
+) -> Result<(), String> {
+    if content.trim().is_empty() {
+        return Err("Message cannot be empty".to_string());
+    }
+    let pool = {
+        let guard = state.lock().await;
+        guard.db().pool().clone()
+    };
+    let message = find_turn_user_message(&pool, &conversation_id, &message_id).await?;
+    if message.id != message_id {
+        return Err("Only user messages can be edited".to_string());
+    }
+
+    stream_turn(
+        state,
+        mcp_manager,
+        skill_manager,
+        window,
+        conversation_id,
+        content,
+        workspace_directory,
+        None,
+        None,
+        None,
+        Some(message.parent_id),
+        message.attachment_context,
+    )
+    .await
+}
+
+/// Answers the turn containing `message_id` again on a new branch, optionally with another
+/// model for this turn only.
+#[tauri::command]
+pub async fn regenerate_message(
+    state: State<'_, AppState>,
+    mcp_manager: State<'_, McpState>,
+    skill_manager: State<'_, SkillManagerState>,
+    window: Window,
+    conversation_id: String,
+    message_id: String,
+    model: Option<String>,
+    workspace_directory: Option<String>,
+) -> Result<(), String> {
+    let pool = {
+        let guard = state.lock().await;
+        guard.db().pool().clone()
+    };
+    let message = find_turn_user_message(&pool, &conversation_id, &message_id).await?;
+
+    stream_turn(
+        state,
+        mcp_manager,
+        skill_manager,
+        window,
+        conversation_id,
+        message.content,
+        workspace_directory,
+        None,
+        None,
+        model
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty()),
+        Some(message.parent_id),
+        message.attachment_context,
+    )
+    .await
+}
+
+async fn stream_turn(
+    state: State<'_, AppState>,
+    mcp_manager: State<'_, McpState>,
+    skill_manager: State<'_, SkillManagerState>,
+    window: Window,
+    conversation_id: String,
+    content: String,
+    workspace_directory: Option<String>,
+    attachments: Option<Vec<UploadedAttachmentInput>>,
+    mcp_resources: Option<Vec<McpResourceAttachmentInput>>,
+    model_override: Option<String>,
+    // Set when the turn starts a new branch after this parent message (`None` for the root).
+    branch_parent: Option<Option<String>>,
+    // Hidden context of the message an edited or regenerated turn replaces.
+    replayed_attachment_context: Option<String>,
+) -> Result<(), String> {
+    let stop_flag = try_register_stream_stop_flag(&conversation_id)
+        .await
+        .ok_or_else(|| {
+            format!(
+                "Conversation {} is still running a turn; stop it first",
+                conversation_id
+            )
+        })?;
 
     let result = async {
         let config = crate::utils::load_config::<Config>().map_err(|e| e.to_string())?;
@@ -318,18 +501,44 @@ pub async fn stream_message(
             tool_map,
         } = build_runtime_tool_catalog(mcp_state, &config, &workspace_root).await?;
         let uploaded_attachments = normalize_uploaded_attachments(attachments, &workspace_root)?;
+        let mcp_resource_attachments =
+            load_mcp_resource_attachments(mcp_state, mcp_resources).await?;
 
         let pool = {
             let guard = state.lock().await;
             guard.db().pool().clone()
         };
-        let model_to_use =
-            resolve_conversation_model(&pool, &conversation_id, &config.model).await?;
-        let llm_service = resolve_text_llm_service(&config, &model_to_use)?;
+        let model_to_use = match model_override {
+            Some(model) => model,
+            None => resolve_conversation_model(&pool, &conversation_id, &config.model).await?,
+        };
+        let mut llm_routes =
+            LlmRouteChain::for_conversation(&config, &pool, &conversation_id, &model_to_use)
+                .await?;
+        let tool_profile = resolve_conversation_tool_profile(&pool, &conversation_id).await?;
+        let mut tool_selection = ToolSelection::new(
+            &config.tool_selection,
+            available_tools,
+            tool_profile.as_deref(),
+            &content,
+        );
         let turn_id = Uuid::new_v4().to_string();
+        let _timeline_turn = enter_timeline_turn(&conversation_id, &turn_id);
         let mut seq: i64 = 0;
 
-        insert_message(&pool, &conversation_id, "user", &content, None, None).await?;
+        // The branch only moves once the turn is ready to run, so an early failure keeps it.
+        if let Some(parent_id) = &branch_parent {
+            set_active_leaf(&pool, &conversation_id, parent_id.as_deref()).await?;
+        }
+        let user_message_id = insert_user_message(
+            &pool,
+            &conversation_id,
+            &content,
+            build_mcp_resource_attachments_guidance(&mcp_resource_attachments)
+                .or(replayed_attachment_context),
+        )
+        .await?;
+        let mcp_resource_refs = mcp_resource_attachment_refs(&mcp_resource_attachments);
         seq += 1;
         let user_event_created_at = Utc::now().to_rfc3339();
         let user_event = PendingTimelineEvent {
@@ -337,7 +546,11 @@ pub async fn stream_message(
             seq,
             event_type: TimelineEventType::UserMessage,
             tool_call_id: None,
-            payload: json!({ "content": content }),
+            payload: json!({
+                "content": content,
+                "messageId": user_message_id,
+                "mcpResources": mcp_resource_refs
+            }),
             created_at: user_event_created_at.clone(),
         };
         insert_timeline_event(&pool, &conversation_id, &user_event).await?;
@@ -349,11 +562,20 @@ pub async fn stream_message(
                 "seq": seq,
                 "eventType": "user_message",
                 "createdAt": user_event_created_at,
-                "content": content
+                "content": content,
+                "messageId": user_message_id,
+                "mcpResources": mcp_resource_refs
             }),
         );
 
-        let mut context_messages = load_conversation_context(&pool, &conversation_id).await?;
+        let mut context_messages = prepare_conversation_context(
+            &pool,
+            llm_routes.active_service(),
+            &conversation_id,
+            &model_to_use,
+            &config.context_compaction,
+        )
+        .await?;
         prepend_system_prompt(&mut context_messages, config.system_prompt.as_deref());
         if let Some(memory_prompt) =
             maybe_prepare_memory_prompt(&pool, &config, &model_to_use, &conversation_id, &content)
@@ -363,12 +585,14 @@ pub async fn stream_message(
         }
         prepend_tool_usage_guidance(&mut context_messages);
         prepend_skills_usage_guidance(&mut context_messages, &skills_guidance);
+        prepend_todo_plan_guidance(&pool, &mut context_messages, &conversation_id).await;
         prepend_uploaded_attachments_guidance(
             &mut context_messages,
             &uploaded_attachments,
             &workspace_root,
         );
 
+        let context_budget = resolve_context_budget(&config.context_compaction, &model_to_use);
         let mut always_allowed_tools = HashSet::<String>::new();
         let mut last_tool_signature: Option<String> = None;
         let mut repeated_signature_rounds = 0usize;
@@ -379,13 +603,17 @@ pub async fn stream_message(
                 return Ok(());
             }
 
+            if config.context_compaction.enabled {
+                fit_context_to_budget(&mut context_messages, &context_budget);
+            }
+
+            let available_tools = tool_selection.available_tools();
             let stream_round = run_stream_round(
-                &llm_service,
+                &mut llm_routes,
                 &window,
                 &conversation_id,
                 &turn_id,
                 &mut seq,
-                &model_to_use,
                 context_messages.clone(),
                 &available_tools,
                 stop_flag.clone(),
@@ -393,6 +621,15 @@ pub async fn stream_message(
             .await?;
             insert_timeline_events(&pool, &conversation_id, &stream_round.timeline_events).await?;
             let stream_result = stream_round.stream_result;
+            record_usage_logged(
+                &pool,
+                Some(conversation_id.as_str()),
+                Some(turn_id.as_str()),
+                UsageSource::Chat,
+                llm_routes.active_model(),
+                stream_result.usage.as_ref(),
+            )
+            .await;
 
             let assistant_content = stream_result.content.clone();
             let assistant_reasoning = if stream_result.reasoning.trim().is_empty() {
@@ -423,32 +660,13 @@ pub async fn stream_message(
                 }
 
                 let paused_content = if assistant_content.trim().is_empty() {
-                    seq += 1;
-                    let paused_created_at = Utc::now().to_rfc3339();
-                    window
-                        .emit(
-                            "chat-chunk",
-                            json!({
-                                "conversationId": conversation_id,
-                                "turnId": turn_id,
-                                "seq": seq,
-                                "eventType": "assistant_text",
-                                "createdAt": paused_created_at,
-                                "chunk": STREAM_PAUSED_TEXT
-                            }),
-                        )
-                        .map_err(|e| e.to_string())?;
-                    insert_timeline_event(
+                    emit_and_record_assistant_text(
                         &pool,
+                        &window,
                         &conversation_id,
-                        &PendingTimelineEvent {
-                            turn_id: turn_id.clone(),
-                            seq,
-                            event_type: TimelineEventType::AssistantText,
-                            tool_call_id: None,
-                            payload: json!({ "text": STREAM_PAUSED_TEXT }),
-                            created_at: paused_created_at,
-                        },
+                        &turn_id,
+                        &mut seq,
+                        STREAM_PAUSED_TEXT,
                     )
                     .await?;
                     STREAM_PAUSED_TEXT.to_string()
@@ -520,32 +738,13 @@ pub async fn stream_message(
 
             if repeated_signature_rounds >= 2 {
                 let guard_text = REPEATED_TOOL_GUARD_TEXT;
-                seq += 1;
-                let guard_created_at = Utc::now().to_rfc3339();
-                window
-                    .emit(
-                        "chat-chunk",
-                        json!({
-                            "conversationId": conversation_id,
-                            "turnId": turn_id,
-                            "seq": seq,
-                            "eventType": "assistant_text",
-                            "createdAt": guard_created_at,
-                            "chunk": guard_text
-                        }),
-                    )
-                    .map_err(|e| e.to_string())?;
-                insert_timeline_event(
+                emit_and_record_assistant_text(
                     &pool,
+                    &window,
                     &conversation_id,
-                    &PendingTimelineEvent {
-                        turn_id: turn_id.clone(),
-                        seq,
-                        event_type: TimelineEventType::AssistantText,
-                        tool_call_id: None,
-                        payload: json!({ "text": guard_text }),
-                        created_at: guard_created_at,
-                    },
+                    &turn_id,
+                    &mut seq,
+                    guard_text,
                 )
                 .await?;
                 insert_message(&pool, &conversation_id, "assistant", guard_text, None, None)
@@ -554,162 +753,146 @@ pub async fn stream_message(
                 return Ok(());
             }
 
+            // Approvals stay sequential; only consecutive parallel-safe calls execute together,
+            // and results are recorded in the order the model issued the calls.
+            let tool_calls = stream_result.tool_calls;
+            let max_parallel = config.max_parallel_tool_calls.max(1);
             let mut cancelled_during_tools = false;
-            for tool_call in stream_result.tool_calls {
-                if stop_flag.load(Ordering::Relaxed) {
-                    cancelled_during_tools = true;
-                    emit_and_record_tool_result_event(
-                        &pool,
+            for segment in partition_tool_call_segments(
+                &tool_calls,
+                |tool_call| tool_call.function.name.as_str(),
+                max_parallel,
+            ) {
+                // One outcome slot per call; slots are recorded strictly in model order, so a
+                // call decided during approval waits for approved calls before it to run.
+                let calls = &tool_calls[segment];
+                let mut outcomes: Vec<Option<Result<String, String>>> = vec![None; calls.len()];
+                let mut recorded = 0usize;
+                let mut approved_calls: Vec<(ChatToolCall, HunkSelection)> = Vec::new();
+                let mut approved_indexes: Vec<usize> = Vec::new();
+                let mut paused_index: Option<usize> = None;
+                for (index, tool_call) in calls.iter().enumerate() {
+                    if stop_flag.load(Ordering::Relaxed) {
+                        paused_index = Some(index);
+                        break;
+                    }
+
+                    let parsed_arguments = parse_tool_arguments(&tool_call.function.arguments);
+                    if let Err(error) = tool_selection.ensure_allowed(&tool_call.function.name) {
+                        outcomes[index] = Some(Err(error));
+                        continue;
+                    }
+                    if tool_call.function.name == TOOL_SEARCH_TOOL {
+                        outcomes[index] =
+                            Some(tool_selection.search(&parsed_arguments).map(|value| {
+                                serde_json::to_string_pretty(&value)
+                                    .unwrap_or_else(|_| value.to_string())
+                            }));
+                        continue;
+                    }
+                    let resolution = resolve_tool_execution_decision(
+                        &config,
                         &window,
                         &conversation_id,
-                        &turn_id,
-                        &mut seq,
-                        &tool_call,
-                        None,
-                        Some("Paused by user"),
-                    )
-                    .await?;
-                    let result_text = format_tool_error_result("Paused by user")?;
-                    persist_tool_result_message(
-                        &pool,
-                        &conversation_id,
-                        &mut context_messages,
-                        &tool_call,
-                        result_text,
+                        tool_call,
+                        &parsed_arguments,
+                        &always_allowed_tools,
+                        &workspace_root,
                     )
                     .await?;
-                    break;
-                }
 
-                let parsed_arguments = parse_tool_arguments(&tool_call.function.arguments);
-                let decision = resolve_tool_execution_decision(
-                    &config,
-                    &window,
-                    &conversation_id,
-                    &tool_call,
-                    &parsed_arguments,
-                    &always_allowed_tools,
-                )
-                .await?;
+                    if stop_flag.load(Ordering::Relaxed) {
+                        paused_index = Some(index);
+                        break;
+                    }
 
-                if stop_flag.load(Ordering::Relaxed) {
-                    cancelled_during_tools = true;
-                    emit_and_record_tool_result_event(
-                        &pool,
-                        &window,
-                        &conversation_id,
-                        &turn_id,
-                        &mut seq,
-                        &tool_call,
-                        None,
-                        Some("Paused by user"),
-                    )
-                    .await?;
-                    let result_text = format_tool_error_result("Paused by user")?;
-                    persist_tool_result_message(
-                        &pool,
-                        &conversation_id,
-                        &mut context_messages,
-                        &tool_call,
-                        result_text,
-                    )
-                    .await?;
-                    break;
+                    match resolution.decision {
+                        ToolApprovalDecision::AllowAlways => {
+                            always_allowed_tools.insert(tool_call.function.name.clone());
+                        }
+                        ToolApprovalDecision::AllowOnce => {}
+                        ToolApprovalDecision::Deny => {
+                            outcomes[index] = Some(Err(format!(
+                                "User denied execution of tool '{}'",
+                                tool_call.function.name
+                            )));
+                            continue;
+                        }
+                    }
+                    approved_calls.push((
+                        tool_call.clone(),
+                        read_hunk_selection(resolution.response.as_ref()),
+                    ));
+                    approved_indexes.push(index);
                 }
 
-                match decision {
-                    ToolApprovalDecision::AllowAlways => {
-                        always_allowed_tools.insert(tool_call.function.name.clone());
-                    }
-                    ToolApprovalDecision::AllowOnce => {}
-                    ToolApprovalDecision::Deny => {
-                        let error_text = format!(
-                            "User denied execution of tool '{}'",
-                            tool_call.function.name
-                        );
-                        emit_and_record_tool_result_event(
+                if let Some(paused_index) = paused_index {
+                    // Calls approved earlier in this segment have not started yet.
+                    cancelled_during_tools = true;
+                    for (tool_call, outcome) in
+                        calls.iter().zip(outcomes.iter_mut()).take(paused_index + 1)
+                    {
+                        let outcome = outcome
+                            .take()
+                            .unwrap_or_else(|| Err("Paused by user".to_string()));
+                        record_tool_call_outcome(
                             &pool,
                             &window,
                             &conversation_id,
                             &turn_id,
                             &mut seq,
-                            &tool_call,
-                            None,
-                            Some(&error_text),
-                        )
-                        .await?;
-                        let result_text = format_tool_error_result(&error_text)?;
-                        persist_tool_result_message(
-                            &pool,
-                            &conversation_id,
                             &mut context_messages,
-                            &tool_call,
-                            result_text,
+                            tool_call,
+                            outcome,
                         )
                         .await?;
-                        continue;
                     }
+                    break;
                 }
 
-                let tool_result = execute_tool_call(
+                let run_policy = RunPolicy::default();
+                let mut results = std::pin::pin!(execute_tool_calls_ordered(
                     mcp_state,
                     skill_state,
                     &config,
                     &tool_map,
-                    &tool_call,
+                    approved_calls,
+                    &run_policy,
                     &workspace_root,
                     &conversation_id,
                     &pool,
-                    &llm_service,
-                    &model_to_use,
-                )
-                .await;
-                match tool_result {
-                    Ok(value) => {
-                        let result_text = serde_json::to_string_pretty(&value)
-                            .unwrap_or_else(|_| value.to_string());
-                        emit_and_record_tool_result_event(
+                    llm_routes.active_service(),
+                    llm_routes.active_model(),
+                    max_parallel,
+                ));
+                let mut approved_indexes = approved_indexes.into_iter();
+                loop {
+                    while recorded < calls.len() {
+                        let Some(outcome) = outcomes[recorded].take() else {
+                            break;
+                        };
+                        record_tool_call_outcome(
                             &pool,
                             &window,
                             &conversation_id,
                             &turn_id,
                             &mut seq,
-                            &tool_call,
-                            Some(&result_text),
-                            None,
-                        )
-                        .await?;
-                        persist_tool_result_message(
-                            &pool,
-                            &conversation_id,
                             &mut context_messages,
-                            &tool_call,
-                            result_text,
-                        )
-                        .await?;
-                    }
-                    Err(error_text) => {
-                        emit_and_record_tool_result_event(
-                            &pool,
-                            &window,
-                            &conversation_id,
-                            &turn_id,
-                            &mut seq,
-                            &tool_call,
-                            None,
-                            Some(&error_text),
-                        )
-                        .await?;
-                        let result_text = format_tool_error_result(&error_text)?;
-                        persist_tool_result_message(
-                            &pool,
-                            &conversation_id,
-                            &mut context_messages,
-                            &tool_call,
-                            result_text,
+                            &calls[recorded],
+                            outcome,
                         )
                         .await?;
+                        recorded += 1;
                     }
+                    let Some((_, tool_result)) = results.next().await else {
+                        break;
+                    };
+                    let Some(index) = approved_indexes.next() else {
+                        break;
+                    };
+                    outcomes[index] = Some(tool_result.map(|value| {
+                        serde_json::to_string_pretty(&value).unwrap_or_else(|_| value.to_string())
+                    }));
                 }
             }
 
@@ -735,8 +918,11 @@ pub async fn get_conversations(state: State<'_, AppState>) -> Result<Vec<Convers
         guard.db().pool().clone()
     };
 
-    let rows = sqlx::query_as::<_, (String, String, String, String, String)>(
-        "SELECT id, title, model, created_at, updated_at FROM conversations ORDER BY updated_at DESC",
+    let rows = sqlx::query_as::<
+        _,
+        (String, String, String, Option<String>, Option<bool>, String, String),
+    >(
+        "SELECT id, title, model, tool_profile, sandboxed, created_at, updated_at FROM conversations ORDER BY updated_at DESC",
     )
     .fetch_all(&pool)
     .await
@@ -745,12 +931,16 @@ pub async fn get_conversations(state: State<'_, AppState>) -> Result<Vec<Convers
     let conversations = rows
         .into_iter()
         .map(
-            |(id, title, model, created_at_raw, updated_at_raw)| Conversation {
-                id,
-                title,
-                model,
-                created_at: created_at_raw.parse().unwrap_or_else(|_| Utc::now()),
-                updated_at: updated_at_raw.parse().unwrap_or_else(|_| Utc::now()),
+            |(id, title, model, tool_profile, sandboxed, created_at_raw, updated_at_raw)| {
+                Conversation {
+                    id,
+                    title,
+                    model,
+                    tool_profile,
+                    sandboxed,
+                    created_at: created_at_raw.parse().unwrap_or_else(|_| Utc::now()),
+                    updated_at: updated_at_raw.parse().unwrap_or_else(|_| Utc::now()),
+                }
             },
         )
         .collect();
@@ -778,10 +968,12 @@ pub async fn get_messages(
             String,
             Option<String>,
             Option<String>,
+            Option<String>,
         ),
-    >(
-        "SELECT id, conversation_id, role, content, created_at, tool_calls, reasoning FROM messages WHERE conversation_id = ? ORDER BY created_at ASC",
-    )
+    >(&format!(
+        "{} SELECT m.id, m.conversation_id, m.role, m.content, m.created_at, m.tool_calls, m.reasoning, m.parent_id FROM messages m JOIN branch ON m.id = branch.id ORDER BY branch.depth DESC",
+        ACTIVE_BRANCH_CTE
+    ))
     .bind(&conversation_id)
     .fetch_all(&pool)
     .await
@@ -798,6 +990,7 @@ pub async fn get_messages(
                 created_at_raw,
                 tool_calls_raw,
                 reasoning_raw,
+                parent_id,
             )| {
                 let role = match role_raw.as_str() {
                     "user" => MessageRole::User,
@@ -855,6 +1048,7 @@ pub async fn get_messages(
                 Message {
                     id,
                     conversation_id,
+                    parent_id,
                     role,
                     content,
                     reasoning: reasoning_raw.and_then(|value| {
@@ -881,10 +1075,27 @@ fn parse_timeline_event_type(raw: &str) -> TimelineEventType {
         "assistant_reasoning" => TimelineEventType::AssistantReasoning,
         "assistant_tool_call" => TimelineEventType::AssistantToolCall,
         "assistant_tool_result" => TimelineEventType::AssistantToolResult,
+        "model_fallback" => TimelineEventType::ModelFallback,
+        "checkpoint_restored" => TimelineEventType::CheckpointRestored,
+        "plan_updated" => TimelineEventType::PlanUpdated,
+        "sub_agent_step" => TimelineEventType::SubAgentStep,
         _ => TimelineEventType::AssistantText,
     }
 }
 
+#[tauri::command]
+pub async fn get_conversation_plan(
+    state: State<'_, AppState>,
+    conversation_id: String,
+) -> Result<TodoPlan, String> {
+    let pool = {
+        let guard = state.lock().await;
+        guard.db().pool().clone()
+    };
+
+    load_todo_plan(&pool, &conversation_id).await
+}
+
 #[tauri::command]
 pub async fn get_conversation_timeline(
     state: State<'_, AppState>,
@@ -917,9 +1128,10 @@ pub async fn get_conversation_timeline(
     .fetch_all(&pool)
     .await
     .map_err(|e| e.to_string())?;
+    let branches = load_branch_points(&pool, &conversation_id).await?;
 
     if !event_rows.is_empty() {
-        let events = event_rows
+        let events: Vec<TimelineEvent> = event_rows
             .into_iter()
             .map(
                 |(
@@ -944,9 +1156,33 @@ pub async fn get_conversation_timeline(
             )
             .collect();
 
+        // Turns opened by a message that was since edited or regenerated belong to another
+        // branch.
+        let branch_ids = load_active_branch_ids(&pool, &conversation_id)
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let hidden_turns = events
+            .iter()
+            .filter(|event| matches!(event.event_type, TimelineEventType::UserMessage))
+            .filter(|event| {
+                event
+                    .payload
+                    .get("messageId")
+                    .and_then(Value::as_str)
+                    .is_some_and(|id| !branch_ids.contains(id))
+            })
+            .map(|event| event.turn_id.clone())
+            .collect::<HashSet<_>>();
+        let events = events
+            .into_iter()
+            .filter(|event| !hidden_turns.contains(&event.turn_id))
+            .collect();
+
         return Ok(ConversationTimeline {
             events,
             legacy: false,
+            branches,
         });
     }
 
@@ -961,12 +1197,13 @@ pub async fn get_conversation_timeline(
             Option<String>,
             Option<String>,
         ),
-    >(
-        "SELECT id, role, content, created_at, conversation_id, tool_calls, reasoning
-         FROM messages
-         WHERE conversation_id = ?
-         ORDER BY created_at ASC",
-    )
+    >(&format!(
+        "{} SELECT m.id, m.role, m.content, m.created_at, m.conversation_id, m.tool_calls, m.reasoning
+         FROM messages m
+         JOIN branch ON m.id = branch.id
+         ORDER BY branch.depth DESC",
+        ACTIVE_BRANCH_CTE
+    ))
     .bind(&conversation_id)
     .fetch_all(&pool)
     .await
@@ -1000,7 +1237,7 @@ pub async fn get_conversation_timeline(
                     seq,
                     event_type: TimelineEventType::UserMessage,
                     tool_call_id: None,
-                    payload: json!({ "content": content }),
+                    payload: json!({ "content": content, "messageId": message_id }),
                     created_at: created_at.clone(),
                 });
             }
@@ -1131,6 +1368,7 @@ pub async fn get_conversation_timeline(
     Ok(ConversationTimeline {
         events,
         legacy: true,
+        branches,
     })
 }
 
@@ -1164,6 +1402,8 @@ pub async fn create_conversation(
         id,
         title,
         model,
+        tool_profile: None,
+        sandboxed: None,
         created_at: Utc::now(),
         updated_at: Utc::now(),
     })
@@ -1182,6 +1422,14 @@ pub async fn delete_conversation(state: State<'_, AppState>, id: String) -> Resu
         .await
         .map_err(|e| e.to_string())?;
 
+    let stopped = terminate_conversation_workspace_processes(&id).await;
+    if stopped > 0 {
+        eprintln!(
+            "[process] stopped {} background process(es) of deleted conversation {}",
+            stopped, id
+        );
+    }
+
     Ok(())
 }
 
@@ -1248,3 +1496,114 @@ pub async fn update_conversation_model(
 
     Ok(())
 }
+
+#[tauri::command]
+pub async fn set_conversation_fallback_models(
+    state: State<'_, AppState>,
+    id: String,
+    models: Vec<String>,
+) -> Result<(), String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for model in models {
+        let model = model.trim();
+        if !model.is_empty() && !normalized.iter().any(|item| item == model) {
+            normalized.push(model.to_string());
+        }
+    }
+    // An empty list clears the override so the global `llm_retry.fallback_models` applies.
+    let stored = if normalized.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(&normalized).map_err(|e| e.to_string())?)
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let pool = {
+        let guard = state.lock().await;
+        guard.db().pool().clone()
+    };
+
+    let result =
+        sqlx::query("UPDATE conversations SET fallback_models = ?, updated_at = ? WHERE id = ?")
+            .bind(stored)
+            .bind(&now)
+            .bind(&id)
+            .execute(&pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    if result.rows_affected() == 0 {
+        return Err(format!("Conversation not found: {}", id));
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_conversation_tool_profile(
+    state: State<'_, AppState>,
+    id: String,
+    profile: Option<String>,
+) -> Result<(), String> {
+    // An empty profile clears the override so `tool_selection.default_profile` applies.
+    let profile = profile
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string);
+
+    let now = Utc::now().to_rfc3339();
+    let pool = {
+        let guard = state.lock().await;
+        guard.db().pool().clone()
+    };
+
+    let result =
+        sqlx::query("UPDATE conversations SET tool_profile = ?, updated_at = ? WHERE id = ?")
+            .bind(profile)
+            .bind(&now)
+            .bind(&id)
+            .execute(&pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    if result.rows_affected() == 0 {
+        return Err(format!("Conversation not found: {}", id));
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_conversation_sandbox(
+    state: State<'_, AppState>,
+    id: String,
+    sandboxed: Option<bool>,
+) -> Result<(), String> {
+    // None clears the override so `sandbox.enabled` applies.
+    let now = Utc::now().to_rfc3339();
+    let pool = {
+        let guard = state.lock().await;
+        guard.db().pool().clone()
+    };
+
+    let result = sqlx::query("UPDATE conversations SET sandboxed = ?, updated_at = ? WHERE id = ?")
+        .bind(sandboxed)
+        .bind(&now)
+        .bind(&id)
+        .execute(&pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if result.rows_affected() == 0 {
+        return Err(format!("Conversation not found: {}", id));
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_tool_profiles() -> Result<Vec<String>, String> {
+    let config = crate::utils::load_config::<Config>().map_err(|e| e.to_string())?;
+    Ok(list_tool_profile_names(&config.tool_selection))
+}
@@ -0,0 +1,499 @@
+use crate::models::config::ContextCompactionConfig;
+use crate::services::llm::{ChatMessage, LlmService};
+use crate::services::usage::{record_usage_logged, UsageSource};
+use sqlx::SqlitePool;
+
+use super::storage::*;
+
+const DEFAULT_CONTEXT_WINDOW_TOKENS: usize = 32_000;
+const MIN_USABLE_CONTEXT_TOKENS: usize = 4_000;
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+const MIN_TOOL_RESULT_CHARS: usize = 256;
+const TRANSCRIPT_MESSAGE_MAX_CHARS: usize = 6_000;
+const TRANSCRIPT_TOOL_RESULT_MAX_CHARS: usize = 1_500;
+const SUMMARY_CONTEXT_HEADER: &str =
+    "Summary of earlier conversation turns (older messages were compacted to fit the context window):";
+const SUMMARY_SYSTEM_PROMPT: &str = "You compress earlier parts of an agent conversation so it can continue within a limited context window. \
+Write a concise but complete summary that preserves: the user's goals and constraints, decisions made, important facts, file paths and identifiers, \
+tool actions taken and their key results, and any open tasks or unanswered questions. \
+Merge the previous summary (if any) with the new excerpt into a single summary. Do not invent details. \
+Reply with the summary only, in the same language the user used.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ContextBudget {
+    pub(crate) usable_tokens: usize,
+    pub(crate) trigger_tokens: usize,
+    pub(crate) keep_recent_tokens: usize,
+    pub(crate) stale_tool_result_chars: usize,
+}
+
+fn builtin_context_window(model: &str) -> usize {
+    let normalized = model.trim().to_ascii_lowercase();
+    if normalized.starts_with("minimax")
+        || normalized.starts_with("claude")
+        || normalized.starts_with("glm-4.6")
+        || normalized.starts_with("glm-5")
+    {
+        200_000
+    } else if normalized.starts_with("glm-") || normalized.starts_with("doubao") {
+        128_000
+    } else if normalized.starts_with("gpt-4.1") {
+        1_000_000
+    } else if normalized.starts_with("gpt-4o") || normalized.starts_with("gpt-5") {
+        128_000
+    } else {
+        DEFAULT_CONTEXT_WINDOW_TOKENS
+    }
+}
+
+pub(crate) fn resolve_model_context_window(config: &ContextCompactionConfig, model: &str) -> usize {
+    let normalized = model.trim().to_ascii_lowercase();
+    let configured = config
+        .model_context_windows
+        .iter()
+        .filter(|(key, value)| {
+            let key = key.trim().to_ascii_lowercase();
+            **value > 0 && !key.is_empty() && normalized.starts_with(&key)
+        })
+        .max_by_key(|(key, _)| key.trim().len())
+        .map(|(_, value)| *value);
+
+    configured.unwrap_or_else(|| builtin_context_window(&normalized))
+}
+
+pub(crate) fn resolve_context_budget(
+    config: &ContextCompactionConfig,
+    model: &str,
+) -> ContextBudget {
+    let context_window = resolve_model_context_window(config, model);
+    let usable_tokens = context_window
+        .saturating_sub(config.reserved_output_tokens)
+        .max(MIN_USABLE_CONTEXT_TOKENS);
+    let trigger_ratio = config.trigger_ratio.clamp(0.1, 1.0);
+    let keep_recent_ratio = config.keep_recent_ratio.clamp(0.05, trigger_ratio);
+
+    ContextBudget {
+        usable_tokens,
+        trigger_tokens: (usable_tokens as f64 * trigger_ratio) as usize,
+        keep_recent_tokens: (usable_tokens as f64 * keep_recent_ratio) as usize,
+        stale_tool_result_chars: config.stale_tool_result_chars.max(MIN_TOOL_RESULT_CHARS),
+    }
+}
+
+/// Rough token estimate: ~4 ASCII chars per token, one token per CJK/other wide char.
+pub(crate) fn estimate_text_tokens(text: &str) -> usize {
+    let mut ascii_chars = 0usize;
+    let mut wide_chars = 0usize;
+    for ch in text.chars() {
+        if ch.is_ascii() {
+            ascii_chars += 1;
+        } else {
+            wide_chars += 1;
+        }
+    }
+    ascii_chars.div_ceil(4) + wide_chars
+}
+
+pub(crate) fn estimate_message_tokens(message: &ChatMessage) -> usize {
+    let mut tokens = MESSAGE_OVERHEAD_TOKENS;
+    if let Some(content) = message.content.as_deref() {
+        tokens += estimate_text_tokens(content);
+    }
+    if let Some(reasoning) = message.reasoning.as_deref() {
+        tokens += estimate_text_tokens(reasoning);
+    }
+    if let Some(tool_calls) = message.tool_calls.as_ref() {
+        for tool_call in tool_calls {
+            tokens += MESSAGE_OVERHEAD_TOKENS
+                + estimate_text_tokens(&tool_call.function.name)
+                + estimate_text_tokens(&tool_call.function.arguments);
+        }
+    }
+    tokens
+}
+
+pub(crate) fn estimate_messages_tokens(messages: &[ChatMessage]) -> usize {
+    messages.iter().map(estimate_message_tokens).sum()
+}
+
+fn truncate_with_marker(content: &str, max_chars: usize) -> Option<String> {
+    let total_chars = content.chars().count();
+    if total_chars <= max_chars {
+        return None;
+    }
+
+    let kept = content.chars().take(max_chars).collect::<String>();
+    Some(format!(
+        "{}\n...[truncated: {} of {} chars omitted]",
+        kept,
+        total_chars - max_chars,
+        total_chars
+    ))
+}
+
+/// Truncates tool outputs that belong to turns before the latest user message.
+pub(crate) fn elide_stale_tool_outputs(messages: &mut [ChatMessage], max_chars: usize) {
+    let Some(last_user_index) = messages.iter().rposition(|message| message.role == "user") else {
+        return;
+    };
+
+    for message in messages.iter_mut().take(last_user_index) {
+        if message.role != "tool" {
+            continue;
+        }
+        if let Some(truncated) = message
+            .content
+            .as_deref()
+            .and_then(|content| truncate_with_marker(content, max_chars))
+        {
+            message.content = Some(truncated);
+        }
+    }
+}
+
+/// Shrinks tool outputs (oldest first) until the in-flight context fits the usable budget.
+/// Used between tool rounds, where a single turn can accumulate large results.
+pub(crate) fn fit_context_to_budget(messages: &mut [ChatMessage], budget: &ContextBudget) {
+    let mut total = estimate_messages_tokens(messages);
+    if total <= budget.usable_tokens {
+        return;
+    }
+
+    for max_chars in [budget.stale_tool_result_chars, MIN_TOOL_RESULT_CHARS] {
+        for message in messages.iter_mut() {
+            if total <= budget.usable_tokens {
+                return;
+            }
+            if message.role != "tool" {
+                continue;
+            }
+            let before = estimate_message_tokens(message);
+            if let Some(truncated) = message
+                .content
+                .as_deref()
+                .and_then(|content| truncate_with_marker(content, max_chars))
+            {
+                message.content = Some(truncated);
+                total = total.saturating_sub(before) + estimate_message_tokens(message);
+            }
+        }
+    }
+}
+
+/// Picks the index of the first message kept verbatim. The boundary always lands on a user
+/// message so an assistant tool call is never separated from its tool results, and the
+/// latest user message is always kept.
+pub(crate) fn select_compaction_boundary(
+    messages: &[ChatMessage],
+    keep_recent_tokens: usize,
+) -> Option<usize> {
+    let mut tail_tokens = 0usize;
+    let mut boundary = None;
+
+    for (index, message) in messages.iter().enumerate().rev() {
+        tail_tokens += estimate_message_tokens(message);
+        if message.role != "user" {
+            continue;
+        }
+        if boundary.is_some() && tail_tokens > keep_recent_tokens {
+            break;
+        }
+        boundary = Some(index);
+    }
+
+    boundary.filter(|index| *index > 0)
+}
+
+fn render_transcript_entry(message: &ChatMessage) -> String {
+    let mut parts = Vec::new();
+    if let Some(content) = message
+        .content
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+    {
+        let limit = if message.role == "tool" {
+            TRANSCRIPT_TOOL_RESULT_MAX_CHARS
+        } else {
+            TRANSCRIPT_MESSAGE_MAX_CHARS
+        };
+        parts.push(truncate_with_marker(content, limit).unwrap_or_else(|| content.to_string()));
+    }
+    if let Some(tool_calls) = message.tool_calls.as_ref() {
+        for tool_call in tool_calls {
+            let arguments = truncate_with_marker(
+                &tool_call.function.arguments,
+                TRANSCRIPT_TOOL_RESULT_MAX_CHARS,
+            )
+            .unwrap_or_else(|| tool_call.function.arguments.clone());
+            parts.push(format!(
+                "-> call {}({})",
+                tool_call.function.name, arguments
+            ));
+        }
+    }
+
+    format!("[{}]\n{}", message.role, parts.join("\n"))
+}
+
+fn build_transcript_chunks(messages: &[ChatMessage], chunk_tokens: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0usize;
+
+    for message in messages {
+        let entry = render_transcript_entry(message);
+        let entry_tokens = estimate_text_tokens(&entry);
+        if !current.is_empty() && current_tokens + entry_tokens > chunk_tokens {
+            chunks.push(std::mem::take(&mut current));
+            current_tokens = 0;
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(&entry);
+        current_tokens += entry_tokens;
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+async fn summarize_messages(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    llm_service: &LlmService,
+    model: &str,
+    previous_summary: Option<&str>,
+    messages: &[ChatMessage],
+    chunk_tokens: usize,
+) -> Result<String, String> {
+    let mut summary = previous_summary.map(str::to_string);
+
+    for chunk in build_transcript_chunks(messages, chunk_tokens) {
+        let request = format!(
+            "Previous summary:\n{}\n\nConversation excerpt to merge:\n{}",
+            summary.as_deref().unwrap_or("(none)"),
+            chunk
+        );
+        let output = llm_service
+            .chat_with_usage(
+                model,
+                vec![
+                    ChatMessage {
+                        role: "system".to_string(),
+                        content: Some(SUMMARY_SYSTEM_PROMPT.to_string()),
+                        tool_calls: None,
+                        tool_call_id: None,
+                        reasoning_details: None,
+                        reasoning: None,
+                    },
+                    ChatMessage {
+                        role: "user".to_string(),
+                        content: Some(request),
+                        tool_calls: None,
+                        tool_call_id: None,
+                        reasoning_details: None,
+                        reasoning: None,
+                    },
+                ],
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        record_usage_logged(
+            pool,
+            Some(conversation_id),
+            None,
+            UsageSource::Compaction,
+            model,
+            output.usage.as_ref(),
+        )
+        .await;
+
+        let trimmed = output.content.trim();
+        if trimmed.is_empty() {
+            return Err("Context summary response was empty".to_string());
+        }
+        summary = Some(trimmed.to_string());
+    }
+
+    summary.ok_or_else(|| "Nothing to summarize".to_string())
+}
+
+pub(crate) fn build_summary_context_message(summary: &str) -> ChatMessage {
+    ChatMessage {
+        role: "system".to_string(),
+        content: Some(format!("{}\n\n{}", SUMMARY_CONTEXT_HEADER, summary.trim())),
+        tool_calls: None,
+        tool_call_id: None,
+        reasoning_details: None,
+        reasoning: None,
+    }
+}
+
+/// Summarizes older turns into a new `conversation_summaries` record when the stored history
+/// no longer fits the model budget. The boundary only ever moves forward, so earlier summaries
+/// are folded into the new one instead of being recomputed. Returns whether a summary was written.
+pub(crate) async fn maybe_compact_conversation(
+    pool: &SqlitePool,
+    llm_service: &LlmService,
+    conversation_id: &str,
+    model: &str,
+    config: &ContextCompactionConfig,
+) -> Result<bool, String> {
+    let budget = resolve_context_budget(config, model);
+    let previous = load_latest_conversation_summary(pool, conversation_id).await?;
+    let rows = load_conversation_context_rows(
+        pool,
+        conversation_id,
+        previous
+            .as_ref()
+            .map(|record| record.boundary_created_at.as_str()),
+    )
+    .await?;
+
+    let mut messages = rows
+        .iter()
+        .map(|row| row.message.clone())
+        .collect::<Vec<_>>();
+    elide_stale_tool_outputs(&mut messages, budget.stale_tool_result_chars);
+
+    let summary_tokens = previous
+        .as_ref()
+        .map(|record| estimate_message_tokens(&build_summary_context_message(&record.summary)))
+        .unwrap_or(0);
+    if summary_tokens + estimate_messages_tokens(&messages) <= budget.trigger_tokens {
+        return Ok(false);
+    }
+
+    let Some(boundary) = select_compaction_boundary(&messages, budget.keep_recent_tokens) else {
+        return Ok(false);
+    };
+
+    let summary = summarize_messages(
+        pool,
+        conversation_id,
+        llm_service,
+        model,
+        previous.as_ref().map(|record| record.summary.as_str()),
+        &messages[..boundary],
+        (budget.usable_tokens / 2).max(MIN_USABLE_CONTEXT_TOKENS / 2),
+    )
+    .await?;
+
+    let last_summarized = &rows[boundary - 1];
+    let record = ConversationSummaryRecord {
+        summary,
+        boundary_message_id: last_summarized.id.clone(),
+        boundary_created_at: last_summarized.created_at.clone(),
+        summarized_messages: previous
+            .as_ref()
+            .map(|record| record.summarized_messages)
+            .unwrap_or(0)
+            + boundary as i64,
+    };
+    insert_conversation_summary(pool, conversation_id, &record, model).await?;
+    Ok(true)
+}
+
+/// Loads the model-facing history for a conversation: compacts it first when it exceeds the
+/// budget, then replays the latest summary plus the messages after its boundary.
+pub(crate) async fn prepare_conversation_context(
+    pool: &SqlitePool,
+    llm_service: &LlmService,
+    conversation_id: &str,
+    model: &str,
+    config: &ContextCompactionConfig,
+) -> Result<Vec<ChatMessage>, String> {
+    if !config.enabled {
+        return load_conversation_context(pool, conversation_id).await;
+    }
+
+    if let Err(error) =
+        maybe_compact_conversation(pool, llm_service, conversation_id, model, config).await
+    {
+        eprintln!(
+            "[context] compaction failed for conversation {}: {}",
+            conversation_id, error
+        );
+    }
+
+    let mut messages = load_conversation_context(pool, conversation_id).await?;
+    let budget = resolve_context_budget(config, model);
+    elide_stale_tool_outputs(&mut messages, budget.stale_tool_result_chars);
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: Some(content.to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            reasoning_details: None,
+            reasoning: None,
+        }
+    }
+
+    #[test]
+    fn estimates_ascii_and_wide_text_differently() {
+        assert_eq!(estimate_text_tokens("abcdefgh"), 2);
+        assert_eq!(estimate_text_tokens("你好"), 2);
+    }
+
+    #[test]
+    fn configured_context_window_prefers_longest_prefix() {
+        let mut config = ContextCompactionConfig::default();
+        config
+            .model_context_windows
+            .insert("glm".to_string(), 10_000);
+        config
+            .model_context_windows
+            .insert("glm-4.6".to_string(), 50_000);
+        assert_eq!(resolve_model_context_window(&config, "GLM-4.6-air"), 50_000);
+        assert_eq!(resolve_model_context_window(&config, "glm-4"), 10_000);
+        assert_eq!(
+            resolve_model_context_window(&config, "unknown"),
+            DEFAULT_CONTEXT_WINDOW_TOKENS
+        );
+    }
+
+    #[test]
+    fn elides_only_tool_outputs_from_earlier_turns() {
+        let long = "x".repeat(1_000);
+        let mut messages = vec![
+            message("user", "first"),
+            message("tool", &long),
+            message("user", "second"),
+            message("tool", &long),
+        ];
+        elide_stale_tool_outputs(&mut messages, 300);
+        assert!(messages[1]
+            .content
+            .as_deref()
+            .unwrap()
+            .contains("truncated"));
+        assert_eq!(messages[3].content.as_deref(), Some(long.as_str()));
+    }
+
+    #[test]
+    fn boundary_lands_on_user_message_and_keeps_latest_turn() {
+        let long = "y".repeat(4_000);
+        let messages = vec![
+            message("user", "one"),
+            message("assistant", &long),
+            message("user", "two"),
+            message("tool", &long),
+            message("user", "three"),
+        ];
+        assert_eq!(select_compaction_boundary(&messages, 10), Some(4));
+        assert_eq!(select_compaction_boundary(&messages, 1_200), Some(2));
+        assert_eq!(select_compaction_boundary(&messages[..1], 10), None);
+    }
+}
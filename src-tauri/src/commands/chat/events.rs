@@ -0,0 +1,20 @@
+use serde_json::Value;
+use std::sync::OnceLock;
+use tauri::{AppHandle, Emitter};
+
+static APP_EVENTS: OnceLock<AppHandle> = OnceLock::new();
+
+/// Lets code without a window (tool executors, background runs) notify the UI.
+pub(crate) fn init_app_events(app: AppHandle) {
+    let _ = APP_EVENTS.set(app);
+}
+
+pub(crate) fn app_events_handle() -> Option<AppHandle> {
+    APP_EVENTS.get().cloned()
+}
+
+pub(crate) fn emit_app_event(event: &str, payload: Value) {
+    if let Some(app) = APP_EVENTS.get() {
+        let _ = app.emit(event, payload);
+    }
+}
@@ -0,0 +1,209 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use similar::TextDiff;
+use std::collections::HashMap;
+
+const DIFF_CONTEXT_LINES: usize = 3;
+const MAX_DIFF_CHARS: usize = 60_000;
+
+/// Field of an approval response holding the hunks the user accepted for a write, edit or
+/// patch call. Files missing from it are applied in full.
+pub(crate) const APPROVED_HUNKS_FIELD: &str = "approved_hunks";
+
+/// Approved hunk indices per workspace-relative path. It travels next to the tool call,
+/// never inside its arguments, so the model cannot pick hunks for itself.
+pub(crate) type HunkSelection = HashMap<String, Vec<usize>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DiffHunk {
+    pub index: usize,
+    pub header: String,
+    pub diff: String,
+}
+
+/// One file a tool call is about to change, as shown in the approval card.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct FileChangePreview {
+    pub path: String,
+    /// add, update or delete.
+    pub operation: String,
+    pub move_to: Option<String>,
+    pub binary: bool,
+    pub diff: String,
+    pub hunks: Vec<DiffHunk>,
+}
+
+impl FileChangePreview {
+    pub(crate) fn new(
+        path: String,
+        operation: &str,
+        move_to: Option<String>,
+        before: &str,
+        after: &str,
+    ) -> Self {
+        let binary = before.contains('\0') || after.contains('\0');
+        let (diff, hunks) = if binary {
+            (String::new(), Vec::new())
+        } else {
+            (
+                render_unified_diff(&path, before, after),
+                diff_hunks(before, after),
+            )
+        };
+        Self {
+            path,
+            operation: operation.to_string(),
+            move_to,
+            binary,
+            diff,
+            hunks,
+        }
+    }
+}
+
+/// The content to write after applying only the approved hunks of a file.
+pub(crate) struct HunkSelectionOutcome {
+    pub content: String,
+    pub applied: Vec<usize>,
+    pub report: Value,
+}
+
+pub(crate) fn render_unified_diff(path: &str, before: &str, after: &str) -> String {
+    let diff = TextDiff::from_lines(before, after)
+        .unified_diff()
+        .context_radius(DIFF_CONTEXT_LINES)
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string();
+    match diff.char_indices().nth(MAX_DIFF_CHARS) {
+        Some((index, _)) => format!("{}\n... diff truncated ...\n", &diff[..index]),
+        None => diff,
+    }
+}
+
+pub(crate) fn diff_hunks(before: &str, after: &str) -> Vec<DiffHunk> {
+    let diff = TextDiff::from_lines(before, after);
+    let mut unified = diff.unified_diff();
+    unified.context_radius(DIFF_CONTEXT_LINES);
+    unified
+        .iter_hunks()
+        .enumerate()
+        .map(|(index, hunk)| DiffHunk {
+            index,
+            header: hunk.header().to_string(),
+            diff: hunk.to_string(),
+        })
+        .collect()
+}
+
+/// Rebuilds `before` with the changes of the `accepted` hunks only. Hunks are numbered as
+/// in `diff_hunks`, so the indices shown in the approval card apply here unchanged.
+pub(crate) fn apply_selected_hunks(before: &str, after: &str, accepted: &[usize]) -> String {
+    let diff = TextDiff::from_lines(before, after);
+    let old_lines = diff.old_slices();
+    let new_lines = diff.new_slices();
+    let mut merged = String::with_capacity(before.len().max(after.len()));
+    let mut cursor = 0;
+    for (index, group) in diff.grouped_ops(DIFF_CONTEXT_LINES).iter().enumerate() {
+        let take_new = accepted.contains(&index);
+        for op in group {
+            let old_range = op.old_range();
+            merged.extend(old_lines[cursor..old_range.start].iter().copied());
+            if take_new {
+                merged.extend(new_lines[op.new_range()].iter().copied());
+            } else {
+                merged.extend(old_lines[old_range.clone()].iter().copied());
+            }
+            cursor = old_range.end;
+        }
+    }
+    merged.extend(old_lines[cursor..].iter().copied());
+    merged
+}
+
+/// The hunk choice of an approval response; empty when the user approved everything.
+pub(crate) fn read_hunk_selection(response: Option<&Value>) -> HunkSelection {
+    response
+        .and_then(|value| value.get(APPROVED_HUNKS_FIELD))
+        .and_then(Value::as_object)
+        .map(|files| {
+            files
+                .iter()
+                .map(|(path, indices)| {
+                    let indices = indices
+                        .as_array()
+                        .map(|items| {
+                            items
+                                .iter()
+                                .filter_map(Value::as_u64)
+                                .map(|index| index as usize)
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    (path.clone(), indices)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Applies the user's hunk choice for `path`, or returns None when the whole change was
+/// approved. The report lists applied and rejected hunks so the model knows what landed.
+pub(crate) fn select_hunks(
+    selection: &HunkSelection,
+    path: &str,
+    before: &str,
+    after: &str,
+) -> Option<HunkSelectionOutcome> {
+    let accepted = selection.get(path)?;
+    let hunks = diff_hunks(before, after);
+    let (applied, rejected): (Vec<&DiffHunk>, Vec<&DiffHunk>) = hunks
+        .iter()
+        .partition(|hunk| accepted.contains(&hunk.index));
+    let content = if rejected.is_empty() {
+        after.to_string()
+    } else {
+        apply_selected_hunks(before, after, accepted)
+    };
+    Some(HunkSelectionOutcome {
+        content,
+        applied: applied.iter().map(|hunk| hunk.index).collect(),
+        report: json!({
+            "path": path,
+            "total_hunks": hunks.len(),
+            "applied_hunks": applied.iter().map(|hunk| json!({ "index": hunk.index, "header": hunk.header })).collect::<Vec<_>>(),
+            "rejected_hunks": rejected.iter().map(|hunk| json!({ "index": hunk.index, "header": hunk.header, "diff": hunk.diff })).collect::<Vec<_>>()
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_only_accepted_hunks() {
+        let before = (1..=20)
+            .map(|line| format!("line {}\n", line))
+            .collect::<String>();
+        let after = before
+            .replace("line 2\n", "line two\n")
+            .replace("line 18\n", "line eighteen\n");
+
+        let hunks = diff_hunks(&before, &after);
+        assert_eq!(hunks.len(), 2);
+        assert!(hunks[0].header.starts_with("@@ -1,"));
+        assert!(hunks[1].diff.contains("+line eighteen\n"));
+
+        let merged = apply_selected_hunks(&before, &after, &[1]);
+        assert!(merged.contains("line 2\n") && merged.contains("line eighteen\n"));
+        assert_eq!(apply_selected_hunks(&before, &after, &[0, 1]), after);
+        assert_eq!(apply_selected_hunks(&before, &after, &[]), before);
+
+        let selection =
+            read_hunk_selection(Some(&json!({ "approved_hunks": { "notes.txt": [0] } })));
+        let outcome = select_hunks(&selection, "notes.txt", &before, &after).unwrap();
+        assert_eq!(outcome.applied, vec![0]);
+        assert_eq!(outcome.report["rejected_hunks"][0]["index"], 1);
+        assert!(select_hunks(&selection, "other.txt", &before, &after).is_none());
+    }
+}
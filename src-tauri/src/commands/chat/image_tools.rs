@@ -1,4 +1,4 @@
-use crate::services::llm::LlmService;
+use crate::models::config::Config;
 use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine as _};
 use std::fs;
 use std::io::Read;
@@ -9,7 +9,7 @@ use serde_json::{json, Value};
 
 use super::{
     is_forbidden_loopback_host, read_bool_argument, read_optional_string_argument,
-    read_string_argument, read_u64_argument, resolve_workspace_target,
+    read_string_argument, read_u64_argument, resolve_text_llm_provider, resolve_workspace_target,
     workspace_relative_display_path,
 };
 
@@ -210,16 +210,30 @@ pub(super) async fn execute_image_probe(
 pub(super) async fn execute_image_understand(
     arguments: &Value,
     workspace_root: &Path,
-    llm_service: &LlmService,
-    default_model: &str,
+    config: &Config,
 ) -> Result<Value, String> {
     let raw_prompt = read_string_argument(arguments, "prompt")?;
     let prompt = format!(
         "{}\n\n(System Instruction: If the user needs to interact with or click elements in a canvas, game, or UI, please proactively describe the exact coordinate positions [x, y] or bounding boxes [x, y, w, h] of those buttons/elements in the image. Do not rely on guessing. **CRITICAL INFO: if you are trying to locate text buttons to click, immediately STOP guessing using the vision model, and use the `ocr_locate` tool on the image instead for 100% accurate coordinates.**)",
         raw_prompt
     );
-    let model = read_optional_string_argument(arguments, "model")
-        .unwrap_or_else(|| default_model.to_string());
+    let model = read_optional_string_argument(arguments, "model").unwrap_or_else(|| {
+        let configured = config.image_understand_model.trim();
+        if configured.is_empty() {
+            "glm-4.6v".to_string()
+        } else {
+            configured.to_string()
+        }
+    });
+    // Route by the vision model itself, not by the conversation's chat model.
+    let provider = resolve_text_llm_provider(config, &model)?;
+    if !provider.capabilities.vision {
+        return Err(format!(
+            "Model {} (provider {}) does not support image input",
+            model, provider.name
+        ));
+    }
+    let llm_service = provider.build_service();
     let enable_thinking = read_bool_argument(arguments, "thinking", false);
     let max_bytes = arguments
         .get("max_bytes")
@@ -0,0 +1,318 @@
+use crate::models::config::Config;
+use crate::services::llm::{
+    is_transient_llm_error, ChatMessage, ChatTool, LlmChatOutput, LlmService, LlmStreamEvent,
+    LlmStreamResult, ModelFallback,
+};
+use sqlx::SqlitePool;
+
+use super::llm_provider::resolve_text_llm_provider;
+
+pub(crate) struct LlmRoute {
+    pub model: String,
+    pub provider_name: String,
+    pub service: LlmService,
+    pub tools_enabled: bool,
+}
+
+/// Ordered list of models to try for a turn. Each `LlmService` already retries transient
+/// errors with backoff; the chain moves on to the next route only once those are exhausted.
+/// A route that takes over stays active for the rest of the turn.
+pub(crate) struct LlmRouteChain {
+    routes: Vec<LlmRoute>,
+    active: usize,
+}
+
+pub(crate) async fn resolve_conversation_fallback_models(
+    pool: &SqlitePool,
+    conversation_id: &str,
+) -> Result<Option<Vec<String>>, String> {
+    let raw = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT fallback_models FROM conversations WHERE id = ?",
+    )
+    .bind(conversation_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .flatten();
+    Ok(raw.and_then(|value| serde_json::from_str::<Vec<String>>(&value).ok()))
+}
+
+impl LlmRouteChain {
+    /// The primary model must resolve; fallbacks without usable credentials are skipped.
+    pub fn resolve(
+        config: &Config,
+        primary_model: &str,
+        fallback_models: Option<&[String]>,
+    ) -> Result<Self, String> {
+        let mut routes = vec![Self::route(config, primary_model)?];
+        let fallback_models =
+            fallback_models.unwrap_or(config.llm_retry.fallback_models.as_slice());
+        for model in fallback_models {
+            let model = model.trim();
+            if model.is_empty() || routes.iter().any(|route| route.model == model) {
+                continue;
+            }
+            match Self::route(config, model) {
+                Ok(route) => routes.push(route),
+                Err(error) => {
+                    eprintln!("[llm] skip fallback model {}: {}", model, error);
+                }
+            }
+        }
+        Ok(Self { routes, active: 0 })
+    }
+
+    pub async fn for_conversation(
+        config: &Config,
+        pool: &SqlitePool,
+        conversation_id: &str,
+        primary_model: &str,
+    ) -> Result<Self, String> {
+        let fallback_models = resolve_conversation_fallback_models(pool, conversation_id).await?;
+        Self::resolve(config, primary_model, fallback_models.as_deref())
+    }
+
+    fn route(config: &Config, model: &str) -> Result<LlmRoute, String> {
+        let provider = resolve_text_llm_provider(config, model)?;
+        Ok(LlmRoute {
+            model: model.to_string(),
+            provider_name: provider.name.clone(),
+            service: provider.build_service(),
+            tools_enabled: provider.capabilities.tools,
+        })
+    }
+
+    pub fn active(&self) -> &LlmRoute {
+        &self.routes[self.active]
+    }
+
+    pub fn active_model(&self) -> &str {
+        &self.active().model
+    }
+
+    pub fn active_service(&self) -> &LlmService {
+        &self.active().service
+    }
+
+    fn can_fail_over(&self, error: &anyhow::Error) -> bool {
+        self.active + 1 < self.routes.len() && is_transient_llm_error(error)
+    }
+
+    fn advance(&mut self, error: &anyhow::Error) -> ModelFallback {
+        let from_model = self.active().model.clone();
+        self.active += 1;
+        let next = self.active();
+        eprintln!(
+            "[llm] failing over from {} to {} ({}): {}",
+            from_model, next.model, next.provider_name, error
+        );
+        ModelFallback {
+            from_model,
+            to_model: next.model.clone(),
+            provider: next.provider_name.clone(),
+            error: error.to_string(),
+        }
+    }
+
+    pub async fn chat_stream_with_tools(
+        &mut self,
+        messages: Vec<ChatMessage>,
+        tools: &[ChatTool],
+        mut callback: impl FnMut(LlmStreamEvent) + Send,
+        should_cancel: impl Fn() -> bool + Send + Sync,
+    ) -> anyhow::Result<LlmStreamResult> {
+        loop {
+            let route = self.active();
+            let route_tools = if tools.is_empty() || !route.tools_enabled {
+                None
+            } else {
+                Some(tools.to_vec())
+            };
+            let mut emitted = false;
+            let result = route
+                .service
+                .chat_stream_with_tools(
+                    &route.model,
+                    messages.clone(),
+                    route_tools,
+                    |event| {
+                        emitted = true;
+                        callback(event);
+                    },
+                    &should_cancel,
+                )
+                .await;
+            let error = match result {
+                Ok(output) => return Ok(output),
+                Err(error) => error,
+            };
+            if emitted || should_cancel() || !self.can_fail_over(&error) {
+                return Err(error);
+            }
+            let fallback = self.advance(&error);
+            callback(LlmStreamEvent::ModelFallback(fallback));
+        }
+    }
+
+    pub async fn chat_with_usage(
+        &mut self,
+        messages: Vec<ChatMessage>,
+    ) -> anyhow::Result<LlmChatOutput> {
+        loop {
+            let route = self.active();
+            let error = match route
+                .service
+                .chat_with_usage(&route.model, messages.clone())
+                .await
+            {
+                Ok(output) => return Ok(output),
+                Err(error) => error,
+            };
+            if !self.can_fail_over(&error) {
+                return Err(error);
+            }
+            self.advance(&error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::config::{LlmRetryConfig, LlmWireProtocol};
+    use crate::services::test_http::{TestResponse, TestServer};
+    use serde_json::json;
+
+    fn test_route(model: &str, server: &TestServer) -> LlmRoute {
+        LlmRoute {
+            model: model.to_string(),
+            provider_name: format!("{}-provider", model),
+            service: LlmService::with_protocol(
+                String::new(),
+                server.base_url.clone(),
+                LlmWireProtocol::OpenaiChat,
+            )
+            .with_retry_policy(LlmRetryConfig {
+                max_retries: 1,
+                initial_backoff_ms: 1,
+                max_backoff_ms: 1,
+                max_retry_after_ms: 1_000,
+                fallback_models: Vec::new(),
+            }),
+            tools_enabled: true,
+        }
+    }
+
+    fn user_message() -> ChatMessage {
+        ChatMessage {
+            role: "user".to_string(),
+            content: Some("hi".to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            reasoning_details: None,
+            reasoning: None,
+        }
+    }
+
+    fn completion(content: &str) -> serde_json::Value {
+        json!({
+            "model": "test-model",
+            "choices": [{ "index": 0, "message": { "role": "assistant", "content": content } }]
+        })
+    }
+
+    #[tokio::test]
+    async fn fails_over_in_order_after_retries_and_stays_on_the_fallback() {
+        let busy = json!({ "error": { "message": "overloaded" } });
+        let primary = TestServer::scripted(vec![(529, busy.clone())]).await;
+        let second = TestServer::scripted(vec![(503, busy)]).await;
+        let third = TestServer::scripted(vec![(200, completion("from third"))]).await;
+        let mut chain = LlmRouteChain {
+            routes: vec![
+                test_route("primary", &primary),
+                test_route("second", &second),
+                test_route("third", &third),
+            ],
+            active: 0,
+        };
+
+        let output = chain.chat_with_usage(vec![user_message()]).await.unwrap();
+        assert_eq!(output.content, "from third");
+        assert_eq!(chain.active_model(), "third");
+        // Each route exhausts its own retries before the chain moves on.
+        assert_eq!(primary.requests().len(), 2);
+        assert_eq!(second.requests().len(), 2);
+        assert_eq!(third.requests().len(), 1);
+
+        chain.chat_with_usage(vec![user_message()]).await.unwrap();
+        assert_eq!(primary.requests().len(), 2);
+        assert_eq!(third.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn non_transient_errors_do_not_fail_over() {
+        let primary =
+            TestServer::scripted(vec![(401, json!({ "error": { "message": "bad key" } }))]).await;
+        let fallback = TestServer::scripted(vec![(200, completion("unused"))]).await;
+        let mut chain = LlmRouteChain {
+            routes: vec![
+                test_route("primary", &primary),
+                test_route("fallback", &fallback),
+            ],
+            active: 0,
+        };
+
+        let error = chain.chat_with_usage(vec![user_message()]).await.unwrap_err();
+        assert!(error.to_string().contains("bad key"));
+        assert_eq!(chain.active_model(), "primary");
+        assert_eq!(primary.requests().len(), 1);
+        assert!(fallback.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn stream_failover_reports_the_switch_before_fallback_output() {
+        let primary =
+            TestServer::scripted(vec![(503, json!({ "error": { "message": "busy" } }))]).await;
+        let fallback = TestServer::spawn(|_| {
+            TestResponse::new(
+                200,
+                "text/event-stream",
+                "data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\ndata: [DONE]\n\n",
+            )
+        })
+        .await;
+        let mut chain = LlmRouteChain {
+            routes: vec![
+                test_route("primary", &primary),
+                test_route("fallback", &fallback),
+            ],
+            active: 0,
+        };
+
+        let mut events = Vec::new();
+        let result = chain
+            .chat_stream_with_tools(
+                vec![user_message()],
+                &[],
+                |event| {
+                    events.push(match event {
+                        LlmStreamEvent::ModelFallback(fallback) => {
+                            format!("fallback:{}->{}", fallback.from_model, fallback.to_model)
+                        }
+                        LlmStreamEvent::Content(content) => format!("content:{}", content),
+                        _ => "other".to_string(),
+                    })
+                },
+                || false,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.content, "hi");
+        assert_eq!(
+            events,
+            vec!["fallback:primary->fallback".to_string(), "content:hi".to_string()]
+        );
+        assert_eq!(primary.requests().len(), 2);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::models::config::Config;
+use crate::services::llm::ChatMessage;
+use crate::services::mcp_client::{JsonRpcError, McpMessageHandler};
+use crate::services::usage::{record_usage_logged, UsageSource};
+use crate::utils::load_config;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Emitter};
+
+use super::llm_failover::LlmRouteChain;
+use super::stream::{request_approval, ToolApprovalDecision};
+use super::tool_catalog::resolve_workspace_root;
+
+pub(crate) const MCP_SAMPLING_APPROVAL_TOOL: &str = "mcp_sampling";
+pub(crate) const MCP_ELICITATION_APPROVAL_TOOL: &str = "mcp_elicitation";
+
+const REQUEST_REJECTED: i32 = -1;
+const INTERNAL_ERROR: i32 = -32603;
+const INVALID_PARAMS: i32 = -32602;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// The conversation on whose behalf one of a server's tools is running.
+#[derive(Debug, Clone)]
+pub(crate) struct McpCallContext {
+    pub conversation_id: String,
+    pub workspace_root: PathBuf,
+    pub model: String,
+    pub pool: SqlitePool,
+}
+
+struct ActiveMcpCall {
+    id: u64,
+    server_name: String,
+    context: McpCallContext,
+}
+
+static ACTIVE_MCP_CALLS: OnceLock<Mutex<Vec<ActiveMcpCall>>> = OnceLock::new();
+static NEXT_MCP_CALL_ID: AtomicU64 = AtomicU64::new(1);
+
+fn active_mcp_calls() -> &'static Mutex<Vec<ActiveMcpCall>> {
+    ACTIVE_MCP_CALLS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// Removes the call when it finishes or its future is dropped.
+pub(crate) struct McpCallContextGuard {
+    id: u64,
+}
+
+impl Drop for McpCallContextGuard {
+    fn drop(&mut self) {
+        if let Ok(mut calls) = active_mcp_calls().lock() {
+            calls.retain(|call| call.id != self.id);
+        }
+    }
+}
+
+pub(crate) fn enter_mcp_call_context(
+    server_name: &str,
+    context: McpCallContext,
+) -> McpCallContextGuard {
+    let id = NEXT_MCP_CALL_ID.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut calls) = active_mcp_calls().lock() {
+        calls.push(ActiveMcpCall {
+            id,
+            server_name: server_name.to_string(),
+            context,
+        });
+    }
+    McpCallContextGuard { id }
+}
+
+/// The context of the tool call `server_name` is serving. Tool calls run concurrently, so a
+/// server request is only attributed when all of that server's running calls belong to one
+/// conversation; otherwise it could prompt or bill the wrong one.
+fn current_mcp_call_context(server_name: &str) -> Option<McpCallContext> {
+    let calls = active_mcp_calls().lock().ok()?;
+    let mut serving = calls.iter().filter(|call| call.server_name == server_name);
+    let latest = serving.clone().last()?;
+    serving
+        .all(|call| call.context.conversation_id == latest.context.conversation_id)
+        .then(|| latest.context.clone())
+}
+
+static UPDATED_MCP_RESOURCES: OnceLock<Mutex<HashSet<(String, String)>>> = OnceLock::new();
+
+/// Subscribed resources whose `notifications/resources/updated` arrived since the agent
+/// last read them.
+fn updated_mcp_resources() -> &'static Mutex<HashSet<(String, String)>> {
+    UPDATED_MCP_RESOURCES.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+pub(crate) fn mcp_resource_has_update(server_name: &str, uri: &str) -> bool {
+    updated_mcp_resources()
+        .lock()
+        .map(|updated| updated.contains(&(server_name.to_string(), uri.to_string())))
+        .unwrap_or(false)
+}
+
+pub(crate) fn take_mcp_resource_update(server_name: &str, uri: &str) -> bool {
+    updated_mcp_resources()
+        .lock()
+        .map(|mut updated| updated.remove(&(server_name.to_string(), uri.to_string())))
+        .unwrap_or(false)
+}
+
+/// Serves server-initiated MCP requests: `roots/list` from the conversation workspace,
+/// `sampling/createMessage` through the configured LLM after user approval, and
+/// `elicitation/create` as a form in the approval card.
+pub(crate) struct McpHost {
+    app: AppHandle,
+    trusted_sampling_servers: Mutex<HashSet<String>>,
+}
+
+impl McpHost {
+    pub fn new(app: AppHandle) -> Self {
+        Self {
+            app,
+            trusted_sampling_servers: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn list_roots(&self, server_name: &str) -> Result<Value, JsonRpcError> {
+        let workspace_root = match current_mcp_call_context(server_name) {
+            Some(context) => Some(context.workspace_root),
+            None => load_config::<Config>()
+                .ok()
+                .and_then(|config| resolve_workspace_root(&config, None).ok()),
+        };
+        let roots = workspace_root
+            .and_then(|root| {
+                let name = root
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "workspace".to_string());
+                reqwest::Url::from_file_path(&root)
+                    .ok()
+                    .map(|uri| json!({ "uri": uri.to_string(), "name": name }))
+            })
+            .into_iter()
+            .collect::<Vec<_>>();
+        Ok(json!({ "roots": roots }))
+    }
+
+    fn sampling_trusted(&self, server_name: &str) -> bool {
+        self.trusted_sampling_servers
+            .lock()
+            .map(|servers| servers.contains(server_name))
+            .unwrap_or(false)
+    }
+
+    async fn create_message(
+        &self,
+        server_name: &str,
+        params: Option<Value>,
+    ) -> Result<Value, JsonRpcError> {
+        let context = current_mcp_call_context(server_name).ok_or_else(|| {
+            JsonRpcError::new(
+                REQUEST_REJECTED,
+                "Sampling is only available while one of this server's tools is running",
+            )
+        })?;
+        let params = params.unwrap_or_else(|| json!({}));
+        let messages = sampling_messages(&params)
+            .map_err(|message| JsonRpcError::new(INVALID_PARAMS, message))?;
+
+        if !self.sampling_trusted(server_name) {
+            let arguments = json!({
+                "server": server_name,
+                "systemPrompt": params.get("systemPrompt"),
+                "messages": params.get("messages"),
+                "maxTokens": params.get("maxTokens"),
+            });
+            let resolution = request_approval(
+                &self.app,
+                &context.conversation_id,
+                &format!("mcp-sampling-{}", server_name),
+                MCP_SAMPLING_APPROVAL_TOOL,
+                arguments.to_string(),
+                Vec::new(),
+            )
+            .await
+            .map_err(|error| JsonRpcError::new(INTERNAL_ERROR, error))?;
+            match resolution.decision {
+                ToolApprovalDecision::Deny => {
+                    return Err(JsonRpcError::new(
+                        REQUEST_REJECTED,
+                        "User rejected the sampling request",
+                    ));
+                }
+                ToolApprovalDecision::AllowAlways => {
+                    if let Ok(mut servers) = self.trusted_sampling_servers.lock() {
+                        servers.insert(server_name.to_string());
+                    }
+                }
+                ToolApprovalDecision::AllowOnce => {}
+            }
+        }
+
+        let config = load_config::<Config>()
+            .map_err(|error| JsonRpcError::new(INTERNAL_ERROR, error.to_string()))?;
+        let mut llm_routes = LlmRouteChain::resolve(&config, &context.model, None)
+            .map_err(|error| JsonRpcError::new(INTERNAL_ERROR, error))?;
+        let output = llm_routes
+            .chat_with_usage(messages)
+            .await
+            .map_err(|error| JsonRpcError::new(INTERNAL_ERROR, error.to_string()))?;
+        record_usage_logged(
+            &context.pool,
+            Some(context.conversation_id.as_str()),
+            None,
+            UsageSource::Tool,
+            llm_routes.active_model(),
+            output.usage.as_ref(),
+        )
+        .await;
+
+        Ok(json!({
+            "role": "assistant",
+            "content": { "type": "text", "text": output.content },
+            "model": llm_routes.active_model(),
+            "stopReason": "endTurn"
+        }))
+    }
+
+    async fn elicit(
+        &self,
+        server_name: &str,
+        params: Option<Value>,
+    ) -> Result<Value, JsonRpcError> {
+        let Some(context) = current_mcp_call_context(server_name) else {
+            return Ok(json!({ "action": "cancel" }));
+        };
+        let params = params.unwrap_or_else(|| json!({}));
+        let arguments = json!({
+            "server": server_name,
+            "message": params.get("message"),
+            "requestedSchema": params.get("requestedSchema"),
+        });
+        let resolution = request_approval(
+            &self.app,
+            &context.conversation_id,
+            &format!("mcp-elicitation-{}", server_name),
+            MCP_ELICITATION_APPROVAL_TOOL,
+            arguments.to_string(),
+            Vec::new(),
+        )
+        .await
+        .map_err(|error| JsonRpcError::new(INTERNAL_ERROR, error))?;
+
+        Ok(match resolution.decision {
+            ToolApprovalDecision::Deny => json!({ "action": "decline" }),
+            ToolApprovalDecision::AllowOnce | ToolApprovalDecision::AllowAlways => json!({
+                "action": "accept",
+                "content": resolution.response.unwrap_or_else(|| json!({}))
+            }),
+        })
+    }
+}
+
+fn sampling_content_text(content: &Value) -> String {
+    match content {
+        Value::Array(items) => items
+            .iter()
+            .map(sampling_content_text)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Value::Object(_) => match content.get("type").and_then(Value::as_str) {
+            Some("text") => content
+                .get("text")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            Some(other) => format!("[{} content omitted]", other),
+            None => String::new(),
+        },
+        Value::String(text) => text.clone(),
+        _ => String::new(),
+    }
+}
+
+fn sampling_messages(params: &Value) -> Result<Vec<ChatMessage>, String> {
+    let mut messages = Vec::new();
+    if let Some(system_prompt) = params
+        .get("systemPrompt")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|prompt| !prompt.is_empty())
+    {
+        messages.push(text_message("system", system_prompt.to_string()));
+    }
+    let items = params
+        .get("messages")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "sampling/createMessage requires messages".to_string())?;
+    for item in items {
+        let role = match item.get("role").and_then(Value::as_str) {
+            Some("assistant") => "assistant",
+            _ => "user",
+        };
+        let text = item
+            .get("content")
+            .map(sampling_content_text)
+            .unwrap_or_default();
+        messages.push(text_message(role, text));
+    }
+    Ok(messages)
+}
+
+fn text_message(role: &str, content: String) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content: Some(content),
+        tool_calls: None,
+        tool_call_id: None,
+        reasoning_details: None,
+        reasoning: None,
+    }
+}
+
+#[async_trait]
+impl McpMessageHandler for McpHost {
+    async fn handle_request(
+        &self,
+        server_name: &str,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<Value, JsonRpcError> {
+        match method {
+            "ping" => Ok(json!({})),
+            "roots/list" => self.list_roots(server_name),
+            "sampling/createMessage" => self.create_message(server_name, params).await,
+            "elicitation/create" => self.elicit(server_name, params).await,
+            _ => Err(JsonRpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Method not supported by client: {}", method),
+            )),
+        }
+    }
+
+    async fn handle_notification(&self, server_name: &str, method: &str, params: Option<Value>) {
+        if method == "notifications/resources/updated" {
+            let Some(uri) = params
+                .as_ref()
+                .and_then(|params| params.get("uri"))
+                .and_then(Value::as_str)
+            else {
+                return;
+            };
+            if let Ok(mut updated) = updated_mcp_resources().lock() {
+                updated.insert((server_name.to_string(), uri.to_string()));
+            }
+            let _ = self.app.emit(
+                "mcp-resource-updated",
+                json!({ "server": server_name, "uri": uri }),
+            );
+            return;
+        }
+        eprintln!("[mcp] {} notification: {}", server_name, method);
+    }
+
+    fn client_capabilities(&self) -> Value {
+        json!({
+            "roots": { "listChanged": true },
+            "sampling": {},
+            "elicitation": {}
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(conversation_id: &str) -> McpCallContext {
+        McpCallContext {
+            conversation_id: conversation_id.to_string(),
+            workspace_root: PathBuf::from("/tmp"),
+            model: "test-model".to_string(),
+            pool: SqlitePool::connect_lazy("sqlite::memory:").unwrap(),
+        }
+    }
+
+    fn conversation_of(server_name: &str) -> Option<String> {
+        current_mcp_call_context(server_name).map(|context| context.conversation_id)
+    }
+
+    #[tokio::test]
+    async fn server_requests_only_see_their_own_calls() {
+        let first = enter_mcp_call_context("context-test-a", context("c1"));
+        assert_eq!(conversation_of("context-test-a").as_deref(), Some("c1"));
+        // Another server cannot sample on behalf of a call it is not serving.
+        assert_eq!(conversation_of("context-test-b"), None);
+
+        let same_conversation = enter_mcp_call_context("context-test-a", context("c1"));
+        assert_eq!(conversation_of("context-test-a").as_deref(), Some("c1"));
+        drop(same_conversation);
+
+        let other_conversation = enter_mcp_call_context("context-test-a", context("c2"));
+        assert_eq!(conversation_of("context-test-a"), None);
+        drop(other_conversation);
+
+        assert_eq!(conversation_of("context-test-a").as_deref(), Some("c1"));
+        drop(first);
+        assert_eq!(conversation_of("context-test-a"), None);
+    }
+}
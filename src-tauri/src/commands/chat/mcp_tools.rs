@@ -0,0 +1,167 @@
+use serde_json::{json, Value};
+
+use crate::commands::mcp::McpState;
+use crate::services::mcp_client::{render_prompt_messages, render_resource_contents};
+
+use super::{
+    mcp_resource_has_update, read_bool_argument, read_optional_string_argument,
+    read_string_argument, read_u64_argument, take_mcp_resource_update,
+};
+
+const DEFAULT_RESOURCE_MAX_CHARS: u64 = 100_000;
+const MAX_RESOURCE_MAX_CHARS: u64 = 400_000;
+
+fn is_filtered_out(filter: Option<&str>, server_name: &str) -> bool {
+    matches!(filter, Some(filter) if filter != server_name)
+}
+
+pub(super) async fn execute_mcp_resources_list(
+    mcp_state: &McpState,
+    arguments: &Value,
+) -> Result<Value, String> {
+    let filter = read_optional_string_argument(arguments, "server");
+    let manager = mcp_state.lock().await;
+    let mut servers = Vec::new();
+    for (server_name, client) in manager.list_clients() {
+        if is_filtered_out(filter.as_deref(), &server_name) {
+            continue;
+        }
+        if client.capabilities.resources != Some(true) {
+            continue;
+        }
+        let mut resources = client.list_resources();
+        resources.sort_by(|left, right| left.uri.cmp(&right.uri));
+        let resources = resources
+            .into_iter()
+            .map(|resource| {
+                json!({
+                    "uri": resource.uri,
+                    "name": resource.name,
+                    "description": resource.description,
+                    "mime_type": resource.mime_type,
+                    "subscribed": client.is_subscribed(&resource.uri),
+                    "updated": mcp_resource_has_update(&server_name, &resource.uri),
+                })
+            })
+            .collect::<Vec<_>>();
+        servers.push(json!({
+            "server": server_name,
+            "supports_subscribe": client.capabilities.resource_subscribe == Some(true),
+            "resources": resources,
+            "resource_templates": client.list_resource_templates(),
+        }));
+    }
+
+    Ok(json!({ "servers": servers }))
+}
+
+pub(super) async fn execute_mcp_resource_read(
+    mcp_state: &McpState,
+    arguments: &Value,
+) -> Result<Value, String> {
+    let server = read_string_argument(arguments, "server")?;
+    let uri = read_string_argument(arguments, "uri")?;
+    let max_chars = read_u64_argument(arguments, "max_chars", DEFAULT_RESOURCE_MAX_CHARS)
+        .clamp(1, MAX_RESOURCE_MAX_CHARS) as usize;
+
+    let mut manager = mcp_state.lock().await;
+    let client = manager
+        .get_client_mut(&server)
+        .ok_or_else(|| format!("MCP server '{}' is not connected", server))?;
+    let result = client
+        .read_resource(&uri)
+        .await
+        .map_err(|e| e.to_string())?;
+    take_mcp_resource_update(&server, &uri);
+
+    Ok(json!({
+        "server": server,
+        "uri": uri,
+        "contents": render_resource_contents(&result, max_chars),
+    }))
+}
+
+pub(super) async fn execute_mcp_resource_subscribe(
+    mcp_state: &McpState,
+    arguments: &Value,
+) -> Result<Value, String> {
+    let server = read_string_argument(arguments, "server")?;
+    let uri = read_string_argument(arguments, "uri")?;
+    let subscribe = read_bool_argument(arguments, "subscribe", true);
+
+    let mut manager = mcp_state.lock().await;
+    let client = manager
+        .get_client_mut(&server)
+        .ok_or_else(|| format!("MCP server '{}' is not connected", server))?;
+    if subscribe {
+        client
+            .subscribe_resource(&uri)
+            .await
+            .map_err(|e| e.to_string())?;
+    } else {
+        client
+            .unsubscribe_resource(&uri)
+            .await
+            .map_err(|e| e.to_string())?;
+        take_mcp_resource_update(&server, &uri);
+    }
+
+    Ok(json!({
+        "server": server,
+        "uri": uri,
+        "subscribed": subscribe,
+    }))
+}
+
+pub(super) async fn execute_mcp_prompts_list(
+    mcp_state: &McpState,
+    arguments: &Value,
+) -> Result<Value, String> {
+    let filter = read_optional_string_argument(arguments, "server");
+    let manager = mcp_state.lock().await;
+    let mut prompts = Vec::new();
+    for (server_name, client) in manager.list_clients() {
+        if is_filtered_out(filter.as_deref(), &server_name) {
+            continue;
+        }
+        for prompt in client.list_prompts() {
+            prompts.push(json!({
+                "server": server_name,
+                "name": prompt.name,
+                "description": prompt.description,
+                "arguments": prompt.arguments,
+            }));
+        }
+    }
+
+    Ok(json!({ "prompts": prompts }))
+}
+
+pub(super) async fn execute_mcp_prompt_get(
+    mcp_state: &McpState,
+    arguments: &Value,
+) -> Result<Value, String> {
+    let server = read_string_argument(arguments, "server")?;
+    let name = read_string_argument(arguments, "name")?;
+    let prompt_arguments = arguments
+        .get("arguments")
+        .filter(|value| value.is_object())
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+
+    let mut manager = mcp_state.lock().await;
+    let client = manager
+        .get_client_mut(&server)
+        .ok_or_else(|| format!("MCP server '{}' is not connected", server))?;
+    let result = client
+        .get_prompt(&name, prompt_arguments)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(json!({
+        "server": server,
+        "name": name,
+        "description": result.get("description").cloned().unwrap_or(Value::Null),
+        "text": render_prompt_messages(&result),
+    }))
+}
@@ -0,0 +1,760 @@
+use similar::TextDiff;
+
+/// Context lines a hunk may lose at either edge and still apply, like `patch --fuzz=2`.
+const MAX_CONTEXT_FUZZ: usize = 2;
+const MAX_REPORT_LINES: usize = 12;
+
+pub(crate) enum PatchOperation {
+    Add {
+        path: String,
+        lines: Vec<String>,
+    },
+    Delete {
+        path: String,
+    },
+    Update {
+        path: String,
+        move_to: Option<String>,
+        hunks: Vec<PatchHunk>,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PatchHunk {
+    /// Text after `@@` in an envelope hunk, e.g. the enclosing function signature.
+    pub context: Option<String>,
+    /// 1-based start line in the old file, when the header carries `-start,count`.
+    pub old_start: Option<usize>,
+    /// Set by `*** End of File`: the hunk belongs at the end of the file.
+    pub at_eof: bool,
+    /// Hunk body; every line starts with ' ', '+' or '-'.
+    pub lines: Vec<String>,
+}
+
+struct HunkMatch {
+    position: usize,
+    skip_leading: usize,
+    skip_trailing: usize,
+}
+
+type LineMatcher = fn(&str, &str) -> bool;
+
+/// Tried in order, so whitespace is only ignored when an exact match does not exist.
+const LINE_MATCHERS: [LineMatcher; 4] = [
+    |left, right| left == right,
+    |left, right| left.trim_end() == right.trim_end(),
+    |left, right| left.trim() == right.trim(),
+    |left, right| normalize_line(left) == normalize_line(right),
+];
+
+/// Accepts the `*** Begin Patch` envelope as well as unified diffs (`diff -u`, `git diff`).
+pub(crate) fn parse_patch(patch: &str) -> Result<Vec<PatchOperation>, String> {
+    let patch = strip_code_fence(patch);
+    if patch.starts_with("*** Begin Patch") {
+        parse_patch_envelope(patch)
+    } else if patch
+        .lines()
+        .any(|line| line.starts_with("--- ") || line.starts_with("diff --git "))
+    {
+        parse_unified_diff(patch)
+    } else {
+        Err(
+            "Patch must start with '*** Begin Patch' or be a unified diff ('--- a/path', '+++ b/path', '@@ ... @@')"
+                .to_string(),
+        )
+    }
+}
+
+fn strip_code_fence(patch: &str) -> &str {
+    let trimmed = patch.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    let body = rest.split_once('\n').map(|(_, body)| body).unwrap_or("");
+    body.trim_end().strip_suffix("```").unwrap_or(body).trim()
+}
+
+pub(crate) fn parse_patch_envelope(patch: &str) -> Result<Vec<PatchOperation>, String> {
+    let lines: Vec<&str> = patch.lines().collect();
+    if lines.first().map(|line| line.trim()) != Some("*** Begin Patch") {
+        return Err("Patch must start with '*** Begin Patch'".to_string());
+    }
+
+    let mut index = 1usize;
+    let mut operations = Vec::new();
+
+    while index < lines.len() {
+        let line = lines[index].trim_end();
+        if line == "*** End Patch" {
+            return Ok(operations);
+        }
+        if line.is_empty() {
+            index += 1;
+            continue;
+        }
+
+        if let Some(path) = line.strip_prefix("*** Add File: ") {
+            index += 1;
+            let mut add_lines = Vec::new();
+            while index < lines.len() && !lines[index].starts_with("*** ") {
+                let Some(added) = lines[index].strip_prefix('+') else {
+                    return Err(format!(
+                        "Add file operation expects '+' lines, found: {}",
+                        lines[index]
+                    ));
+                };
+                add_lines.push(added.to_string());
+                index += 1;
+            }
+            operations.push(PatchOperation::Add {
+                path: path.trim().to_string(),
+                lines: add_lines,
+            });
+            continue;
+        }
+
+        if let Some(path) = line.strip_prefix("*** Delete File: ") {
+            operations.push(PatchOperation::Delete {
+                path: path.trim().to_string(),
+            });
+            index += 1;
+            continue;
+        }
+
+        if let Some(path) = line.strip_prefix("*** Update File: ") {
+            index += 1;
+            let mut move_to = None;
+            if index < lines.len() {
+                if let Some(target) = lines[index].strip_prefix("*** Move to: ") {
+                    move_to = Some(target.trim().to_string());
+                    index += 1;
+                }
+            }
+
+            let mut hunks = Vec::new();
+            while index < lines.len() && !lines[index].starts_with("*** ") {
+                let mut hunk = if lines[index].starts_with("@@") {
+                    let (old_start, context) = parse_hunk_header(lines[index]);
+                    index += 1;
+                    PatchHunk {
+                        context,
+                        old_start,
+                        ..PatchHunk::default()
+                    }
+                } else if hunks.is_empty() {
+                    // Models often drop the first `@@`; the body alone still locates itself.
+                    PatchHunk::default()
+                } else {
+                    return Err(format!("Expected hunk header '@@', got: {}", lines[index]));
+                };
+                // Bare empty lines are empty context lines, unless they trail the hunk.
+                let mut pending_blank_lines = 0usize;
+                while index < lines.len()
+                    && !lines[index].starts_with("@@")
+                    && !lines[index].starts_with("*** ")
+                {
+                    let body_line = lines[index];
+                    index += 1;
+                    if body_line.is_empty() {
+                        pending_blank_lines += 1;
+                        continue;
+                    }
+                    let prefix = body_line.chars().next().unwrap_or(' ');
+                    if prefix != ' ' && prefix != '+' && prefix != '-' {
+                        return Err(format!("Invalid hunk line prefix: {}", body_line));
+                    }
+                    hunk.lines
+                        .extend(std::iter::repeat_n(" ".to_string(), pending_blank_lines));
+                    pending_blank_lines = 0;
+                    hunk.lines.push(body_line.to_string());
+                }
+                if index < lines.len() && lines[index].trim_end() == "*** End of File" {
+                    hunk.at_eof = true;
+                    index += 1;
+                }
+                hunks.push(hunk);
+            }
+
+            operations.push(PatchOperation::Update {
+                path: path.trim().to_string(),
+                move_to,
+                hunks,
+            });
+            continue;
+        }
+
+        return Err(format!("Unknown patch operation: {}", line));
+    }
+
+    Err("Patch is missing '*** End Patch'".to_string())
+}
+
+#[derive(Default)]
+struct UnifiedFileHeader {
+    rename_from: Option<String>,
+    rename_to: Option<String>,
+}
+
+impl UnifiedFileHeader {
+    /// A git rename without content changes has no `---`/`+++` lines.
+    fn take_pure_rename(&mut self) -> Option<PatchOperation> {
+        match (self.rename_from.take(), self.rename_to.take()) {
+            (Some(path), Some(target)) => Some(PatchOperation::Update {
+                path,
+                move_to: Some(target),
+                hunks: Vec::new(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn parse_unified_diff(patch: &str) -> Result<Vec<PatchOperation>, String> {
+    let lines: Vec<&str> = patch.lines().collect();
+    let mut operations = Vec::new();
+    let mut header = UnifiedFileHeader::default();
+    let mut index = 0usize;
+
+    while index < lines.len() {
+        let line = lines[index];
+        if line.starts_with("diff --git ") {
+            operations.extend(header.take_pure_rename());
+            index += 1;
+            continue;
+        }
+        if let Some(path) = line.strip_prefix("rename from ") {
+            header.rename_from = Some(path.trim().to_string());
+            index += 1;
+            continue;
+        }
+        if let Some(path) = line.strip_prefix("rename to ") {
+            header.rename_to = Some(path.trim().to_string());
+            index += 1;
+            continue;
+        }
+        let Some(old_raw) = line.strip_prefix("--- ") else {
+            // index, mode and similarity lines carry nothing we need.
+            index += 1;
+            continue;
+        };
+        let Some(new_raw) = lines
+            .get(index + 1)
+            .and_then(|next| next.strip_prefix("+++ "))
+        else {
+            return Err(format!("Expected '+++' after '{}'", line));
+        };
+        let old_path = unified_diff_path(old_raw);
+        let new_path = unified_diff_path(new_raw);
+        header = UnifiedFileHeader::default();
+        index += 2;
+
+        let mut hunks = Vec::new();
+        while index < lines.len() && lines[index].starts_with("@@") {
+            let hunk_header = lines[index];
+            let (old_start, context) = parse_hunk_header(hunk_header);
+            // A header without ranges (`@@` or `@@ fn name`) runs until the next hunk or file.
+            let counts = hunk_line_counts(hunk_header);
+            let (mut old_remaining, mut new_remaining) = counts.unwrap_or_default();
+            index += 1;
+            let mut hunk = PatchHunk {
+                context,
+                old_start,
+                ..PatchHunk::default()
+            };
+            let mut trailing_blank_lines = 0;
+            while index < lines.len() {
+                let body_line = lines[index];
+                let hunk_ended = match counts {
+                    Some(_) => old_remaining == 0 && new_remaining == 0,
+                    None => {
+                        body_line.starts_with("@@")
+                            || body_line.starts_with("--- ")
+                            || body_line.starts_with("diff --git ")
+                    }
+                };
+                if hunk_ended {
+                    break;
+                }
+                match body_line.chars().next() {
+                    Some('\\') => {}
+                    Some('+') => new_remaining = new_remaining.saturating_sub(1),
+                    Some('-') => old_remaining = old_remaining.saturating_sub(1),
+                    Some(' ') | None => {
+                        old_remaining = old_remaining.saturating_sub(1);
+                        new_remaining = new_remaining.saturating_sub(1);
+                    }
+                    Some(_) => break,
+                }
+                if !body_line.starts_with('\\') {
+                    hunk.lines.push(if body_line.is_empty() {
+                        " ".to_string()
+                    } else {
+                        body_line.to_string()
+                    });
+                    trailing_blank_lines = if body_line.is_empty() {
+                        trailing_blank_lines + 1
+                    } else {
+                        0
+                    };
+                }
+                index += 1;
+            }
+            if counts.is_none() {
+                // Blank lines between hunks or at the end separate them; they are not context.
+                hunk.lines.truncate(hunk.lines.len() - trailing_blank_lines);
+            }
+            while index < lines.len() && lines[index].starts_with('\\') {
+                index += 1;
+            }
+            if hunk.lines.is_empty() {
+                return Err(format!("Hunk '{}' has no lines", hunk_header));
+            }
+            hunks.push(hunk);
+        }
+
+        operations.push(match (old_path, new_path) {
+            (None, Some(path)) => PatchOperation::Add {
+                path,
+                lines: hunks
+                    .iter()
+                    .flat_map(|hunk| hunk.lines.iter())
+                    .filter_map(|line| line.strip_prefix('+'))
+                    .map(str::to_string)
+                    .collect(),
+            },
+            (Some(path), None) => PatchOperation::Delete { path },
+            (Some(path), Some(target)) => PatchOperation::Update {
+                move_to: (target != path).then_some(target),
+                path,
+                hunks,
+            },
+            (None, None) => return Err(format!("Both sides of '{}' are /dev/null", line)),
+        });
+    }
+    operations.extend(header.take_pure_rename());
+
+    if operations.is_empty() {
+        return Err("Unified diff does not contain any file changes".to_string());
+    }
+    Ok(operations)
+}
+
+fn unified_diff_path(raw: &str) -> Option<String> {
+    // `diff -u` appends a tab and a timestamp; git quotes paths with special characters.
+    let path = raw
+        .split('\t')
+        .next()
+        .unwrap_or(raw)
+        .trim()
+        .trim_matches('"');
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+    Some(path.to_string())
+}
+
+/// Reads `@@ -12,7 +12,8 @@ fn name` as (Some(12), Some("fn name")); a bare envelope
+/// header `@@ fn name` only has the context part.
+fn parse_hunk_header(line: &str) -> (Option<usize>, Option<String>) {
+    let rest = line.trim_start_matches('@').trim();
+    let context = |text: &str| {
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_string())
+    };
+    let Some(ranges) = rest.strip_prefix('-') else {
+        return (None, context(rest));
+    };
+    let Some((ranges, tail)) = ranges.split_once("@@") else {
+        return (None, context(rest));
+    };
+    let old_start = ranges
+        .split_whitespace()
+        .next()
+        .and_then(|range| range.split(',').next())
+        .and_then(|start| start.parse::<usize>().ok());
+    match old_start {
+        Some(start) => (Some(start), context(tail)),
+        None => (None, context(rest)),
+    }
+}
+
+/// Line counts of both sides, or None when the header carries no ranges.
+fn hunk_line_counts(line: &str) -> Option<(usize, usize)> {
+    let count = |range: &str| match range.split_once(',') {
+        Some((_, count)) => count.parse().unwrap_or(0),
+        None => 1,
+    };
+    let mut ranges = line.trim_start_matches('@').split_whitespace();
+    let old = ranges.next()?.strip_prefix('-')?;
+    let new = ranges.next()?.strip_prefix('+')?;
+    Some((count(old), count(new)))
+}
+
+fn normalize_line(line: &str) -> String {
+    line.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .map(|ch| match ch {
+            '\u{2018}' | '\u{2019}' | '\u{201B}' => '\'',
+            '\u{201C}' | '\u{201D}' | '\u{201F}' => '"',
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => '-',
+            '\u{00A0}' => ' ',
+            other => other,
+        })
+        .collect()
+}
+
+fn split_hunk_line(line: &str) -> (char, &str) {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    match line.chars().next() {
+        Some(prefix @ ('+' | '-' | ' ')) => (prefix, &line[1..]),
+        _ => (' ', line),
+    }
+}
+
+/// Returns the match closest to `expected`, preferring matches at or after `cursor` so
+/// hunks keep applying top to bottom.
+fn find_nearest_match(
+    lines: &[String],
+    needle: &[&str],
+    cursor: usize,
+    expected: usize,
+    matcher: LineMatcher,
+) -> Option<usize> {
+    if needle.is_empty() || lines.len() < needle.len() {
+        return None;
+    }
+    let matches: Vec<usize> = (0..=lines.len() - needle.len())
+        .filter(|start| {
+            needle
+                .iter()
+                .enumerate()
+                .all(|(offset, expected_line)| matcher(&lines[start + offset], expected_line))
+        })
+        .collect();
+    let nearest = |candidates: &mut dyn Iterator<Item = usize>| {
+        candidates.min_by_key(|start| start.abs_diff(expected))
+    };
+    nearest(&mut matches.iter().copied().filter(|start| *start >= cursor))
+        .or_else(|| nearest(&mut matches.iter().copied()))
+}
+
+fn locate_hunk(
+    lines: &[String],
+    body: &[(char, &str)],
+    cursor: usize,
+    expected: usize,
+) -> Option<HunkMatch> {
+    let leading_context = body.iter().take_while(|(prefix, _)| *prefix == ' ').count();
+    let trailing_context = body
+        .iter()
+        .rev()
+        .take_while(|(prefix, _)| *prefix == ' ')
+        .count();
+    let mut tried = Vec::new();
+    for fuzz in 0..=MAX_CONTEXT_FUZZ {
+        let skip_leading = fuzz.min(leading_context);
+        let skip_trailing = fuzz.min(trailing_context);
+        if tried.contains(&(skip_leading, skip_trailing))
+            || skip_leading + skip_trailing >= body.len()
+        {
+            continue;
+        }
+        tried.push((skip_leading, skip_trailing));
+        let old_lines: Vec<&str> = body[skip_leading..body.len() - skip_trailing]
+            .iter()
+            .filter(|(prefix, _)| *prefix != '+')
+            .map(|(_, text)| *text)
+            .collect();
+        for matcher in LINE_MATCHERS {
+            if let Some(position) =
+                find_nearest_match(lines, &old_lines, cursor, expected + skip_leading, matcher)
+            {
+                return Some(HunkMatch {
+                    position,
+                    skip_leading,
+                    skip_trailing,
+                });
+            }
+        }
+    }
+    None
+}
+
+fn expected_hunk_position(
+    hunk: &PatchHunk,
+    lines: &[String],
+    cursor: usize,
+    line_offset: isize,
+    old_len: usize,
+) -> usize {
+    if hunk.at_eof {
+        return lines.len().saturating_sub(old_len);
+    }
+    if let Some(start) = hunk.old_start {
+        // `-5,0` inserts after line 5; any other range starts at line `start`.
+        let start = if old_len == 0 {
+            start
+        } else {
+            start.saturating_sub(1)
+        };
+        return start.saturating_add_signed(line_offset).min(lines.len());
+    }
+    if let Some(context) = hunk.context.as_deref() {
+        if let Some(anchor) = lines
+            .iter()
+            .skip(cursor)
+            .position(|line| line.trim() == context || line.contains(context))
+        {
+            return cursor + anchor + 1;
+        }
+    }
+    cursor
+}
+
+/// The window of `lines` most like `old_lines`, with its similarity in percent.
+fn closest_candidate(
+    lines: &[String],
+    old_lines: &[&str],
+    expected: usize,
+) -> Option<(usize, usize)> {
+    if lines.is_empty() || old_lines.is_empty() {
+        return None;
+    }
+    let window = old_lines.len().min(lines.len());
+    let normalized_old: Vec<String> = old_lines.iter().map(|line| normalize_line(line)).collect();
+    let normalized_lines: Vec<String> = lines.iter().map(|line| normalize_line(line)).collect();
+    let expected_text = normalized_old.join("\n");
+    let similarity = |start: usize| {
+        TextDiff::from_chars(
+            normalized_lines[start..start + window].join("\n").as_str(),
+            expected_text.as_str(),
+        )
+        .ratio()
+    };
+
+    let equal_lines = |start: usize| {
+        normalized_old
+            .iter()
+            .zip(&normalized_lines[start..start + window])
+            .filter(|(old, line)| old == line)
+            .count()
+    };
+    let starts = 0..=lines.len() - window;
+    let best_by_lines = starts.clone().max_by_key(|start| {
+        (
+            equal_lines(*start),
+            std::cmp::Reverse(start.abs_diff(expected)),
+        )
+    })?;
+    let position = if equal_lines(best_by_lines) > 0 {
+        best_by_lines
+    } else {
+        // Nothing matches line for line; fall back to the window whose first line reads
+        // most like the hunk's first line.
+        let first = normalized_old[0].as_str();
+        starts.max_by(|left, right| {
+            let score = |start: usize| {
+                TextDiff::from_chars(normalized_lines[start].as_str(), first).ratio()
+            };
+            score(*left).total_cmp(&score(*right))
+        })?
+    };
+    let percent = (similarity(position) * 100.0).round() as usize;
+    (percent > 0).then_some((position, percent))
+}
+
+fn describe_hunk_failure(
+    lines: &[String],
+    old_lines: &[&str],
+    hunk_index: usize,
+    expected: usize,
+) -> String {
+    let mut report = format!(
+        "Failed to locate hunk {} (tried exact, whitespace-insensitive and fuzzy context matching).\nExpected lines:\n",
+        hunk_index + 1
+    );
+    for line in old_lines.iter().take(MAX_REPORT_LINES) {
+        report.push_str(&format!("       | {}\n", line));
+    }
+    match closest_candidate(lines, old_lines, expected) {
+        Some((position, percent)) => {
+            report.push_str(&format!(
+                "Closest candidate starts at line {} ({}% similar):\n",
+                position + 1,
+                percent
+            ));
+            let end = (position + old_lines.len()).min(lines.len());
+            for (offset, line) in lines[position..end]
+                .iter()
+                .take(MAX_REPORT_LINES)
+                .enumerate()
+            {
+                report.push_str(&format!("{:>6} | {}\n", position + offset + 1, line));
+            }
+            report.push_str(
+                "Update the hunk's context and '-' lines to match these lines and retry.",
+            );
+        }
+        None => report.push_str(
+            "No similar lines exist in the file. Re-read the file before writing the patch.",
+        ),
+    }
+    report
+}
+
+/// Applies update hunks tolerantly: CRLF files keep their line endings, whitespace
+/// differences are ignored when nothing matches exactly, and up to `MAX_CONTEXT_FUZZ` edge
+/// context lines may be out of date. Context lines keep the file's own text.
+pub(crate) fn apply_hunks_to_content(content: &str, hunks: &[PatchHunk]) -> Result<String, String> {
+    let line_ending = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let had_trailing_newline = content.ends_with('\n');
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut cursor = 0usize;
+    let mut line_offset = 0isize;
+
+    for (hunk_index, hunk) in hunks.iter().enumerate() {
+        let body: Vec<(char, &str)> = hunk
+            .lines
+            .iter()
+            .map(|line| split_hunk_line(line))
+            .collect();
+        let old_lines: Vec<&str> = body
+            .iter()
+            .filter(|(prefix, _)| *prefix != '+')
+            .map(|(_, text)| *text)
+            .collect();
+        let expected = expected_hunk_position(hunk, &lines, cursor, line_offset, old_lines.len());
+
+        let found = if old_lines.is_empty() {
+            Some(HunkMatch {
+                position: expected,
+                skip_leading: 0,
+                skip_trailing: 0,
+            })
+        } else {
+            locate_hunk(&lines, &body, cursor, expected)
+        };
+        let Some(found) = found else {
+            return Err(describe_hunk_failure(
+                &lines, &old_lines, hunk_index, expected,
+            ));
+        };
+
+        let mut replacement = Vec::new();
+        let mut file_index = found.position;
+        for (prefix, text) in &body[found.skip_leading..body.len() - found.skip_trailing] {
+            match prefix {
+                ' ' => {
+                    replacement.push(lines[file_index].clone());
+                    file_index += 1;
+                }
+                '-' => file_index += 1,
+                _ => replacement.push(text.to_string()),
+            }
+        }
+        line_offset += replacement.len() as isize - (file_index - found.position) as isize;
+        cursor = found.position + replacement.len();
+        lines.splice(found.position..file_index, replacement);
+    }
+
+    let mut result = lines.join(line_ending);
+    if had_trailing_newline {
+        result.push_str(line_ending);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update_hunks(patch: &str) -> Vec<PatchHunk> {
+        match parse_patch(patch).unwrap().remove(0) {
+            PatchOperation::Update { hunks, .. } => hunks,
+            _ => panic!("expected an update"),
+        }
+    }
+
+    #[test]
+    fn applies_envelope_with_crlf_whitespace_drift_and_stale_context() {
+        let content = "fn main() {\r\n    let a = 1;\r\n    let b = 2;\r\n    println!(\"{}\", a + b);\r\n}\r\n";
+        let hunks = update_hunks(
+            "*** Begin Patch\n*** Update File: src/main.rs\n@@ fn main() {\n  let a = 1;\n-  let b = 2;\n+  let b = 3;\n     println!(\"{}\", a - b);\n*** End Patch",
+        );
+        let updated = apply_hunks_to_content(content, &hunks).unwrap();
+        assert_eq!(
+            updated,
+            "fn main() {\r\n    let a = 1;\r\n  let b = 3;\r\n    println!(\"{}\", a + b);\r\n}\r\n"
+        );
+    }
+
+    #[test]
+    fn parses_git_diff_and_uses_line_hints() {
+        let operations = parse_patch(
+            "diff --git a/notes.txt b/docs/notes.txt\nsimilarity index 90%\nrename from notes.txt\nrename to docs/notes.txt\n--- a/notes.txt\n+++ b/docs/notes.txt\n@@ -3,1 +3,1 @@\n-item\n+done\ndiff --git a/old.txt b/old.txt\ndeleted file mode 100644\n--- a/old.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-bye\n",
+        )
+        .unwrap();
+        assert_eq!(operations.len(), 2);
+        let PatchOperation::Update {
+            path,
+            move_to,
+            hunks,
+        } = &operations[0]
+        else {
+            panic!("expected an update");
+        };
+        assert_eq!(
+            (path.as_str(), move_to.as_deref()),
+            ("notes.txt", Some("docs/notes.txt"))
+        );
+        // Line 3 is the second "item"; the hint picks it over the first one.
+        let updated = apply_hunks_to_content("item\nother\nitem\n", hunks).unwrap();
+        assert_eq!(updated, "item\nother\ndone\n");
+        assert!(matches!(&operations[1], PatchOperation::Delete { path } if path == "old.txt"));
+    }
+
+    #[test]
+    fn failure_report_points_at_closest_candidate() {
+        let hunks = update_hunks(
+            "*** Begin Patch\n*** Update File: a.txt\n@@\n alpha\n-beta gamma\n+beta\n*** End Patch",
+        );
+        let error = apply_hunks_to_content("zero\nalpha\nbeta delta\n", &hunks).unwrap_err();
+        assert!(
+            error.contains("Closest candidate starts at line 2"),
+            "{}",
+            error
+        );
+        assert!(error.contains("     3 | beta delta"), "{}", error);
+    }
+
+    #[test]
+    fn unified_hunks_without_ranges_run_to_the_next_hunk_or_file() {
+        let operations = parse_unified_diff(
+            "--- a/a.txt\n+++ b/a.txt\n@@\n alpha\n-beta\n+gamma\n\n@@ fn tail @@\n-omega\n+end\n--- a/b.txt\n+++ b/b.txt\n@@\n-one\n+two\n",
+        )
+        .unwrap();
+        assert_eq!(operations.len(), 2);
+        let PatchOperation::Update { hunks, .. } = &operations[0] else {
+            panic!("expected an update");
+        };
+        assert_eq!(hunks.len(), 2);
+        let updated = apply_hunks_to_content("alpha\nbeta\nomega\n", hunks).unwrap();
+        assert_eq!(updated, "alpha\ngamma\nend\n");
+        assert!(matches!(&operations[1], PatchOperation::Update { path, .. } if path == "b.txt"));
+
+        let Err(error) = parse_unified_diff("--- a/a.txt\n+++ b/a.txt\n@@\n") else {
+            panic!("expected an empty hunk to be rejected");
+        };
+        assert!(error.contains("has no lines"), "{}", error);
+    }
+}
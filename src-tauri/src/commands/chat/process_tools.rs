@@ -1,34 +1,174 @@
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::{Read, Write};
 use std::path::Path;
 use std::process::{Child, Command, Stdio};
 use std::sync::{Arc, Mutex as StdMutex, OnceLock};
+use std::time::{Duration, Instant};
 
-use chrono::Utc;
+use chrono::{SecondsFormat, Utc};
+use portable_pty::{native_pty_system, CommandBuilder, MasterPty, PtySize};
+use regex::Regex;
+use serde::Serialize;
 use serde_json::{json, Value};
 use uuid::Uuid;
 
+use crate::services::sandbox::{shell_command, SandboxPolicy};
+
 use super::{
-    read_bool_argument, read_optional_string_argument, read_string_argument, read_u64_argument,
-    resolve_workspace_target, workspace_relative_display_path,
+    emit_app_event, read_bool_argument, read_optional_string_argument, read_string_argument,
+    read_u64_argument, resolve_workspace_target, workspace_relative_display_path,
 };
 
+const MAX_PROCESS_OUTPUT_CHARS: usize = 200_000;
+const READ_CHUNK_BYTES: usize = 8192;
+const DEFAULT_READY_TIMEOUT_MS: u64 = 30_000;
+const MAX_READY_TIMEOUT_MS: u64 = 300_000;
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const READY_OUTPUT_TAIL_CHARS: usize = 4_000;
+/// A child that stops reading its input fills the pipe and blocks the writer.
+const STDIN_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize)]
+struct OutputChunk {
+    seq: u64,
+    at: String,
+    /// stdout, stderr, or pty when both share the terminal.
+    stream: &'static str,
+    text: String,
+}
+
 #[derive(Debug, Default)]
-struct ProcessOutputBuffer {
-    stdout: String,
-    stderr: String,
+struct ProcessOutput {
+    chunks: VecDeque<OutputChunk>,
+    chars: usize,
+    next_seq: u64,
+    detected_ports: BTreeSet<u16>,
+}
+
+impl ProcessOutput {
+    fn push(&mut self, stream: &'static str, text: String) -> OutputChunk {
+        self.detected_ports.extend(detect_ports(&text));
+        let chunk = OutputChunk {
+            seq: self.next_seq,
+            at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            stream,
+            text,
+        };
+        self.next_seq += 1;
+        self.chars += chunk.text.chars().count();
+        self.chunks.push_back(chunk.clone());
+        while self.chars > MAX_PROCESS_OUTPUT_CHARS && self.chunks.len() > 1 {
+            if let Some(dropped) = self.chunks.pop_front() {
+                self.chars -= dropped.text.chars().count();
+            }
+        }
+        chunk
+    }
+
+    /// Oldest seq still buffered; anything before it has been dropped.
+    fn first_seq(&self) -> u64 {
+        self.chunks.front().map_or(self.next_seq, |chunk| chunk.seq)
+    }
+
+    fn stream_text(&self, stream: &str) -> String {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.stream == stream)
+            .map(|chunk| chunk.text.as_str())
+            .collect()
+    }
+
+    fn text_since(&self, since_seq: u64) -> String {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.seq >= since_seq)
+            .map(|chunk| chunk.text.as_str())
+            .collect()
+    }
+}
+
+enum ProcessHandle {
+    Piped(Child),
+    Pty {
+        child: Box<dyn portable_pty::Child + Send + Sync>,
+        // Dropping the master hangs up the terminal, so it lives as long as the process.
+        _master: Box<dyn MasterPty + Send>,
+    },
+}
+
+#[derive(Clone, Copy)]
+struct ProcessExit {
+    code: Option<i32>,
+    success: bool,
+}
+
+impl ProcessHandle {
+    fn try_wait(&mut self) -> Result<Option<ProcessExit>, String> {
+        match self {
+            Self::Piped(child) => child
+                .try_wait()
+                .map(|status| {
+                    status.map(|status| ProcessExit {
+                        code: status.code(),
+                        success: status.success(),
+                    })
+                })
+                .map_err(|e| e.to_string()),
+            Self::Pty { child, .. } => child
+                .try_wait()
+                .map(|status| {
+                    status.map(|status| ProcessExit {
+                        code: Some(status.exit_code() as i32),
+                        success: status.success(),
+                    })
+                })
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Kills the process and everything it forked, then reaps it.
+    fn kill(&mut self, pid: u32) -> Result<ProcessExit, String> {
+        if let Some(exit) = self.try_wait()? {
+            return Ok(exit);
+        }
+        kill_process_group(pid);
+        match self {
+            Self::Piped(child) => {
+                let _ = child.kill();
+                let status = child.wait().map_err(|e| e.to_string())?;
+                Ok(ProcessExit {
+                    code: status.code(),
+                    success: status.success(),
+                })
+            }
+            Self::Pty { child, .. } => {
+                let _ = child.kill();
+                let status = child.wait().map_err(|e| e.to_string())?;
+                Ok(ProcessExit {
+                    code: Some(status.exit_code() as i32),
+                    success: status.success(),
+                })
+            }
+        }
+    }
 }
 
-#[derive(Debug)]
 struct ManagedProcess {
     id: String,
+    conversation_id: String,
+    /// Started under a sandbox policy; a sandboxed run may only drive such processes.
+    sandboxed: bool,
     command: String,
     started_at: String,
     pid: u32,
-    child: Child,
-    output: Arc<StdMutex<ProcessOutputBuffer>>,
+    pty: bool,
+    handle: ProcessHandle,
+    stdin: Option<ProcessStdin>,
+    output: Arc<StdMutex<ProcessOutput>>,
 }
 
+type ProcessStdin = Arc<StdMutex<Box<dyn Write + Send>>>;
+
 type ProcessStore = tokio::sync::Mutex<HashMap<String, ManagedProcess>>;
 
 static PROCESS_STORE: OnceLock<ProcessStore> = OnceLock::new();
@@ -37,54 +177,316 @@ fn process_store() -> &'static ProcessStore {
     PROCESS_STORE.get_or_init(|| tokio::sync::Mutex::new(HashMap::new()))
 }
 
-fn trim_process_output(buffer: &mut String, max_chars: usize) {
-    if buffer.chars().count() <= max_chars {
+/// Processes are only visible to the conversation that started them; other ids read as
+/// unknown so one conversation cannot probe another's processes.
+fn owned_process<'a>(
+    store: &'a mut HashMap<String, ManagedProcess>,
+    process_id: &str,
+    conversation_id: &str,
+) -> Result<&'a mut ManagedProcess, String> {
+    store
+        .get_mut(process_id)
+        .filter(|process| process.conversation_id == conversation_id)
+        .ok_or_else(|| format!("Unknown process id: {}", process_id))
+}
+
+// Dev servers and installers fork helpers that outlive the shell. Each process is started
+// as its own process group, so the whole group is signalled.
+#[cfg(unix)]
+fn kill_process_group(pid: u32) {
+    if pid == 0 {
         return;
     }
-    let tail = buffer
-        .chars()
-        .rev()
-        .take(max_chars)
-        .collect::<Vec<_>>()
-        .into_iter()
-        .rev()
-        .collect::<String>();
-    *buffer = tail;
+    let _ = Command::new("kill")
+        .args(["-s", "KILL", "--", &format!("-{}", pid)])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(_pid: u32) {}
+
+fn ansi_escape_regex() -> &'static Regex {
+    static ANSI: OnceLock<Regex> = OnceLock::new();
+    ANSI.get_or_init(|| {
+        Regex::new(
+            r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[()][0-9A-Za-z]|\x1b[@-_]",
+        )
+        .expect("valid ANSI escape regex")
+    })
+}
+
+fn port_regex() -> &'static Regex {
+    static PORT: OnceLock<Regex> = OnceLock::new();
+    PORT.get_or_init(|| {
+        Regex::new(
+            r"(?i)(?:localhost|127\.0\.0\.1|0\.0\.0\.0|\[::1?\]):(\d{2,5})\b|\bport\s+(\d{2,5})\b",
+        )
+        .expect("valid port regex")
+    })
+}
+
+/// Ports a process announces, such as `http://localhost:5173` or `listening on port 8080`.
+fn detect_ports(text: &str) -> Vec<u16> {
+    port_regex()
+        .captures_iter(text)
+        .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+        .filter_map(|port| port.as_str().parse::<u16>().ok())
+        .filter(|port| *port > 0)
+        .collect()
+}
+
+/// Terminal colours and cursor movement mean nothing to the model or the output panel.
+fn clean_terminal_output(text: &str) -> String {
+    ansi_escape_regex()
+        .replace_all(text, "")
+        .replace("\r\n", "\n")
+}
+
+/// Decodes the complete UTF-8 prefix of `pending`, keeping a character split across reads
+/// for the next one.
+fn take_utf8_prefix(pending: &mut Vec<u8>) -> String {
+    let valid = match std::str::from_utf8(pending) {
+        Err(error) if error.error_len().is_none() => error.valid_up_to(),
+        _ => pending.len(),
+    };
+    let rest = pending.split_off(valid);
+    let text = String::from_utf8_lossy(pending).to_string();
+    *pending = rest;
+    text
+}
+
+fn tail_chars(content: &str, max_chars: usize) -> String {
+    if max_chars == 0 {
+        return String::new();
+    }
+    match content.char_indices().rev().nth(max_chars - 1) {
+        Some((index, _)) => content[index..].to_string(),
+        None => content.to_string(),
+    }
 }
 
 fn spawn_process_reader<R: Read + Send + 'static>(
-    reader: R,
-    output: Arc<StdMutex<ProcessOutputBuffer>>,
-    is_stdout: bool,
+    mut reader: R,
+    process_id: String,
+    conversation_id: String,
+    output: Arc<StdMutex<ProcessOutput>>,
+    stream: &'static str,
 ) {
     std::thread::spawn(move || {
-        let mut reader = BufReader::new(reader);
+        let mut buffer = [0u8; READ_CHUNK_BYTES];
+        let mut pending = Vec::new();
         loop {
-            let mut line = String::new();
-            match reader.read_line(&mut line) {
-                Ok(0) => break,
-                Ok(_) => {
-                    if let Ok(mut guard) = output.lock() {
-                        if is_stdout {
-                            guard.stdout.push_str(&line);
-                            trim_process_output(&mut guard.stdout, 200_000);
-                        } else {
-                            guard.stderr.push_str(&line);
-                            trim_process_output(&mut guard.stderr, 200_000);
-                        }
-                    } else {
-                        break;
-                    }
-                }
+            let read = match reader.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            pending.extend_from_slice(&buffer[..read]);
+            let text = clean_terminal_output(&take_utf8_prefix(&mut pending));
+            if text.is_empty() {
+                continue;
+            }
+            let chunk = match output.lock() {
+                Ok(mut output) => output.push(stream, text),
                 Err(_) => break,
+            };
+            emit_app_event(
+                "workspace-process-output",
+                json!({
+                    "conversationId": conversation_id,
+                    "processId": process_id,
+                    "seq": chunk.seq,
+                    "at": chunk.at,
+                    "stream": chunk.stream,
+                    "text": chunk.text
+                }),
+            );
+        }
+        if !pending.is_empty() {
+            if let Ok(mut output) = output.lock() {
+                output.push(stream, String::from_utf8_lossy(&pending).to_string());
             }
         }
+        emit_app_event(
+            "workspace-process-output",
+            json!({
+                "conversationId": conversation_id,
+                "processId": process_id,
+                "stream": stream,
+                "closed": true
+            }),
+        );
     });
 }
 
+type SpawnedPty = (
+    ProcessHandle,
+    Box<dyn Read + Send>,
+    Box<dyn Write + Send>,
+    u32,
+);
+
+fn spawn_in_pty(command: &Command) -> Result<SpawnedPty, String> {
+    let pair = native_pty_system()
+        .openpty(PtySize {
+            rows: 40,
+            cols: 120,
+            ..Default::default()
+        })
+        .map_err(|e| format!("Failed to open a pseudo-terminal: {}", e))?;
+
+    let mut builder = CommandBuilder::new(command.get_program());
+    builder.args(command.get_args());
+    if let Some(cwd) = command.get_current_dir() {
+        builder.cwd(cwd);
+    }
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => builder.env(key, value),
+            None => builder.env_remove(key),
+        }
+    }
+    if builder.get_env("TERM").is_none() {
+        builder.env("TERM", "xterm-256color");
+    }
+
+    let child = pair
+        .slave
+        .spawn_command(builder)
+        .map_err(|e| e.to_string())?;
+    drop(pair.slave);
+    let pid = child.process_id().unwrap_or(0);
+    let reader = pair.master.try_clone_reader().map_err(|e| e.to_string())?;
+    let writer = pair.master.take_writer().map_err(|e| e.to_string())?;
+    Ok((
+        ProcessHandle::Pty {
+            child,
+            _master: pair.master,
+        },
+        reader,
+        writer,
+        pid,
+    ))
+}
+
+/// Waits until a ready pattern shows up in the output or a port accepts connections,
+/// whichever comes first.
+struct ReadinessCheck {
+    pattern: Option<Regex>,
+    port: Option<u16>,
+    timeout: Duration,
+}
+
+fn read_readiness_check(arguments: &Value) -> Result<Option<ReadinessCheck>, String> {
+    let pattern = read_optional_string_argument(arguments, "ready_pattern")
+        .map(|pattern| Regex::new(&pattern).map_err(|e| format!("Invalid ready_pattern: {}", e)))
+        .transpose()?;
+    let port = arguments
+        .get("ready_port")
+        .and_then(Value::as_u64)
+        .map(|port| u16::try_from(port).map_err(|_| format!("Invalid ready_port: {}", port)))
+        .transpose()?;
+    if pattern.is_none() && port.is_none() {
+        return Ok(None);
+    }
+    let timeout_ms = read_u64_argument(arguments, "ready_timeout_ms", DEFAULT_READY_TIMEOUT_MS)
+        .min(MAX_READY_TIMEOUT_MS);
+    Ok(Some(ReadinessCheck {
+        pattern,
+        port,
+        timeout: Duration::from_millis(timeout_ms),
+    }))
+}
+
+async fn port_accepts_connections(port: u16) -> bool {
+    matches!(
+        tokio::time::timeout(
+            Duration::from_millis(300),
+            tokio::net::TcpStream::connect(("localhost", port)),
+        )
+        .await,
+        Ok(Ok(_))
+    )
+}
+
+async fn process_has_exited(process_id: &str) -> bool {
+    let mut store = process_store().lock().await;
+    match store.get_mut(process_id) {
+        Some(process) => !matches!(process.handle.try_wait(), Ok(None)),
+        None => true,
+    }
+}
+
+async fn wait_for_ready(
+    process_id: &str,
+    output: &Arc<StdMutex<ProcessOutput>>,
+    since_seq: u64,
+    check: &ReadinessCheck,
+) -> Value {
+    let started = Instant::now();
+    let finish = |ready: bool, reason: &str| {
+        json!({
+            "ready": ready,
+            "reason": reason,
+            "waited_ms": started.elapsed().as_millis() as u64
+        })
+    };
+    loop {
+        if let Some(pattern) = &check.pattern {
+            let text = output
+                .lock()
+                .map(|output| output.text_since(since_seq))
+                .unwrap_or_default();
+            if let Some(found) = pattern.find(&text) {
+                let line_start = text[..found.start()]
+                    .rfind('\n')
+                    .map_or(0, |index| index + 1);
+                let line_end = text[found.end()..]
+                    .find('\n')
+                    .map_or(text.len(), |index| found.end() + index);
+                let mut result = finish(true, "pattern");
+                result["matched_line"] = json!(text[line_start..line_end].trim());
+                return result;
+            }
+        }
+        if let Some(port) = check.port {
+            if port_accepts_connections(port).await {
+                let mut result = finish(true, "port");
+                result["port"] = json!(port);
+                return result;
+            }
+        }
+        if process_has_exited(process_id).await {
+            return finish(false, "exited");
+        }
+        if started.elapsed() >= check.timeout {
+            return finish(false, "timeout");
+        }
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+}
+
+fn status_json(exit: Option<&ProcessExit>) -> Value {
+    match exit {
+        Some(exit) => json!({
+            "running": false,
+            "exit_code": exit.code,
+            "success": exit.success
+        }),
+        None => json!({
+            "running": true,
+            "exit_code": Value::Null,
+            "success": Value::Null
+        }),
+    }
+}
+
 pub(super) async fn execute_workspace_process_start(
     arguments: &Value,
     workspace_root: &Path,
+    conversation_id: &str,
+    sandbox: Option<&SandboxPolicy>,
 ) -> Result<Value, String> {
     let command = read_string_argument(arguments, "command")?;
     let cwd_raw =
@@ -93,100 +495,180 @@ pub(super) async fn execute_workspace_process_start(
     if !cwd.is_dir() {
         return Err(format!("Not a directory: {}", cwd.display()));
     }
+    let use_pty = read_bool_argument(arguments, "pty", false);
+    let readiness = read_readiness_check(arguments)?;
+
+    let mut cmd = shell_command(&command, &cwd, workspace_root, sandbox)?;
+    let process_id = Uuid::new_v4().to_string();
+    let output = Arc::new(StdMutex::new(ProcessOutput::default()));
 
-    let mut cmd = if cfg!(target_os = "windows") {
-        let wrapped_command = format!(
-            "$OutputEncoding = [Console]::OutputEncoding = [System.Text.UTF8Encoding]::new($false); [Console]::InputEncoding = [System.Text.UTF8Encoding]::new($false); chcp 65001 > $null; {}",
-            command
+    let (handle, stdin, pid) = if use_pty {
+        let (handle, reader, writer, pid) = spawn_in_pty(&cmd)?;
+        spawn_process_reader(
+            reader,
+            process_id.clone(),
+            conversation_id.to_string(),
+            Arc::clone(&output),
+            "pty",
         );
-        let mut process = Command::new("powershell");
-        process.args(["-NoProfile", "-NonInteractive", "-Command", &wrapped_command]);
-        process
+        (handle, writer, pid)
     } else {
-        let mut process = Command::new("sh");
-        process.args(["-lc", &command]);
-        process
-    };
-    cmd.current_dir(&cwd);
-    cmd.stdin(Stdio::null());
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::piped());
-
-    let mut child = cmd.spawn().map_err(|e| e.to_string())?;
-    let pid = child.id();
-    let output = Arc::new(StdMutex::new(ProcessOutputBuffer::default()));
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        cmd.stdin(Stdio::piped());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
 
-    if let Some(stdout) = child.stdout.take() {
-        spawn_process_reader(stdout, Arc::clone(&output), true);
-    }
-    if let Some(stderr) = child.stderr.take() {
-        spawn_process_reader(stderr, Arc::clone(&output), false);
-    }
+        let mut child = cmd.spawn().map_err(|e| e.to_string())?;
+        let pid = child.id();
+        if let Some(stdout) = child.stdout.take() {
+            spawn_process_reader(
+                stdout,
+                process_id.clone(),
+                conversation_id.to_string(),
+                Arc::clone(&output),
+                "stdout",
+            );
+        }
+        if let Some(stderr) = child.stderr.take() {
+            spawn_process_reader(
+                stderr,
+                process_id.clone(),
+                conversation_id.to_string(),
+                Arc::clone(&output),
+                "stderr",
+            );
+        }
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| "Failed to open process stdin".to_string())?;
+        (
+            ProcessHandle::Piped(child),
+            Box::new(stdin) as Box<dyn Write + Send>,
+            pid,
+        )
+    };
 
-    let process_id = Uuid::new_v4().to_string();
     let managed = ManagedProcess {
         id: process_id.clone(),
+        conversation_id: conversation_id.to_string(),
+        sandboxed: sandbox.is_some(),
         command: command.clone(),
         started_at: Utc::now().to_rfc3339(),
         pid,
-        child,
-        output,
+        pty: use_pty,
+        handle,
+        stdin: Some(Arc::new(StdMutex::new(stdin))),
+        output: Arc::clone(&output),
     };
 
     let mut store = process_store().lock().await;
     store.insert(process_id.clone(), managed);
+    drop(store);
 
-    Ok(json!({
+    if let Some(limit_seconds) = sandbox
+        .map(|policy| policy.max_process_seconds)
+        .filter(|seconds| *seconds > 0)
+    {
+        spawn_process_deadline(process_id.clone(), limit_seconds);
+    }
+
+    let mut result = json!({
         "process_id": process_id,
         "pid": pid,
         "command": command,
+        "pty": use_pty,
         "cwd": workspace_relative_display_path(workspace_root, &cwd)
-    }))
+    });
+    if let Some(check) = readiness {
+        result["ready"] = wait_for_ready(&process_id, &output, 0, &check).await;
+        if let Ok(output) = output.lock() {
+            result["output_tail"] =
+                json!(tail_chars(&output.text_since(0), READY_OUTPUT_TAIL_CHARS));
+            result["detected_ports"] = json!(output.detected_ports);
+            result["next_seq"] = json!(output.next_seq);
+        }
+    }
+    if let Some(policy) = sandbox {
+        result["sandbox"] = policy.describe(workspace_root);
+    }
+    Ok(result)
+}
+
+/// Kills a sandboxed background process that is still running after `limit_seconds`; its
+/// output stays readable until it is terminated or cleaned up.
+fn spawn_process_deadline(process_id: String, limit_seconds: u64) {
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(limit_seconds)).await;
+        let mut store = process_store().lock().await;
+        let Some(process) = store.get_mut(&process_id) else {
+            return;
+        };
+        if matches!(process.handle.try_wait(), Ok(None)) {
+            if let Err(error) = process.handle.kill(process.pid) {
+                eprintln!("[sandbox] failed to stop process {}: {}", process_id, error);
+                return;
+            }
+            eprintln!(
+                "[sandbox] process {} stopped after {}s",
+                process_id, limit_seconds
+            );
+        }
+    });
 }
 
-pub(super) async fn execute_workspace_process_list(arguments: &Value) -> Result<Value, String> {
+pub(super) async fn execute_workspace_process_list(
+    arguments: &Value,
+    conversation_id: &str,
+) -> Result<Value, String> {
     let cleanup_exited = read_bool_argument(arguments, "cleanup_exited", false);
     let mut store = process_store().lock().await;
     let mut exited_ids = Vec::<String>::new();
     let mut items = Vec::<Value>::new();
 
-    for (process_id, process) in store.iter_mut() {
-        let status = match process.child.try_wait() {
-            Ok(Some(status)) => {
-                if cleanup_exited {
+    for (process_id, process) in store
+        .iter_mut()
+        .filter(|(_, process)| process.conversation_id == conversation_id)
+    {
+        let status = match process.handle.try_wait() {
+            Ok(exit) => {
+                if exit.is_some() && cleanup_exited {
                     exited_ids.push(process_id.clone());
                 }
-                json!({
-                    "running": false,
-                    "exit_code": status.code(),
-                    "success": status.success()
-                })
+                status_json(exit.as_ref())
             }
-            Ok(None) => json!({
-                "running": true,
-                "exit_code": Value::Null,
-                "success": Value::Null
-            }),
             Err(error) => json!({
                 "running": false,
-                "error": error.to_string()
+                "error": error
             }),
         };
 
-        let (stdout_chars, stderr_chars) = match process.output.lock() {
-            Ok(buffer) => (buffer.stdout.chars().count(), buffer.stderr.chars().count()),
-            Err(_) => (0usize, 0usize),
+        let (stdout_chars, stderr_chars, detected_ports) = match process.output.lock() {
+            Ok(output) => {
+                let stderr_chars = output.stream_text("stderr").chars().count();
+                (
+                    output.chars - stderr_chars,
+                    stderr_chars,
+                    output.detected_ports.iter().copied().collect::<Vec<_>>(),
+                )
+            }
+            Err(_) => (0usize, 0usize, Vec::new()),
         };
 
         items.push(json!({
             "id": process.id,
             "process_id": process_id,
+            "conversation_id": process.conversation_id,
             "pid": process.pid,
             "command": process.command,
             "started_at": process.started_at,
+            "pty": process.pty,
+            "stdin_open": process.stdin.is_some(),
             "status": status,
             "stdout_chars": stdout_chars,
-            "stderr_chars": stderr_chars
+            "stderr_chars": stderr_chars,
+            "detected_ports": detected_ports
         }));
     }
 
@@ -201,82 +683,292 @@ pub(super) async fn execute_workspace_process_list(arguments: &Value) -> Result<
     }))
 }
 
-pub(super) async fn execute_workspace_process_read(arguments: &Value) -> Result<Value, String> {
+pub(super) async fn execute_workspace_process_read(
+    arguments: &Value,
+    conversation_id: &str,
+) -> Result<Value, String> {
     let process_id = read_string_argument(arguments, "process_id")?;
     let max_chars = read_u64_argument(arguments, "max_chars", 10_000).clamp(200, 200_000) as usize;
-    let mut store = process_store().lock().await;
-    let process = store
-        .get_mut(&process_id)
-        .ok_or_else(|| format!("Unknown process id: {}", process_id))?;
-    let status = process.child.try_wait().map_err(|e| e.to_string())?;
+    let since_seq = arguments.get("since_seq").and_then(Value::as_u64);
+    let readiness = read_readiness_check(arguments)?;
+
+    let output = {
+        let mut store = process_store().lock().await;
+        Arc::clone(&owned_process(&mut store, &process_id, conversation_id)?.output)
+    };
+    let ready = match &readiness {
+        Some(check) => {
+            Some(wait_for_ready(&process_id, &output, since_seq.unwrap_or(0), check).await)
+        }
+        None => None,
+    };
 
-    let (stdout, stderr) = process
-        .output
+    let (status, pty) = {
+        let mut store = process_store().lock().await;
+        let process = store
+            .get_mut(&process_id)
+            .ok_or_else(|| format!("Process {} was terminated while waiting", process_id))?;
+        (process.handle.try_wait()?, process.pty)
+    };
+    let output = output
         .lock()
-        .map(|buffer| (buffer.stdout.clone(), buffer.stderr.clone()))
         .map_err(|_| "Failed to lock process output".to_string())?;
 
-    let trim_tail = |content: String| -> String {
-        if content.chars().count() <= max_chars {
-            return content;
+    let mut result = json!({
+        "process_id": process_id,
+        "pty": pty,
+        "next_seq": output.next_seq,
+        "detected_ports": output.detected_ports,
+        "status": status_json(status.as_ref())
+    });
+    match since_seq {
+        Some(since_seq) => {
+            let mut budget = max_chars;
+            let mut chunks = Vec::new();
+            for chunk in output.chunks.iter().rev() {
+                if chunk.seq < since_seq || budget == 0 {
+                    break;
+                }
+                let mut chunk = chunk.clone();
+                let chars = chunk.text.chars().count();
+                if chars > budget {
+                    chunk.text = tail_chars(&chunk.text, budget);
+                }
+                budget = budget.saturating_sub(chars);
+                chunks.push(chunk);
+            }
+            chunks.reverse();
+            // Older output was dropped from the buffer, or did not fit in max_chars.
+            let first_returned = chunks.first().map_or(output.next_seq, |chunk| chunk.seq);
+            let dropped = since_seq < output.first_seq();
+            let cut = first_returned > since_seq.max(output.first_seq());
+            result["chunks"] = json!(chunks);
+            result["truncated"] = json!(dropped || cut);
+        }
+        None => {
+            result["stdout"] = json!(tail_chars(
+                &output.stream_text(if pty { "pty" } else { "stdout" }),
+                max_chars
+            ));
+            result["stderr"] = json!(tail_chars(&output.stream_text("stderr"), max_chars));
+        }
+    }
+    if let Some(ready) = ready {
+        result["ready"] = ready;
+    }
+    Ok(result)
+}
+
+/// `sandbox` is the caller's policy: a sandboxed run cannot feed input to a process that
+/// runs outside the sandbox.
+pub(super) async fn execute_workspace_process_write(
+    arguments: &Value,
+    conversation_id: &str,
+    sandbox: Option<&SandboxPolicy>,
+) -> Result<Value, String> {
+    let process_id = read_string_argument(arguments, "process_id")?;
+    let input = arguments
+        .get("input")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let append_newline = read_bool_argument(arguments, "append_newline", true);
+    let close_stdin = read_bool_argument(arguments, "close_stdin", false);
+
+    // The write itself happens without the store lock, so a child that is not reading
+    // cannot stall every other process tool.
+    let (stdin, pty, output) = {
+        let mut store = process_store().lock().await;
+        let process = owned_process(&mut store, &process_id, conversation_id)?;
+        if sandbox.is_some() && !process.sandboxed {
+            return Err(format!(
+                "Process {} runs outside the sandbox; a sandboxed run cannot write to it",
+                process_id
+            ));
         }
-        content
-            .chars()
-            .rev()
-            .take(max_chars)
-            .collect::<Vec<_>>()
-            .into_iter()
-            .rev()
-            .collect::<String>()
+        if let Some(exit) = process.handle.try_wait()? {
+            return Err(format!(
+                "Process {} has already exited with code {:?}",
+                process_id, exit.code
+            ));
+        }
+        let stdin = if close_stdin && !process.pty {
+            process.stdin.take()
+        } else {
+            process.stdin.clone()
+        }
+        .ok_or_else(|| format!("stdin of process {} is closed", process_id))?;
+        (stdin, process.pty, Arc::clone(&process.output))
     };
 
+    let mut data = input.to_string();
+    if append_newline {
+        // A terminal sends carriage return for Enter; raw-mode prompts only accept that.
+        data.push(if pty { '\r' } else { '\n' });
+    }
+    if close_stdin && pty {
+        data.push('\u{4}');
+    }
+    let bytes = data.as_bytes().to_vec();
+    let write = tokio::task::spawn_blocking(move || {
+        let mut stdin = stdin
+            .lock()
+            .map_err(|_| "stdin is unavailable".to_string())?;
+        stdin
+            .write_all(&bytes)
+            .and_then(|_| stdin.flush())
+            .map_err(|e| e.to_string())
+    });
+    match tokio::time::timeout(STDIN_WRITE_TIMEOUT, write).await {
+        Ok(result) => result
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("Failed to write to process {}: {}", process_id, e))?,
+        Err(_) => {
+            return Err(format!(
+                "Timed out writing to process {}; it is not reading its input",
+                process_id
+            ))
+        }
+    }
+    let next_seq = output
+        .lock()
+        .map(|output| output.next_seq)
+        .unwrap_or_default();
+
     Ok(json!({
         "process_id": process_id,
-        "stdout": trim_tail(stdout),
-        "stderr": trim_tail(stderr),
-        "status": match status {
-            Some(exit) => json!({
-                "running": false,
-                "exit_code": exit.code(),
-                "success": exit.success()
-            }),
-            None => json!({
-                "running": true
-            })
-        }
+        "bytes_written": data.len(),
+        "stdin_closed": close_stdin,
+        "next_seq": next_seq
     }))
 }
 
 pub(super) async fn execute_workspace_process_terminate(
     arguments: &Value,
+    conversation_id: &str,
 ) -> Result<Value, String> {
     let process_id = read_string_argument(arguments, "process_id")?;
     let mut store = process_store().lock().await;
+    owned_process(&mut store, &process_id, conversation_id)?;
     let mut process = store
         .remove(&process_id)
         .ok_or_else(|| format!("Unknown process id: {}", process_id))?;
 
-    let mut result = json!({
-        "process_id": process_id,
-        "terminated": false
-    });
+    let already_exited = process.handle.try_wait()?;
+    let exit = match already_exited {
+        Some(exit) => exit,
+        None => process.handle.kill(process.pid)?,
+    };
+    let mut result = status_json(Some(&exit));
+    result["process_id"] = json!(process_id);
+    result["terminated"] = json!(true);
+    result["already_exited"] = json!(already_exited.is_some());
+    Ok(result)
+}
 
-    match process.child.try_wait().map_err(|e| e.to_string())? {
-        Some(status) => {
-            result["terminated"] = json!(true);
-            result["already_exited"] = json!(true);
-            result["exit_code"] = json!(status.code());
-            result["success"] = json!(status.success());
+/// Kills every process a conversation started; called when the conversation is deleted.
+pub(super) async fn terminate_conversation_processes(conversation_id: &str) -> usize {
+    let mut store = process_store().lock().await;
+    let ids = store
+        .iter()
+        .filter(|(_, process)| process.conversation_id == conversation_id)
+        .map(|(id, _)| id.clone())
+        .collect::<Vec<_>>();
+    for id in &ids {
+        if let Some(mut process) = store.remove(id) {
+            if let Err(error) = process.handle.kill(process.pid) {
+                eprintln!("[process] failed to stop process {}: {}", id, error);
+            }
         }
-        None => {
-            process.child.kill().map_err(|e| e.to_string())?;
-            let status = process.child.wait().map_err(|e| e.to_string())?;
-            result["terminated"] = json!(true);
-            result["already_exited"] = json!(false);
-            result["exit_code"] = json!(status.code());
-            result["success"] = json!(status.success());
+    }
+    ids.len()
+}
+
+/// Kills every managed process on app exit. Runs from the synchronous exit hook, so it
+/// gives up instead of blocking when the store is busy.
+pub(super) fn terminate_all_processes() {
+    let Ok(mut store) = process_store().try_lock() else {
+        eprintln!("[process] process store busy on exit; background processes not stopped");
+        return;
+    };
+    for (id, mut process) in store.drain() {
+        if let Err(error) = process.handle.kill(process.pid) {
+            eprintln!("[process] failed to stop process {}: {}", id, error);
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleans_terminal_output_and_detects_ports() {
+        assert_eq!(
+            clean_terminal_output("\x1b[32m  VITE ready\x1b[0m\r\n\x1b]0;title\x07done"),
+            "  VITE ready\ndone"
+        );
+        assert_eq!(
+            detect_ports("Local: http://localhost:5173/\nListening on port 8080"),
+            vec![5173, 8080]
+        );
+
+        let mut pending = "héllo".as_bytes().to_vec();
+        let tail = pending.split_off(2);
+        assert_eq!(take_utf8_prefix(&mut pending), "h");
+        pending.extend_from_slice(&tail);
+        assert_eq!(take_utf8_prefix(&mut pending), "éllo");
+        assert!(pending.is_empty());
+
+        let mut output = ProcessOutput::default();
+        output.push("stdout", "a".repeat(MAX_PROCESS_OUTPUT_CHARS));
+        output.push("stderr", "b".repeat(10));
+        assert_eq!(output.first_seq(), 1);
+        assert_eq!(output.text_since(0), "b".repeat(10));
+        assert_eq!(tail_chars("abcdef", 3), "def");
+    }
 
-    Ok(result)
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn processes_are_scoped_to_their_conversation_and_sandbox() {
+        let workspace = std::env::temp_dir();
+        let started = execute_workspace_process_start(
+            &json!({ "command": "cat" }),
+            &workspace,
+            "owner",
+            None,
+        )
+        .await
+        .unwrap();
+        let process_id = started["process_id"].as_str().unwrap().to_string();
+        let target = json!({ "process_id": process_id, "input": "hi" });
+
+        let listed = execute_workspace_process_list(&json!({}), "other")
+            .await
+            .unwrap();
+        assert!(!listed.to_string().contains(&process_id));
+        assert!(execute_workspace_process_read(&target, "other")
+            .await
+            .is_err());
+        assert!(execute_workspace_process_write(&target, "other", None)
+            .await
+            .is_err());
+        assert!(execute_workspace_process_terminate(&target, "other")
+            .await
+            .is_err());
+
+        let policy = SandboxPolicy::from_config(
+            &crate::models::config::SandboxConfig::default(),
+            crate::services::sandbox::SandboxReason::BackgroundRun,
+        );
+        let error = execute_workspace_process_write(&target, "owner", Some(&policy))
+            .await
+            .unwrap_err();
+        assert!(error.contains("outside the sandbox"));
+        execute_workspace_process_write(&target, "owner", None)
+            .await
+            .unwrap();
+        execute_workspace_process_terminate(&target, "owner")
+            .await
+            .unwrap();
+    }
 }
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use crate::commands::mcp::McpState;
+use crate::commands::skills::SkillManagerState;
+use crate::models::chat::TimelineEventType;
+use crate::services::usage::UsageSource;
+
+use super::commands::{try_register_stream_stop_flag, StreamStopFlagGuard};
+use super::stream::{
+    run_live_agent_loop, BackgroundAgentRunRequest, BackgroundAgentRunResult, RunPolicy,
+};
+use super::{
+    emit_app_event, insert_message, insert_timeline_event, read_string_argument, read_u64_argument,
+    PendingTimelineEvent,
+};
+
+/// Finished runs kept for sessions_run_status; older ones are forgotten first.
+const MAX_FINISHED_SESSION_RUNS: usize = 32;
+const MAX_STATUS_WAIT_MS: u64 = 600_000;
+
+type SessionRunOutcome = Option<Result<Value, String>>;
+
+struct SessionRunEntry {
+    conversation_id: String,
+    started_at: String,
+    outcome: watch::Receiver<SessionRunOutcome>,
+}
+
+fn session_runs() -> &'static Mutex<HashMap<String, SessionRunEntry>> {
+    static RUNS: OnceLock<Mutex<HashMap<String, SessionRunEntry>>> = OnceLock::new();
+    RUNS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn prune_finished_session_runs(runs: &mut HashMap<String, SessionRunEntry>) {
+    let mut finished = runs
+        .iter()
+        .filter(|(_, entry)| entry.outcome.borrow().is_some())
+        .map(|(run_id, entry)| (entry.started_at.clone(), run_id.clone()))
+        .collect::<Vec<_>>();
+    if finished.len() <= MAX_FINISHED_SESSION_RUNS {
+        return;
+    }
+    finished.sort();
+    let excess = finished.len() - MAX_FINISHED_SESSION_RUNS;
+    for (_, run_id) in finished.into_iter().take(excess) {
+        runs.remove(&run_id);
+    }
+}
+
+fn running_status(run_id: &str, conversation_id: &str, started_at: &str) -> Value {
+    json!({
+        "run_id": run_id,
+        "conversation_id": conversation_id,
+        "status": "running",
+        "started_at": started_at
+    })
+}
+
+fn session_run_summary(
+    run_id: &str,
+    conversation_id: &str,
+    result: BackgroundAgentRunResult,
+) -> Value {
+    let status = if result.stopped {
+        "stopped"
+    } else if result.guard_stopped {
+        "stopped_repeating"
+    } else {
+        "completed"
+    };
+    json!({
+        "run_id": run_id,
+        "conversation_id": conversation_id,
+        "status": status,
+        "model": result.model,
+        "assistant_response": result.content,
+        "rounds": result.rounds,
+        "tool_calls": result.tool_calls,
+        "blocked_tools": result.blocked_tools,
+        "usage": result.usage
+    })
+}
+
+/// Boxed because the run's own tools include sessions_send and sessions_spawn.
+fn run_session_loop(
+    pool: SqlitePool,
+    mcp_state: McpState,
+    skill_state: SkillManagerState,
+    request: BackgroundAgentRunRequest,
+    stop_flag: Arc<AtomicBool>,
+) -> Pin<Box<dyn Future<Output = Result<BackgroundAgentRunResult, String>> + Send>> {
+    Box::pin(run_live_agent_loop(
+        pool,
+        mcp_state,
+        skill_state,
+        request,
+        stop_flag,
+    ))
+}
+
+/// Stores a message that starts no run and shows it on the conversation's timeline.
+pub(crate) async fn record_session_user_message(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    content: &str,
+) -> Result<(), String> {
+    let message_id = insert_message(pool, conversation_id, "user", content, None, None).await?;
+    let event = PendingTimelineEvent {
+        turn_id: Uuid::new_v4().to_string(),
+        seq: 1,
+        event_type: TimelineEventType::UserMessage,
+        tool_call_id: None,
+        payload: json!({ "content": content, "messageId": message_id }),
+        created_at: Utc::now().to_rfc3339(),
+    };
+    insert_timeline_event(pool, conversation_id, &event).await?;
+    emit_app_event(
+        "chat-user-message",
+        json!({
+            "conversationId": conversation_id,
+            "turnId": event.turn_id,
+            "seq": event.seq,
+            "eventType": "user_message",
+            "createdAt": event.created_at,
+            "content": content,
+            "messageId": message_id
+        }),
+    );
+    Ok(())
+}
+
+/// The target's turn runs under the caller's policy, so a whitelisted or sandboxed run
+/// cannot escape it by messaging another conversation.
+fn session_run_request(
+    caller_policy: &RunPolicy,
+    target_conversation_id: &str,
+    content: String,
+    model_override: Option<String>,
+    workspace_root: &Path,
+) -> BackgroundAgentRunRequest {
+    BackgroundAgentRunRequest {
+        target_conversation_id: target_conversation_id.to_string(),
+        content,
+        workspace_directory: Some(workspace_root.to_string_lossy().to_string()),
+        model_override,
+        persist_main_context: true,
+        tool_whitelist: caller_policy.tool_whitelist.clone(),
+        sandboxed: caller_policy.sandboxed,
+        tool_profile: None,
+        usage_source: UsageSource::Tool,
+        sub_agent: None,
+        live_timeline: true,
+        use_memory: true,
+    }
+}
+
+/// Runs a full agent turn for `content` in another conversation, streamed live into it.
+/// Unless `wait` is set, returns a run handle for sessions_run_status right away.
+pub(crate) async fn start_session_run(
+    pool: &SqlitePool,
+    mcp_state: &McpState,
+    skill_manager_state: &SkillManagerState,
+    caller_policy: &RunPolicy,
+    caller_conversation_id: &str,
+    target_conversation_id: &str,
+    content: String,
+    model_override: Option<String>,
+    workspace_root: &Path,
+    wait: bool,
+) -> Result<Value, String> {
+    if target_conversation_id == caller_conversation_id {
+        return Err(
+            "A conversation cannot run a turn in itself; answer directly instead".to_string(),
+        );
+    }
+    // Registered before the run is spawned, so a second start fails instead of racing it.
+    let stop_flag = try_register_stream_stop_flag(target_conversation_id)
+        .await
+        .ok_or_else(|| {
+            format!(
+                "Conversation {} is already running a turn; wait for it to finish",
+                target_conversation_id
+            )
+        })?;
+    let registration = StreamStopFlagGuard::new(target_conversation_id, stop_flag.clone());
+
+    let run_id = Uuid::new_v4().to_string();
+    let started_at = Utc::now().to_rfc3339();
+    let (sender, receiver) = watch::channel(None);
+    {
+        let mut runs = session_runs()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        prune_finished_session_runs(&mut runs);
+        runs.insert(
+            run_id.clone(),
+            SessionRunEntry {
+                conversation_id: target_conversation_id.to_string(),
+                started_at: started_at.clone(),
+                outcome: receiver.clone(),
+            },
+        );
+    }
+
+    let request = session_run_request(
+        caller_policy,
+        target_conversation_id,
+        content,
+        model_override,
+        workspace_root,
+    );
+    let pool = pool.clone();
+    let mcp_state = mcp_state.clone();
+    let skill_state = skill_manager_state.clone();
+    let task_run_id = run_id.clone();
+    let target = target_conversation_id.to_string();
+    tokio::spawn(async move {
+        let _registration = registration;
+        let outcome = run_session_loop(pool, mcp_state, skill_state, request, stop_flag)
+            .await
+            .map(|result| session_run_summary(&task_run_id, &target, result));
+        if let Err(error) = &outcome {
+            eprintln!(
+                "[sessions] run {} in conversation {} failed: {}",
+                task_run_id, target, error
+            );
+        }
+        let _ = sender.send(Some(outcome));
+    });
+
+    if !wait {
+        return Ok(running_status(&run_id, target_conversation_id, &started_at));
+    }
+    let mut receiver = receiver;
+    let outcome = receiver
+        .wait_for(Option::is_some)
+        .await
+        .map_err(|_| "Session run ended without a result".to_string())?
+        .clone();
+    outcome.unwrap_or_else(|| Err("Session run ended without a result".to_string()))
+}
+
+pub(crate) async fn execute_sessions_run_status(arguments: &Value) -> Result<Value, String> {
+    let run_id = read_string_argument(arguments, "run_id")?;
+    let wait_ms = read_u64_argument(arguments, "wait_ms", 0).min(MAX_STATUS_WAIT_MS);
+    let (conversation_id, started_at, mut outcome) = {
+        let runs = session_runs().lock().map_err(|e| e.to_string())?;
+        let entry = runs
+            .get(&run_id)
+            .ok_or_else(|| format!("Unknown session run: {}", run_id))?;
+        (
+            entry.conversation_id.clone(),
+            entry.started_at.clone(),
+            entry.outcome.clone(),
+        )
+    };
+    if wait_ms > 0 {
+        let _ = tokio::time::timeout(
+            Duration::from_millis(wait_ms),
+            outcome.wait_for(Option::is_some),
+        )
+        .await;
+    }
+
+    let finished = outcome.borrow().clone();
+    // A run whose task died drops its sender without reporting a result.
+    let finished = match finished {
+        None if outcome.has_changed().is_err() => {
+            Some(Err("Session run ended without a result".to_string()))
+        }
+        finished => finished,
+    };
+    match finished {
+        Some(Ok(summary)) => Ok(summary),
+        Some(Err(error)) => Ok(json!({
+            "run_id": run_id,
+            "conversation_id": conversation_id,
+            "status": "failed",
+            "error": error
+        })),
+        None => Ok(running_status(&run_id, &conversation_id, &started_at)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mcp_client::McpManager;
+    use crate::services::skill_manager::SkillManager;
+    use std::collections::HashSet;
+
+    async fn start_test_run(
+        caller: &str,
+        target: &str,
+        skills_dir: &Path,
+    ) -> Result<Value, String> {
+        let pool = SqlitePool::connect_lazy("sqlite::memory:").unwrap();
+        let mcp_state: McpState = Arc::new(tokio::sync::Mutex::new(McpManager::new()));
+        let skill_state: SkillManagerState = Arc::new(tokio::sync::Mutex::new(
+            SkillManager::new(skills_dir.to_path_buf()).unwrap(),
+        ));
+        start_session_run(
+            &pool,
+            &mcp_state,
+            &skill_state,
+            &RunPolicy::default(),
+            caller,
+            target,
+            "hello".to_string(),
+            None,
+            skills_dir,
+            true,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn rejects_runs_in_the_caller_and_in_busy_conversations() {
+        let root = std::env::temp_dir().join(format!("petool-session-runs-{}", Uuid::new_v4()));
+        let caller = Uuid::new_v4().to_string();
+        let busy = Uuid::new_v4().to_string();
+
+        let error = start_test_run(&caller, &caller, &root).await.unwrap_err();
+        assert!(error.contains("cannot run a turn in itself"));
+
+        let _busy_turn = try_register_stream_stop_flag(&busy).await.unwrap();
+        let error = start_test_run(&caller, &busy, &root).await.unwrap_err();
+        assert!(error.contains("already running a turn"));
+        // The rejected start leaves the running turn registered.
+        assert!(try_register_stream_stop_flag(&busy).await.is_none());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn a_panicking_run_releases_its_conversation() {
+        let target = Uuid::new_v4().to_string();
+        let flag = try_register_stream_stop_flag(&target).await.unwrap();
+        let registration = StreamStopFlagGuard::new(&target, flag);
+        let run = tokio::spawn(async move {
+            let _registration = registration;
+            panic!("run failed");
+        });
+        assert!(run.await.is_err());
+
+        let next = try_register_stream_stop_flag(&target).await;
+        assert!(next.is_some());
+        // An old guard never unregisters the turn that replaced it.
+        drop(StreamStopFlagGuard::new(
+            &target,
+            Arc::new(AtomicBool::new(false)),
+        ));
+        assert!(try_register_stream_stop_flag(&target).await.is_none());
+    }
+
+    #[test]
+    fn target_runs_inherit_the_caller_policy() {
+        let whitelist = HashSet::from(["workspace_read_file".to_string()]);
+        let caller_policy = RunPolicy {
+            tool_whitelist: Some(whitelist.clone()),
+            sandboxed: true,
+        };
+        let request = session_run_request(
+            &caller_policy,
+            "target",
+            "hello".to_string(),
+            None,
+            Path::new("/workspace"),
+        );
+        assert_eq!(request.tool_whitelist.as_ref(), Some(&whitelist));
+        assert!(request.sandboxed);
+        // Runs the target starts in turn are held to the same policy.
+        let nested = request.run_policy();
+        assert_eq!(nested.tool_whitelist, Some(whitelist));
+        assert!(nested.sandboxed);
+
+        let open = session_run_request(
+            &RunPolicy::default(),
+            "target",
+            "hello".to_string(),
+            None,
+            Path::new("/workspace"),
+        );
+        assert!(open.tool_whitelist.is_none());
+        assert!(!open.sandboxed);
+    }
+
+    #[test]
+    fn prunes_only_the_oldest_finished_runs() {
+        let mut runs = HashMap::new();
+        let (_running_sender, running) = watch::channel(None);
+        runs.insert(
+            "running".to_string(),
+            SessionRunEntry {
+                conversation_id: "conversation-a".to_string(),
+                started_at: "2026-01-01T00:00:00Z".to_string(),
+                outcome: running,
+            },
+        );
+        for index in 0..MAX_FINISHED_SESSION_RUNS + 2 {
+            let (_, finished) = watch::channel(Some(Ok(json!({}))));
+            runs.insert(
+                format!("finished-{}", index),
+                SessionRunEntry {
+                    conversation_id: "conversation-b".to_string(),
+                    started_at: format!("2026-01-02T00:00:{:02}Z", index),
+                    outcome: finished,
+                },
+            );
+        }
+
+        prune_finished_session_runs(&mut runs);
+        assert_eq!(runs.len(), MAX_FINISHED_SESSION_RUNS + 1);
+        assert!(runs.contains_key("running"));
+        assert!(!runs.contains_key("finished-0"));
+        assert!(!runs.contains_key("finished-1"));
+        assert!(runs.contains_key("finished-2"));
+    }
+}
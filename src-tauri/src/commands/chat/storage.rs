@@ -3,12 +3,15 @@ use crate::services::llm::{ChatMessage, ChatToolCall};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use sqlx::SqlitePool;
-use std::collections::HashMap;
 use std::sync::OnceLock;
-use tauri::{Emitter, Window};
+use tauri::Emitter;
 use chrono::Utc;
 use uuid::Uuid;
 
+use super::context_compaction::build_summary_context_message;
+use super::events::emit_app_event;
+use super::tool_catalog::{CORE_TASK_TOOL, TODO_WRITE_TOOL};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub(crate) enum TodoStatus {
@@ -27,12 +30,114 @@ pub(crate) struct TodoItem {
     pub(crate) updated_at: String,
 }
 
-pub(crate) type TodoStore = tokio::sync::Mutex<HashMap<String, Vec<TodoItem>>>;
+/// The todo list of one conversation. Interactive turns and scheduled runs read and write
+/// the same plan, so work can resume across restarts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TodoPlan {
+    pub(crate) conversation_id: String,
+    /// Bumped on every todo_write; 0 means the conversation never had a plan.
+    pub(crate) version: i64,
+    pub(crate) items: Vec<TodoItem>,
+    pub(crate) updated_at: Option<String>,
+}
+
+static TODO_PLAN_WRITE_LOCK: OnceLock<tokio::sync::Mutex<()>> = OnceLock::new();
+
+/// Serializes read-modify-write cycles on todo plans across turns and background runs.
+pub(crate) fn todo_plan_write_lock() -> &'static tokio::sync::Mutex<()> {
+    TODO_PLAN_WRITE_LOCK.get_or_init(|| tokio::sync::Mutex::new(()))
+}
+
+pub(crate) async fn load_todo_plan(
+    pool: &SqlitePool,
+    conversation_id: &str,
+) -> Result<TodoPlan, String> {
+    let row = sqlx::query_as::<_, (i64, String, String)>(
+        "SELECT version, items, updated_at FROM todo_plans WHERE conversation_id = ?",
+    )
+    .bind(conversation_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let Some((version, items, updated_at)) = row else {
+        return Ok(TodoPlan {
+            conversation_id: conversation_id.to_string(),
+            ..Default::default()
+        });
+    };
+    Ok(TodoPlan {
+        conversation_id: conversation_id.to_string(),
+        version,
+        items: serde_json::from_str(&items).map_err(|e| e.to_string())?,
+        updated_at: Some(updated_at),
+    })
+}
+
+/// Stores `items` as the next version of the plan and emits `todo-plan-updated`.
+pub(crate) async fn save_todo_plan(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    items: Vec<TodoItem>,
+) -> Result<TodoPlan, String> {
+    let updated_at = Utc::now().to_rfc3339();
+    // Runs the upsert to completion: `fetch_one` stops at the returned row, and SQLite only
+    // commits the statement once it has finished stepping.
+    let version = sqlx::query_scalar::<_, i64>(
+        "INSERT INTO todo_plans (conversation_id, version, items, updated_at) VALUES (?, 1, ?, ?)
+         ON CONFLICT(conversation_id) DO UPDATE SET version = todo_plans.version + 1, items = excluded.items, updated_at = excluded.updated_at
+         RETURNING version",
+    )
+    .bind(conversation_id)
+    .bind(serde_json::to_string(&items).map_err(|e| e.to_string())?)
+    .bind(&updated_at)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .into_iter()
+    .next()
+    .ok_or_else(|| "Todo plan upsert returned no version".to_string())?;
 
-pub(crate) static TODO_STORE: OnceLock<TodoStore> = OnceLock::new();
+    let plan = TodoPlan {
+        conversation_id: conversation_id.to_string(),
+        version,
+        items,
+        updated_at: Some(updated_at),
+    };
+    emit_app_event(
+        "todo-plan-updated",
+        serde_json::to_value(&plan).map_err(|e| e.to_string())?,
+    );
+    Ok(plan)
+}
 
-pub(crate) fn todo_store() -> &'static TodoStore {
-    TODO_STORE.get_or_init(|| tokio::sync::Mutex::new(HashMap::new()))
+/// The plan as a system message, so a new turn or scheduled run picks up where the last
+/// one stopped. None when there is nothing left to do.
+pub(crate) fn build_todo_plan_context(plan: &TodoPlan) -> Option<String> {
+    if !plan
+        .items
+        .iter()
+        .any(|item| !matches!(item.status, TodoStatus::Completed))
+    {
+        return None;
+    }
+    let lines = plan
+        .items
+        .iter()
+        .map(|item| {
+            let mark = match item.status {
+                TodoStatus::Pending => " ",
+                TodoStatus::InProgress => "~",
+                TodoStatus::Completed => "x",
+            };
+            format!("- [{}] {} (id: {})", mark, item.text, item.id)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some(format!(
+        "Current plan for this conversation (version {}, [~] = in progress):\n{}\nContinue from the first unfinished item and keep the plan current with todo_write.",
+        plan.version, lines
+    ))
 }
 
 #[derive(Debug, Clone)]
@@ -49,7 +154,7 @@ pub(crate) async fn insert_timeline_event(
     pool: &SqlitePool,
     conversation_id: &str,
     event: &PendingTimelineEvent,
-) -> Result<(), String> {
+) -> Result<String, String> {
     let id = Uuid::new_v4().to_string();
     let payload = serde_json::to_string(&event.payload).map_err(|e| e.to_string())?;
     let event_type = serde_json::to_string(&event.event_type)
@@ -72,7 +177,25 @@ pub(crate) async fn insert_timeline_event(
     .await
     .map_err(|e| e.to_string())?;
 
-    Ok(())
+    Ok(id)
+}
+
+/// The `seq` after the last event recorded for a turn, for events added from outside the
+/// turn's own stream.
+pub(crate) async fn next_turn_seq(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    turn_id: &str,
+) -> Result<i64, String> {
+    let last = sqlx::query_scalar::<_, Option<i64>>(
+        "SELECT MAX(seq) FROM message_events WHERE conversation_id = ? AND turn_id = ?",
+    )
+    .bind(conversation_id)
+    .bind(turn_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(last.unwrap_or(0) + 1)
 }
 
 pub(crate) async fn insert_timeline_events(
@@ -86,6 +209,16 @@ pub(crate) async fn insert_timeline_events(
     Ok(())
 }
 
+/// Walks the active branch from `conversations.active_leaf_id` up through `parent_id`.
+/// Binds the conversation id; `depth` is 0 at the leaf, so sort by it descending.
+pub(crate) const ACTIVE_BRANCH_CTE: &str = "WITH RECURSIVE branch(id, depth) AS (
+    SELECT active_leaf_id, 0 FROM conversations WHERE id = ? AND active_leaf_id IS NOT NULL
+    UNION ALL
+    SELECT messages.parent_id, branch.depth + 1 FROM messages JOIN branch ON messages.id = branch.id
+    WHERE messages.parent_id IS NOT NULL
+)";
+
+/// Appends a message to the active branch and returns its id.
 pub(crate) async fn insert_message(
     pool: &SqlitePool,
     conversation_id: &str,
@@ -93,50 +226,185 @@ pub(crate) async fn insert_message(
     content: &str,
     tool_calls: Option<String>,
     reasoning: Option<String>,
-) -> Result<(), String> {
+) -> Result<String, String> {
+    insert_message_row(
+        pool,
+        conversation_id,
+        role,
+        content,
+        tool_calls,
+        reasoning,
+        None,
+    )
+    .await
+}
+
+/// Appends a user message together with the hidden context rendered from its attachments,
+/// so the context is replayed with the message on later turns and regenerations.
+pub(crate) async fn insert_user_message(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    content: &str,
+    attachment_context: Option<String>,
+) -> Result<String, String> {
+    insert_message_row(
+        pool,
+        conversation_id,
+        "user",
+        content,
+        None,
+        None,
+        attachment_context,
+    )
+    .await
+}
+
+async fn insert_message_row(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    role: &str,
+    content: &str,
+    tool_calls: Option<String>,
+    reasoning: Option<String>,
+    attachment_context: Option<String>,
+) -> Result<String, String> {
     let message_id = Uuid::new_v4().to_string();
     let now = Utc::now().to_rfc3339();
 
+    // Reading the leaf and moving it in one transaction keeps concurrent appends from
+    // branching off the same parent.
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
     sqlx::query(
-        "INSERT INTO messages (id, conversation_id, role, content, created_at, tool_calls, reasoning) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        "INSERT INTO messages (id, conversation_id, parent_id, role, content, created_at, tool_calls, reasoning, attachment_context) VALUES (?, ?, (SELECT active_leaf_id FROM conversations WHERE id = ?), ?, ?, ?, ?, ?, ?)",
     )
     .bind(&message_id)
     .bind(conversation_id)
+    .bind(conversation_id)
     .bind(role)
     .bind(content)
     .bind(&now)
     .bind(tool_calls)
     .bind(reasoning)
-    .execute(pool)
+    .bind(attachment_context)
+    .execute(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
 
-    sqlx::query("UPDATE conversations SET updated_at = ? WHERE id = ?")
+    sqlx::query("UPDATE conversations SET updated_at = ?, active_leaf_id = ? WHERE id = ?")
         .bind(&now)
+        .bind(&message_id)
         .bind(conversation_id)
-        .execute(pool)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())?;
 
-    Ok(())
+    Ok(message_id)
 }
 
-pub(crate) async fn load_conversation_context(
+/// Message ids of the active branch, oldest first.
+pub(crate) async fn load_active_branch_ids(
     pool: &SqlitePool,
     conversation_id: &str,
-) -> Result<Vec<ChatMessage>, String> {
-    let rows = sqlx::query_as::<_, (String, String, Option<String>, Option<String>)>(
-        "SELECT role, content, tool_calls, reasoning FROM messages WHERE conversation_id = ? ORDER BY created_at ASC",
+) -> Result<Vec<String>, String> {
+    sqlx::query_scalar::<_, String>(&format!(
+        "{} SELECT id FROM branch ORDER BY depth DESC",
+        ACTIVE_BRANCH_CTE
+    ))
+    .bind(conversation_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct StoredContextMessage {
+    pub(crate) id: String,
+    pub(crate) created_at: String,
+    pub(crate) message: ChatMessage,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ConversationSummaryRecord {
+    pub(crate) summary: String,
+    pub(crate) boundary_message_id: String,
+    pub(crate) boundary_created_at: String,
+    pub(crate) summarized_messages: i64,
+}
+
+pub(crate) async fn load_latest_conversation_summary(
+    pool: &SqlitePool,
+    conversation_id: &str,
+) -> Result<Option<ConversationSummaryRecord>, String> {
+    // Summaries written on another branch cover messages this branch never had.
+    let row = sqlx::query_as::<_, (String, String, String, i64)>(&format!(
+        "{} SELECT summary, boundary_message_id, boundary_created_at, summarized_messages FROM conversation_summaries WHERE conversation_id = ? AND boundary_message_id IN (SELECT id FROM branch) ORDER BY boundary_created_at DESC, created_at DESC LIMIT 1",
+        ACTIVE_BRANCH_CTE
+    ))
+    .bind(conversation_id)
+    .bind(conversation_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(row.map(
+        |(summary, boundary_message_id, boundary_created_at, summarized_messages)| {
+            ConversationSummaryRecord {
+                summary,
+                boundary_message_id,
+                boundary_created_at,
+                summarized_messages,
+            }
+        },
+    ))
+}
+
+pub(crate) async fn insert_conversation_summary(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    record: &ConversationSummaryRecord,
+    model: &str,
+) -> Result<(), String> {
+    sqlx::query(
+        "INSERT INTO conversation_summaries (id, conversation_id, summary, boundary_message_id, boundary_created_at, summarized_messages, model, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
     )
+    .bind(Uuid::new_v4().to_string())
     .bind(conversation_id)
+    .bind(&record.summary)
+    .bind(&record.boundary_message_id)
+    .bind(&record.boundary_created_at)
+    .bind(record.summarized_messages)
+    .bind(model)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Loads the active branch's messages strictly after `after_created_at` (or all of them when
+/// `None`).
+pub(crate) async fn load_conversation_context_rows(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    after_created_at: Option<&str>,
+) -> Result<Vec<StoredContextMessage>, String> {
+    let rows = sqlx::query_as::<_, (String, String, String, Option<String>, Option<String>, Option<String>, String)>(&format!(
+        "{} SELECT m.id, m.role, m.content, m.tool_calls, m.reasoning, m.attachment_context, m.created_at FROM messages m JOIN branch ON m.id = branch.id WHERE m.created_at > ? ORDER BY branch.depth DESC",
+        ACTIVE_BRANCH_CTE
+    ))
+    .bind(conversation_id)
+    .bind(after_created_at.unwrap_or(""))
     .fetch_all(pool)
     .await
     .map_err(|e| e.to_string())?;
 
     let mut messages = Vec::new();
 
-    for (role, content, tool_calls_raw, reasoning_raw) in rows {
-        match role.as_str() {
+    for (id, role, content, tool_calls_raw, reasoning_raw, attachment_context, created_at) in rows
+    {
+        let message = match role.as_str() {
             "assistant" => {
                 let tool_calls = tool_calls_raw
                     .as_deref()
@@ -148,7 +416,7 @@ pub(crate) async fn load_conversation_context(
                     .map(|value| value.to_string());
                 let reasoning_details = reasoning.as_deref().and_then(crate::services::llm::reasoning_details_from_text);
 
-                messages.push(ChatMessage {
+                ChatMessage {
                     role,
                     content: if content.is_empty() {
                         None
@@ -159,7 +427,7 @@ pub(crate) async fn load_conversation_context(
                     tool_call_id: None,
                     reasoning_details,
                     reasoning,
-                });
+                }
             }
             "tool" => {
                 let tool_call_id = tool_calls_raw
@@ -172,33 +440,63 @@ pub(crate) async fn load_conversation_context(
                             .map(|item| item.to_string())
                     });
 
-                messages.push(ChatMessage {
+                ChatMessage {
                     role,
                     content: Some(content),
                     tool_calls: None,
                     tool_call_id,
                     reasoning_details: None,
                     reasoning: None,
-                });
-            }
-            _ => {
-                messages.push(ChatMessage {
-                    role,
-                    content: Some(content),
-                    tool_calls: None,
-                    tool_call_id: None,
-                    reasoning_details: None,
-                    reasoning: None,
-                });
+                }
             }
-        }
+            _ => ChatMessage {
+                role,
+                content: Some(match attachment_context {
+                    Some(context) if !context.is_empty() => format!("{}\n\n{}", context, content),
+                    _ => content,
+                }),
+                tool_calls: None,
+                tool_call_id: None,
+                reasoning_details: None,
+                reasoning: None,
+            },
+        };
+
+        messages.push(StoredContextMessage {
+            id,
+            created_at,
+            message,
+        });
     }
 
     Ok(messages)
 }
 
-pub(crate) fn emit_tool_result_event(
-    window: &Window,
+/// Replays the conversation from its latest compaction summary onwards.
+pub(crate) async fn load_conversation_context(
+    pool: &SqlitePool,
+    conversation_id: &str,
+) -> Result<Vec<ChatMessage>, String> {
+    let summary = load_latest_conversation_summary(pool, conversation_id).await?;
+    let rows = load_conversation_context_rows(
+        pool,
+        conversation_id,
+        summary
+            .as_ref()
+            .map(|record| record.boundary_created_at.as_str()),
+    )
+    .await?;
+
+    let mut messages = Vec::with_capacity(rows.len() + 1);
+    if let Some(summary) = summary {
+        messages.push(build_summary_context_message(&summary.summary));
+    }
+    messages.extend(rows.into_iter().map(|row| row.message));
+    Ok(messages)
+}
+
+pub(crate) fn emit_tool_result_event<R: tauri::Runtime>(
+    emitter: &impl Emitter<R>,
     conversation_id: &str,
     turn_id: &str,
     seq: i64,
@@ -207,7 +505,7 @@ pub(crate) fn emit_tool_result_event(
     result: Option<&str>,
     error: Option<&str>,
 ) -> Result<(), String> {
-    window
+    emitter
         .emit(
             "chat-tool-result",
             json!({
@@ -225,9 +523,9 @@ pub(crate) fn emit_tool_result_event(
         .map_err(|e| e.to_string())
 }
 
-pub(crate) async fn emit_and_record_tool_result_event(
+pub(crate) async fn emit_and_record_tool_result_event<R: tauri::Runtime>(
     pool: &SqlitePool,
-    window: &Window,
+    emitter: &impl Emitter<R>,
     conversation_id: &str,
     turn_id: &str,
     seq_counter: &mut i64,
@@ -239,7 +537,7 @@ pub(crate) async fn emit_and_record_tool_result_event(
     let seq = *seq_counter;
     let created_at = Utc::now().to_rfc3339();
     emit_tool_result_event(
-        window,
+        emitter,
         conversation_id,
         turn_id,
         seq,
@@ -265,6 +563,44 @@ pub(crate) async fn emit_and_record_tool_result_event(
     Ok(())
 }
 
+/// Streams text the app itself adds to the reply (pause and guard notices) as one chunk.
+pub(crate) async fn emit_and_record_assistant_text<R: tauri::Runtime>(
+    pool: &SqlitePool,
+    emitter: &impl Emitter<R>,
+    conversation_id: &str,
+    turn_id: &str,
+    seq_counter: &mut i64,
+    text: &str,
+) -> Result<(), String> {
+    *seq_counter += 1;
+    let seq = *seq_counter;
+    let created_at = Utc::now().to_rfc3339();
+    emitter
+        .emit(
+            "chat-chunk",
+            json!({
+                "conversationId": conversation_id,
+                "turnId": turn_id,
+                "seq": seq,
+                "eventType": "assistant_text",
+                "createdAt": created_at,
+                "chunk": text
+            }),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let event = PendingTimelineEvent {
+        turn_id: turn_id.to_string(),
+        seq,
+        event_type: TimelineEventType::AssistantText,
+        tool_call_id: None,
+        payload: json!({ "text": text }),
+        created_at,
+    };
+    insert_timeline_event(pool, conversation_id, &event).await?;
+    Ok(())
+}
+
 pub(crate) fn build_tool_call_metadata(tool_call: &ChatToolCall) -> Result<String, String> {
     serde_json::to_string(&json!({
         "tool_call_id": &tool_call.id,
@@ -310,6 +646,131 @@ pub(crate) async fn persist_tool_result_message(
     Ok(())
 }
 
+/// Records the plan version a todo_write produced in the turn, so the timeline keeps every
+/// version of the plan.
+async fn record_plan_updated_event(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    turn_id: &str,
+    seq_counter: &mut i64,
+    result_text: &str,
+) -> Result<Option<PendingTimelineEvent>, String> {
+    let Ok(result) = serde_json::from_str::<Value>(result_text) else {
+        return Ok(None);
+    };
+    *seq_counter += 1;
+    let event = PendingTimelineEvent {
+        turn_id: turn_id.to_string(),
+        seq: *seq_counter,
+        event_type: TimelineEventType::PlanUpdated,
+        tool_call_id: None,
+        payload: json!({
+            "version": result["version"],
+            "action": result["action"],
+            "items": result["items"]
+        }),
+        created_at: Utc::now().to_rfc3339(),
+    };
+    insert_timeline_event(pool, conversation_id, &event).await?;
+    Ok(Some(event))
+}
+
+async fn emit_and_record_plan_updated_event<R: tauri::Runtime>(
+    pool: &SqlitePool,
+    emitter: &impl Emitter<R>,
+    conversation_id: &str,
+    turn_id: &str,
+    seq_counter: &mut i64,
+    result_text: &str,
+) -> Result<(), String> {
+    let Some(event) =
+        record_plan_updated_event(pool, conversation_id, turn_id, seq_counter, result_text).await?
+    else {
+        return Ok(());
+    };
+    let _ = emitter.emit(
+        "chat-plan-updated",
+        json!({
+            "conversationId": conversation_id,
+            "turnId": turn_id,
+            "seq": event.seq,
+            "eventType": "plan_updated",
+            "createdAt": event.created_at,
+            "version": event.payload["version"],
+            "action": event.payload["action"],
+            "items": event.payload["items"]
+        }),
+    );
+    Ok(())
+}
+
+/// Emits the result event and persists the `tool` message for one finished tool call.
+/// `outcome` carries the result text on success and the raw error text on failure.
+pub(crate) async fn record_tool_call_outcome<R: tauri::Runtime>(
+    pool: &SqlitePool,
+    emitter: &impl Emitter<R>,
+    conversation_id: &str,
+    turn_id: &str,
+    seq_counter: &mut i64,
+    context_messages: &mut Vec<ChatMessage>,
+    tool_call: &ChatToolCall,
+    outcome: Result<String, String>,
+) -> Result<(), String> {
+    // The sub-agent of a core_task call records its steps under this turn meanwhile.
+    if tool_call.function.name == CORE_TASK_TOOL {
+        *seq_counter = (*seq_counter).max(next_turn_seq(pool, conversation_id, turn_id).await? - 1);
+    }
+    let result_text = match outcome {
+        Ok(result_text) => {
+            emit_and_record_tool_result_event(
+                pool,
+                emitter,
+                conversation_id,
+                turn_id,
+                seq_counter,
+                tool_call,
+                Some(&result_text),
+                None,
+            )
+            .await?;
+            if tool_call.function.name == TODO_WRITE_TOOL {
+                emit_and_record_plan_updated_event(
+                    pool,
+                    emitter,
+                    conversation_id,
+                    turn_id,
+                    seq_counter,
+                    &result_text,
+                )
+                .await?;
+            }
+            result_text
+        }
+        Err(error_text) => {
+            emit_and_record_tool_result_event(
+                pool,
+                emitter,
+                conversation_id,
+                turn_id,
+                seq_counter,
+                tool_call,
+                None,
+                Some(&error_text),
+            )
+            .await?;
+            format_tool_error_result(&error_text)?
+        }
+    };
+    persist_tool_result_message(
+        pool,
+        conversation_id,
+        context_messages,
+        tool_call,
+        result_text,
+    )
+    .await
+}
+
 pub(crate) fn push_background_tool_result_message(
     context_messages: &mut Vec<ChatMessage>,
     tool_call: &ChatToolCall,
@@ -324,3 +785,165 @@ pub(crate) fn push_background_tool_result_message(
         reasoning: None,
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::tool_executor::execute_todo_write;
+    use super::*;
+    use crate::services::database::Database;
+
+    #[tokio::test]
+    async fn attachment_context_is_replayed_with_its_user_message() {
+        let root = std::env::temp_dir().join(format!("petool-storage-test-{}", Uuid::new_v4()));
+        let db = Database::new(root.join("petool.db")).await.unwrap();
+        let pool = db.pool();
+        sqlx::query(
+            "INSERT INTO conversations (id, title, model, created_at, updated_at) VALUES ('c1', 'First', 'gpt-4o', '', '')",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+
+        insert_user_message(
+            pool,
+            "c1",
+            "summarize the spec",
+            Some("<resource uri=\"docs://spec\">spec body</resource>".to_string()),
+        )
+        .await
+        .unwrap();
+        insert_message(pool, "c1", "assistant", "done", None, None)
+            .await
+            .unwrap();
+        insert_message(pool, "c1", "user", "and now?", None, None)
+            .await
+            .unwrap();
+
+        let contents = load_conversation_context(pool, "c1")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|message| message.content.unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            contents,
+            vec![
+                "<resource uri=\"docs://spec\">spec body</resource>\n\nsummarize the spec",
+                "done",
+                "and now?",
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn todo_writes_persist_each_version_and_record_it_on_the_timeline() {
+        let root = std::env::temp_dir().join(format!("petool-storage-test-{}", Uuid::new_v4()));
+        let db = Database::new(root.join("petool.db")).await.unwrap();
+        let pool = db.pool();
+        sqlx::query(
+            "INSERT INTO conversations (id, title, model, created_at, updated_at) VALUES ('c1', 'First', 'gpt-4o', '', '')",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+
+        let empty = load_todo_plan(pool, "c1").await.unwrap();
+        assert_eq!(empty.version, 0);
+        assert!(empty.items.is_empty());
+
+        let added = execute_todo_write(
+            &json!({ "action": "add", "id": "a", "text": "write tests" }),
+            "c1",
+            pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(added["version"], 1);
+        let created_at = load_todo_plan(pool, "c1").await.unwrap().items[0]
+            .created_at
+            .clone();
+
+        let replaced = execute_todo_write(
+            &json!({
+                "action": "set",
+                "items": [
+                    { "id": "a", "text": "write tests", "status": "completed" },
+                    { "id": "b", "text": "ship it" }
+                ]
+            }),
+            "c1",
+            pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(replaced["version"], 2);
+
+        let plan = load_todo_plan(pool, "c1").await.unwrap();
+        assert_eq!(plan.version, 2);
+        assert_eq!(
+            plan.items
+                .iter()
+                .map(|item| item.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+        assert!(matches!(plan.items[0].status, TodoStatus::Completed));
+        assert_eq!(plan.items[0].created_at, created_at);
+        assert!(load_todo_plan(pool, "other")
+            .await
+            .unwrap()
+            .items
+            .is_empty());
+
+        let mut seq = 4;
+        record_plan_updated_event(pool, "c1", "turn-1", &mut seq, &replaced.to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(seq, 5);
+        let (event_type, event_seq, payload) = sqlx::query_as::<_, (String, i64, String)>(
+            "SELECT event_type, seq, payload FROM message_events WHERE conversation_id = 'c1' AND turn_id = 'turn-1'",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!(event_type, "plan_updated");
+        assert_eq!(event_seq, 5);
+        let payload: Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["version"], 2);
+        assert_eq!(payload["items"].as_array().unwrap().len(), 2);
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn concurrent_appends_stay_on_one_branch() {
+        let root = std::env::temp_dir().join(format!("petool-storage-test-{}", Uuid::new_v4()));
+        let db = Database::new(root.join("petool.db")).await.unwrap();
+        let pool = db.pool().clone();
+        sqlx::query(
+            "INSERT INTO conversations (id, title, model, created_at, updated_at) VALUES ('c1', 'First', 'gpt-4o', '', '')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let appends = (0..16)
+            .map(|index| {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    insert_message(&pool, "c1", "user", &format!("m{}", index), None, None).await
+                })
+            })
+            .collect::<Vec<_>>();
+        for append in appends {
+            append.await.unwrap().unwrap();
+        }
+
+        assert_eq!(load_active_branch_ids(&pool, "c1").await.unwrap().len(), 16);
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
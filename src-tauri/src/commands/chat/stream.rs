@@ -6,10 +6,13 @@ use crate::models::config::{
 };
 use crate::services::desktop;
 use crate::services::llm::{
-    reasoning_details_from_text, ChatMessage, ChatTool, ChatToolCall, LlmService,
-    LlmStreamEvent, LlmStreamResult,
+    is_transient_llm_error, reasoning_details_from_text, ChatMessage, ChatTool, ChatToolCall,
+    LlmStreamEvent, LlmStreamResult, LlmUsage,
 };
+use crate::services::mcp_client::{render_resource_contents, RenderedResource};
 use crate::services::memory::prepare_memory_prompt_and_remember_turn;
+use crate::services::sandbox::with_background_sandbox;
+use crate::services::usage::{record_usage_logged, UsageSource};
 use chrono::Utc;
 
 use serde::{Deserialize, Serialize};
@@ -27,12 +30,14 @@ use uuid::Uuid;
 
 use crate::AppState;
 use super::*;
+use super::checkpoints::{enter_checkpoint_turn, enter_timeline_turn};
+use super::sub_agents::{report_sub_agent_step, sub_agent_step_preview, SubAgentRun};
 use super::storage::*;
 use super::llm_provider::*;
 use super::tool_executor::*;
 use super::commands::*;
 
-pub(crate) type ToolApprovalSender = oneshot::Sender<ToolApprovalDecision>;
+pub(crate) type ToolApprovalSender = oneshot::Sender<ToolApprovalResolution>;
 
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
@@ -43,6 +48,13 @@ pub enum ToolApprovalDecision {
     Deny,
 }
 
+/// The user's decision plus any data they entered (e.g. the form of an MCP elicitation).
+#[derive(Debug, Clone)]
+pub(crate) struct ToolApprovalResolution {
+    pub decision: ToolApprovalDecision,
+    pub response: Option<Value>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct ToolApprovalRequestPayload {
@@ -51,6 +63,9 @@ pub(crate) struct ToolApprovalRequestPayload {
     tool_call_id: String,
     tool_name: String,
     arguments: String,
+    /// Diff of each file a write/edit/patch call would change; the user may reject hunks.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    file_changes: Vec<FileChangePreview>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -78,6 +93,24 @@ pub(crate) struct UploadedAttachment {
     extension: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResourceAttachmentInput {
+    pub server: String,
+    pub uri: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct McpResourceAttachment {
+    server: String,
+    uri: String,
+    name: String,
+    contents: Vec<RenderedResource>,
+}
+
+const MCP_RESOURCE_ATTACHMENT_MAX_CHARS: usize = 60_000;
+
 
 
 pub(crate) const DEFAULT_WEB_ACCEPT_LANGUAGE: &str = "en-US,en;q=0.9,zh-CN;q=0.8,zh;q=0.7";
@@ -85,6 +118,7 @@ pub(crate) const DEFAULT_WEB_USER_AGENT: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36";
 pub(crate) const DEFAULT_EXA_MCP_ENDPOINT: &str = "https://mcp.exa.ai/mcp";
 pub(crate) const REPEATED_TOOL_GUARD_TEXT: &str = "Detected repeated identical tool calls from the model. Automatic tool loop was stopped. Please provide a more specific target (file/directory) and try again.";
+const SUB_AGENT_FINAL_ROUND_TEXT: &str = "You have reached the round limit for this task. Tools are no longer available: reply now with your final result and list anything left unfinished.";
 pub(crate) const STREAM_PAUSED_TEXT: &str = "（已暂停）";
 
 
@@ -211,6 +245,25 @@ pub(crate) fn prepend_skills_usage_guidance(messages: &mut Vec<ChatMessage>, ski
     );
 }
 
+/// Puts the conversation's unfinished todo plan in front of the model so interactive turns
+/// and scheduled runs continue the same plan.
+pub(crate) async fn prepend_todo_plan_guidance(
+    pool: &SqlitePool,
+    messages: &mut Vec<ChatMessage>,
+    conversation_id: &str,
+) {
+    match load_todo_plan(pool, conversation_id).await {
+        Ok(plan) => {
+            let guidance = build_todo_plan_context(&plan);
+            prepend_system_prompt(messages, guidance.as_deref());
+        }
+        Err(error) => eprintln!(
+            "[todo] load plan failed for conversation {}: {}",
+            conversation_id, error
+        ),
+    }
+}
+
 pub(crate) fn normalize_uploaded_attachments(
     attachments: Option<Vec<UploadedAttachmentInput>>,
     workspace_root: &Path,
@@ -336,6 +389,119 @@ pub(crate) fn prepend_uploaded_attachments_guidance(
     );
 }
 
+/// Reads the MCP resources the user attached to this turn so their text can ride along as
+/// hidden context, the way uploaded files do.
+pub(crate) async fn load_mcp_resource_attachments(
+    mcp_state: &McpState,
+    attachments: Option<Vec<McpResourceAttachmentInput>>,
+) -> Result<Vec<McpResourceAttachment>, String> {
+    let Some(items) = attachments else {
+        return Ok(Vec::new());
+    };
+
+    let mut loaded = Vec::new();
+    let mut manager = mcp_state.lock().await;
+    for item in items {
+        let server = item.server.trim().to_string();
+        let uri = item.uri.trim().to_string();
+        if server.is_empty() || uri.is_empty() {
+            continue;
+        }
+
+        let client = manager
+            .get_client_mut(&server)
+            .ok_or_else(|| format!("MCP server '{}' is not connected", server))?;
+        let result = client
+            .read_resource(&uri)
+            .await
+            .map_err(|e| format!("Failed to read MCP resource {}: {}", uri, e))?;
+        let name = item
+            .name
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string())
+            .or_else(|| {
+                client
+                    .resources
+                    .get(&uri)
+                    .map(|resource| resource.name.clone())
+            })
+            .unwrap_or_else(|| uri.clone());
+
+        // The user just read the latest contents, so the update marker is spent.
+        take_mcp_resource_update(&server, &uri);
+        loaded.push(McpResourceAttachment {
+            contents: render_resource_contents(&result, MCP_RESOURCE_ATTACHMENT_MAX_CHARS),
+            server,
+            uri,
+            name,
+        });
+    }
+
+    Ok(loaded)
+}
+
+/// Renders the attached resources into the hidden context stored with the user message.
+pub(crate) fn build_mcp_resource_attachments_guidance(
+    attachments: &[McpResourceAttachment],
+) -> Option<String> {
+    if attachments.is_empty() {
+        return None;
+    }
+
+    let mut lines = Vec::new();
+    lines.push(
+        "Attached MCP resources for this message (hidden context, do not echo verbatim):"
+            .to_string(),
+    );
+    lines.push(
+        "- Use attached resources as primary context for the user request below.".to_string(),
+    );
+
+    for (index, item) in attachments.iter().enumerate() {
+        lines.push(format!(
+            "{}. {} | server: {} | uri: {}",
+            index + 1,
+            item.name,
+            item.server,
+            item.uri
+        ));
+        for content in &item.contents {
+            let mime_type = content.mime_type.as_deref().unwrap_or("text/plain");
+            if let Some(text) = content.text.as_deref() {
+                lines.push(format!(
+                    "<resource uri=\"{}\" mime=\"{}\">",
+                    content.uri, mime_type
+                ));
+                lines.push(text.to_string());
+                lines.push("</resource>".to_string());
+                if content.truncated {
+                    lines.push(format!(
+                        "(truncated; call `{}` with max_chars for the rest)",
+                        MCP_RESOURCE_READ_TOOL
+                    ));
+                }
+            } else if let Some(bytes) = content.blob_bytes {
+                lines.push(format!(
+                    "- {} is binary ({}, {} bytes) and was not inlined.",
+                    content.uri, mime_type, bytes
+                ));
+            }
+        }
+    }
+
+    Some(lines.join("\n"))
+}
+
+/// Identifies the attached resources for the timeline, without their contents.
+pub(crate) fn mcp_resource_attachment_refs(attachments: &[McpResourceAttachment]) -> Vec<Value> {
+    attachments
+        .iter()
+        .map(|item| json!({ "server": item.server, "uri": item.uri, "name": item.name }))
+        .collect()
+}
+
 pub(crate) async fn build_skills_usage_guidance(skill_manager_state: &SkillManagerState) -> String {
     let manager = skill_manager_state.lock().await;
     let mut skills = manager.list_skills();
@@ -374,32 +540,25 @@ pub(crate) struct StreamRoundOutput {
     pub(crate) timeline_events: Vec<PendingTimelineEvent>,
 }
 
-pub(crate) async fn run_stream_round(
-    llm_service: &LlmService,
-    window: &Window,
+pub(crate) async fn run_stream_round<R: tauri::Runtime>(
+    llm_routes: &mut LlmRouteChain,
+    emitter: &(impl Emitter<R> + Sync),
     conversation_id: &str,
     turn_id: &str,
     seq_counter: &mut i64,
-    model: &str,
     context_messages: Vec<ChatMessage>,
     available_tools: &[ChatTool],
     stop_flag: Arc<AtomicBool>,
 ) -> Result<StreamRoundOutput, String> {
     let conversation_id_for_stream = conversation_id.to_string();
     let turn_id_for_stream = turn_id.to_string();
-    let window_for_stream = window.clone();
     let mut timeline_events: Vec<PendingTimelineEvent> = Vec::new();
     let mut stream_tool_call_ids_by_index: HashMap<usize, String> = HashMap::new();
     let mut stream_tool_call_names_by_index: HashMap<usize, String> = HashMap::new();
-    let stream_result = llm_service
+    let stream_result = llm_routes
         .chat_stream_with_tools(
-            model,
             context_messages,
-            if available_tools.is_empty() {
-                None
-            } else {
-                Some(available_tools.to_vec())
-            },
+            available_tools,
             |event| match event {
                 LlmStreamEvent::Content(chunk) => {
                     if !chunk.is_empty() {
@@ -414,7 +573,7 @@ pub(crate) async fn run_stream_round(
                             payload: json!({ "text": chunk.clone() }),
                             created_at: created_at.clone(),
                         });
-                        let _ = window_for_stream.emit(
+                        let _ = emitter.emit(
                             "chat-chunk",
                             json!({
                                 "conversationId": conversation_id_for_stream.clone(),
@@ -440,7 +599,7 @@ pub(crate) async fn run_stream_round(
                             payload: json!({ "text": chunk.clone() }),
                             created_at: created_at.clone(),
                         });
-                        let _ = window_for_stream.emit(
+                        let _ = emitter.emit(
                             "chat-reasoning",
                             json!({
                                 "conversationId": conversation_id_for_stream.clone(),
@@ -505,7 +664,40 @@ pub(crate) async fn run_stream_round(
                         "name": resolved_name,
                         "argumentsChunk": delta.arguments_chunk,
                     });
-                    let _ = window_for_stream.emit("chat-tool-call", payload);
+                    let _ = emitter.emit("chat-tool-call", payload);
+                }
+                LlmStreamEvent::ModelFallback(fallback) => {
+                    *seq_counter += 1;
+                    let seq = *seq_counter;
+                    let created_at = Utc::now().to_rfc3339();
+                    let payload = json!({
+                        "fromModel": fallback.from_model,
+                        "toModel": fallback.to_model,
+                        "provider": fallback.provider,
+                        "error": fallback.error,
+                    });
+                    timeline_events.push(PendingTimelineEvent {
+                        turn_id: turn_id_for_stream.clone(),
+                        seq,
+                        event_type: TimelineEventType::ModelFallback,
+                        tool_call_id: None,
+                        payload: payload.clone(),
+                        created_at: created_at.clone(),
+                    });
+                    let _ = emitter.emit(
+                        "chat-model-fallback",
+                        json!({
+                            "conversationId": conversation_id_for_stream.clone(),
+                            "turnId": turn_id_for_stream.clone(),
+                            "seq": seq,
+                            "eventType": "model_fallback",
+                            "createdAt": created_at,
+                            "fromModel": payload["fromModel"],
+                            "toModel": payload["toModel"],
+                            "provider": payload["provider"],
+                            "error": payload["error"],
+                        }),
+                    );
                 }
             },
             move || stop_flag.load(Ordering::Relaxed),
@@ -531,7 +723,8 @@ pub(crate) async fn resolve_tool_execution_decision(
     tool_call: &ChatToolCall,
     parsed_arguments: &Value,
     always_allowed_tools: &HashSet<String>,
-) -> Result<ToolApprovalDecision, String> {
+    workspace_root: &Path,
+) -> Result<ToolApprovalResolution, String> {
     let configured_action = resolve_tool_permission_action(
         config,
         &tool_call.function.name,
@@ -545,10 +738,13 @@ pub(crate) async fn resolve_tool_execution_decision(
     );
 
     if config.auto_approve_tool_requests && configured_action != ToolPermissionAction::Deny {
-        return Ok(ToolApprovalDecision::AllowAlways);
+        return Ok(ToolApprovalResolution {
+            decision: ToolApprovalDecision::AllowAlways,
+            response: None,
+        });
     }
 
-    Ok(match configured_action {
+    let decision = match configured_action {
         ToolPermissionAction::Deny => ToolApprovalDecision::Deny,
         ToolPermissionAction::Allow => ToolApprovalDecision::AllowAlways,
         ToolPermissionAction::Ask => {
@@ -558,12 +754,26 @@ pub(crate) async fn resolve_tool_execution_decision(
                 && batch_call_targets_are_safe(parsed_arguments)
             {
                 ToolApprovalDecision::AllowOnce
-            } else if tool_call.function.name == WORKSPACE_PARSE_PDF_TOOL {
+            } else if matches!(
+                tool_call.function.name.as_str(),
+                WORKSPACE_PARSE_PDF_TOOL | MCP_RESOURCES_LIST_TOOL | MCP_PROMPTS_LIST_TOOL
+            ) {
                 ToolApprovalDecision::AllowOnce
             } else {
-                request_tool_approval(window, conversation_id, tool_call).await?
+                return request_tool_approval(
+                    window,
+                    conversation_id,
+                    tool_call,
+                    parsed_arguments,
+                    workspace_root,
+                )
+                .await;
             }
         }
+    };
+    Ok(ToolApprovalResolution {
+        decision,
+        response: None,
     })
 }
 
@@ -575,6 +785,36 @@ pub(crate) struct BackgroundAgentRunRequest {
     pub model_override: Option<String>,
     pub persist_main_context: bool,
     pub tool_whitelist: Option<HashSet<String>>,
+    /// Runs commands sandboxed, as scheduler and heartbeat runs do.
+    pub sandboxed: bool,
+    /// Falls back to the conversation's profile when running in the main context.
+    pub tool_profile: Option<String>,
+    pub usage_source: UsageSource,
+    /// Set when core_task delegates to a sub-agent.
+    pub sub_agent: Option<SubAgentRun>,
+    /// Streams the run into the conversation's timeline with the usual chat-* events, so it
+    /// renders like a turn the user started. Needs `persist_main_context`.
+    pub live_timeline: bool,
+    /// Recalls long-term memory for the prompt and remembers the turn, as chats do.
+    pub use_memory: bool,
+}
+
+/// What a run hands down to the runs its tools start (core_task, sessions_send,
+/// sessions_spawn), so those never get more than their caller. The default, for turns the
+/// user started, restricts nothing.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RunPolicy {
+    pub tool_whitelist: Option<HashSet<String>>,
+    pub sandboxed: bool,
+}
+
+impl BackgroundAgentRunRequest {
+    pub(crate) fn run_policy(&self) -> RunPolicy {
+        RunPolicy {
+            tool_whitelist: self.tool_whitelist.clone(),
+            sandboxed: self.sandboxed,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -586,6 +826,13 @@ pub(crate) struct BackgroundAgentRunResult {
     pub tool_calls: usize,
     pub blocked_tools: usize,
     pub guard_stopped: bool,
+    /// A sub-agent used up its rounds; its last round ran without tools.
+    pub max_rounds_reached: bool,
+    /// A live run was stopped from its conversation.
+    pub stopped: bool,
+    pub usage: LlmUsage,
+    /// Model that produced the final reply, which differs from the requested one after failover.
+    pub model: String,
 }
 
 fn is_tool_allowed_by_scheduler_whitelist(
@@ -616,13 +863,14 @@ fn is_tool_allowed_by_scheduler_whitelist(
     false
 }
 
+/// `None` when the policy leaves the call to the user.
 fn resolve_background_tool_execution_decision(
     config: &Config,
     tool_call: &ChatToolCall,
     parsed_arguments: &Value,
     always_allowed_tools: &HashSet<String>,
     whitelist: Option<&HashSet<String>>,
-) -> ToolApprovalDecision {
+) -> Option<ToolApprovalDecision> {
     let configured_action = resolve_tool_permission_action(
         config,
         &tool_call.function.name,
@@ -636,7 +884,7 @@ fn resolve_background_tool_execution_decision(
     );
 
     if configured_action == ToolPermissionAction::Deny {
-        return ToolApprovalDecision::Deny;
+        return Some(ToolApprovalDecision::Deny);
     }
 
     if let Some(whitelist) = whitelist {
@@ -645,46 +893,164 @@ fn resolve_background_tool_execution_decision(
             &tool_call.function.name,
             parsed_arguments,
         ) {
-            return ToolApprovalDecision::Deny;
+            return Some(ToolApprovalDecision::Deny);
         }
-        return ToolApprovalDecision::AllowAlways;
+        return Some(ToolApprovalDecision::AllowAlways);
     }
 
     match configured_action {
-        ToolPermissionAction::Allow => ToolApprovalDecision::AllowAlways,
+        ToolPermissionAction::Allow => Some(ToolApprovalDecision::AllowAlways),
         ToolPermissionAction::Ask => {
             if config.auto_approve_tool_requests
                 || always_allowed_tools.contains(&tool_call.function.name)
             {
-                ToolApprovalDecision::AllowAlways
+                Some(ToolApprovalDecision::AllowAlways)
             } else {
-                ToolApprovalDecision::Deny
+                None
             }
         }
-        ToolPermissionAction::Deny => ToolApprovalDecision::Deny,
+        ToolPermissionAction::Deny => Some(ToolApprovalDecision::Deny),
     }
 }
 
 
+/// Failure of a background run. `transient` marks a provider error (retryable status or
+/// dropped connection) that outlasted the model chain, so the caller may try again later.
+#[derive(Debug, Clone)]
+pub(crate) struct BackgroundAgentRunError {
+    pub message: String,
+    pub transient: bool,
+}
+
+impl From<String> for BackgroundAgentRunError {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            transient: false,
+        }
+    }
+}
+
+/// Runs a scheduler or heartbeat turn; unlike `run_agent_loop` it keeps the error typed.
 pub(crate) async fn run_agent_turn_background(
     state: AppState,
     mcp_state: McpState,
     skill_state: SkillManagerState,
     request: BackgroundAgentRunRequest,
+) -> Result<BackgroundAgentRunResult, BackgroundAgentRunError> {
+    let pool = state.lock().await.db().pool().clone();
+    if request.live_timeline {
+        return run_agent_loop(pool, mcp_state, skill_state, request)
+            .await
+            .map_err(BackgroundAgentRunError::from);
+    }
+    run_agent_rounds(pool, mcp_state, skill_state, request, None).await
+}
+
+pub(crate) async fn run_agent_loop(
+    pool: SqlitePool,
+    mcp_state: McpState,
+    skill_state: SkillManagerState,
+    request: BackgroundAgentRunRequest,
+) -> Result<BackgroundAgentRunResult, String> {
+    if !request.live_timeline {
+        return run_agent_rounds(pool, mcp_state, skill_state, request, None)
+            .await
+            .map_err(|error| error.message);
+    }
+    let stop_flag = try_register_stream_stop_flag(&request.target_conversation_id)
+        .await
+        .ok_or_else(|| {
+            format!(
+                "Conversation {} is already running a turn; wait for it to finish",
+                request.target_conversation_id
+            )
+        })?;
+    run_live_agent_loop(pool, mcp_state, skill_state, request, stop_flag).await
+}
+
+/// Runs a live run whose stop flag the caller has already registered. A live run can be
+/// stopped from its conversation like a chat turn, and ends there even when it fails.
+pub(crate) async fn run_live_agent_loop(
+    pool: SqlitePool,
+    mcp_state: McpState,
+    skill_state: SkillManagerState,
+    request: BackgroundAgentRunRequest,
+    stop_flag: Arc<AtomicBool>,
 ) -> Result<BackgroundAgentRunResult, String> {
+    let conversation_id = request.target_conversation_id.clone();
+    emit_app_event("chat-start", json!({ "conversationId": conversation_id }));
+    let result = run_agent_rounds(pool, mcp_state, skill_state, request, Some(stop_flag))
+        .await
+        .map_err(|error| error.message);
+    clear_stream_stop_flag(&conversation_id).await;
+    emit_app_event("chat-end", json!({ "conversationId": conversation_id }));
+    result
+}
+
+/// Sub-agents read the conversation's plan but leave changing it to the run that delegated
+/// to them.
+const SUB_AGENT_PLAN_READ_ONLY: &str =
+    "Sub-agents cannot change the plan; include the changes you suggest in your result";
+
+/// Sandboxed runs, and the runs they start, run commands sandboxed.
+async fn run_agent_rounds(
+    pool: SqlitePool,
+    mcp_state: McpState,
+    skill_state: SkillManagerState,
+    request: BackgroundAgentRunRequest,
+    stop_flag: Option<Arc<AtomicBool>>,
+) -> Result<BackgroundAgentRunResult, BackgroundAgentRunError> {
+    let sandboxed = request.sandboxed;
+    let run = run_scoped_agent_rounds(pool, mcp_state, skill_state, request, stop_flag);
+    with_background_sandbox(sandboxed, run).await
+}
+
+async fn run_scoped_agent_rounds(
+    pool: SqlitePool,
+    mcp_state: McpState,
+    skill_state: SkillManagerState,
+    request: BackgroundAgentRunRequest,
+    stop_flag: Option<Arc<AtomicBool>>,
+) -> Result<BackgroundAgentRunResult, BackgroundAgentRunError> {
     let content = request.content.trim().to_string();
     if content.is_empty() {
-        return Err("Background run content cannot be empty".to_string());
+        return Err("Background run content cannot be empty".to_string().into());
+    }
+    if request.live_timeline && !request.persist_main_context {
+        return Err("Live runs must run in the conversation's main context"
+            .to_string()
+            .into());
     }
+    let live_app = if request.live_timeline {
+        Some(app_events_handle().ok_or_else(|| "App events are not initialized".to_string())?)
+    } else {
+        None
+    };
+    let stop_flag = stop_flag.unwrap_or_default();
 
     let config = crate::utils::load_config::<Config>().map_err(|e| e.to_string())?;
     let workspace_root = resolve_workspace_root(&config, request.workspace_directory.as_deref())?;
     let RuntimeToolCatalog {
-        available_tools,
+        mut available_tools,
         tool_map,
     } = build_runtime_tool_catalog(&mcp_state, &config, &workspace_root).await?;
+    if request.sub_agent.is_some() {
+        available_tools.retain(|tool| tool.function.name != TODO_WRITE_TOOL);
+    }
 
-    let pool = state.lock().await.db().pool().clone();
+    // File edits are checkpointed under the run's own turn, which is also its timeline turn
+    // when the run is live. Sub-agents keep the checkpoint turn of the run that started them.
+    let turn_id = Uuid::new_v4().to_string();
+    let mut seq: i64 = 0;
+    let _checkpoint_turn = request.sub_agent.is_none().then(|| {
+        if request.live_timeline {
+            enter_timeline_turn(&request.target_conversation_id, &turn_id)
+        } else {
+            enter_checkpoint_turn(&request.target_conversation_id, &turn_id)
+        }
+    });
+    let run_policy = request.run_policy();
 
     let model_to_use = if let Some(override_model) = request
         .model_override
@@ -699,28 +1065,111 @@ pub(crate) async fn run_agent_turn_background(
         config.model.clone()
     };
 
-    let llm_service = resolve_text_llm_service(&config, &model_to_use)?;
+    let mut llm_routes = if request.persist_main_context {
+        LlmRouteChain::for_conversation(
+            &config,
+            &pool,
+            &request.target_conversation_id,
+            &model_to_use,
+        )
+        .await?
+    } else {
+        LlmRouteChain::resolve(&config, &model_to_use, None)?
+    };
+
+    let tool_profile = match request.tool_profile.clone() {
+        Some(profile) => Some(profile),
+        None if request.persist_main_context => {
+            resolve_conversation_tool_profile(&pool, &request.target_conversation_id).await?
+        }
+        None => None,
+    };
+    let mut tool_selection = ToolSelection::new(
+        &config.tool_selection,
+        available_tools,
+        tool_profile.as_deref(),
+        &content,
+    );
 
     let mut context_messages = if request.persist_main_context {
-        load_conversation_context(&pool, &request.target_conversation_id).await?
+        prepare_conversation_context(
+            &pool,
+            llm_routes.active_service(),
+            &request.target_conversation_id,
+            &model_to_use,
+            &config.context_compaction,
+        )
+        .await?
     } else {
         Vec::new()
     };
     prepend_system_prompt(&mut context_messages, config.system_prompt.as_deref());
+    if request.use_memory {
+        if let Some(memory_prompt) = maybe_prepare_memory_prompt(
+            &pool,
+            &config,
+            &model_to_use,
+            &request.target_conversation_id,
+            &content,
+        )
+        .await
+        {
+            prepend_system_prompt(&mut context_messages, Some(memory_prompt.as_str()));
+        }
+    }
     prepend_tool_usage_guidance(&mut context_messages);
     let skills_guidance = build_skills_usage_guidance(&skill_state).await;
     prepend_skills_usage_guidance(&mut context_messages, &skills_guidance);
+    match request.sub_agent.as_ref() {
+        Some(run) => prepend_system_prompt(&mut context_messages, Some(run.prompt().as_str())),
+        None => {
+            prepend_todo_plan_guidance(
+                &pool,
+                &mut context_messages,
+                &request.target_conversation_id,
+            )
+            .await
+        }
+    }
 
-    if request.persist_main_context {
-        insert_message(
-            &pool,
-            &request.target_conversation_id,
-            "user",
-            &content,
-            None,
-            None,
+    let user_message_id = if request.persist_main_context {
+        Some(
+            insert_message(
+                &pool,
+                &request.target_conversation_id,
+                "user",
+                &content,
+                None,
+                None,
+            )
+            .await?,
         )
-        .await?;
+    } else {
+        None
+    };
+    if let Some(app) = live_app.as_ref() {
+        seq += 1;
+        let user_event = PendingTimelineEvent {
+            turn_id: turn_id.clone(),
+            seq,
+            event_type: TimelineEventType::UserMessage,
+            tool_call_id: None,
+            payload: json!({ "content": content, "messageId": user_message_id }),
+            created_at: Utc::now().to_rfc3339(),
+        };
+        insert_timeline_event(&pool, &request.target_conversation_id, &user_event).await?;
+        let _ = app.emit(
+            "chat-user-message",
+            json!({
+                "conversationId": request.target_conversation_id,
+                "turnId": turn_id,
+                "seq": seq,
+                "eventType": "user_message",
+                "createdAt": user_event.created_at,
+                "content": content,
+                "messageId": user_message_id
+            }),
+        );
     }
 
     context_messages.push(ChatMessage {
@@ -739,23 +1188,80 @@ pub(crate) async fn run_agent_turn_background(
     let mut total_tool_calls = 0usize;
     let mut blocked_tools = 0usize;
     let mut guard_stopped = false;
+    let mut max_rounds_reached = false;
+    let mut usage = LlmUsage::default();
+    let context_budget = resolve_context_budget(&config.context_compaction, &model_to_use);
+    let max_rounds = request.sub_agent.as_ref().map(|run| run.max_rounds);
 
     loop {
         rounds += 1;
-        let stream_result = llm_service
-            .chat_stream_with_tools(
-                &model_to_use,
-                context_messages.clone(),
-                if available_tools.is_empty() {
-                    None
-                } else {
-                    Some(available_tools.clone())
-                },
-                |_| {},
-                || false,
-            )
-            .await
-            .map_err(|e| e.to_string())?;
+        // A sub-agent's last round gets no tools, so it always ends with an answer.
+        let final_round = max_rounds.is_some_and(|max_rounds| rounds >= max_rounds);
+        if final_round && rounds > 1 {
+            max_rounds_reached = true;
+            context_messages.push(ChatMessage {
+                role: "user".to_string(),
+                content: Some(SUB_AGENT_FINAL_ROUND_TEXT.to_string()),
+                tool_calls: None,
+                tool_call_id: None,
+                reasoning_details: None,
+                reasoning: None,
+            });
+        }
+        if config.context_compaction.enabled {
+            fit_context_to_budget(&mut context_messages, &context_budget);
+        }
+        let available_tools = if final_round {
+            Vec::new()
+        } else {
+            tool_selection.available_tools()
+        };
+        let stream_result = match live_app.as_ref() {
+            Some(app) => {
+                let stream_round = run_stream_round(
+                    &mut llm_routes,
+                    app,
+                    &request.target_conversation_id,
+                    &turn_id,
+                    &mut seq,
+                    context_messages.clone(),
+                    &available_tools,
+                    stop_flag.clone(),
+                )
+                .await?;
+                insert_timeline_events(
+                    &pool,
+                    &request.target_conversation_id,
+                    &stream_round.timeline_events,
+                )
+                .await?;
+                stream_round.stream_result
+            }
+            None => llm_routes
+                .chat_stream_with_tools(
+                    context_messages.clone(),
+                    &available_tools,
+                    |_| {},
+                    || false,
+                )
+                .await
+                .map_err(|error| BackgroundAgentRunError {
+                    message: error.to_string(),
+                    transient: is_transient_llm_error(&error),
+                })?,
+        };
+        if let Some(round_usage) = stream_result.usage.as_ref() {
+            usage.add(round_usage);
+        }
+        record_usage_logged(
+            &pool,
+            Some(request.target_conversation_id.as_str()),
+            live_app.is_some().then_some(turn_id.as_str()),
+            request.usage_source,
+            llm_routes.active_model(),
+            stream_result.usage.as_ref(),
+        )
+        .await;
 
         let assistant_content = stream_result.content.clone();
         let assistant_reasoning = if stream_result.reasoning.trim().is_empty() {
@@ -768,6 +1274,69 @@ pub(crate) async fn run_agent_turn_background(
                 .as_deref()
                 .and_then(reasoning_details_from_text)
         });
+        if let Some(run) = request.sub_agent.as_ref() {
+            if !assistant_content.trim().is_empty() {
+                report_sub_agent_step(
+                    &pool,
+                    &request.target_conversation_id,
+                    run,
+                    "text",
+                    json!({ "round": rounds, "text": sub_agent_step_preview(&assistant_content) }),
+                )
+                .await;
+            }
+        }
+
+        if let (true, Some(app)) = (stream_result.cancelled, live_app.as_ref()) {
+            for tool_call in &stream_result.tool_calls {
+                let _ = emit_and_record_tool_result_event(
+                    &pool,
+                    app,
+                    &request.target_conversation_id,
+                    &turn_id,
+                    &mut seq,
+                    tool_call,
+                    None,
+                    Some("Paused by user"),
+                )
+                .await;
+            }
+            let paused_content = if assistant_content.trim().is_empty() {
+                emit_and_record_assistant_text(
+                    &pool,
+                    app,
+                    &request.target_conversation_id,
+                    &turn_id,
+                    &mut seq,
+                    STREAM_PAUSED_TEXT,
+                )
+                .await?;
+                STREAM_PAUSED_TEXT.to_string()
+            } else {
+                assistant_content
+            };
+            insert_message(
+                &pool,
+                &request.target_conversation_id,
+                "assistant",
+                &paused_content,
+                None,
+                assistant_reasoning.clone(),
+            )
+            .await?;
+            return Ok(BackgroundAgentRunResult {
+                content: paused_content,
+                reasoning: assistant_reasoning,
+                rounds,
+                tool_calls: total_tool_calls,
+                blocked_tools,
+                guard_stopped,
+                max_rounds_reached,
+                stopped: true,
+                usage,
+                model: llm_routes.active_model().to_string(),
+            });
+        }
 
         if stream_result.tool_calls.is_empty() {
             if request.persist_main_context {
@@ -789,6 +1358,10 @@ pub(crate) async fn run_agent_turn_background(
                 tool_calls: total_tool_calls,
                 blocked_tools,
                 guard_stopped,
+                max_rounds_reached,
+                stopped: false,
+                usage,
+                model: llm_routes.active_model().to_string(),
             });
         }
 
@@ -829,6 +1402,17 @@ pub(crate) async fn run_agent_turn_background(
         if repeated_signature_rounds >= 2 {
             guard_stopped = true;
             let guard_text = REPEATED_TOOL_GUARD_TEXT.to_string();
+            if let Some(app) = live_app.as_ref() {
+                emit_and_record_assistant_text(
+                    &pool,
+                    app,
+                    &request.target_conversation_id,
+                    &turn_id,
+                    &mut seq,
+                    &guard_text,
+                )
+                .await?;
+            }
             if request.persist_main_context {
                 insert_message(
                     &pool,
@@ -847,103 +1431,180 @@ pub(crate) async fn run_agent_turn_background(
                 tool_calls: total_tool_calls,
                 blocked_tools,
                 guard_stopped,
+                max_rounds_reached,
+                stopped: false,
+                usage,
+                model: llm_routes.active_model().to_string(),
             });
         }
 
+        let mut paused = false;
         for tool_call in stream_result.tool_calls {
             let parsed_arguments = parse_tool_arguments(&tool_call.function.arguments);
-            let decision = resolve_background_tool_execution_decision(
-                &config,
-                &tool_call,
-                &parsed_arguments,
-                &always_allowed_tools,
-                request.tool_whitelist.as_ref(),
-            );
-
-            if decision == ToolApprovalDecision::Deny {
-                blocked_tools += 1;
-                let error_text = format!(
-                    "Background scheduler denied tool '{}' by whitelist/policy",
-                    tool_call.function.name
-                );
-                let result_text = format_tool_error_result(&error_text)?;
-                if request.persist_main_context {
-                    persist_tool_result_message(
-                        &pool,
-                        &request.target_conversation_id,
-                        &mut context_messages,
-                        &tool_call,
-                        result_text,
-                    )
-                    .await?;
-                } else {
-                    push_background_tool_result_message(
-                        &mut context_messages,
-                        &tool_call,
-                        result_text,
-                    );
-                }
-                continue;
+            if let Some(run) = request.sub_agent.as_ref() {
+                report_sub_agent_step(
+                    &pool,
+                    &request.target_conversation_id,
+                    run,
+                    "tool_call",
+                    json!({
+                        "name": tool_call.function.name,
+                        "arguments": sub_agent_step_preview(&tool_call.function.arguments)
+                    }),
+                )
+                .await;
             }
 
-            always_allowed_tools.insert(tool_call.function.name.clone());
-
-            let tool_result = execute_tool_call_background(
-                &mcp_state,
-                &skill_state,
-                &config,
-                &tool_map,
-                &tool_call,
-                &workspace_root,
-                &request.target_conversation_id,
-                &pool,
-                &llm_service,
-                &model_to_use,
-            )
-            .await;
-
-            match tool_result {
-                Ok(value) => {
-                    let result_text =
-                        serde_json::to_string_pretty(&value).unwrap_or_else(|_| value.to_string());
-                    if request.persist_main_context {
-                        persist_tool_result_message(
-                            &pool,
+            paused = paused || stop_flag.load(Ordering::Relaxed);
+            let outcome = if paused {
+                Err("Paused by user".to_string())
+            } else if let Err(error) = tool_selection.ensure_allowed(&tool_call.function.name) {
+                Err(error)
+            } else if tool_call.function.name == TOOL_SEARCH_TOOL {
+                tool_selection.search(&parsed_arguments)
+            } else if request.sub_agent.is_some() && tool_call.function.name == TODO_WRITE_TOOL {
+                Err(SUB_AGENT_PLAN_READ_ONLY.to_string())
+            } else {
+                let resolution = match resolve_background_tool_execution_decision(
+                    &config,
+                    &tool_call,
+                    &parsed_arguments,
+                    &always_allowed_tools,
+                    request.tool_whitelist.as_ref(),
+                ) {
+                    Some(decision) => ToolApprovalResolution {
+                        decision,
+                        response: None,
+                    },
+                    // A sub-agent works on the user's turn in its parent conversation, so
+                    // the user is asked there.
+                    None => match (request.sub_agent.as_ref(), app_events_handle()) {
+                        (Some(_), Some(app)) => request_tool_approval(
+                            &app,
                             &request.target_conversation_id,
-                            &mut context_messages,
                             &tool_call,
-                            result_text,
+                            &parsed_arguments,
+                            &workspace_root,
                         )
-                        .await?;
-                    } else {
-                        push_background_tool_result_message(
-                            &mut context_messages,
-                            &tool_call,
-                            result_text,
-                        );
+                        .await
+                        .unwrap_or(ToolApprovalResolution {
+                            decision: ToolApprovalDecision::Deny,
+                            response: None,
+                        }),
+                        _ => ToolApprovalResolution {
+                            decision: ToolApprovalDecision::Deny,
+                            response: None,
+                        },
+                    },
+                };
+                match resolution.decision {
+                    ToolApprovalDecision::Deny => {
+                        blocked_tools += 1;
+                        Err(if request.sub_agent.is_some() {
+                            format!(
+                                "Sub-agent tool '{}' was denied by the user or the tool policy",
+                                tool_call.function.name
+                            )
+                        } else {
+                            format!(
+                                "Background scheduler denied tool '{}' by whitelist/policy",
+                                tool_call.function.name
+                            )
+                        })
                     }
-                }
-                Err(error_text) => {
-                    let result_text = format_tool_error_result(&error_text)?;
-                    if request.persist_main_context {
-                        persist_tool_result_message(
-                            &pool,
-                            &request.target_conversation_id,
-                            &mut context_messages,
+                    decision => {
+                        if decision == ToolApprovalDecision::AllowAlways {
+                            always_allowed_tools.insert(tool_call.function.name.clone());
+                        }
+                        execute_tool_call_background(
+                            &mcp_state,
+                            &skill_state,
+                            &config,
+                            &tool_map,
                             &tool_call,
-                            result_text,
+                            &read_hunk_selection(resolution.response.as_ref()),
+                            &run_policy,
+                            &workspace_root,
+                            &request.target_conversation_id,
+                            &pool,
+                            llm_routes.active_service(),
+                            llm_routes.active_model(),
                         )
-                        .await?;
-                    } else {
-                        push_background_tool_result_message(
-                            &mut context_messages,
-                            &tool_call,
-                            result_text,
-                        );
+                        .await
                     }
                 }
+            };
+
+            if let Some(run) = request.sub_agent.as_ref() {
+                let (is_error, preview) = match &outcome {
+                    Ok(value) => (false, sub_agent_step_preview(&value.to_string())),
+                    Err(error_text) => (true, sub_agent_step_preview(error_text)),
+                };
+                report_sub_agent_step(
+                    &pool,
+                    &request.target_conversation_id,
+                    run,
+                    "tool_result",
+                    json!({
+                        "name": tool_call.function.name,
+                        "isError": is_error,
+                        "preview": preview
+                    }),
+                )
+                .await;
+            }
+
+            let outcome = outcome.map(|value| {
+                serde_json::to_string_pretty(&value).unwrap_or_else(|_| value.to_string())
+            });
+            if let Some(app) = live_app.as_ref() {
+                record_tool_call_outcome(
+                    &pool,
+                    app,
+                    &request.target_conversation_id,
+                    &turn_id,
+                    &mut seq,
+                    &mut context_messages,
+                    &tool_call,
+                    outcome,
+                )
+                .await?;
+                continue;
+            }
+            // The run's own turn never reaches the timeline, so a plan version it writes is
+            // only stored; `todo-plan-updated` still brings it to the plan panel.
+            let result_text = match outcome {
+                Ok(result_text) => result_text,
+                Err(error_text) => format_tool_error_result(&error_text)?,
+            };
+            if request.persist_main_context {
+                persist_tool_result_message(
+                    &pool,
+                    &request.target_conversation_id,
+                    &mut context_messages,
+                    &tool_call,
+                    result_text,
+                )
+                .await?;
+            } else {
+                push_background_tool_result_message(&mut context_messages, &tool_call, result_text);
             }
         }
+
+        if paused {
+            return Ok(BackgroundAgentRunResult {
+                content: STREAM_PAUSED_TEXT.to_string(),
+                reasoning: None,
+                rounds,
+                tool_calls: total_tool_calls,
+                blocked_tools,
+                guard_stopped,
+                max_rounds_reached,
+                stopped: true,
+                usage,
+                model: llm_routes.active_model().to_string(),
+            });
+        }
     }
 }
 
@@ -1160,6 +1821,7 @@ pub(crate) fn should_auto_allow_batch_tool(tool_name: &str) -> bool {
             | WORKSPACE_GREP_TOOL
             | WORKSPACE_CODESEARCH_TOOL
             | WORKSPACE_LSP_SYMBOLS_TOOL
+            | WORKSPACE_SEMANTIC_SEARCH_TOOL
             | TODO_READ_TOOL
             | TODO_WRITE_TOOL
             | WEB_FETCH_TOOL
@@ -1168,6 +1830,7 @@ pub(crate) fn should_auto_allow_batch_tool(tool_name: &str) -> bool {
             | IMAGE_UNDERSTAND_TOOL
             | SESSIONS_LIST_TOOL
             | SESSIONS_HISTORY_TOOL
+            | SESSIONS_RUN_STATUS_TOOL
             | AGENTS_LIST_TOOL
             | SKILL_DISCOVER_TOOL
             | SKILL_LIST_TOOL
@@ -1224,13 +1887,47 @@ pub(crate) fn is_forbidden_loopback_host(url: &reqwest::Url) -> bool {
 }
 
 
-async fn request_tool_approval(
-    window: &Window,
+async fn request_tool_approval<R: tauri::Runtime>(
+    emitter: &impl Emitter<R>,
     conversation_id: &str,
     tool_call: &ChatToolCall,
-) -> Result<ToolApprovalDecision, String> {
+    parsed_arguments: &Value,
+    workspace_root: &Path,
+) -> Result<ToolApprovalResolution, String> {
+    // A preview that cannot be computed (missing file, stale patch) just falls back to the
+    // raw arguments; the tool reports the real error when it runs.
+    let file_changes =
+        preview_file_changes(&tool_call.function.name, parsed_arguments, workspace_root)
+            .unwrap_or_else(|error| {
+                eprintln!(
+                    "[tool-approval] diff preview for {} failed: {}",
+                    tool_call.function.name, error
+                );
+                Vec::new()
+            });
+    request_approval(
+        emitter,
+        conversation_id,
+        &tool_call.id,
+        &tool_call.function.name,
+        tool_call.function.arguments.clone(),
+        file_changes,
+    )
+    .await
+}
+
+/// Emits `chat-tool-approval-request` and waits for `resolve_tool_approval`. Anything that
+/// needs the user's go-ahead (tool calls, MCP sampling/elicitation) goes through here.
+pub(crate) async fn request_approval<R: tauri::Runtime>(
+    emitter: &impl Emitter<R>,
+    conversation_id: &str,
+    tool_call_id: &str,
+    tool_name: &str,
+    arguments: String,
+    file_changes: Vec<FileChangePreview>,
+) -> Result<ToolApprovalResolution, String> {
     let request_id = Uuid::new_v4().to_string();
-    let (tx, rx) = oneshot::channel::<ToolApprovalDecision>();
+    let (tx, rx) = oneshot::channel::<ToolApprovalResolution>();
 
     {
         let mut waiters = tool_approval_waiters().lock().await;
@@ -1240,12 +1937,13 @@ async fn request_tool_approval(
     let payload = ToolApprovalRequestPayload {
         request_id: request_id.clone(),
         conversation_id: conversation_id.to_string(),
-        tool_call_id: tool_call.id.clone(),
-        tool_name: tool_call.function.name.clone(),
-        arguments: tool_call.function.arguments.clone(),
+        tool_call_id: tool_call_id.to_string(),
+        tool_name: tool_name.to_string(),
+        arguments,
+        file_changes,
     };
 
-    if let Err(error) = window.emit("chat-tool-approval-request", payload) {
+    if let Err(error) = emitter.emit("chat-tool-approval-request", payload) {
         let mut waiters = tool_approval_waiters().lock().await;
         waiters.remove(&request_id);
         return Err(error.to_string());
@@ -1260,8 +1958,10 @@ async fn request_tool_approval(
 
     Ok(match decision {
         Ok(Ok(value)) => value,
-        Ok(Err(_)) => ToolApprovalDecision::Deny,
-        Err(_) => ToolApprovalDecision::Deny,
+        Ok(Err(_)) | Err(_) => ToolApprovalResolution {
+            decision: ToolApprovalDecision::Deny,
+            response: None,
+        },
     })
 }
 
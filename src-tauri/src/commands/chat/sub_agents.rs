@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Mutex, OnceLock};
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::commands::mcp::McpState;
+use crate::commands::skills::SkillManagerState;
+use crate::models::chat::TimelineEventType;
+use crate::models::config::{AgentDefinitionConfig, Config};
+use crate::services::usage::UsageSource;
+
+use super::checkpoints::current_timeline_turn;
+use super::stream::{
+    run_agent_loop, BackgroundAgentRunRequest, BackgroundAgentRunResult, RunPolicy,
+};
+use super::{
+    emit_app_event, insert_timeline_event, next_turn_seq, read_optional_string_argument,
+    read_string_argument, read_u64_argument, resolve_workspace_target, PendingTimelineEvent,
+};
+
+const DEFAULT_AGENT_ID: &str = "default";
+/// Sub-agents running at once in one conversation, which also bounds how deep delegation
+/// can nest.
+const MAX_ACTIVE_SUB_AGENTS: usize = 3;
+const STEP_PREVIEW_CHARS: usize = 600;
+
+const BUILTIN_AGENTS: &[(&str, &str, &str, Option<&str>, &str)] = &[
+    (
+        DEFAULT_AGENT_ID,
+        "Default Agent",
+        "General-purpose coding and assistant workflow.",
+        None,
+        "Work through the task with the tools you have and finish with a concise report of what you did and what you found.",
+    ),
+    (
+        "planner",
+        "Planner Agent",
+        "Task decomposition and structured planning.",
+        Some("research"),
+        "Investigate only as much as the plan needs, then reply with ordered, concrete steps and the files, commands or sources each step involves. Do not carry out the plan.",
+    ),
+    (
+        "analyst",
+        "Analyst Agent",
+        "Investigation and comparative reasoning.",
+        Some("research"),
+        "Gather evidence with the available tools, compare the alternatives, and reply with your findings, the evidence behind each one and any open questions.",
+    ),
+];
+
+/// A delegated run inside another agent's loop. Its steps are reported to the parent
+/// conversation instead of being stored as messages.
+#[derive(Debug, Clone)]
+pub(crate) struct SubAgentRun {
+    pub run_id: String,
+    pub agent_id: String,
+    pub agent_name: String,
+    pub system_prompt: String,
+    pub max_rounds: usize,
+    /// Timeline turn of the delegating run, when it has one; nested steps are recorded there.
+    pub parent_turn_id: Option<String>,
+}
+
+impl SubAgentRun {
+    pub(crate) fn prompt(&self) -> String {
+        format!(
+            "You are the sub-agent '{}', working on a task delegated by another agent. Nobody can answer questions during the run, so work with what you have. Your final reply is all the delegating agent receives: make it a self-contained result.\n\n{}",
+            self.agent_name,
+            self.system_prompt.trim()
+        )
+        .trim_end()
+        .to_string()
+    }
+}
+
+/// Built-in agents, with configured agents replacing them by id or added after them.
+pub(crate) fn list_agent_definitions(config: &Config) -> Vec<AgentDefinitionConfig> {
+    let mut agents = BUILTIN_AGENTS
+        .iter()
+        .map(
+            |(id, name, description, tool_profile, system_prompt)| AgentDefinitionConfig {
+                id: id.to_string(),
+                name: name.to_string(),
+                description: description.to_string(),
+                system_prompt: system_prompt.to_string(),
+                tool_profile: tool_profile.map(str::to_string),
+                ..Default::default()
+            },
+        )
+        .collect::<Vec<_>>();
+
+    for custom in &config.agents {
+        let id = custom.id.trim();
+        if id.is_empty() {
+            continue;
+        }
+        let custom = AgentDefinitionConfig {
+            id: id.to_string(),
+            ..custom.clone()
+        };
+        match agents.iter_mut().find(|agent| agent.id == custom.id) {
+            Some(existing) => *existing = custom,
+            None => agents.push(custom),
+        }
+    }
+    agents
+}
+
+pub(crate) fn execute_agents_list(config: &Config) -> Result<Value, String> {
+    Ok(json!({ "agents": list_agent_definitions(config) }))
+}
+
+fn active_sub_agents() -> &'static Mutex<HashMap<String, usize>> {
+    static ACTIVE: OnceLock<Mutex<HashMap<String, usize>>> = OnceLock::new();
+    ACTIVE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+struct SubAgentSlot {
+    conversation_id: String,
+}
+
+impl Drop for SubAgentSlot {
+    fn drop(&mut self) {
+        if let Ok(mut active) = active_sub_agents().lock() {
+            if let Some(count) = active.get_mut(&self.conversation_id) {
+                *count -= 1;
+                if *count == 0 {
+                    active.remove(&self.conversation_id);
+                }
+            }
+        }
+    }
+}
+
+fn acquire_sub_agent_slot(conversation_id: &str) -> Result<SubAgentSlot, String> {
+    let mut active = active_sub_agents().lock().map_err(|e| e.to_string())?;
+    let count = active.entry(conversation_id.to_string()).or_insert(0);
+    if *count >= MAX_ACTIVE_SUB_AGENTS {
+        return Err(format!(
+            "At most {} sub-agents can run at once in a conversation; do this step directly instead",
+            MAX_ACTIVE_SUB_AGENTS
+        ));
+    }
+    *count += 1;
+    Ok(SubAgentSlot {
+        conversation_id: conversation_id.to_string(),
+    })
+}
+
+pub(crate) fn sub_agent_step_preview(text: &str) -> String {
+    let mut chars = text.chars();
+    let preview = chars.by_ref().take(STEP_PREVIEW_CHARS).collect::<String>();
+    if chars.next().is_some() {
+        format!("{}…", preview)
+    } else {
+        preview
+    }
+}
+
+/// Streams one step of a sub-agent as `sub-agent-progress` and records it under the
+/// parent's timeline turn, numbered after the events already recorded there.
+pub(crate) async fn report_sub_agent_step(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    run: &SubAgentRun,
+    kind: &str,
+    detail: Value,
+) {
+    let seq = match run.parent_turn_id.as_deref() {
+        Some(turn_id) => match next_turn_seq(pool, conversation_id, turn_id).await {
+            Ok(seq) => seq,
+            Err(error) => {
+                eprintln!(
+                    "[sub-agent] record step failed for conversation {}: {}",
+                    conversation_id, error
+                );
+                return;
+            }
+        },
+        None => 0,
+    };
+    let created_at = Utc::now().to_rfc3339();
+    let mut payload = json!({
+        "runId": run.run_id,
+        "agentId": run.agent_id,
+        "agentName": run.agent_name,
+        "kind": kind
+    });
+    if let (Some(payload), Value::Object(detail)) = (payload.as_object_mut(), detail) {
+        payload.extend(detail);
+    }
+    emit_app_event(
+        "sub-agent-progress",
+        json!({
+            "conversationId": conversation_id,
+            "turnId": run.parent_turn_id,
+            "seq": seq,
+            "eventType": "sub_agent_step",
+            "createdAt": created_at,
+            "payload": payload
+        }),
+    );
+
+    let Some(turn_id) = run.parent_turn_id.clone() else {
+        return;
+    };
+    let event = PendingTimelineEvent {
+        turn_id,
+        seq,
+        event_type: TimelineEventType::SubAgentStep,
+        tool_call_id: None,
+        payload,
+        created_at,
+    };
+    if let Err(error) = insert_timeline_event(pool, conversation_id, &event).await {
+        eprintln!(
+            "[sub-agent] record step failed for conversation {}: {}",
+            conversation_id, error
+        );
+    }
+}
+
+/// The agent's configured directory, which may not lead it out of the delegating workspace.
+fn resolve_agent_workspace(
+    agent: &AgentDefinitionConfig,
+    workspace_root: &Path,
+) -> Result<PathBuf, String> {
+    let Some(directory) = agent
+        .workspace_directory
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+    else {
+        return Ok(workspace_root.to_path_buf());
+    };
+    resolve_workspace_target(workspace_root, directory, false).map_err(|error| {
+        format!(
+            "Agent '{}' has an invalid workspace directory '{}': {}",
+            agent.id, directory, error
+        )
+    })
+}
+
+/// Boxed because the sub-agent's own tools include core_task.
+fn run_sub_agent_loop(
+    pool: SqlitePool,
+    mcp_state: McpState,
+    skill_state: SkillManagerState,
+    request: BackgroundAgentRunRequest,
+) -> Pin<Box<dyn Future<Output = Result<BackgroundAgentRunResult, String>> + Send>> {
+    Box::pin(run_agent_loop(pool, mcp_state, skill_state, request))
+}
+
+pub(crate) async fn execute_core_task(
+    arguments: &Value,
+    mcp_state: &McpState,
+    skill_manager_state: &SkillManagerState,
+    config: &Config,
+    run_policy: &RunPolicy,
+    workspace_root: &Path,
+    conversation_id: &str,
+    pool: &SqlitePool,
+    default_model: &str,
+) -> Result<Value, String> {
+    let prompt = read_string_argument(arguments, "prompt")?;
+    let agent_id = read_optional_string_argument(arguments, "agent")
+        .unwrap_or_else(|| DEFAULT_AGENT_ID.to_string());
+    let agent = list_agent_definitions(config)
+        .into_iter()
+        .find(|agent| agent.id == agent_id)
+        .ok_or_else(|| {
+            format!(
+                "Unknown agent '{}'. Call agents_list to see the available agents.",
+                agent_id
+            )
+        })?;
+    let model = read_optional_string_argument(arguments, "model")
+        .or_else(|| {
+            agent
+                .model
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| default_model.to_string());
+    let agent_max_rounds = u64::from(agent.max_rounds.max(1));
+    let max_rounds =
+        read_u64_argument(arguments, "max_rounds", agent_max_rounds).clamp(1, agent_max_rounds);
+    let workspace_directory = resolve_agent_workspace(&agent, workspace_root)?;
+
+    let _slot = acquire_sub_agent_slot(conversation_id)?;
+    let run = SubAgentRun {
+        run_id: Uuid::new_v4().to_string(),
+        agent_id: agent.id.clone(),
+        agent_name: if agent.name.trim().is_empty() {
+            agent.id.clone()
+        } else {
+            agent.name.clone()
+        },
+        system_prompt: agent.system_prompt.clone(),
+        max_rounds: max_rounds as usize,
+        parent_turn_id: current_timeline_turn(conversation_id),
+    };
+    let request = BackgroundAgentRunRequest {
+        target_conversation_id: conversation_id.to_string(),
+        content: prompt,
+        workspace_directory: Some(workspace_directory.to_string_lossy().to_string()),
+        model_override: Some(model),
+        persist_main_context: false,
+        tool_whitelist: run_policy.tool_whitelist.clone(),
+        sandboxed: run_policy.sandboxed,
+        tool_profile: agent.tool_profile.clone(),
+        usage_source: UsageSource::SubAgent,
+        sub_agent: Some(run.clone()),
+        live_timeline: false,
+        use_memory: false,
+    };
+    let result = run_sub_agent_loop(
+        pool.clone(),
+        mcp_state.clone(),
+        skill_manager_state.clone(),
+        request,
+    )
+    .await?;
+
+    let status = if result.guard_stopped {
+        "stopped_repeating"
+    } else if result.max_rounds_reached {
+        "max_rounds"
+    } else {
+        "completed"
+    };
+    Ok(json!({
+        "run_id": run.run_id,
+        "agent": run.agent_id,
+        "model": result.model,
+        "status": status,
+        "result": result.content,
+        "rounds": result.rounds,
+        "tool_calls": result.tool_calls,
+        "blocked_tools": result.blocked_tools,
+        "usage": result.usage
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_agents_override_builtins_and_slots_are_bounded() {
+        let mut config = Config::default();
+        config.agents = vec![
+            AgentDefinitionConfig {
+                id: " planner ".to_string(),
+                name: "Release Planner".to_string(),
+                max_rounds: 4,
+                ..Default::default()
+            },
+            AgentDefinitionConfig {
+                id: "reviewer".to_string(),
+                tool_profile: Some("coding".to_string()),
+                ..Default::default()
+            },
+        ];
+        let agents = list_agent_definitions(&config);
+        let ids = agents
+            .iter()
+            .map(|agent| agent.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["default", "planner", "analyst", "reviewer"]);
+        assert_eq!(agents[1].name, "Release Planner");
+        assert_eq!(agents[1].max_rounds, 4);
+        assert_eq!(agents[3].max_rounds, 12);
+
+        let slots = (0..MAX_ACTIVE_SUB_AGENTS)
+            .map(|_| acquire_sub_agent_slot("conversation-a").unwrap())
+            .collect::<Vec<_>>();
+        assert!(acquire_sub_agent_slot("conversation-a").is_err());
+        assert!(acquire_sub_agent_slot("conversation-b").is_ok());
+        drop(slots);
+        assert!(acquire_sub_agent_slot("conversation-a").is_ok());
+    }
+
+    #[test]
+    fn agent_workspace_directories_stay_inside_the_workspace() {
+        let root = std::env::temp_dir().join(format!("petool-agent-workspace-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("workspace/docs")).unwrap();
+        let workspace_root = root.join("workspace");
+        let agent = |directory: &str| AgentDefinitionConfig {
+            id: "docs".to_string(),
+            workspace_directory: Some(directory.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            resolve_agent_workspace(&agent("docs"), &workspace_root).unwrap(),
+            workspace_root.join("docs").canonicalize().unwrap()
+        );
+        assert_eq!(
+            resolve_agent_workspace(&agent(" "), &workspace_root).unwrap(),
+            workspace_root
+        );
+        assert!(resolve_agent_workspace(&agent(".."), &workspace_root).is_err());
+        assert!(resolve_agent_workspace(&agent("docs/../.."), &workspace_root).is_err());
+        let outside = root.to_string_lossy().to_string();
+        assert!(resolve_agent_workspace(&agent(&outside), &workspace_root).is_err());
+        assert!(resolve_agent_workspace(&agent("missing"), &workspace_root).is_err());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -3,10 +3,16 @@ use std::path::{Path, PathBuf};
 
 use serde_json::{json, Value};
 use crate::commands::mcp::McpState;
-use crate::models::config::{Config, McpTransport};
-use crate::services::mcp_client::{HttpTransport, McpClient, StdioTransport};
+use crate::models::config::Config;
+use crate::models::mcp::McpServerState;
+use crate::services::mcp_client::connect_client;
 use crate::services::llm::{ChatTool, ChatToolFunction};
 
+pub(crate) const MCP_RESOURCES_LIST_TOOL: &str = "mcp_resources_list";
+pub(crate) const MCP_RESOURCE_READ_TOOL: &str = "mcp_resource_read";
+pub(crate) const MCP_RESOURCE_SUBSCRIBE_TOOL: &str = "mcp_resource_subscribe";
+pub(crate) const MCP_PROMPTS_LIST_TOOL: &str = "mcp_prompts_list";
+pub(crate) const MCP_PROMPT_GET_TOOL: &str = "mcp_prompt_get";
 pub(crate) const WORKSPACE_LIST_TOOL: &str = "workspace_list_directory";
 pub(crate) const WORKSPACE_READ_TOOL: &str = "workspace_read_file";
 pub(crate) const WORKSPACE_WRITE_TOOL: &str = "workspace_write_file";
@@ -15,10 +21,13 @@ pub(crate) const WORKSPACE_GLOB_TOOL: &str = "workspace_search_glob";
 pub(crate) const WORKSPACE_GREP_TOOL: &str = "workspace_search_grep";
 pub(crate) const WORKSPACE_CODESEARCH_TOOL: &str = "workspace_search_code";
 pub(crate) const WORKSPACE_LSP_SYMBOLS_TOOL: &str = "workspace_lsp_symbols";
+pub(crate) const WORKSPACE_SEMANTIC_SEARCH_TOOL: &str = "workspace_semantic_search";
 pub(crate) const WORKSPACE_APPLY_PATCH_TOOL: &str = "workspace_apply_patch";
+pub(crate) const WORKSPACE_CHECKPOINT_TOOL: &str = "workspace_checkpoint";
 pub(crate) const WORKSPACE_PROCESS_START_TOOL: &str = "workspace_process_start";
 pub(crate) const WORKSPACE_PROCESS_LIST_TOOL: &str = "workspace_process_list";
 pub(crate) const WORKSPACE_PROCESS_READ_TOOL: &str = "workspace_process_read";
+pub(crate) const WORKSPACE_PROCESS_WRITE_TOOL: &str = "workspace_process_write";
 pub(crate) const WORKSPACE_PROCESS_TERMINATE_TOOL: &str = "workspace_process_terminate";
 pub(crate) const WORKSPACE_RUN_TOOL: &str = "bash";
 pub(crate) const WORKSPACE_PARSE_PDF_TOOL: &str = "workspace_parse_pdf_markdown";
@@ -42,6 +51,7 @@ pub(crate) const SESSIONS_LIST_TOOL: &str = "sessions_list";
 pub(crate) const SESSIONS_HISTORY_TOOL: &str = "sessions_history";
 pub(crate) const SESSIONS_SEND_TOOL: &str = "sessions_send";
 pub(crate) const SESSIONS_SPAWN_TOOL: &str = "sessions_spawn";
+pub(crate) const SESSIONS_RUN_STATUS_TOOL: &str = "sessions_run_status";
 pub(crate) const AGENTS_LIST_TOOL: &str = "agents_list";
 pub(crate) const SCHEDULER_JOBS_LIST_TOOL: &str = "scheduler_jobs_list";
 pub(crate) const SCHEDULER_JOB_CREATE_TOOL: &str = "scheduler_job_create";
@@ -49,6 +59,7 @@ pub(crate) const SCHEDULER_JOB_UPDATE_TOOL: &str = "scheduler_job_update";
 pub(crate) const SCHEDULER_JOB_DELETE_TOOL: &str = "scheduler_job_delete";
 pub(crate) const SCHEDULER_JOB_RUN_TOOL: &str = "scheduler_job_run";
 pub(crate) const SCHEDULER_RUNS_LIST_TOOL: &str = "scheduler_runs_list";
+pub(crate) const TOOL_SEARCH_TOOL: &str = "tool_search";
 
 #[derive(Debug, Clone)]
 pub(crate) enum RuntimeTool {
@@ -56,6 +67,11 @@ pub(crate) enum RuntimeTool {
         server_name: String,
         tool_name: String,
     },
+    McpResourcesList,
+    McpResourceRead,
+    McpResourceSubscribe,
+    McpPromptsList,
+    McpPromptGet,
     WorkspaceListDirectory,
     WorkspaceReadFile,
     WorkspaceWriteFile,
@@ -64,12 +80,15 @@ pub(crate) enum RuntimeTool {
     WorkspaceGrep,
     WorkspaceCodeSearch,
     WorkspaceLspSymbols,
+    WorkspaceSemanticSearch,
     WorkspaceApplyPatch,
+    WorkspaceCheckpoint,
     WorkspaceRunCommand,
     WorkspaceParsePdfMarkdown,
     WorkspaceProcessStart,
     WorkspaceProcessList,
     WorkspaceProcessRead,
+    WorkspaceProcessWrite,
     WorkspaceProcessTerminate,
     SkillDiscover,
     SkillInstallFromRepo,
@@ -91,6 +110,7 @@ pub(crate) enum RuntimeTool {
     SessionsHistory,
     SessionsSend,
     SessionsSpawn,
+    SessionsRunStatus,
     AgentsList,
     SchedulerJobsList,
     SchedulerJobCreate,
@@ -201,6 +221,8 @@ pub(crate) fn parse_tool_arguments(raw: &str) -> Value {
     })
 }
 
+/// Connects enabled servers that are not running yet. A server that fails is handed to the
+/// supervisor for retries instead of failing the turn; its tools are simply missing.
 pub(crate) async fn ensure_mcp_servers_connected(mcp_state: &McpState, config: &Config) -> Result<(), String> {
     let mut manager = mcp_state.lock().await;
 
@@ -208,23 +230,27 @@ pub(crate) async fn ensure_mcp_servers_connected(mcp_state: &McpState, config: &
         if manager.get_client(&server.name).is_some() {
             continue;
         }
-
-        let transport: Box<dyn crate::services::mcp_client::McpTransport> = match &server.transport
+        if manager
+            .server_status(&server.name)
+            .is_some_and(|status| status.state == McpServerState::Restarting)
         {
-            McpTransport::Stdio { command, args } => {
-                Box::new(StdioTransport::new(command, args).map_err(|e| e.to_string())?)
+            continue;
+        }
+
+        let connected =
+            connect_client(&server.name, &server.transport, manager.message_handler()).await;
+        let attached = match connected {
+            Ok(client) => {
+                manager
+                    .attach_client(server.name.clone(), server.transport.clone(), client)
+                    .await
             }
-            McpTransport::Http { url } => Box::new(HttpTransport::new(url.clone())),
+            Err(error) => Err(error),
         };
-
-        let client = McpClient::new(server.name.clone(), transport)
-            .await
-            .map_err(|e| e.to_string())?;
-
-        manager
-            .add_client(server.name.clone(), client)
-            .await
-            .map_err(|e| e.to_string())?;
+        if let Err(error) = attached {
+            eprintln!("[mcp] {} failed to connect: {}", server.name, error);
+            manager.track_failed_connect(&server.name, server.transport.clone(), &error);
+        }
     }
 
     Ok(())
@@ -234,8 +260,21 @@ pub(crate) async fn collect_mcp_tools(mcp_state: &McpState) -> (Vec<ChatTool>, H
     let manager = mcp_state.lock().await;
     let mut tools = Vec::new();
     let mut tool_map = HashMap::new();
+    let mut resource_servers = Vec::new();
+    let mut subscribe_servers = Vec::new();
+    let mut prompt_servers = Vec::new();
 
     for (server_name, client) in manager.list_clients() {
+        if client.capabilities.resources == Some(true) {
+            resource_servers.push(server_name.clone());
+            if client.capabilities.resource_subscribe == Some(true) {
+                subscribe_servers.push(server_name.clone());
+            }
+        }
+        if !client.prompts.is_empty() {
+            prompt_servers.push(server_name.clone());
+        }
+
         for tool in client.list_tools() {
             let mut alias = build_tool_alias(&server_name, &tool.name);
             let mut collision_index = 1usize;
@@ -281,6 +320,111 @@ pub(crate) async fn collect_mcp_tools(mcp_state: &McpState) -> (Vec<ChatTool>, H
         }
     }
 
+    if !resource_servers.is_empty() {
+        resource_servers.sort();
+        register_runtime_tool(
+            &mut tools,
+            &mut tool_map,
+            MCP_RESOURCES_LIST_TOOL,
+            format!(
+                "List resources and resource templates exposed by connected MCP servers ({}). \
+                 Templates are RFC 6570 URI templates; expand them yourself and pass the URI to {}.",
+                resource_servers.join(", "),
+                MCP_RESOURCE_READ_TOOL
+            ),
+            json!({
+                "type": "object",
+                "properties": {
+                    "server": { "type": "string", "description": "Only list this server's resources." }
+                }
+            }),
+            RuntimeTool::McpResourcesList,
+        );
+        register_runtime_tool(
+            &mut tools,
+            &mut tool_map,
+            MCP_RESOURCE_READ_TOOL,
+            "Read an MCP resource by URI. Text is returned inline; binary contents only report their size."
+                .to_string(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "server": { "type": "string" },
+                    "uri": { "type": "string" },
+                    "max_chars": { "type": "integer", "description": "Default 100000, max 400000." }
+                },
+                "required": ["server", "uri"]
+            }),
+            RuntimeTool::McpResourceRead,
+        );
+    }
+
+    if !subscribe_servers.is_empty() {
+        subscribe_servers.sort();
+        register_runtime_tool(
+            &mut tools,
+            &mut tool_map,
+            MCP_RESOURCE_SUBSCRIBE_TOOL,
+            format!(
+                "Subscribe to (or unsubscribe from) change notifications for an MCP resource on {}. \
+                 Changed resources show updated=true in {} until they are read again.",
+                subscribe_servers.join(", "),
+                MCP_RESOURCES_LIST_TOOL
+            ),
+            json!({
+                "type": "object",
+                "properties": {
+                    "server": { "type": "string" },
+                    "uri": { "type": "string" },
+                    "subscribe": { "type": "boolean", "description": "false to unsubscribe. Default true." }
+                },
+                "required": ["server", "uri"]
+            }),
+            RuntimeTool::McpResourceSubscribe,
+        );
+    }
+
+    if !prompt_servers.is_empty() {
+        prompt_servers.sort();
+        register_runtime_tool(
+            &mut tools,
+            &mut tool_map,
+            MCP_PROMPTS_LIST_TOOL,
+            format!(
+                "List prompt templates offered by connected MCP servers ({}), with their arguments.",
+                prompt_servers.join(", ")
+            ),
+            json!({
+                "type": "object",
+                "properties": {
+                    "server": { "type": "string", "description": "Only list this server's prompts." }
+                }
+            }),
+            RuntimeTool::McpPromptsList,
+        );
+        register_runtime_tool(
+            &mut tools,
+            &mut tool_map,
+            MCP_PROMPT_GET_TOOL,
+            "Render an MCP prompt template with arguments and return its text, to follow as instructions."
+                .to_string(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "server": { "type": "string" },
+                    "name": { "type": "string" },
+                    "arguments": {
+                        "type": "object",
+                        "description": "Prompt arguments as string values.",
+                        "additionalProperties": { "type": "string" }
+                    }
+                },
+                "required": ["server", "name"]
+            }),
+            RuntimeTool::McpPromptGet,
+        );
+    }
+
     (tools, tool_map)
 }
 
@@ -440,7 +584,7 @@ pub(super) fn collect_workspace_tools(
         &mut tool_map,
         WORKSPACE_WRITE_TOOL,
         format!(
-            "Write text content to a file in the local workspace (atomic overwrite). Workspace root: {}",
+            "Write text content to a file in the local workspace (atomic overwrite). If the result has `hunks`, the user rejected part of the change. Workspace root: {}",
             root_hint
         ),
         json!({
@@ -460,7 +604,7 @@ pub(super) fn collect_workspace_tools(
         &mut tool_map,
         WORKSPACE_EDIT_TOOL,
         format!(
-            "Edit a file by replacing an exact snippet. Workspace root: {}",
+            "Edit a file by replacing an exact snippet. If the result has `hunks`, the user rejected part of the change. Workspace root: {}",
             root_hint
         ),
         json!({
@@ -481,13 +625,13 @@ pub(super) fn collect_workspace_tools(
         &mut tool_map,
         WORKSPACE_APPLY_PATCH_TOOL,
         format!(
-            "Apply a structured patch envelope (*** Begin Patch ... *** End Patch) in the workspace. Workspace root: {}",
+            "Apply a structured patch envelope (*** Begin Patch ... *** End Patch) or a unified diff (`git diff` output) in the workspace. Hunks still apply when whitespace, line endings or a couple of edge context lines differ; on failure the error shows the closest matching location. If the result has `hunks`, the user rejected some hunks and only `applied_hunks` were written. Workspace root: {}",
             root_hint
         ),
         json!({
             "type": "object",
             "properties": {
-                "patch": { "type": "string" },
+                "patch": { "type": "string", "description": "Patch envelope or unified diff text." },
                 "dry_run": { "type": "boolean" }
             },
             "required": ["patch"]
@@ -495,12 +639,31 @@ pub(super) fn collect_workspace_tools(
         RuntimeTool::WorkspaceApplyPatch,
     );
 
+    register_runtime_tool(
+        &mut tools,
+        &mut tool_map,
+        WORKSPACE_CHECKPOINT_TOOL,
+        format!(
+            "Inspect or undo file changes of earlier turns in this conversation. Files changed by write/edit/apply_patch are checkpointed per turn: 'list' shows turns and their files, 'diff' shows the changes made since a turn's checkpoint, 'restore' puts the files (or only 'paths') back to their state before that turn. Workspace root: {}",
+            root_hint
+        ),
+        json!({
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": ["list", "diff", "restore"], "description": "Default list" },
+                "turn_id": { "type": "string", "description": "Required for diff and restore" },
+                "paths": { "type": "array", "items": { "type": "string" }, "description": "Restore only these files (workspace-relative)" }
+            }
+        }),
+        RuntimeTool::WorkspaceCheckpoint,
+    );
+
     register_runtime_tool(
         &mut tools,
         &mut tool_map,
         WORKSPACE_GLOB_TOOL,
         format!(
-            "Find workspace paths by glob patterns. Workspace root: {}",
+            "Find workspace files by glob patterns, most recently modified first. Files ignored by .gitignore are skipped unless include_directories is set. Workspace root: {}",
             root_hint
         ),
         json!({
@@ -521,7 +684,7 @@ pub(super) fn collect_workspace_tools(
         &mut tool_map,
         WORKSPACE_GREP_TOOL,
         format!(
-            "Search file contents with regex/string matching in the workspace. Workspace root: {}",
+            "Search file contents with regex/string matching in the workspace. Backed by an incremental search index that skips .gitignore'd files; files whose path matches the pattern and recently modified files come first. Workspace root: {}",
             root_hint
         ),
         json!({
@@ -544,7 +707,7 @@ pub(super) fn collect_workspace_tools(
         &mut tool_map,
         WORKSPACE_CODESEARCH_TOOL,
         format!(
-            "Code-aware text search with surrounding context snippets. Workspace root: {}",
+            "Code-aware text search with surrounding context snippets, served from the workspace search index (respects .gitignore, ranks path matches and recently modified files first). Workspace root: {}",
             root_hint
         ),
         json!({
@@ -566,20 +729,46 @@ pub(super) fn collect_workspace_tools(
         &mut tool_map,
         WORKSPACE_LSP_SYMBOLS_TOOL,
         format!(
-            "Best-effort symbol scan (functions/classes/types) without a language server. Workspace root: {}",
+            "Parser-based symbol index for Rust, TypeScript/JavaScript, Vue, Python, Go, Java and C/C++. \
+             action=search finds symbols by name, definition locates where a symbol is declared (accepts Type::name or Type.name), \
+             references lists every use with the source line, outline returns the nested symbol tree of one file. Workspace root: {}",
             root_hint
         ),
         json!({
             "type": "object",
             "properties": {
-                "query": { "type": "string", "description": "Optional symbol name filter" },
-                "path": { "type": "string", "description": "Base directory, default ." },
+                "action": { "type": "string", "enum": ["search", "definition", "references", "outline"], "description": "Default search" },
+                "query": { "type": "string", "description": "search: optional symbol name filter" },
+                "symbol": { "type": "string", "description": "definition/references: symbol name, optionally qualified" },
+                "path": { "type": "string", "description": "Base directory to limit results, or the file for outline. Default ." },
                 "max_results": { "type": "integer", "description": "Default 200" }
             }
         }),
         RuntimeTool::WorkspaceLspSymbols,
     );
 
+    register_runtime_tool(
+        &mut tools,
+        &mut tool_map,
+        WORKSPACE_SEMANTIC_SEARCH_TOOL,
+        format!(
+            "Semantic search over workspace code and docs by meaning rather than exact text, e.g. \"where do we handle login retries\". \
+             Returns ranked snippets with paths and line ranges. Files are embedded incrementally; a first search on a large workspace may report pending_files that later searches fill in. Workspace root: {}",
+            root_hint
+        ),
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "Natural language description of what to find" },
+                "path": { "type": "string", "description": "Base directory, default ." },
+                "glob": { "type": "string", "description": "Optional file glob filter, e.g. **/*.rs" },
+                "max_results": { "type": "integer", "description": "Default 10, max 50" }
+            },
+            "required": ["query"]
+        }),
+        RuntimeTool::WorkspaceSemanticSearch,
+    );
+
     register_runtime_tool(
         &mut tools,
         &mut tool_map,
@@ -587,7 +776,8 @@ pub(super) fn collect_workspace_tools(
         format!(
             "Run a shell command in the local workspace and return stdout/stderr. \
              On Windows this executes in PowerShell (-NoProfile -Command). \
-             Prefer this tool for recursive file traversal, file statistics, folder size, bulk listing/sorting/filtering, and other batch filesystem tasks. Workspace root: {}",
+             Prefer this tool for recursive file traversal, file statistics, folder size, bulk listing/sorting/filtering, and other batch filesystem tasks. \
+             If the result has `sandbox`, the command ran isolated: only the listed paths are writable and network is off unless `network` is true. Workspace root: {}",
             root_hint
         ),
         json!({
@@ -606,13 +796,18 @@ pub(super) fn collect_workspace_tools(
         &mut tool_map,
         WORKSPACE_PROCESS_START_TOOL,
         format!(
-            "Start a long-running background process in the workspace. Workspace root: {}",
+            "Start a long-running background process in the workspace (dev server, watcher, REPL, installer). Its stdin stays open for workspace_process_write; set pty=true for programs that only prompt on a terminal. Pass ready_pattern and/or ready_port to wait until it is up; the result then reports ready, the reason (pattern, port, exited or timeout) and the output so far. Processes are stopped when the conversation is deleted or the app exits. Workspace root: {}",
             root_hint
         ),
         json!({
             "type": "object",
             "properties": {
-                "command": { "type": "string" }
+                "command": { "type": "string" },
+                "cwd": { "type": "string", "description": "Working directory, relative to the workspace root. Default '.'" },
+                "pty": { "type": "boolean", "description": "Run in a pseudo-terminal; stdout and stderr are merged. Default false" },
+                "ready_pattern": { "type": "string", "description": "Regex that marks the process as ready, e.g. 'ready in|Listening on'" },
+                "ready_port": { "type": "integer", "description": "Ready once this localhost port accepts connections" },
+                "ready_timeout_ms": { "type": "integer", "description": "Default 30000, max 300000" }
             },
             "required": ["command"]
         }),
@@ -623,7 +818,7 @@ pub(super) fn collect_workspace_tools(
         &mut tools,
         &mut tool_map,
         WORKSPACE_PROCESS_LIST_TOOL,
-        "List managed background processes started by workspace_process_start.".to_string(),
+        "List the background processes this conversation started with workspace_process_start.".to_string(),
         json!({
             "type": "object",
             "properties": {}
@@ -635,18 +830,40 @@ pub(super) fn collect_workspace_tools(
         &mut tools,
         &mut tool_map,
         WORKSPACE_PROCESS_READ_TOOL,
-        "Read stdout/stderr snapshots for a managed background process.".to_string(),
+        "Read output of a managed background process. Without since_seq returns stdout/stderr tails; with since_seq returns the timestamped chunks from that seq on, so pass the previous next_seq to read only new output. ready_pattern/ready_port wait for new output or a listening port first. Also reports ports the process announced.".to_string(),
         json!({
             "type": "object",
             "properties": {
                 "process_id": { "type": "string" },
-                "max_chars": { "type": "integer", "description": "Default 6000" }
+                "max_chars": { "type": "integer", "description": "Default 10000" },
+                "since_seq": { "type": "integer", "description": "Return chunks with seq >= since_seq" },
+                "ready_pattern": { "type": "string", "description": "Regex to wait for in output since since_seq" },
+                "ready_port": { "type": "integer", "description": "Wait until this localhost port accepts connections" },
+                "ready_timeout_ms": { "type": "integer", "description": "Default 30000, max 300000" }
             },
             "required": ["process_id"]
         }),
         RuntimeTool::WorkspaceProcessRead,
     );
 
+    register_runtime_tool(
+        &mut tools,
+        &mut tool_map,
+        WORKSPACE_PROCESS_WRITE_TOOL,
+        "Write to the stdin of a managed background process, e.g. to answer a prompt. A newline (Enter) is appended unless append_newline is false; in pty mode control characters such as \\u0003 (Ctrl-C) are passed through. close_stdin sends end of input.".to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "process_id": { "type": "string" },
+                "input": { "type": "string" },
+                "append_newline": { "type": "boolean", "description": "Default true" },
+                "close_stdin": { "type": "boolean", "description": "Default false" }
+            },
+            "required": ["process_id", "input"]
+        }),
+        RuntimeTool::WorkspaceProcessWrite,
+    );
+
     register_runtime_tool(
         &mut tools,
         &mut tool_map,
@@ -773,12 +990,14 @@ pub(super) fn collect_core_tools() -> (Vec<ChatTool>, HashMap<String, RuntimeToo
         &mut tools,
         &mut tool_map,
         CORE_TASK_TOOL,
-        "Run a sub-task prompt with text-only response.".to_string(),
+        "Delegate a self-contained task to a sub-agent (see agents_list). The sub-agent runs its own tool loop with a fresh context and its agent's tool profile, and returns its final report with status, rounds and tool usage. Tools that would need user approval are denied inside sub-agents. Give it everything it needs in the prompt.".to_string(),
         json!({
             "type": "object",
             "properties": {
-                "prompt": { "type": "string" },
-                "model": { "type": "string" }
+                "prompt": { "type": "string", "description": "The complete task, including any context the sub-agent needs." },
+                "agent": { "type": "string", "description": "Agent id from agents_list (default: \"default\")." },
+                "model": { "type": "string", "description": "Overrides the agent's model." },
+                "max_rounds": { "type": "integer", "minimum": 1, "description": "Lowers the agent's round limit." }
             },
             "required": ["prompt"]
         }),
@@ -789,7 +1008,7 @@ pub(super) fn collect_core_tools() -> (Vec<ChatTool>, HashMap<String, RuntimeToo
         &mut tools,
         &mut tool_map,
         TODO_WRITE_TOOL,
-        "Create/update/remove TODO items for the current conversation.".to_string(),
+        "Create/update/remove TODO items for the current conversation. The list is the conversation plan: it is saved, versioned and shown to later turns and scheduled runs.".to_string(),
         json!({
             "type": "object",
             "properties": {
@@ -1036,7 +1255,7 @@ pub(super) fn collect_core_tools() -> (Vec<ChatTool>, HashMap<String, RuntimeToo
         &mut tools,
         &mut tool_map,
         SESSIONS_SEND_TOOL,
-        "Send a message into an existing conversation and optionally run one assistant step."
+        "Send a message into an existing conversation. With run_assistant, that conversation runs a full agent turn (its own tools, prompt and memory) that streams live into it; by default the call waits for the reply, with wait=false it returns a run_id for sessions_run_status."
             .to_string(),
         json!({
             "type": "object",
@@ -1044,7 +1263,8 @@ pub(super) fn collect_core_tools() -> (Vec<ChatTool>, HashMap<String, RuntimeToo
                 "conversation_id": { "type": "string" },
                 "content": { "type": "string" },
                 "run_assistant": { "type": "boolean" },
-                "model": { "type": "string" }
+                "model": { "type": "string" },
+                "wait": { "type": "boolean", "description": "Wait for the turn to finish (default true)." }
             },
             "required": ["conversation_id", "content"]
         }),
@@ -1055,25 +1275,42 @@ pub(super) fn collect_core_tools() -> (Vec<ChatTool>, HashMap<String, RuntimeToo
         &mut tools,
         &mut tool_map,
         SESSIONS_SPAWN_TOOL,
-        "Create a new conversation/session and optionally run one assistant step.".to_string(),
+        "Create a new conversation/session. With content and run_assistant, it runs a full agent turn like sessions_send does; the result is under 'run'.".to_string(),
         json!({
             "type": "object",
             "properties": {
                 "title": { "type": "string" },
                 "content": { "type": "string" },
                 "model": { "type": "string" },
-                "run_assistant": { "type": "boolean" }
+                "run_assistant": { "type": "boolean" },
+                "wait": { "type": "boolean", "description": "Wait for the turn to finish (default true)." }
             },
             "required": ["title"]
         }),
         RuntimeTool::SessionsSpawn,
     );
 
+    register_runtime_tool(
+        &mut tools,
+        &mut tool_map,
+        SESSIONS_RUN_STATUS_TOOL,
+        "Check a turn started by sessions_send or sessions_spawn with wait=false. Returns status 'running' until it finishes, then the reply.".to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "run_id": { "type": "string" },
+                "wait_ms": { "type": "integer", "description": "Wait up to this long for the run to finish (default 0, max 600000)." }
+            },
+            "required": ["run_id"]
+        }),
+        RuntimeTool::SessionsRunStatus,
+    );
+
     register_runtime_tool(
         &mut tools,
         &mut tool_map,
         AGENTS_LIST_TOOL,
-        "List available built-in agent profiles.".to_string(),
+        "List the agents core_task can delegate to, with their prompts, models, tool profiles and round limits.".to_string(),
         json!({
             "type": "object",
             "properties": {}
@@ -1117,6 +1354,7 @@ pub(super) fn collect_core_tools() -> (Vec<ChatTool>, HashMap<String, RuntimeToo
                 "model_override": { "type": "string" },
                 "workspace_directory": { "type": "string" },
                 "tool_whitelist": { "type": "array", "items": { "type": "string" } },
+                "tool_profile": { "type": "string", "description": "coding|research|office|all, or a custom profile name." },
                 "run_timeout_seconds": { "type": "integer" },
                 "delete_after_run": { "type": "boolean" }
             },
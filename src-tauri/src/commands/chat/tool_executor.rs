@@ -2,17 +2,29 @@ use crate::services::desktop::{self, types::DesktopToolRequest};
 use crate::commands::mcp::McpState;
 use tauri;
 use crate::commands::skills::SkillManagerState;
-use super::{browser_tools, image_tools, process_tools, web_tools};
-use crate::services::llm::{ChatMessage, ChatToolCall};
+use super::{
+    browser_tools, image_tools, mcp_tools, process_tools, session_runs, sub_agents, web_tools,
+};
+use crate::services::llm::ChatToolCall;
 use crate::commands::chat::{TodoItem, TodoStatus};
 use crate::models::config::Config;
 use crate::services::llm::LlmService;
 use crate::services::pdf_parse::{parse_pdf_to_markdown as parse_pdf_to_markdown_service, ParsePdfOptions};
 use crate::services::scheduler::scheduler_manager;
 use crate::services::scheduler::models::*;
-use crate::commands::chat::todo_store;
+use crate::services::code_index::search::{indexed_workspace_files, search_candidate_files, CodeSearchQuery};
+use crate::services::code_index::semantic::semantic_search;
+use crate::services::code_index::{self, symbols::{is_symbol_source, with_symbol_index}};
+use crate::services::memory::build_embedder;
+use crate::services::sandbox::{
+    background_sandbox_active, shell_command, SandboxPolicy, SandboxReason,
+};
+use crate::commands::chat::{
+    load_todo_plan, save_todo_plan, todo_plan_write_lock, ACTIVE_BRANCH_CTE,
+};
 
 use chrono::Utc;
+use futures_util::{Stream, StreamExt};
 use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use regex::{Regex, RegexBuilder};
 use serde_json::{json, Value};
@@ -20,6 +32,7 @@ use sqlx::SqlitePool;
 use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::process::Command as TokioCommand;
@@ -30,8 +43,12 @@ use walkdir::WalkDir;
 // what we need from chat::tool_catalog and chat::llm_provider
 // Assuming these are accessible over `super` or `crate::commands::chat`
 
+use super::checkpoints::{checkpoint_targets, execute_workspace_checkpoint, record_checkpoint};
+use super::file_diff::{select_hunks, FileChangePreview, HunkSelection};
+use super::stream::RunPolicy;
+use super::patch::{apply_hunks_to_content, parse_patch, PatchOperation};
 use super::{
-    insert_message, load_conversation_context,
+    emit_app_event, enter_mcp_call_context, McpCallContext,
     resolve_clawhub_settings_for_discovery,
     should_auto_allow_batch_tool,
     tool_catalog::*,
@@ -347,6 +364,7 @@ pub(crate) fn write_file_atomic(path: &Path, content: &[u8]) -> Result<(), Strin
 pub(crate) fn execute_workspace_write_file(
     arguments: &Value,
     workspace_root: &Path,
+    hunk_selection: &HunkSelection,
 ) -> Result<Value, String> {
     let raw_path = read_path_argument(arguments, "path")?;
     let content = arguments
@@ -361,6 +379,20 @@ pub(crate) fn execute_workspace_write_file(
 
     let file_path = resolve_workspace_target(workspace_root, &raw_path, true)?;
 
+    if !hunk_selection.is_empty() {
+        let before = read_text_lossy(&file_path);
+        let after = if append {
+            format!("{}{}", before, content)
+        } else {
+            content.clone()
+        };
+        if let Some(result) =
+            write_selected_hunks(workspace_root, &file_path, hunk_selection, &before, &after)?
+        {
+            return Ok(result);
+        }
+    }
+
     if append {
         if let Some(parent) = file_path.parent() {
             fs::create_dir_all(parent).map_err(|e| e.to_string())?;
@@ -388,6 +420,7 @@ pub(crate) fn execute_workspace_write_file(
 pub(crate) fn execute_workspace_edit_file(
     arguments: &Value,
     workspace_root: &Path,
+    hunk_selection: &HunkSelection,
 ) -> Result<Value, String> {
     let raw_path = read_path_argument(arguments, "path")?;
     let old_string = read_string_argument(arguments, "old_string")?;
@@ -404,16 +437,22 @@ pub(crate) fn execute_workspace_edit_file(
     }
 
     let content = fs::read_to_string(&file_path).map_err(|e| e.to_string())?;
-    let updated = if replace_all {
-        content.replace(&old_string, &new_string)
-    } else {
-        content.replacen(&old_string, &new_string, 1)
-    };
+    let updated = edited_content(&content, &old_string, &new_string, replace_all);
 
     if updated == content {
         return Err("No matching content found to edit".to_string());
     }
 
+    if let Some(result) = write_selected_hunks(
+        workspace_root,
+        &file_path,
+        hunk_selection,
+        &content,
+        &updated,
+    )? {
+        return Ok(result);
+    }
+
     write_file_atomic(&file_path, updated.as_bytes())?;
 
     Ok(json!({
@@ -424,9 +463,79 @@ pub(crate) fn execute_workspace_edit_file(
     }))
 }
 
+fn edited_content(content: &str, old_string: &str, new_string: &str, replace_all: bool) -> String {
+    if replace_all {
+        content.replace(old_string, new_string)
+    } else {
+        content.replacen(old_string, new_string, 1)
+    }
+}
+
+fn read_text_lossy(path: &Path) -> String {
+    fs::read(path)
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_default()
+}
+
+/// Writes only the hunks the user approved for this file. Returns None when the file was
+/// approved as a whole and the caller should write it normally.
+fn write_selected_hunks(
+    workspace_root: &Path,
+    file_path: &Path,
+    hunk_selection: &HunkSelection,
+    before: &str,
+    after: &str,
+) -> Result<Option<Value>, String> {
+    let display_path = workspace_relative_display_path(workspace_root, file_path);
+    let Some(selected) = select_hunks(hunk_selection, &display_path, before, after) else {
+        return Ok(None);
+    };
+    let status = if selected.applied.is_empty() {
+        "skipped"
+    } else if selected.content == after {
+        "updated"
+    } else {
+        "partially_applied"
+    };
+    if !selected.applied.is_empty() {
+        write_file_atomic(file_path, selected.content.as_bytes())?;
+    }
+    Ok(Some(json!({
+        "workspace_root": workspace_root.to_string_lossy().to_string(),
+        "path": file_path.to_string_lossy().to_string(),
+        "status": status,
+        "hunks": selected.report
+    })))
+}
+
+/// Commands run sandboxed when the conversation opted in (or `sandbox.enabled` is set and it
+/// has no override), and always inside scheduler and heartbeat runs.
+pub(crate) async fn resolve_sandbox_policy(
+    config: &Config,
+    pool: &SqlitePool,
+    conversation_id: &str,
+) -> Result<Option<SandboxPolicy>, String> {
+    if background_sandbox_active() {
+        return Ok(Some(SandboxPolicy::from_config(
+            &config.sandbox,
+            SandboxReason::BackgroundRun,
+        )));
+    }
+    let sandboxed =
+        sqlx::query_scalar::<_, Option<bool>>("SELECT sandboxed FROM conversations WHERE id = ?")
+            .bind(conversation_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .flatten()
+            .unwrap_or(config.sandbox.enabled);
+    Ok(sandboxed.then(|| SandboxPolicy::from_config(&config.sandbox, SandboxReason::Conversation)))
+}
+
 pub(crate) async fn execute_workspace_run_command(
     arguments: &Value,
     workspace_root: &Path,
+    sandbox: Option<&SandboxPolicy>,
 ) -> Result<Value, String> {
     let command = arguments
         .get("command")
@@ -438,27 +547,20 @@ pub(crate) async fn execute_workspace_run_command(
 
     let timeout_ms = read_u64_argument(arguments, "timeout_ms", 20_000).clamp(1_000, 120_000);
 
-    let mut cmd = if cfg!(target_os = "windows") {
-        let wrapped_command = format!(
-            "$OutputEncoding = [Console]::OutputEncoding = [System.Text.UTF8Encoding]::new($false); [Console]::InputEncoding = [System.Text.UTF8Encoding]::new($false); chcp 65001 > $null; {}",
-            command
-        );
-        let mut process = TokioCommand::new("powershell");
-        process.args(["-NoProfile", "-NonInteractive", "-Command", &wrapped_command]);
-        process
-    } else {
-        let mut process = TokioCommand::new("sh");
-        process.args(["-lc", &command]);
-        process
-    };
-
-    cmd.current_dir(workspace_root);
+    let mut cmd = TokioCommand::from(shell_command(
+        &command,
+        workspace_root,
+        workspace_root,
+        sandbox,
+    )?);
+    // A sandboxed command must not outlive its time cap.
+    cmd.kill_on_drop(sandbox.is_some());
     let output = tokio::time::timeout(Duration::from_millis(timeout_ms), cmd.output())
         .await
         .map_err(|_| format!("Command timed out after {} ms", timeout_ms))?
         .map_err(|e| e.to_string())?;
 
-    Ok(json!({
+    let mut result = json!({
         "workspace_root": workspace_root.to_string_lossy().to_string(),
         "command": command,
         "timeout_ms": timeout_ms,
@@ -466,7 +568,11 @@ pub(crate) async fn execute_workspace_run_command(
         "success": output.status.success(),
         "stdout": String::from_utf8_lossy(&output.stdout).to_string(),
         "stderr": String::from_utf8_lossy(&output.stderr).to_string()
-    }))
+    });
+    if let Some(policy) = sandbox {
+        result["sandbox"] = policy.describe(workspace_root);
+    }
+    Ok(result)
 }
 pub(crate) fn collect_workspace_files(
     workspace_root: &Path,
@@ -500,6 +606,49 @@ pub(crate) fn collect_workspace_files(
     Ok(results)
 }
 
+/// Narrows indexed files to `base_directory` and `glob_pattern`, keeping their ranking.
+fn filter_indexed_files(
+    workspace_root: &Path,
+    base_directory: &Path,
+    glob_pattern: Option<&str>,
+    files: Vec<PathBuf>,
+    max_files: usize,
+) -> Result<Vec<PathBuf>, String> {
+    let glob_set = compile_glob_set(&[glob_pattern.unwrap_or("**/*").to_string()])?;
+    Ok(files
+        .into_iter()
+        .filter(|path| path.starts_with(base_directory))
+        .filter(|path| {
+            glob_set.is_match(workspace_relative_display_path(workspace_root, path).as_str())
+        })
+        .take(max_files)
+        .collect())
+}
+
+/// Files worth reading for a content search, best candidates first. Served by the
+/// persistent code search index, with a plain walk as fallback.
+fn collect_search_candidates(
+    workspace_root: &Path,
+    base_directory: &Path,
+    glob_pattern: Option<&str>,
+    query: &CodeSearchQuery,
+    max_files: usize,
+) -> Result<Vec<PathBuf>, String> {
+    match search_candidate_files(workspace_root, query) {
+        Ok(files) => filter_indexed_files(
+            workspace_root,
+            base_directory,
+            glob_pattern,
+            files,
+            max_files,
+        ),
+        Err(error) => {
+            eprintln!("[code_index] search index unavailable: {}", error);
+            collect_workspace_files(workspace_root, base_directory, glob_pattern, max_files)
+        }
+    }
+}
+
 pub(crate) fn execute_workspace_glob(
     arguments: &Value,
     workspace_root: &Path,
@@ -514,6 +663,36 @@ pub(crate) fn execute_workspace_glob(
         return Err(format!("Not a directory: {}", base_dir.display()));
     }
 
+    if !include_directories {
+        if let Ok(files) = indexed_workspace_files(workspace_root) {
+            let files = filter_indexed_files(
+                workspace_root,
+                &base_dir,
+                Some(&pattern),
+                files,
+                max_results,
+            )?;
+            let matches = files
+                .iter()
+                .filter_map(|path| {
+                    let metadata = fs::metadata(path).ok()?;
+                    Some(json!({
+                        "path": workspace_relative_display_path(workspace_root, path),
+                        "absolute_path": path.to_string_lossy().to_string(),
+                        "is_dir": false,
+                        "size": metadata.len()
+                    }))
+                })
+                .collect::<Vec<_>>();
+            return Ok(json!({
+                "workspace_root": workspace_root.to_string_lossy().to_string(),
+                "base_directory": base_dir.to_string_lossy().to_string(),
+                "matches": matches,
+                "truncated": matches.len() >= max_results
+            }));
+        }
+    }
+
     let glob_set = compile_glob_set(&[pattern])?;
     let mut matches = Vec::new();
     for entry in WalkDir::new(&base_dir).follow_links(false) {
@@ -579,11 +758,23 @@ pub(crate) fn execute_workspace_grep(
         return Err(format!("Not a directory: {}", base_dir.display()));
     }
 
-    let files = collect_workspace_files(workspace_root, &base_dir, glob.as_deref(), 20_000)?;
+    let files = collect_search_candidates(
+        workspace_root,
+        &base_dir,
+        glob.as_deref(),
+        &CodeSearchQuery {
+            pattern: &pattern,
+            regex: use_regex,
+            case_sensitive,
+        },
+        20_000,
+    )?;
     let mut matches = Vec::new();
 
     for file_path in files {
-        let bytes = fs::read(&file_path).map_err(|e| e.to_string())?;
+        let Ok(bytes) = fs::read(&file_path) else {
+            continue;
+        };
         if bytes.is_empty() || is_probably_binary(&bytes) {
             continue;
         }
@@ -635,11 +826,23 @@ pub(crate) fn execute_workspace_codesearch(
         return Err(format!("Not a directory: {}", base_dir.display()));
     }
 
-    let files = collect_workspace_files(workspace_root, &base_dir, glob.as_deref(), 20_000)?;
+    let files = collect_search_candidates(
+        workspace_root,
+        &base_dir,
+        glob.as_deref(),
+        &CodeSearchQuery {
+            pattern: &query,
+            regex: false,
+            case_sensitive: false,
+        },
+        20_000,
+    )?;
     let mut matches = Vec::new();
 
     for file_path in files {
-        let bytes = fs::read(&file_path).map_err(|e| e.to_string())?;
+        let Ok(bytes) = fs::read(&file_path) else {
+            continue;
+        };
         if bytes.is_empty() || is_probably_binary(&bytes) {
             continue;
         }
@@ -682,267 +885,114 @@ pub(crate) fn execute_workspace_lsp_symbols(
     arguments: &Value,
     workspace_root: &Path,
 ) -> Result<Value, String> {
+    let action =
+        read_optional_string_argument(arguments, "action").unwrap_or_else(|| "search".to_string());
     let raw_path =
         read_optional_string_argument(arguments, "path").unwrap_or_else(|| ".".to_string());
-    let query = read_optional_string_argument(arguments, "query")
-        .map(|value| value.to_lowercase())
-        .unwrap_or_default();
     let max_results = read_u64_argument(arguments, "max_results", 200).clamp(1, 2_000) as usize;
-    let base_dir = resolve_workspace_target(workspace_root, &raw_path, false)?;
-    if !base_dir.is_dir() {
-        return Err(format!("Not a directory: {}", base_dir.display()));
-    }
-
-    let symbol_patterns = vec![
-        (
-            "function",
-            Regex::new(r"^\s*(?:pub\s+)?(?:async\s+)?fn\s+([A-Za-z_][A-Za-z0-9_]*)")
-                .map_err(|e| e.to_string())?,
-        ),
-        (
-            "class",
-            Regex::new(r"^\s*class\s+([A-Za-z_][A-Za-z0-9_]*)").map_err(|e| e.to_string())?,
-        ),
-        (
-            "type",
-            Regex::new(
-                r"^\s*(?:export\s+)?(?:interface|type|struct|enum)\s+([A-Za-z_][A-Za-z0-9_]*)",
-            )
-            .map_err(|e| e.to_string())?,
-        ),
-    ];
-
-    let files = collect_workspace_files(workspace_root, &base_dir, None, 40_000)?;
-    let mut symbols = Vec::new();
-
-    for file_path in files {
-        let relative = workspace_relative_display_path(workspace_root, &file_path);
-        let extension = file_path
-            .extension()
-            .and_then(|value| value.to_str())
-            .unwrap_or_default()
-            .to_lowercase();
-        if !matches!(
-            extension.as_str(),
-            "rs" | "ts"
-                | "tsx"
-                | "js"
-                | "jsx"
-                | "py"
-                | "go"
-                | "java"
-                | "c"
-                | "cpp"
-                | "h"
-                | "hpp"
-                | "vue"
-        ) {
-            continue;
-        }
-
-        let bytes = fs::read(&file_path).map_err(|e| e.to_string())?;
-        if bytes.is_empty() || is_probably_binary(&bytes) {
-            continue;
-        }
-
-        let text = String::from_utf8_lossy(&bytes);
-        for (line_idx, line) in text.lines().enumerate() {
-            for (kind, regex) in &symbol_patterns {
-                if let Some(captures) = regex.captures(line) {
-                    let symbol_name = captures
-                        .get(1)
-                        .map(|value| value.as_str())
-                        .unwrap_or_default()
-                        .to_string();
-                    if !query.is_empty() && !symbol_name.to_lowercase().contains(&query) {
-                        continue;
-                    }
-                    symbols.push(json!({
-                        "name": symbol_name,
-                        "kind": kind,
-                        "path": relative,
-                        "line_number": line_idx + 1
-                    }));
-                    if symbols.len() >= max_results {
-                        break;
-                    }
-                }
-            }
-            if symbols.len() >= max_results {
-                break;
-            }
-        }
-        if symbols.len() >= max_results {
-            break;
-        }
-    }
-
-    Ok(json!({
-        "query": query,
-        "symbols": symbols,
-        "truncated": symbols.len() >= max_results
-    }))
-}
-pub(crate) enum PatchOperation {
-    Add {
-        path: String,
-        lines: Vec<String>,
-    },
-    Delete {
-        path: String,
-    },
-    Update {
-        path: String,
-        move_to: Option<String>,
-        hunks: Vec<Vec<String>>,
-    },
-}
-
-pub(crate) fn parse_patch_envelope(patch: &str) -> Result<Vec<PatchOperation>, String> {
-    let lines: Vec<&str> = patch.lines().collect();
-    if lines.is_empty() || lines.first().copied() != Some("*** Begin Patch") {
-        return Err("Patch must start with '*** Begin Patch'".to_string());
-    }
-
-    let mut index = 1usize;
-    let mut operations = Vec::new();
+    let target = resolve_workspace_target(workspace_root, &raw_path, false)?;
+    let canonical_root = workspace_root.canonicalize().map_err(|e| e.to_string())?;
+    let files = indexed_workspace_files(&canonical_root)
+        .unwrap_or_else(|_| code_index::collect_indexable_files(&canonical_root, 40_000))
+        .into_iter()
+        .filter(|path| is_symbol_source(path))
+        .collect::<Vec<_>>();
 
-    while index < lines.len() {
-        let line = lines[index];
-        if line == "*** End Patch" {
-            return Ok(operations);
+    match action.as_str() {
+        "search" => {
+            let query = read_optional_string_argument(arguments, "query").unwrap_or_default();
+            let (symbols, truncated) = with_symbol_index(&canonical_root, &files, |index| {
+                index.search(&query, &target, max_results)
+            })?;
+            Ok(json!({
+                "action": action,
+                "query": query,
+                "symbols": symbols,
+                "truncated": truncated
+            }))
         }
-
-        if let Some(path) = line.strip_prefix("*** Add File: ") {
-            index += 1;
-            let mut add_lines = Vec::new();
-            while index < lines.len() && !lines[index].starts_with("*** ") {
-                if !lines[index].starts_with('+') {
-                    return Err(format!(
-                        "Add file operation expects '+' lines, found: {}",
-                        lines[index]
-                    ));
-                }
-                add_lines.push(lines[index][1..].to_string());
-                index += 1;
-            }
-            operations.push(PatchOperation::Add {
-                path: path.trim().to_string(),
-                lines: add_lines,
-            });
-            continue;
+        "definition" => {
+            let symbol = read_string_argument(arguments, "symbol")?;
+            let definitions = with_symbol_index(&canonical_root, &files, |index| {
+                index.definitions(&symbol, &target)
+            })?;
+            Ok(json!({
+                "action": action,
+                "symbol": symbol,
+                "definitions": definitions
+            }))
         }
-
-        if let Some(path) = line.strip_prefix("*** Delete File: ") {
-            operations.push(PatchOperation::Delete {
-                path: path.trim().to_string(),
-            });
-            index += 1;
-            continue;
+        "references" => {
+            let symbol = read_string_argument(arguments, "symbol")?;
+            let (references, truncated) = with_symbol_index(&canonical_root, &files, |index| {
+                index.references(&symbol, &target, max_results)
+            })?;
+            Ok(json!({
+                "action": action,
+                "symbol": symbol,
+                "references": references,
+                "truncated": truncated
+            }))
         }
-
-        if let Some(path) = line.strip_prefix("*** Update File: ") {
-            index += 1;
-            let mut move_to = None;
-            if index < lines.len() {
-                if let Some(target) = lines[index].strip_prefix("*** Move to: ") {
-                    move_to = Some(target.trim().to_string());
-                    index += 1;
-                }
+        "outline" => {
+            if !target.is_file() {
+                return Err(format!("Not a file: {}", target.display()));
             }
-
-            let mut hunks = Vec::new();
-            while index < lines.len() && !lines[index].starts_with("*** ") {
-                if !lines[index].starts_with("@@") {
-                    return Err(format!("Expected hunk header '@@', got: {}", lines[index]));
-                }
-                index += 1;
-                let mut hunk_lines = Vec::new();
-                while index < lines.len()
-                    && !lines[index].starts_with("@@")
-                    && !lines[index].starts_with("*** ")
-                {
-                    if lines[index] == "*** End of File" {
-                        index += 1;
-                        break;
-                    }
-                    let prefix = lines[index].chars().next().unwrap_or(' ');
-                    if prefix != ' ' && prefix != '+' && prefix != '-' {
-                        return Err(format!("Invalid hunk line prefix: {}", lines[index]));
-                    }
-                    hunk_lines.push(lines[index].to_string());
-                    index += 1;
-                }
-                hunks.push(hunk_lines);
-            }
-
-            operations.push(PatchOperation::Update {
-                path: path.trim().to_string(),
-                move_to,
-                hunks,
-            });
-            continue;
+            let outline =
+                with_symbol_index(&canonical_root, &files, |index| index.outline(&target))?
+                    .ok_or_else(|| format!("Unsupported source file: {}", raw_path))?;
+            Ok(json!({
+                "action": action,
+                "path": workspace_relative_display_path(&canonical_root, &target),
+                "symbols": outline
+            }))
         }
-
-        return Err(format!("Unknown patch operation: {}", line));
+        other => Err(format!(
+            "Unknown action '{}'; expected search, definition, references or outline",
+            other
+        )),
     }
-
-    Err("Patch is missing '*** End Patch'".to_string())
 }
+pub(crate) async fn execute_workspace_semantic_search(
+    arguments: &Value,
+    workspace_root: &Path,
+    config: &Config,
+    model: &str,
+) -> Result<Value, String> {
+    let query = read_string_argument(arguments, "query")?;
+    let raw_path =
+        read_optional_string_argument(arguments, "path").unwrap_or_else(|| ".".to_string());
+    let glob = read_optional_string_argument(arguments, "glob");
+    let max_results = read_u64_argument(arguments, "max_results", 10).clamp(1, 50) as usize;
+    let base_dir = resolve_workspace_target(workspace_root, &raw_path, false)?;
+    let glob_set = compile_glob_set(&[glob.unwrap_or_else(|| "**/*".to_string())])?;
+    let embedder = build_embedder(config, model)?;
 
-pub(crate) fn find_subsequence(
-    haystack: &[String],
-    needle: &[String],
-    start_index: usize,
-) -> Option<usize> {
-    if needle.is_empty() {
-        return Some(start_index.min(haystack.len()));
-    }
-    if haystack.len() < needle.len() {
-        return None;
-    }
-    (start_index..=haystack.len().saturating_sub(needle.len()))
-        .find(|index| haystack[*index..*index + needle.len()] == *needle)
-}
-
-pub(crate) fn apply_hunks_to_content(
-    content: &str,
-    hunks: &[Vec<String>],
-) -> Result<String, String> {
-    let had_trailing_newline = content.ends_with('\n');
-    let mut lines: Vec<String> = content.lines().map(|line| line.to_string()).collect();
-    let mut cursor = 0usize;
-
-    for hunk in hunks {
-        let old_lines: Vec<String> = hunk
-            .iter()
-            .filter_map(|line| match line.chars().next().unwrap_or(' ') {
-                ' ' | '-' => Some(line[1..].to_string()),
-                _ => None,
-            })
-            .collect();
-        let new_lines: Vec<String> = hunk
-            .iter()
-            .filter_map(|line| match line.chars().next().unwrap_or(' ') {
-                ' ' | '+' => Some(line[1..].to_string()),
-                _ => None,
-            })
-            .collect();
-
-        let Some(position) = find_subsequence(&lines, &old_lines, cursor)
-            .or_else(|| find_subsequence(&lines, &old_lines, 0))
-        else {
-            return Err("Failed to locate hunk context in file".to_string());
-        };
-
-        let replace_end = position + old_lines.len();
-        lines.splice(position..replace_end, new_lines.clone());
-        cursor = position + new_lines.len();
-    }
+    let scope_root = workspace_root.to_path_buf();
+    let outcome = semantic_search(
+        workspace_root,
+        embedder.as_ref(),
+        &query,
+        move |path| {
+            path.starts_with(&base_dir)
+                && glob_set.is_match(workspace_relative_display_path(&scope_root, path).as_str())
+        },
+        max_results,
+    )
+    .await?;
 
-    let mut result = lines.join("\n");
-    if had_trailing_newline {
-        result.push('\n');
+    let mut result = json!({
+        "query": query,
+        "results": outcome.hits,
+        "model": outcome.model,
+        "indexed_files": outcome.indexed_files,
+        "embedded_chunks": outcome.embedded_chunks,
+        "pending_files": outcome.pending_files
+    });
+    if outcome.lexical_fallback {
+        result["note"] = json!(
+            "No embedding model is configured for the current provider; results come from a lexical hash embedding. Prefer workspace_search_code for exact terms."
+        );
     }
     Ok(result)
 }
@@ -950,11 +1000,13 @@ pub(crate) fn apply_hunks_to_content(
 pub(crate) fn execute_workspace_apply_patch(
     arguments: &Value,
     workspace_root: &Path,
+    hunk_selection: &HunkSelection,
 ) -> Result<Value, String> {
     let patch_text = read_string_argument(arguments, "patch")?;
     let dry_run = read_bool_argument(arguments, "dry_run", false);
-    let operations = parse_patch_envelope(&patch_text)?;
+    let operations = parse_patch(&patch_text)?;
     let mut applied = Vec::new();
+    let mut hunk_reports = Vec::new();
 
     for operation in operations {
         match operation {
@@ -963,10 +1015,22 @@ pub(crate) fn execute_workspace_apply_patch(
                 if target_path.exists() {
                     return Err(format!("Cannot add file that already exists: {}", path));
                 }
-                let content = if lines.is_empty() {
-                    String::new()
-                } else {
-                    format!("{}\n", lines.join("\n"))
+                let display_path = workspace_relative_display_path(workspace_root, &target_path);
+                let content = added_file_content(&lines);
+                let content = match select_hunks(hunk_selection, &display_path, "", &content) {
+                    Some(selected) => {
+                        hunk_reports.push(selected.report);
+                        if selected.applied.is_empty() {
+                            applied.push(json!({
+                                "operation": "add",
+                                "path": display_path,
+                                "status": "skipped"
+                            }));
+                            continue;
+                        }
+                        selected.content
+                    }
+                    None => content,
                 };
                 if !dry_run {
                     if let Some(parent) = target_path.parent() {
@@ -984,6 +1048,19 @@ pub(crate) fn execute_workspace_apply_patch(
                 if !target_path.exists() {
                     return Err(format!("Cannot delete missing file: {}", path));
                 }
+                let display_path = workspace_relative_display_path(workspace_root, &target_path);
+                let before = read_text_lossy(&target_path);
+                if let Some(selected) = select_hunks(hunk_selection, &display_path, &before, "") {
+                    hunk_reports.push(selected.report);
+                    if selected.applied.is_empty() {
+                        applied.push(json!({
+                            "operation": "delete",
+                            "path": display_path,
+                            "status": "skipped"
+                        }));
+                        continue;
+                    }
+                }
                 if !dry_run {
                     fs::remove_file(&target_path).map_err(|e| e.to_string())?;
                 }
@@ -999,7 +1076,17 @@ pub(crate) fn execute_workspace_apply_patch(
             } => {
                 let source_path = resolve_workspace_target(workspace_root, &path, false)?;
                 let content = fs::read_to_string(&source_path).map_err(|e| e.to_string())?;
-                let updated = apply_hunks_to_content(&content, &hunks)?;
+                let updated = apply_hunks_to_content(&content, &hunks)
+                    .map_err(|error| format!("{}: {}", path, error))?;
+                let source_display = workspace_relative_display_path(workspace_root, &source_path);
+                let updated =
+                    match select_hunks(hunk_selection, &source_display, &content, &updated) {
+                        Some(selected) => {
+                            hunk_reports.push(selected.report);
+                            selected.content
+                        }
+                        None => updated,
+                    };
                 if !dry_run {
                     write_file_atomic(&source_path, updated.as_bytes())?;
                 }
@@ -1026,10 +1113,132 @@ pub(crate) fn execute_workspace_apply_patch(
         }
     }
 
-    Ok(json!({
+    let mut result = json!({
         "dry_run": dry_run,
         "operations": applied
-    }))
+    });
+    if !hunk_reports.is_empty() {
+        result["hunks"] = Value::Array(hunk_reports);
+    }
+    Ok(result)
+}
+
+fn added_file_content(lines: &[String]) -> String {
+    if lines.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", lines.join("\n"))
+    }
+}
+
+/// Computes the diff each file would get from a write, edit or apply_patch call, so the
+/// approval card can show it and let the user pick hunks.
+pub(crate) fn preview_file_changes(
+    tool_name: &str,
+    arguments: &Value,
+    workspace_root: &Path,
+) -> Result<Vec<FileChangePreview>, String> {
+    match tool_name {
+        WORKSPACE_WRITE_TOOL => {
+            let file_path = resolve_workspace_target(
+                workspace_root,
+                &read_path_argument(arguments, "path")?,
+                true,
+            )?;
+            let content = arguments
+                .get("content")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let operation = if file_path.is_file() { "update" } else { "add" };
+            let before = read_text_lossy(&file_path);
+            let after = if read_bool_argument(arguments, "append", false) {
+                format!("{}{}", before, content)
+            } else {
+                content.to_string()
+            };
+            Ok(vec![FileChangePreview::new(
+                workspace_relative_display_path(workspace_root, &file_path),
+                operation,
+                None,
+                &before,
+                &after,
+            )])
+        }
+        WORKSPACE_EDIT_TOOL => {
+            let file_path = resolve_workspace_target(
+                workspace_root,
+                &read_path_argument(arguments, "path")?,
+                false,
+            )?;
+            let before = fs::read_to_string(&file_path).map_err(|e| e.to_string())?;
+            let new_string = arguments
+                .get("new_string")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let after = edited_content(
+                &before,
+                &read_string_argument(arguments, "old_string")?,
+                new_string,
+                read_bool_argument(arguments, "replace_all", false),
+            );
+            Ok(vec![FileChangePreview::new(
+                workspace_relative_display_path(workspace_root, &file_path),
+                "update",
+                None,
+                &before,
+                &after,
+            )])
+        }
+        WORKSPACE_APPLY_PATCH_TOOL => {
+            let operations = parse_patch(&read_string_argument(arguments, "patch")?)?;
+            operations
+                .into_iter()
+                .map(|operation| match operation {
+                    PatchOperation::Add { path, lines } => {
+                        let target_path = resolve_workspace_target(workspace_root, &path, true)?;
+                        Ok(FileChangePreview::new(
+                            workspace_relative_display_path(workspace_root, &target_path),
+                            "add",
+                            None,
+                            "",
+                            &added_file_content(&lines),
+                        ))
+                    }
+                    PatchOperation::Delete { path } => {
+                        let target_path = resolve_workspace_target(workspace_root, &path, false)?;
+                        Ok(FileChangePreview::new(
+                            workspace_relative_display_path(workspace_root, &target_path),
+                            "delete",
+                            None,
+                            &read_text_lossy(&target_path),
+                            "",
+                        ))
+                    }
+                    PatchOperation::Update {
+                        path,
+                        move_to,
+                        hunks,
+                    } => {
+                        let source_path = resolve_workspace_target(workspace_root, &path, false)?;
+                        let before = fs::read_to_string(&source_path).map_err(|e| e.to_string())?;
+                        let after = apply_hunks_to_content(&before, &hunks)?;
+                        let move_to = move_to
+                            .map(|target| resolve_workspace_target(workspace_root, &target, true))
+                            .transpose()?
+                            .map(|target| workspace_relative_display_path(workspace_root, &target));
+                        Ok(FileChangePreview::new(
+                            workspace_relative_display_path(workspace_root, &source_path),
+                            "update",
+                            move_to,
+                            &before,
+                            &after,
+                        ))
+                    }
+                })
+                .collect()
+        }
+        _ => Ok(Vec::new()),
+    }
 }
 
 pub(crate) fn parse_todo_status(status: Option<&str>) -> Result<TodoStatus, String> {
@@ -1048,10 +1257,11 @@ pub(crate) fn parse_todo_status(status: Option<&str>) -> Result<TodoStatus, Stri
 pub(crate) async fn execute_todo_write(
     arguments: &Value,
     conversation_id: &str,
+    pool: &SqlitePool,
 ) -> Result<Value, String> {
     let action = read_string_argument(arguments, "action")?;
-    let mut store = todo_store().lock().await;
-    let items = store.entry(conversation_id.to_string()).or_default();
+    let _write_guard = todo_plan_write_lock().lock().await;
+    let mut items = load_todo_plan(pool, conversation_id).await?.items;
     let now = Utc::now().to_rfc3339();
 
     match action.as_str() {
@@ -1078,7 +1288,7 @@ pub(crate) async fn execute_todo_write(
                 .get("items")
                 .and_then(Value::as_array)
                 .ok_or_else(|| "'items' is required for action 'set'".to_string())?;
-            items.clear();
+            let previous = std::mem::take(&mut items);
             for raw_item in raw_items {
                 let text = raw_item
                     .get("text")
@@ -1095,11 +1305,16 @@ pub(crate) async fn execute_todo_write(
                     .map(str::to_string)
                     .unwrap_or_else(|| Uuid::new_v4().to_string());
                 let status = parse_todo_status(raw_item.get("status").and_then(Value::as_str))?;
+                let created_at = previous
+                    .iter()
+                    .find(|item| item.id == id)
+                    .map(|item| item.created_at.clone())
+                    .unwrap_or_else(|| now.clone());
                 items.push(TodoItem {
                     id,
                     text,
                     status,
-                    created_at: now.clone(),
+                    created_at,
                     updated_at: now.clone(),
                 });
             }
@@ -1134,26 +1349,31 @@ pub(crate) async fn execute_todo_write(
         }
     }
 
+    let plan = save_todo_plan(pool, conversation_id, items).await?;
     Ok(json!({
         "conversation_id": conversation_id,
         "action": action,
-        "items": items
+        "version": plan.version,
+        "items": plan.items
     }))
 }
 
 pub(crate) async fn execute_todo_read(
     arguments: &Value,
     conversation_id: &str,
+    pool: &SqlitePool,
 ) -> Result<Value, String> {
     let include_done = read_bool_argument(arguments, "include_completed", true);
-    let store = todo_store().lock().await;
-    let mut items = store.get(conversation_id).cloned().unwrap_or_default();
+    let mut plan = load_todo_plan(pool, conversation_id).await?;
     if !include_done {
-        items.retain(|item| !matches!(item.status, TodoStatus::Completed));
+        plan.items
+            .retain(|item| !matches!(item.status, TodoStatus::Completed));
     }
     Ok(json!({
         "conversation_id": conversation_id,
-        "items": items
+        "version": plan.version,
+        "updated_at": plan.updated_at,
+        "items": plan.items
     }))
 }
 
@@ -1196,30 +1416,61 @@ pub(crate) async fn execute_desktop(
 pub(crate) async fn execute_image_understand(
     arguments: &Value,
     workspace_root: &Path,
-    llm_service: &LlmService,
-    default_model: &str,
+    config: &Config,
 ) -> Result<Value, String> {
-    image_tools::execute_image_understand(arguments, workspace_root, llm_service, default_model)
-        .await
+    image_tools::execute_image_understand(arguments, workspace_root, config).await
 }
 
 pub(crate) async fn execute_workspace_process_start(
     arguments: &Value,
     workspace_root: &Path,
+    conversation_id: &str,
+    sandbox: Option<&SandboxPolicy>,
 ) -> Result<Value, String> {
-    process_tools::execute_workspace_process_start(arguments, workspace_root).await
+    process_tools::execute_workspace_process_start(
+        arguments,
+        workspace_root,
+        conversation_id,
+        sandbox,
+    )
+    .await
 }
 
-pub(crate) async fn execute_workspace_process_list(arguments: &Value) -> Result<Value, String> {
-    process_tools::execute_workspace_process_list(arguments).await
+pub(crate) async fn execute_workspace_process_list(
+    arguments: &Value,
+    conversation_id: &str,
+) -> Result<Value, String> {
+    process_tools::execute_workspace_process_list(arguments, conversation_id).await
 }
 
-pub(crate) async fn execute_workspace_process_read(arguments: &Value) -> Result<Value, String> {
-    process_tools::execute_workspace_process_read(arguments).await
+pub(crate) async fn execute_workspace_process_read(
+    arguments: &Value,
+    conversation_id: &str,
+) -> Result<Value, String> {
+    process_tools::execute_workspace_process_read(arguments, conversation_id).await
+}
+
+pub(crate) async fn execute_workspace_process_write(
+    arguments: &Value,
+    conversation_id: &str,
+    sandbox: Option<&SandboxPolicy>,
+) -> Result<Value, String> {
+    process_tools::execute_workspace_process_write(arguments, conversation_id, sandbox).await
 }
 
-pub(crate) async fn execute_workspace_process_terminate(arguments: &Value) -> Result<Value, String> {
-    process_tools::execute_workspace_process_terminate(arguments).await
+pub(crate) async fn execute_workspace_process_terminate(
+    arguments: &Value,
+    conversation_id: &str,
+) -> Result<Value, String> {
+    process_tools::execute_workspace_process_terminate(arguments, conversation_id).await
+}
+
+pub(crate) async fn terminate_conversation_workspace_processes(conversation_id: &str) -> usize {
+    process_tools::terminate_conversation_processes(conversation_id).await
+}
+
+pub(crate) fn terminate_all_workspace_processes() {
+    process_tools::terminate_all_processes()
 }
 pub(crate) async fn execute_skill_list(
     skill_manager_state: &SkillManagerState,
@@ -1334,9 +1585,10 @@ pub(crate) async fn execute_sessions_history(
     let conversation_id = read_string_argument(arguments, "conversation_id")?;
     let limit = read_u64_argument(arguments, "limit", 100).clamp(1, 500) as i64;
 
-    let rows = sqlx::query_as::<_, (String, String, String, String, Option<String>)>(
-        "SELECT role, content, created_at, id, tool_calls FROM messages WHERE conversation_id = ? ORDER BY created_at DESC LIMIT ?",
-    )
+    let rows = sqlx::query_as::<_, (String, String, String, String, Option<String>)>(&format!(
+        "{} SELECT m.role, m.content, m.created_at, m.id, m.tool_calls FROM messages m JOIN branch ON m.id = branch.id ORDER BY branch.depth ASC LIMIT ?",
+        ACTIVE_BRANCH_CTE
+    ))
     .bind(&conversation_id)
     .bind(limit)
     .fetch_all(pool)
@@ -1358,62 +1610,72 @@ pub(crate) async fn execute_sessions_history(
         })
         .collect::<Vec<_>>();
     messages.reverse();
+    let plan = load_todo_plan(pool, &conversation_id).await?;
 
     Ok(json!({
         "conversation_id": conversation_id,
         "limit": limit,
-        "messages": messages
+        "messages": messages,
+        "plan": plan
     }))
 }
 
 pub(crate) async fn execute_sessions_send(
     arguments: &Value,
+    mcp_state: &McpState,
+    skill_manager_state: &SkillManagerState,
+    run_policy: &RunPolicy,
+    workspace_root: &Path,
+    conversation_id: &str,
     pool: &SqlitePool,
-    llm_service: &LlmService,
-    default_model: &str,
 ) -> Result<Value, String> {
-    let conversation_id = read_string_argument(arguments, "conversation_id")?;
+    let target_conversation_id = read_string_argument(arguments, "conversation_id")?;
     let content = read_string_argument(arguments, "content")?;
     let run_assistant = read_bool_argument(arguments, "run_assistant", false);
-    let conversation_model =
-        sqlx::query_scalar::<_, String>("SELECT model FROM conversations WHERE id = ?")
-            .bind(&conversation_id)
+    let conversation_exists =
+        sqlx::query_scalar::<_, String>("SELECT id FROM conversations WHERE id = ?")
+            .bind(&target_conversation_id)
             .fetch_optional(pool)
             .await
-            .map_err(|e| e.to_string())?;
-    if conversation_model.is_none() {
-        return Err(format!("Conversation not found: {}", conversation_id));
+            .map_err(|e| e.to_string())?
+            .is_some();
+    if !conversation_exists {
+        return Err(format!(
+            "Conversation not found: {}",
+            target_conversation_id
+        ));
     }
-    let selected_model = read_optional_string_argument(arguments, "model")
-        .or(conversation_model)
-        .unwrap_or_else(|| default_model.to_string());
-
-    insert_message(pool, &conversation_id, "user", &content, None, None).await?;
 
-    let assistant_response = if run_assistant {
-        let messages = load_conversation_context(pool, &conversation_id).await?;
-        let response = llm_service
-            .chat(&selected_model, messages)
-            .await
-            .map_err(|e| e.to_string())?;
-        insert_message(pool, &conversation_id, "assistant", &response, None, None).await?;
-        Some(response)
-    } else {
-        None
-    };
-
-    Ok(json!({
-        "conversation_id": conversation_id,
-        "model": selected_model,
-        "run_assistant": run_assistant,
-        "assistant_response": assistant_response
-    }))
+    if !run_assistant {
+        session_runs::record_session_user_message(pool, &target_conversation_id, &content).await?;
+        return Ok(json!({
+            "conversation_id": target_conversation_id,
+            "run_assistant": false
+        }));
+    }
+    session_runs::start_session_run(
+        pool,
+        mcp_state,
+        skill_manager_state,
+        run_policy,
+        conversation_id,
+        &target_conversation_id,
+        content,
+        read_optional_string_argument(arguments, "model"),
+        workspace_root,
+        read_bool_argument(arguments, "wait", true),
+    )
+    .await
 }
 
 pub(crate) async fn execute_sessions_spawn(
     arguments: &Value,
+    mcp_state: &McpState,
+    skill_manager_state: &SkillManagerState,
+    run_policy: &RunPolicy,
+    workspace_root: &Path,
+    conversation_id: &str,
     pool: &SqlitePool,
-    llm_service: &LlmService,
     default_model: &str,
 ) -> Result<Value, String> {
     let title = read_string_argument(arguments, "title")?;
@@ -1421,6 +1683,9 @@ pub(crate) async fn execute_sessions_spawn(
         .unwrap_or_else(|| default_model.to_string());
     let content = read_optional_string_argument(arguments, "content");
     let run_assistant = read_bool_argument(arguments, "run_assistant", false);
+    if run_assistant && content.is_none() {
+        return Err("'content' is required when 'run_assistant' is true".to_string());
+    }
     let id = Uuid::new_v4().to_string();
     let now = Utc::now().to_rfc3339();
 
@@ -1435,54 +1700,38 @@ pub(crate) async fn execute_sessions_spawn(
     .execute(pool)
     .await
     .map_err(|e| e.to_string())?;
+    emit_app_event("conversations-changed", json!({ "conversationId": id }));
 
-    if let Some(user_text) = content.clone() {
-        insert_message(pool, &id, "user", &user_text, None, None).await?;
-    }
-
-    let assistant_response = if run_assistant {
-        if content.is_none() {
-            return Err("'content' is required when 'run_assistant' is true".to_string());
-        }
-        let messages = load_conversation_context(pool, &id).await?;
-        let response = llm_service
-            .chat(&model, messages)
-            .await
-            .map_err(|e| e.to_string())?;
-        insert_message(pool, &id, "assistant", &response, None, None).await?;
-        Some(response)
-    } else {
-        None
-    };
-
-    Ok(json!({
+    let mut response = json!({
         "conversation_id": id,
         "title": title,
-        "model": model,
-        "assistant_response": assistant_response
-    }))
+        "model": model
+    });
+    match content {
+        Some(content) if run_assistant => {
+            let run = session_runs::start_session_run(
+                pool,
+                mcp_state,
+                skill_manager_state,
+                run_policy,
+                conversation_id,
+                &id,
+                content,
+                None,
+                workspace_root,
+                read_bool_argument(arguments, "wait", true),
+            )
+            .await?;
+            response["run"] = run;
+        }
+        Some(content) => session_runs::record_session_user_message(pool, &id, &content).await?,
+        None => {}
+    }
+    Ok(response)
 }
 
-pub(crate) fn execute_agents_list() -> Result<Value, String> {
-    Ok(json!({
-        "agents": [
-            {
-                "id": "default",
-                "name": "Default Agent",
-                "description": "General-purpose coding and assistant workflow."
-            },
-            {
-                "id": "planner",
-                "name": "Planner Agent",
-                "description": "Task decomposition and structured planning."
-            },
-            {
-                "id": "analyst",
-                "name": "Analyst Agent",
-                "description": "Investigation and comparative reasoning."
-            }
-        ]
-    }))
+pub(crate) fn execute_agents_list(config: &Config) -> Result<Value, String> {
+    sub_agents::execute_agents_list(config)
 }
 
 pub(crate) fn require_scheduler_manager(
@@ -1598,33 +1847,43 @@ pub(crate) async fn execute_scheduler_runs_list(arguments: &Value) -> Result<Val
 
 pub(crate) async fn execute_core_task(
     arguments: &Value,
-    llm_service: &LlmService,
+    mcp_state: &McpState,
+    skill_manager_state: &SkillManagerState,
+    config: &Config,
+    run_policy: &RunPolicy,
+    workspace_root: &Path,
+    conversation_id: &str,
+    pool: &SqlitePool,
     default_model: &str,
 ) -> Result<Value, String> {
-    let prompt = read_string_argument(arguments, "prompt")?;
-    let model = read_optional_string_argument(arguments, "model")
-        .unwrap_or_else(|| default_model.to_string());
-    let response = llm_service
-        .chat(
-            &model,
-            vec![ChatMessage {
-                role: "user".to_string(),
-                content: Some(prompt.clone()),
-                tool_calls: None,
-                tool_call_id: None,
-                reasoning_details: None,
-                reasoning: None,
-            }],
-        )
-        .await
-        .map_err(|e| e.to_string())?;
+    sub_agents::execute_core_task(
+        arguments,
+        mcp_state,
+        skill_manager_state,
+        config,
+        run_policy,
+        workspace_root,
+        conversation_id,
+        pool,
+        default_model,
+    )
+    .await
+}
 
-    Ok(json!({
-        "model": model,
-        "prompt": prompt,
-        "response": response
-    }))
+/// Runs a synchronous workspace tool on the blocking pool, so parallel read-only calls
+/// actually overlap instead of taking turns on the async worker.
+async fn run_blocking_workspace_tool(
+    run: fn(&Value, &Path) -> Result<Value, String>,
+    arguments: &Value,
+    workspace_root: &Path,
+) -> Result<Value, String> {
+    let arguments = arguments.clone();
+    let workspace_root = workspace_root.to_path_buf();
+    tokio::task::spawn_blocking(move || run(&arguments, &workspace_root))
+        .await
+        .map_err(|e| e.to_string())?
 }
+
 pub(crate) async fn execute_core_batch_safe_tool(
     tool_name: &str,
     arguments: &Value,
@@ -1632,65 +1891,169 @@ pub(crate) async fn execute_core_batch_safe_tool(
     conversation_id: &str,
     skill_manager_state: &SkillManagerState,
     pool: &SqlitePool,
-    llm_service: &LlmService,
-    image_default_model: &str,
-    petool_token: Option<&str>,
+    config: &Config,
 ) -> Result<Value, String> {
-    let resolved_image_model = if image_default_model.trim().is_empty() {
-        "glm-4.6v"
-    } else {
-        image_default_model
-    };
-
     match tool_name {
-        WORKSPACE_LIST_TOOL => execute_workspace_list_directory(arguments, workspace_root),
-        WORKSPACE_READ_TOOL => execute_workspace_read_file(arguments, workspace_root),
-        WORKSPACE_PARSE_PDF_TOOL => execute_workspace_parse_pdf_markdown(arguments, workspace_root),
-        WORKSPACE_GLOB_TOOL => execute_workspace_glob(arguments, workspace_root),
-        WORKSPACE_GREP_TOOL => execute_workspace_grep(arguments, workspace_root),
-        WORKSPACE_CODESEARCH_TOOL => execute_workspace_codesearch(arguments, workspace_root),
-        WORKSPACE_LSP_SYMBOLS_TOOL => execute_workspace_lsp_symbols(arguments, workspace_root),
-        TODO_READ_TOOL => execute_todo_read(arguments, conversation_id).await,
-        TODO_WRITE_TOOL => execute_todo_write(arguments, conversation_id).await,
+        WORKSPACE_LIST_TOOL => {
+            run_blocking_workspace_tool(execute_workspace_list_directory, arguments, workspace_root)
+                .await
+        }
+        WORKSPACE_READ_TOOL => {
+            run_blocking_workspace_tool(execute_workspace_read_file, arguments, workspace_root)
+                .await
+        }
+        WORKSPACE_PARSE_PDF_TOOL => {
+            run_blocking_workspace_tool(
+                execute_workspace_parse_pdf_markdown,
+                arguments,
+                workspace_root,
+            )
+            .await
+        }
+        WORKSPACE_GLOB_TOOL => {
+            run_blocking_workspace_tool(execute_workspace_glob, arguments, workspace_root).await
+        }
+        WORKSPACE_GREP_TOOL => {
+            run_blocking_workspace_tool(execute_workspace_grep, arguments, workspace_root).await
+        }
+        WORKSPACE_CODESEARCH_TOOL => {
+            run_blocking_workspace_tool(execute_workspace_codesearch, arguments, workspace_root)
+                .await
+        }
+        WORKSPACE_LSP_SYMBOLS_TOOL => {
+            run_blocking_workspace_tool(execute_workspace_lsp_symbols, arguments, workspace_root)
+                .await
+        }
+        TODO_READ_TOOL => execute_todo_read(arguments, conversation_id, pool).await,
+        TODO_WRITE_TOOL => execute_todo_write(arguments, conversation_id, pool).await,
         WEB_FETCH_TOOL => execute_web_fetch(arguments).await,
         WEB_SEARCH_TOOL => execute_web_search(arguments).await,
         BROWSER_TOOL => execute_browser(arguments).await,
         BROWSER_NAVIGATE_TOOL => execute_browser_navigate(arguments).await,
         IMAGE_PROBE_TOOL => image_tools::execute_image_probe(arguments, workspace_root).await,
-        OCR_LOCATE_TOOL => image_tools::execute_ocr_locate(arguments, workspace_root, petool_token).await,
-        IMAGE_UNDERSTAND_TOOL => {
-            execute_image_understand(
-                arguments,
-                workspace_root,
-                llm_service,
-                resolved_image_model,
-            )
-            .await
+        OCR_LOCATE_TOOL => {
+            image_tools::execute_ocr_locate(arguments, workspace_root, config.petool_token.as_deref())
+                .await
+        }
+        IMAGE_UNDERSTAND_TOOL => execute_image_understand(arguments, workspace_root, config).await,
+        WORKSPACE_SEMANTIC_SEARCH_TOOL => {
+            execute_workspace_semantic_search(arguments, workspace_root, config, &config.model)
+                .await
         }
         SESSIONS_LIST_TOOL => execute_sessions_list(arguments, pool).await,
         SESSIONS_HISTORY_TOOL => execute_sessions_history(arguments, pool).await,
-        AGENTS_LIST_TOOL => execute_agents_list(),
+        SESSIONS_RUN_STATUS_TOOL => session_runs::execute_sessions_run_status(arguments).await,
+        AGENTS_LIST_TOOL => execute_agents_list(config),
         SKILL_DISCOVER_TOOL => execute_skill_discover(arguments, skill_manager_state).await,
         SKILL_LIST_TOOL => execute_skill_list(skill_manager_state).await,
         _ => Err(format!("Unsupported batch tool: {}", tool_name)),
     }
 }
 
+/// Tools without side effects on the workspace, conversation or shared browser/desktop
+/// state; consecutive calls to these may run concurrently.
+pub(crate) fn is_parallel_safe_tool(tool_name: &str) -> bool {
+    matches!(
+        tool_name,
+        WORKSPACE_LIST_TOOL
+            | WORKSPACE_READ_TOOL
+            | WORKSPACE_PARSE_PDF_TOOL
+            | WORKSPACE_GLOB_TOOL
+            | WORKSPACE_GREP_TOOL
+            | WORKSPACE_CODESEARCH_TOOL
+            | WORKSPACE_LSP_SYMBOLS_TOOL
+            | WORKSPACE_SEMANTIC_SEARCH_TOOL
+            | TODO_READ_TOOL
+            | WEB_FETCH_TOOL
+            | WEB_SEARCH_TOOL
+            | IMAGE_PROBE_TOOL
+            | IMAGE_UNDERSTAND_TOOL
+            | OCR_LOCATE_TOOL
+            | SESSIONS_LIST_TOOL
+            | SESSIONS_HISTORY_TOOL
+            | SESSIONS_RUN_STATUS_TOOL
+            | AGENTS_LIST_TOOL
+            | SKILL_DISCOVER_TOOL
+            | SKILL_LIST_TOOL
+            | MCP_RESOURCES_LIST_TOOL
+            | MCP_PROMPTS_LIST_TOOL
+            | TOOL_SEARCH_TOOL
+    )
+}
+
+/// Splits calls into segments that run one after another. A run of consecutive
+/// parallel-safe calls forms a single segment; every other call gets its own, so a write
+/// never overlaps the reads around it.
+pub(crate) fn partition_tool_call_segments<'a, T>(
+    items: &'a [T],
+    tool_name: impl Fn(&'a T) -> &'a str,
+    max_parallel: usize,
+) -> Vec<Range<usize>> {
+    let mut segments: Vec<Range<usize>> = Vec::new();
+    let mut open_parallel = false;
+    for (index, item) in items.iter().enumerate() {
+        let parallel = max_parallel > 1 && is_parallel_safe_tool(tool_name(item));
+        match segments.last_mut() {
+            Some(segment) if parallel && open_parallel => segment.end = index + 1,
+            _ => segments.push(index..index + 1),
+        }
+        open_parallel = parallel;
+    }
+    segments
+}
+
+/// Executes approved tool calls with up to `max_parallel` in flight. Results are yielded in
+/// input order, each as soon as it and everything before it have finished.
+pub(crate) fn execute_tool_calls_ordered<'a>(
+    mcp_state: &'a McpState,
+    skill_manager_state: &'a SkillManagerState,
+    config: &'a Config,
+    tool_map: &'a HashMap<String, RuntimeTool>,
+    tool_calls: Vec<(ChatToolCall, HunkSelection)>,
+    run_policy: &'a RunPolicy,
+    workspace_root: &'a Path,
+    conversation_id: &'a str,
+    pool: &'a SqlitePool,
+    llm_service: &'a LlmService,
+    default_model: &'a str,
+    max_parallel: usize,
+) -> impl Stream<Item = (ChatToolCall, Result<Value, String>)> + 'a {
+    futures_util::stream::iter(tool_calls)
+        .map(move |(tool_call, hunk_selection)| async move {
+            let result = execute_tool_call(
+                mcp_state,
+                skill_manager_state,
+                config,
+                tool_map,
+                &tool_call,
+                &hunk_selection,
+                run_policy,
+                workspace_root,
+                conversation_id,
+                pool,
+                llm_service,
+                default_model,
+            )
+            .await;
+            (tool_call, result)
+        })
+        .buffered(max_parallel.max(1))
+}
+
 pub(crate) async fn execute_core_batch(
     arguments: &Value,
     workspace_root: &Path,
     conversation_id: &str,
     skill_manager_state: &SkillManagerState,
     pool: &SqlitePool,
-    llm_service: &LlmService,
-    image_default_model: &str,
+    config: &Config,
 ) -> Result<Value, String> {
     let calls = arguments
         .get("tool_calls")
         .and_then(Value::as_array)
         .ok_or_else(|| "'tool_calls' must be an array".to_string())?;
 
-    let mut results = Vec::<Value>::new();
+    let mut parsed_calls = Vec::<(usize, &str, Value)>::new();
     for (index, call) in calls.iter().enumerate() {
         let tool_name = call
             .get("tool")
@@ -1703,42 +2066,56 @@ pub(crate) async fn execute_core_batch(
         if !tool_arguments.is_object() {
             return Err(format!("tool_calls[{}].arguments must be an object", index));
         }
-        if !should_auto_allow_batch_tool(tool_name) {
-            results.push(json!({
-                "index": index,
-                "tool": tool_name,
-                "result": Value::Null,
-                "error": "Tool is not allowed in core_batch"
-            }));
-            continue;
-        }
+        parsed_calls.push((index, tool_name, tool_arguments));
+    }
 
-        match execute_core_batch_safe_tool(
-            tool_name,
-            &tool_arguments,
-            workspace_root,
-            conversation_id,
-            skill_manager_state,
-            pool,
-            llm_service,
-            image_default_model,
-            None, // petool_token not available in core_batch context
-        )
-        .await
-        {
-            Ok(value) => results.push(json!({
-                "index": index,
-                "tool": tool_name,
-                "result": value,
-                "error": Value::Null
-            })),
-            Err(error) => results.push(json!({
-                "index": index,
-                "tool": tool_name,
-                "result": Value::Null,
-                "error": error
-            })),
-        }
+    let max_parallel = config.max_parallel_tool_calls.max(1);
+    let mut results = Vec::<Value>::with_capacity(parsed_calls.len());
+    for segment in partition_tool_call_segments(&parsed_calls, |call| call.1, max_parallel) {
+        // Indices rather than slice references: a closure over `&(usize, &str, Value)` is
+        // higher-ranked and makes the enclosing future fail the `Send` check in spawned runs.
+        let parsed_calls = &parsed_calls;
+        let segment_results: Vec<Value> = futures_util::stream::iter(segment)
+            .map(|position| async move {
+                let (index, tool_name, tool_arguments) = &parsed_calls[position];
+                if !should_auto_allow_batch_tool(tool_name) {
+                    return json!({
+                        "index": index,
+                        "tool": tool_name,
+                        "result": Value::Null,
+                        "error": "Tool is not allowed in core_batch"
+                    });
+                }
+
+                match execute_core_batch_safe_tool(
+                    tool_name,
+                    tool_arguments,
+                    workspace_root,
+                    conversation_id,
+                    skill_manager_state,
+                    pool,
+                    config,
+                )
+                .await
+                {
+                    Ok(value) => json!({
+                        "index": index,
+                        "tool": tool_name,
+                        "result": value,
+                        "error": Value::Null
+                    }),
+                    Err(error) => json!({
+                        "index": index,
+                        "tool": tool_name,
+                        "result": Value::Null,
+                        "error": error
+                    }),
+                }
+            })
+            .buffered(max_parallel)
+            .collect()
+            .await;
+        results.extend(segment_results);
     }
 
     Ok(json!({
@@ -1752,55 +2129,133 @@ pub(crate) async fn execute_runtime_tool_non_scheduler(
     config: &Config,
     runtime_tool: RuntimeTool,
     arguments: &Value,
+    hunk_selection: &HunkSelection,
+    run_policy: &RunPolicy,
     workspace_root: &Path,
     conversation_id: &str,
     pool: &SqlitePool,
-    llm_service: &LlmService,
+    _llm_service: &LlmService,
     default_model: &str,
 ) -> Result<Value, String> {
+    if let Some((tool_name, paths)) = checkpoint_targets(&runtime_tool, arguments, workspace_root) {
+        record_checkpoint(pool, conversation_id, workspace_root, tool_name, &paths).await?;
+    }
+
     match runtime_tool {
         RuntimeTool::Mcp {
             server_name,
             tool_name,
         } => {
-            let mut manager = mcp_state.lock().await;
-            let client = manager
-                .get_client_mut(&server_name)
-                .ok_or_else(|| format!("MCP server '{}' is not connected", server_name))?;
-
+            let client = {
+                let mut manager = mcp_state.lock().await;
+                let client = manager
+                    .get_client_mut(&server_name)
+                    .ok_or_else(|| format!("MCP server '{}' is not connected", server_name))?;
+
+                if let Err(error) = client.sync_roots(workspace_root).await {
+                    eprintln!("[mcp] {} roots update failed: {}", server_name, error);
+                }
+                client.handle().await.map_err(|e| e.to_string())?
+            };
+            let _call_context = enter_mcp_call_context(
+                &server_name,
+                McpCallContext {
+                    conversation_id: conversation_id.to_string(),
+                    workspace_root: workspace_root.to_path_buf(),
+                    model: default_model.to_string(),
+                    pool: pool.clone(),
+                },
+            );
             client
                 .call_tool(&tool_name, arguments.clone())
                 .await
                 .map_err(|e| e.to_string())
         }
+        RuntimeTool::McpResourcesList => {
+            mcp_tools::execute_mcp_resources_list(mcp_state, arguments).await
+        }
+        RuntimeTool::McpResourceRead => {
+            mcp_tools::execute_mcp_resource_read(mcp_state, arguments).await
+        }
+        RuntimeTool::McpResourceSubscribe => {
+            mcp_tools::execute_mcp_resource_subscribe(mcp_state, arguments).await
+        }
+        RuntimeTool::McpPromptsList => {
+            mcp_tools::execute_mcp_prompts_list(mcp_state, arguments).await
+        }
+        RuntimeTool::McpPromptGet => mcp_tools::execute_mcp_prompt_get(mcp_state, arguments).await,
         RuntimeTool::WorkspaceListDirectory => {
-            execute_workspace_list_directory(arguments, workspace_root)
+            run_blocking_workspace_tool(execute_workspace_list_directory, arguments, workspace_root)
+                .await
+        }
+        RuntimeTool::WorkspaceReadFile => {
+            run_blocking_workspace_tool(execute_workspace_read_file, arguments, workspace_root)
+                .await
         }
-        RuntimeTool::WorkspaceReadFile => execute_workspace_read_file(arguments, workspace_root),
         RuntimeTool::WorkspaceParsePdfMarkdown => {
-            execute_workspace_parse_pdf_markdown(arguments, workspace_root)
+            run_blocking_workspace_tool(
+                execute_workspace_parse_pdf_markdown,
+                arguments,
+                workspace_root,
+            )
+            .await
+        }
+        RuntimeTool::WorkspaceWriteFile => {
+            execute_workspace_write_file(arguments, workspace_root, hunk_selection)
+        }
+        RuntimeTool::WorkspaceEditFile => {
+            execute_workspace_edit_file(arguments, workspace_root, hunk_selection)
+        }
+        RuntimeTool::WorkspaceGlob => {
+            run_blocking_workspace_tool(execute_workspace_glob, arguments, workspace_root).await
+        }
+        RuntimeTool::WorkspaceGrep => {
+            run_blocking_workspace_tool(execute_workspace_grep, arguments, workspace_root).await
+        }
+        RuntimeTool::WorkspaceCodeSearch => {
+            run_blocking_workspace_tool(execute_workspace_codesearch, arguments, workspace_root)
+                .await
         }
-        RuntimeTool::WorkspaceWriteFile => execute_workspace_write_file(arguments, workspace_root),
-        RuntimeTool::WorkspaceEditFile => execute_workspace_edit_file(arguments, workspace_root),
-        RuntimeTool::WorkspaceGlob => execute_workspace_glob(arguments, workspace_root),
-        RuntimeTool::WorkspaceGrep => execute_workspace_grep(arguments, workspace_root),
-        RuntimeTool::WorkspaceCodeSearch => execute_workspace_codesearch(arguments, workspace_root),
         RuntimeTool::WorkspaceLspSymbols => {
-            execute_workspace_lsp_symbols(arguments, workspace_root)
+            run_blocking_workspace_tool(execute_workspace_lsp_symbols, arguments, workspace_root)
+                .await
+        }
+        RuntimeTool::WorkspaceSemanticSearch => {
+            execute_workspace_semantic_search(arguments, workspace_root, config, default_model)
+                .await
         }
         RuntimeTool::WorkspaceApplyPatch => {
-            execute_workspace_apply_patch(arguments, workspace_root)
+            execute_workspace_apply_patch(arguments, workspace_root, hunk_selection)
+        }
+        RuntimeTool::WorkspaceCheckpoint => {
+            execute_workspace_checkpoint(arguments, conversation_id, pool).await
         }
         RuntimeTool::WorkspaceRunCommand => {
-            execute_workspace_run_command(arguments, workspace_root).await
+            let sandbox = resolve_sandbox_policy(config, pool, conversation_id).await?;
+            execute_workspace_run_command(arguments, workspace_root, sandbox.as_ref()).await
         }
         RuntimeTool::WorkspaceProcessStart => {
-            execute_workspace_process_start(arguments, workspace_root).await
+            let sandbox = resolve_sandbox_policy(config, pool, conversation_id).await?;
+            execute_workspace_process_start(
+                arguments,
+                workspace_root,
+                conversation_id,
+                sandbox.as_ref(),
+            )
+            .await
+        }
+        RuntimeTool::WorkspaceProcessList => {
+            execute_workspace_process_list(arguments, conversation_id).await
+        }
+        RuntimeTool::WorkspaceProcessRead => {
+            execute_workspace_process_read(arguments, conversation_id).await
+        }
+        RuntimeTool::WorkspaceProcessWrite => {
+            let sandbox = resolve_sandbox_policy(config, pool, conversation_id).await?;
+            execute_workspace_process_write(arguments, conversation_id, sandbox.as_ref()).await
         }
-        RuntimeTool::WorkspaceProcessList => execute_workspace_process_list(arguments).await,
-        RuntimeTool::WorkspaceProcessRead => execute_workspace_process_read(arguments).await,
         RuntimeTool::WorkspaceProcessTerminate => {
-            execute_workspace_process_terminate(arguments).await
+            execute_workspace_process_terminate(arguments, conversation_id).await
         }
         RuntimeTool::SkillInstallFromRepo => {
             let repo_url = read_string_argument(arguments, "repo_url")?;
@@ -1839,14 +2294,26 @@ pub(crate) async fn execute_runtime_tool_non_scheduler(
                 conversation_id,
                 skill_manager_state,
                 pool,
-                llm_service,
-                &config.image_understand_model,
+                config,
             )
             .await
         }
-        RuntimeTool::CoreTask => execute_core_task(arguments, llm_service, default_model).await,
-        RuntimeTool::TodoWrite => execute_todo_write(arguments, conversation_id).await,
-        RuntimeTool::TodoRead => execute_todo_read(arguments, conversation_id).await,
+        RuntimeTool::CoreTask => {
+            execute_core_task(
+                arguments,
+                mcp_state,
+                skill_manager_state,
+                config,
+                run_policy,
+                workspace_root,
+                conversation_id,
+                pool,
+                default_model,
+            )
+            .await
+        }
+        RuntimeTool::TodoWrite => execute_todo_write(arguments, conversation_id, pool).await,
+        RuntimeTool::TodoRead => execute_todo_read(arguments, conversation_id, pool).await,
         RuntimeTool::WebFetch => execute_web_fetch(arguments).await,
         RuntimeTool::WebSearch => execute_web_search(arguments).await,
         RuntimeTool::Browser => execute_browser(arguments).await,
@@ -1855,23 +2322,39 @@ pub(crate) async fn execute_runtime_tool_non_scheduler(
         RuntimeTool::ImageProbe => image_tools::execute_image_probe(arguments, workspace_root).await,
         RuntimeTool::OcrLocate => image_tools::execute_ocr_locate(arguments, workspace_root, config.petool_token.as_deref()).await,
         RuntimeTool::ImageUnderstand => {
-            let image_default_model = if config.image_understand_model.trim().is_empty() {
-                "glm-4.6v"
-            } else {
-                config.image_understand_model.as_str()
-            };
-            execute_image_understand(arguments, workspace_root, llm_service, image_default_model)
-                .await
+            execute_image_understand(arguments, workspace_root, config).await
         }
         RuntimeTool::SessionsList => execute_sessions_list(arguments, pool).await,
         RuntimeTool::SessionsHistory => execute_sessions_history(arguments, pool).await,
         RuntimeTool::SessionsSend => {
-            execute_sessions_send(arguments, pool, llm_service, default_model).await
+            execute_sessions_send(
+                arguments,
+                mcp_state,
+                skill_manager_state,
+                run_policy,
+                workspace_root,
+                conversation_id,
+                pool,
+            )
+            .await
         }
         RuntimeTool::SessionsSpawn => {
-            execute_sessions_spawn(arguments, pool, llm_service, default_model).await
+            execute_sessions_spawn(
+                arguments,
+                mcp_state,
+                skill_manager_state,
+                run_policy,
+                workspace_root,
+                conversation_id,
+                pool,
+                default_model,
+            )
+            .await
+        }
+        RuntimeTool::SessionsRunStatus => {
+            session_runs::execute_sessions_run_status(arguments).await
         }
-        RuntimeTool::AgentsList => execute_agents_list(),
+        RuntimeTool::AgentsList => execute_agents_list(config),
         RuntimeTool::SchedulerJobsList => execute_scheduler_jobs_list(arguments).await,
         RuntimeTool::SchedulerJobCreate => execute_scheduler_job_create(arguments).await,
         RuntimeTool::SchedulerJobUpdate => execute_scheduler_job_update(arguments).await,
@@ -1902,6 +2385,8 @@ pub(crate) async fn execute_runtime_tool(
     config: &Config,
     runtime_tool: RuntimeTool,
     arguments: &Value,
+    hunk_selection: &HunkSelection,
+    run_policy: &RunPolicy,
     workspace_root: &Path,
     conversation_id: &str,
     pool: &SqlitePool,
@@ -1925,6 +2410,8 @@ pub(crate) async fn execute_runtime_tool(
             config,
             runtime_tool,
             arguments,
+            hunk_selection,
+            run_policy,
             workspace_root,
             conversation_id,
             pool,
@@ -1941,6 +2428,8 @@ pub(crate) async fn execute_tool_call(
     config: &Config,
     tool_map: &HashMap<String, RuntimeTool>,
     tool_call: &ChatToolCall,
+    hunk_selection: &HunkSelection,
+    run_policy: &RunPolicy,
     workspace_root: &Path,
     conversation_id: &str,
     pool: &SqlitePool,
@@ -1969,6 +2458,8 @@ pub(crate) async fn execute_tool_call(
         config,
         runtime_tool,
         &arguments,
+        hunk_selection,
+        run_policy,
         workspace_root,
         conversation_id,
         pool,
@@ -1984,6 +2475,8 @@ pub(crate) async fn execute_tool_call_background(
     config: &Config,
     tool_map: &HashMap<String, RuntimeTool>,
     tool_call: &ChatToolCall,
+    hunk_selection: &HunkSelection,
+    run_policy: &RunPolicy,
     workspace_root: &Path,
     conversation_id: &str,
     pool: &SqlitePool,
@@ -2012,6 +2505,8 @@ pub(crate) async fn execute_tool_call_background(
         config,
         runtime_tool,
         &arguments,
+        hunk_selection,
+        run_policy,
         workspace_root,
         conversation_id,
         pool,
@@ -2020,3 +2515,85 @@ pub(crate) async fn execute_tool_call_background(
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn blocking_workspace_tools_overlap() {
+        // Each call waits until both are inside the tool at once, so a serial run times out
+        // instead of depending on how long the calls took.
+        static ARRIVED: std::sync::Mutex<usize> = std::sync::Mutex::new(0);
+        static BOTH_ARRIVED: std::sync::Condvar = std::sync::Condvar::new();
+
+        fn rendezvous(_: &Value, _: &Path) -> Result<Value, String> {
+            let mut arrived = ARRIVED.lock().unwrap();
+            *arrived += 1;
+            BOTH_ARRIVED.notify_all();
+            let (arrived, timeout) = BOTH_ARRIVED
+                .wait_timeout_while(arrived, Duration::from_secs(5), |arrived| *arrived < 2)
+                .unwrap();
+            if timeout.timed_out() {
+                return Err(format!("only {} call(s) running at once", *arrived));
+            }
+            Ok(json!({ "ok": true }))
+        }
+
+        let arguments = json!({});
+        let results: Vec<Result<Value, String>> = futures_util::stream::iter(0..2)
+            .map(|_| run_blocking_workspace_tool(rendezvous, &arguments, Path::new(".")))
+            .buffered(2)
+            .collect()
+            .await;
+
+        assert_eq!(results, vec![Ok(json!({ "ok": true })), Ok(json!({ "ok": true }))]);
+    }
+
+    #[test]
+    fn parallel_segments_never_span_side_effecting_tools() {
+        let names = [
+            WORKSPACE_READ_TOOL,
+            WEB_FETCH_TOOL,
+            WORKSPACE_WRITE_TOOL,
+            WORKSPACE_GREP_TOOL,
+            IMAGE_PROBE_TOOL,
+            TODO_READ_TOOL,
+            WORKSPACE_RUN_TOOL,
+        ];
+        let segments = partition_tool_call_segments(&names, |name| *name, 4);
+        assert_eq!(segments, vec![0..2, 2..3, 3..6, 6..7]);
+
+        let serial = partition_tool_call_segments(&names[..2], |name| *name, 1);
+        assert_eq!(serial, vec![0..1, 1..2]);
+    }
+
+    #[test]
+    fn only_the_approval_hunk_choice_limits_a_write() {
+        let root = std::env::temp_dir().join(format!("petool-hunks-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let before = (1..=20)
+            .map(|line| format!("line {}\n", line))
+            .collect::<String>();
+        let after = before
+            .replace("line 2\n", "line two\n")
+            .replace("line 18\n", "line eighteen\n");
+        let write = |arguments: Value, selection: &HunkSelection| {
+            fs::write(root.join("notes.txt"), &before).unwrap();
+            execute_workspace_write_file(&arguments, &root, selection).unwrap();
+            fs::read_to_string(root.join("notes.txt")).unwrap()
+        };
+
+        let self_approved = json!({
+            "path": "notes.txt",
+            "content": after,
+            "approved_hunks": { "notes.txt": [0] }
+        });
+        assert_eq!(write(self_approved, &HunkSelection::new()), after);
+
+        let selection = HunkSelection::from([("notes.txt".to_string(), vec![0])]);
+        let written = write(json!({ "path": "notes.txt", "content": after }), &selection);
+        assert!(written.contains("line two\n") && written.contains("line 18\n"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
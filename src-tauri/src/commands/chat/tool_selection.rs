@@ -0,0 +1,503 @@
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+
+use crate::models::config::ToolSelectionConfig;
+use crate::services::llm::{ChatTool, ChatToolFunction};
+
+use super::tool_catalog::*;
+use super::{read_string_argument, read_u64_argument, wildcard_match};
+
+/// Profile name that sends every tool even when a default profile is configured.
+pub(crate) const ALL_TOOLS_PROFILE: &str = "all";
+
+const DEFAULT_TOOL_SEARCH_LIMIT: u64 = 5;
+const MAX_TOOL_SEARCH_LIMIT: u64 = 20;
+
+const BUILTIN_TOOL_PROFILES: &[(&str, &[&str])] = &[
+    (
+        "coding",
+        &[
+            "workspace_*",
+            WORKSPACE_RUN_TOOL,
+            "core_*",
+            "todo_*",
+            WEB_FETCH_TOOL,
+            WEB_SEARCH_TOOL,
+            "skill_*",
+            "mcp_*",
+        ],
+    ),
+    (
+        "research",
+        &[
+            "web_*",
+            "browser*",
+            WORKSPACE_LIST_TOOL,
+            WORKSPACE_READ_TOOL,
+            WORKSPACE_WRITE_TOOL,
+            "workspace_search_*",
+            WORKSPACE_SEMANTIC_SEARCH_TOOL,
+            WORKSPACE_PARSE_PDF_TOOL,
+            "image_*",
+            OCR_LOCATE_TOOL,
+            CORE_TASK_TOOL,
+            "todo_*",
+            "sessions_*",
+            "mcp_*",
+        ],
+    ),
+    (
+        "office",
+        &[
+            WORKSPACE_LIST_TOOL,
+            WORKSPACE_READ_TOOL,
+            WORKSPACE_WRITE_TOOL,
+            WORKSPACE_EDIT_TOOL,
+            WORKSPACE_CHECKPOINT_TOOL,
+            WORKSPACE_PARSE_PDF_TOOL,
+            DESKTOP_TOOL,
+            "browser*",
+            "image_*",
+            OCR_LOCATE_TOOL,
+            "web_*",
+            "skill_*",
+            "scheduler_*",
+            "todo_*",
+            "mcp_*",
+        ],
+    ),
+];
+
+/// Kept in every pre-selected turn so the model can always look around the workspace.
+const RELEVANCE_BASELINE_TOOLS: &[&str] = &[
+    WORKSPACE_LIST_TOOL,
+    WORKSPACE_READ_TOOL,
+    TODO_READ_TOOL,
+    TODO_WRITE_TOOL,
+];
+
+/// Tool descriptions are English while most requests are not, so common Chinese words
+/// point at the tool families they usually need.
+const KEYWORD_HINTS: &[(&str, &[&str])] = &[
+    ("网页", &["web_*", "browser*"]),
+    ("网站", &["web_*", "browser*"]),
+    ("链接", &[WEB_FETCH_TOOL, "browser*"]),
+    ("搜索", &[WEB_SEARCH_TOOL, "workspace_search_*"]),
+    (
+        "查找",
+        &["workspace_search_*", WORKSPACE_SEMANTIC_SEARCH_TOOL],
+    ),
+    ("浏览器", &["browser*"]),
+    ("文件", &["workspace_*"]),
+    ("目录", &[WORKSPACE_LIST_TOOL, WORKSPACE_GLOB_TOOL]),
+    ("代码", &["workspace_*", WORKSPACE_RUN_TOOL]),
+    ("命令", &[WORKSPACE_RUN_TOOL, "workspace_process_*"]),
+    ("运行", &[WORKSPACE_RUN_TOOL, "workspace_process_*"]),
+    ("撤销", &[WORKSPACE_CHECKPOINT_TOOL]),
+    ("回滚", &[WORKSPACE_CHECKPOINT_TOOL]),
+    ("图片", &["image_*", OCR_LOCATE_TOOL]),
+    ("截图", &["image_*", OCR_LOCATE_TOOL, DESKTOP_TOOL]),
+    ("识别", &[OCR_LOCATE_TOOL, IMAGE_UNDERSTAND_TOOL]),
+    ("桌面", &[DESKTOP_TOOL]),
+    ("点击", &[DESKTOP_TOOL, BROWSER_TOOL]),
+    ("定时", &["scheduler_*"]),
+    ("提醒", &["scheduler_*"]),
+    ("每天", &["scheduler_*"]),
+    ("技能", &["skill_*"]),
+    ("会话", &["sessions_*"]),
+    ("待办", &["todo_*"]),
+];
+
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "with", "this", "that", "from", "into", "you", "are", "can", "use", "not",
+    "please", "what", "how", "tool", "tools",
+];
+
+pub(crate) async fn resolve_conversation_tool_profile(
+    pool: &SqlitePool,
+    conversation_id: &str,
+) -> Result<Option<String>, String> {
+    sqlx::query_scalar::<_, Option<String>>("SELECT tool_profile FROM conversations WHERE id = ?")
+        .bind(conversation_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())
+        .map(Option::flatten)
+}
+
+/// Built-in profiles first, then custom ones that do not override a built-in name.
+pub(crate) fn list_tool_profile_names(config: &ToolSelectionConfig) -> Vec<String> {
+    let mut names = BUILTIN_TOOL_PROFILES
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect::<Vec<_>>();
+    for profile in &config.profiles {
+        let name = profile.name.trim();
+        if !name.is_empty() && !names.iter().any(|item| item.eq_ignore_ascii_case(name)) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+fn resolve_tool_profile_patterns(config: &ToolSelectionConfig, name: &str) -> Option<Vec<String>> {
+    if let Some(profile) = config
+        .profiles
+        .iter()
+        .find(|profile| profile.name.trim().eq_ignore_ascii_case(name))
+    {
+        return Some(profile.tools.clone());
+    }
+    BUILTIN_TOOL_PROFILES
+        .iter()
+        .find(|(builtin, _)| builtin.eq_ignore_ascii_case(name))
+        .map(|(_, patterns)| patterns.iter().map(|pattern| pattern.to_string()).collect())
+}
+
+fn keyword_tokens(text: &str) -> HashSet<String> {
+    text.split(|ch: char| !ch.is_ascii_alphanumeric())
+        .map(|token| token.to_ascii_lowercase())
+        .filter(|token| token.len() >= 3 && !STOPWORDS.contains(&token.as_str()))
+        .map(|token| token.trim_end_matches('s').to_string())
+        .collect()
+}
+
+fn relevance_score(tool: &ChatTool, query_tokens: &HashSet<String>, query: &str) -> usize {
+    let name = tool.function.name.as_str();
+    let tool_tokens = keyword_tokens(&format!("{} {}", name, tool.function.description));
+    let mut score = query_tokens.intersection(&tool_tokens).count();
+    for (keyword, patterns) in KEYWORD_HINTS {
+        if query.contains(*keyword) && patterns.iter().any(|pattern| wildcard_match(pattern, name))
+        {
+            score += 2;
+        }
+    }
+    if query.contains(&name.to_ascii_lowercase()) {
+        score += 5;
+    }
+    score
+}
+
+/// Tools that share at least one keyword with `query`, best match first.
+fn rank_tools<'a>(tools: impl Iterator<Item = &'a ChatTool>, query: &str) -> Vec<&'a ChatTool> {
+    let query = query.to_lowercase();
+    let query_tokens = keyword_tokens(&query);
+    let mut scored = tools
+        .map(|tool| (relevance_score(tool, &query_tokens, &query), tool))
+        .filter(|(score, _)| *score > 0)
+        .collect::<Vec<_>>();
+    scored.sort_by(|left, right| right.0.cmp(&left.0));
+    scored.into_iter().map(|(_, tool)| tool).collect()
+}
+
+fn tool_search_definition(hidden_count: usize) -> ChatTool {
+    ChatTool {
+        tool_type: "function".to_string(),
+        function: ChatToolFunction {
+            name: TOOL_SEARCH_TOOL.to_string(),
+            description: format!(
+                "Search the {} tools that are not loaded in this turn (file, shell, web, browser, desktop, scheduler, skill and MCP tools) and load the best matches so you can call them next. Use it whenever you need a capability that is missing from your tool list.",
+                hidden_count
+            ),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "What you want to do, or a tool name." },
+                    "limit": { "type": "integer", "description": "Maximum tools to load (default 5, max 20)." }
+                },
+                "required": ["query"]
+            }),
+        },
+    }
+}
+
+/// The tools offered to the model during one turn: the profile's tools, optionally narrowed
+/// to those relevant to the user message, plus whatever `tool_search` has loaded since.
+/// Tools outside the profile are never offered, found by `tool_search` or executed.
+pub(crate) struct ToolSelection {
+    tools: Vec<ChatTool>,
+    allowed: Option<HashSet<String>>,
+    active: Option<HashSet<String>>,
+}
+
+impl ToolSelection {
+    pub fn new(
+        config: &ToolSelectionConfig,
+        tools: Vec<ChatTool>,
+        profile: Option<&str>,
+        user_message: &str,
+    ) -> Self {
+        let profile = profile
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .or(config.default_profile.as_deref().map(str::trim))
+            .filter(|value| !value.is_empty() && !value.eq_ignore_ascii_case(ALL_TOOLS_PROFILE));
+        let allowed = profile.and_then(|name| {
+            let Some(patterns) = resolve_tool_profile_patterns(config, name) else {
+                eprintln!(
+                    "[tools] unknown tool profile '{}', sending every tool",
+                    name
+                );
+                return None;
+            };
+            Some(
+                tools
+                    .iter()
+                    .map(|tool| tool.function.name.as_str())
+                    .filter(|tool_name| {
+                        patterns
+                            .iter()
+                            .any(|pattern| wildcard_match(pattern, tool_name))
+                    })
+                    .map(str::to_string)
+                    .collect::<HashSet<_>>(),
+            )
+        });
+
+        let mut active = allowed.clone();
+        let max_tools = config.max_tools.max(1);
+        let candidates = tools
+            .iter()
+            .filter(|tool| {
+                allowed
+                    .as_ref()
+                    .map_or(true, |allowed| allowed.contains(&tool.function.name))
+            })
+            .collect::<Vec<_>>();
+        if config.relevance_enabled && candidates.len() > max_tools {
+            let mut selected = candidates
+                .iter()
+                .map(|tool| tool.function.name.as_str())
+                .filter(|name| RELEVANCE_BASELINE_TOOLS.contains(name))
+                .map(str::to_string)
+                .collect::<HashSet<_>>();
+            for tool in rank_tools(candidates.into_iter(), user_message) {
+                if selected.len() >= max_tools {
+                    break;
+                }
+                selected.insert(tool.function.name.clone());
+            }
+            active = Some(selected);
+        }
+
+        Self {
+            tools,
+            allowed,
+            active,
+        }
+    }
+
+    fn allows(&self, tool_name: &str) -> bool {
+        tool_name == TOOL_SEARCH_TOOL
+            || self
+                .allowed
+                .as_ref()
+                .map_or(true, |allowed| allowed.contains(tool_name))
+    }
+
+    /// Profile tools that are not offered yet and can be loaded through `tool_search`.
+    fn hidden_tools(&self) -> impl Iterator<Item = &ChatTool> {
+        self.tools.iter().filter(|tool| {
+            self.allows(&tool.function.name)
+                && self
+                    .active
+                    .as_ref()
+                    .is_some_and(|active| !active.contains(&tool.function.name))
+        })
+    }
+
+    /// Rejects calls to tools outside the profile: the model may still name a tool it saw
+    /// earlier in the history, or simply invent one.
+    pub fn ensure_allowed(&self, tool_name: &str) -> Result<(), String> {
+        if self.allows(tool_name) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Tool '{}' is not available in this conversation's tool profile",
+                tool_name
+            ))
+        }
+    }
+
+    pub fn available_tools(&self) -> Vec<ChatTool> {
+        let Some(active) = &self.active else {
+            return self.tools.clone();
+        };
+        let mut tools = self
+            .tools
+            .iter()
+            .filter(|tool| active.contains(&tool.function.name))
+            .cloned()
+            .collect::<Vec<_>>();
+        tools.push(tool_search_definition(self.hidden_tools().count()));
+        tools
+    }
+
+    /// Runs `tool_search`, loading the best hidden matches for the next model round.
+    pub fn search(&mut self, arguments: &Value) -> Result<Value, String> {
+        let query = read_string_argument(arguments, "query")?;
+        let limit = read_u64_argument(arguments, "limit", DEFAULT_TOOL_SEARCH_LIMIT)
+            .clamp(1, MAX_TOOL_SEARCH_LIMIT) as usize;
+        if self.active.is_none() {
+            return Ok(
+                json!({ "query": query, "loaded": [], "note": "Every tool is already loaded." }),
+            );
+        }
+
+        let loaded = rank_tools(self.hidden_tools(), &query)
+            .into_iter()
+            .take(limit)
+            .map(|tool| {
+                (
+                    tool.function.name.clone(),
+                    tool.function.description.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        if let Some(active) = self.active.as_mut() {
+            active.extend(loaded.iter().map(|(name, _)| name.clone()));
+        }
+
+        let hidden = self
+            .hidden_tools()
+            .map(|tool| tool.function.name.clone())
+            .collect::<Vec<_>>();
+        let mut result = json!({
+            "query": query,
+            "loaded": loaded
+                .iter()
+                .map(|(name, description)| json!({ "name": name, "description": description }))
+                .collect::<Vec<_>>(),
+            "remaining_hidden": hidden.len(),
+        });
+        if loaded.is_empty() {
+            result["hidden_tools"] = json!(hidden);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &str, description: &str) -> ChatTool {
+        ChatTool {
+            tool_type: "function".to_string(),
+            function: ChatToolFunction {
+                name: name.to_string(),
+                description: description.to_string(),
+                parameters: json!({}),
+            },
+        }
+    }
+
+    fn names(tools: &[ChatTool]) -> Vec<&str> {
+        tools
+            .iter()
+            .map(|tool| tool.function.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn profiles_preselection_and_tool_search() {
+        let catalog = vec![
+            tool(WORKSPACE_READ_TOOL, "Read a file from the workspace."),
+            tool(WORKSPACE_RUN_TOOL, "Run a shell command in the workspace."),
+            tool(WEB_SEARCH_TOOL, "Search the web for pages."),
+            tool(BROWSER_TOOL, "Control a browser tab."),
+            tool(SCHEDULER_JOB_CREATE_TOOL, "Create a scheduler job."),
+        ];
+
+        let mut config = ToolSelectionConfig::default();
+        let all = ToolSelection::new(&config, catalog.clone(), None, "");
+        assert_eq!(all.available_tools().len(), catalog.len());
+
+        let coding = ToolSelection::new(&config, catalog.clone(), Some("coding"), "");
+        assert_eq!(
+            names(&coding.available_tools()),
+            vec![
+                WORKSPACE_READ_TOOL,
+                WORKSPACE_RUN_TOOL,
+                WEB_SEARCH_TOOL,
+                TOOL_SEARCH_TOOL
+            ]
+        );
+
+        config.default_profile = Some("office".to_string());
+        let explicit_all = ToolSelection::new(&config, catalog.clone(), Some("all"), "");
+        assert_eq!(explicit_all.available_tools().len(), catalog.len());
+
+        config.default_profile = None;
+        config.relevance_enabled = true;
+        config.max_tools = 2;
+        let mut selection = ToolSelection::new(&config, catalog, None, "每天提醒我备份");
+        assert_eq!(
+            names(&selection.available_tools()),
+            vec![
+                WORKSPACE_READ_TOOL,
+                SCHEDULER_JOB_CREATE_TOOL,
+                TOOL_SEARCH_TOOL
+            ]
+        );
+
+        let result = selection
+            .search(&json!({ "query": "open the browser", "limit": 1 }))
+            .unwrap();
+        assert_eq!(result["loaded"][0]["name"], BROWSER_TOOL);
+        assert!(names(&selection.available_tools()).contains(&BROWSER_TOOL));
+    }
+
+    fn coding_catalog() -> Vec<ChatTool> {
+        vec![
+            tool(WORKSPACE_READ_TOOL, "Read a file from the workspace."),
+            tool(WORKSPACE_RUN_TOOL, "Run a shell command in the workspace."),
+            tool(WEB_SEARCH_TOOL, "Search the web for pages."),
+            tool(BROWSER_TOOL, "Control a browser tab."),
+        ]
+    }
+
+    #[test]
+    fn tool_search_only_loads_tools_from_the_profile() {
+        let config = ToolSelectionConfig {
+            relevance_enabled: true,
+            max_tools: 1,
+            ..ToolSelectionConfig::default()
+        };
+        let mut selection =
+            ToolSelection::new(&config, coding_catalog(), Some("coding"), "read the file");
+        assert_eq!(
+            names(&selection.available_tools()),
+            vec![WORKSPACE_READ_TOOL, TOOL_SEARCH_TOOL]
+        );
+
+        let result = selection
+            .search(&json!({ "query": "open a browser tab" }))
+            .unwrap();
+        assert_eq!(result["loaded"], json!([]));
+        assert_eq!(result["remaining_hidden"], 2);
+        let hidden = result["hidden_tools"].as_array().unwrap();
+        assert!(!hidden.contains(&json!(BROWSER_TOOL)));
+
+        let result = selection
+            .search(&json!({ "query": "run a shell command" }))
+            .unwrap();
+        assert_eq!(result["loaded"][0]["name"], WORKSPACE_RUN_TOOL);
+        assert!(!names(&selection.available_tools()).contains(&BROWSER_TOOL));
+    }
+
+    #[test]
+    fn calls_to_tools_outside_the_profile_are_rejected() {
+        let config = ToolSelectionConfig::default();
+        let coding = ToolSelection::new(&config, coding_catalog(), Some("coding"), "");
+        assert!(coding.ensure_allowed(WORKSPACE_RUN_TOOL).is_ok());
+        assert!(coding.ensure_allowed(TOOL_SEARCH_TOOL).is_ok());
+        let error = coding.ensure_allowed(BROWSER_TOOL).unwrap_err();
+        assert!(error.contains(BROWSER_TOOL));
+        assert!(coding.ensure_allowed("made_up_tool").is_err());
+
+        let all = ToolSelection::new(&config, coding_catalog(), None, "");
+        assert!(all.ensure_allowed(BROWSER_TOOL).is_ok());
+    }
+}
@@ -1,6 +1,10 @@
 use crate::commands::skills::SkillManagerState;
 use crate::models::config::Config;
 use crate::services::browser::paths::{browser_profile_user_data_dir, sanitize_profile_name};
+use crate::services::llm_registry::{
+    find_llm_provider, merge_discovered_models, resolve_configured_provider,
+};
+use crate::services::local_llm::{self, LocalModelInfo};
 use crate::utils::{
     ensure_writable_directory, get_app_config_dir, load_config, resolve_default_downloads_dir,
     resolve_effective_downloads_dir, resolve_node_download_cache_dir, resolve_node_runtime_root,
@@ -389,16 +393,40 @@ pub async fn validate_api_key(api_key: String, api_base: Option<String>) -> Resu
     let base = api_base.unwrap_or_else(|| "https://open.bigmodel.cn/api/paas/v4".to_string());
     let url = format!("{}/models", base.trim_end_matches('/'));
 
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+    // 本地服务（Ollama / llama.cpp）通常无需 Key，此时不发送 Authorization 头
+    let mut request = client.get(&url);
+    if !api_key.trim().is_empty() {
+        request = request.header("Authorization", format!("Bearer {}", api_key.trim()));
+    }
+    let response = request.send().await.map_err(|e| e.to_string())?;
 
     Ok(response.status().is_success())
 }
 
+#[tauri::command]
+pub async fn discover_local_models(
+    provider: String,
+    persist: Option<bool>,
+) -> Result<Vec<LocalModelInfo>, String> {
+    let mut config = load_config::<Config>().map_err(|e| e.to_string())?;
+    let provider_config = find_llm_provider(&config, &provider)
+        .ok_or_else(|| format!("Unknown LLM provider: {}", provider))?;
+    let resolved = resolve_configured_provider(&config, &provider_config)?;
+    let models = local_llm::discover_local_models(
+        &resolved.api_base,
+        &resolved.api_key,
+        provider_config.local_backend,
+    )
+    .await?;
+
+    if persist.unwrap_or(true) && !models.is_empty() {
+        let ids: Vec<String> = models.iter().map(|model| model.id.clone()).collect();
+        merge_discovered_models(&mut config, &provider_config.name, &ids);
+        save_config(&config).map_err(|e| e.to_string())?;
+    }
+    Ok(models)
+}
+
 #[tauri::command]
 pub async fn submit_feedback(input: FeedbackDraftInput) -> Result<FeedbackDraftSaved, String> {
     let detail = input.detail.trim();
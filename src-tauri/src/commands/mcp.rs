@@ -1,7 +1,12 @@
-use crate::models::config::McpTransport;
+use crate::models::config::{Config, McpServerConfig, McpTransport};
 use crate::models::mcp::*;
-use crate::services::mcp_client::{HttpTransport, McpClient, McpManager, StdioTransport};
+use crate::services::mcp_client::{
+    connect_client, parse_mcp_servers_json, render_prompt_messages, McpManager,
+};
+use crate::services::secret_store;
+use crate::utils::{load_config, save_config};
 use serde_json::Value;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -13,20 +18,14 @@ pub async fn connect_server(
     name: String,
     config: McpTransport,
 ) -> Result<(), String> {
-    let transport: Box<dyn crate::services::mcp_client::McpTransport> = match config {
-        McpTransport::Stdio { command, args } => {
-            Box::new(StdioTransport::new(&command, &args).map_err(|e| e.to_string())?)
-        }
-        McpTransport::Http { url } => Box::new(HttpTransport::new(url)),
-    };
-
-    let client = McpClient::new(name.clone(), transport)
+    let message_handler = mcp_manager.lock().await.message_handler();
+    let client = connect_client(&name, &config, message_handler)
         .await
         .map_err(|e| e.to_string())?;
 
     let mut manager = mcp_manager.lock().await;
     manager
-        .add_client(name, client)
+        .attach_client(name, config, client)
         .await
         .map_err(|e| e.to_string())?;
 
@@ -39,7 +38,7 @@ pub async fn disconnect_server(
     name: String,
 ) -> Result<(), String> {
     let mut manager = mcp_manager.lock().await;
-    if let Some(client) = manager.remove_client(&name) {
+    if let Some(client) = manager.forget_server(&name) {
         client.shutdown().await.map_err(|e| e.to_string())?;
     }
     Ok(())
@@ -60,10 +59,15 @@ pub async fn call_tool(
     name: String,
     arguments: Value,
 ) -> Result<String, String> {
-    let mut manager = mcp_manager.lock().await;
-    let client = manager
-        .get_client_mut(&server)
-        .ok_or_else(|| format!("Server '{}' not found", server))?;
+    let client = {
+        let mut manager = mcp_manager.lock().await;
+        manager
+            .get_client_mut(&server)
+            .ok_or_else(|| format!("Server '{}' not found", server))?
+            .handle()
+            .await
+            .map_err(|e| e.to_string())?
+    };
 
     let result = client
         .call_tool(&name, arguments)
@@ -103,6 +107,20 @@ pub async fn list_resources(
     Ok(result)
 }
 
+#[tauri::command]
+pub async fn list_resource_templates(
+    mcp_manager: tauri::State<'_, McpState>,
+) -> Result<Vec<(String, ResourceTemplate)>, String> {
+    let manager = mcp_manager.lock().await;
+    let mut result = Vec::new();
+    for (server_name, client) in manager.list_clients() {
+        for template in client.list_resource_templates() {
+            result.push((server_name.clone(), template));
+        }
+    }
+    Ok(result)
+}
+
 #[tauri::command]
 pub async fn list_servers(
     mcp_manager: tauri::State<'_, McpState>,
@@ -117,12 +135,33 @@ pub async fn list_servers(
             tools: client.list_tools(),
             prompts: client.list_prompts(),
             resources: client.list_resources(),
+            resource_templates: client.list_resource_templates(),
         });
     }
 
     Ok(servers)
 }
 
+#[tauri::command]
+pub async fn list_server_statuses(
+    mcp_manager: tauri::State<'_, McpState>,
+) -> Result<Vec<McpServerStatus>, String> {
+    let manager = mcp_manager.lock().await;
+    Ok(manager.server_statuses())
+}
+
+#[tauri::command]
+pub async fn restart_server(
+    mcp_manager: tauri::State<'_, McpState>,
+    name: String,
+) -> Result<(), String> {
+    let mut manager = mcp_manager.lock().await;
+    manager
+        .request_restart(&name)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn disconnect_all_servers(mcp_manager: tauri::State<'_, McpState>) -> Result<(), String> {
     let mut manager = mcp_manager.lock().await;
@@ -147,3 +186,61 @@ pub async fn read_resource(
 
     serde_json::to_string_pretty(&result).map_err(|e| e.to_string())
 }
+
+/// Renders an MCP prompt into text the user can send as the opening message.
+#[tauri::command]
+pub async fn get_prompt(
+    mcp_manager: tauri::State<'_, McpState>,
+    server: String,
+    name: String,
+    arguments: Option<HashMap<String, String>>,
+) -> Result<String, String> {
+    let arguments =
+        serde_json::to_value(arguments.unwrap_or_default()).map_err(|e| e.to_string())?;
+    let mut manager = mcp_manager.lock().await;
+    let client = manager
+        .get_client_mut(&server)
+        .ok_or_else(|| format!("Server '{}' not found", server))?;
+
+    let result = client
+        .get_prompt(&name, arguments)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(render_prompt_messages(&result))
+}
+
+/// Merges servers from a pasted `mcpServers` JSON document into the config; entries with
+/// an existing name replace the old definition.
+#[tauri::command]
+pub async fn import_mcp_servers(json: String) -> Result<Vec<McpServerConfig>, String> {
+    let imported = parse_mcp_servers_json(&json).map_err(|e| e.to_string())?;
+    let mut config = load_config::<Config>().map_err(|e| e.to_string())?;
+    for server in &imported {
+        match config
+            .mcp_servers
+            .iter_mut()
+            .find(|existing| existing.name == server.name)
+        {
+            Some(existing) => *existing = server.clone(),
+            None => config.mcp_servers.push(server.clone()),
+        }
+    }
+    save_config(&config).map_err(|e| e.to_string())?;
+    Ok(imported)
+}
+
+#[tauri::command]
+pub async fn set_mcp_secret(name: String, value: String) -> Result<(), String> {
+    secret_store::set_secret(&name, &value)
+}
+
+#[tauri::command]
+pub async fn delete_mcp_secret(name: String) -> Result<(), String> {
+    secret_store::delete_secret(&name)
+}
+
+#[tauri::command]
+pub async fn has_mcp_secret(name: String) -> Result<bool, String> {
+    secret_store::get_secret(&name).map(|secret| secret.is_some())
+}
@@ -5,3 +5,4 @@ pub mod mcp;
 pub mod petool_account;
 pub mod scheduler;
 pub mod skills;
+pub mod usage;
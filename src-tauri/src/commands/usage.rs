@@ -0,0 +1,48 @@
+use tauri::State;
+
+use crate::models::config::Config;
+use crate::services::usage::{aggregate_usage, UsageAggregate, UsageGroupBy, UsageQuery};
+use crate::state::AppState;
+
+async fn aggregate(
+    state: &State<'_, AppState>,
+    group_by: UsageGroupBy,
+    query: Option<UsageQuery>,
+) -> Result<Vec<UsageAggregate>, String> {
+    let config = crate::utils::load_config::<Config>().map_err(|e| e.to_string())?;
+    let pool = {
+        let guard = state.lock().await;
+        guard.db().pool().clone()
+    };
+    aggregate_usage(
+        &pool,
+        group_by,
+        &query.unwrap_or_default(),
+        &config.model_pricing,
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn get_usage_by_conversation(
+    state: State<'_, AppState>,
+    query: Option<UsageQuery>,
+) -> Result<Vec<UsageAggregate>, String> {
+    aggregate(&state, UsageGroupBy::Conversation, query).await
+}
+
+#[tauri::command]
+pub async fn get_usage_by_model(
+    state: State<'_, AppState>,
+    query: Option<UsageQuery>,
+) -> Result<Vec<UsageAggregate>, String> {
+    aggregate(&state, UsageGroupBy::Model, query).await
+}
+
+#[tauri::command]
+pub async fn get_usage_by_day(
+    state: State<'_, AppState>,
+    query: Option<UsageQuery>,
+) -> Result<Vec<UsageAggregate>, String> {
+    aggregate(&state, UsageGroupBy::Day, query).await
+}
@@ -7,10 +7,10 @@ mod services;
 mod state;
 mod utils;
 
-use commands::{chat, config, fs, mcp, petool_account, scheduler, skills};
+use commands::{chat, config, fs, mcp, petool_account, scheduler, skills, usage};
 use models::config::{AutomationCloseBehavior, Config};
 use services::database::Database;
-use services::mcp_client::McpManager;
+use services::mcp_client::{spawn_mcp_supervisor, McpManager};
 use services::scheduler::initialize_scheduler;
 use services::skill_manager::SkillManager;
 use state::AppState;
@@ -18,7 +18,7 @@ use state::AppStateInner;
 use std::sync::Arc;
 use tauri::menu::{Menu, MenuItem};
 use tauri::tray::{MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent};
-use tauri::{Emitter, Manager, WindowEvent};
+use tauri::{Emitter, Manager, RunEvent, WindowEvent};
 use utils::{load_config, resolve_effective_downloads_dir, resolve_skills_dir};
 
 const TRAY_MENU_OPEN: &str = "tray-open-petool";
@@ -96,15 +96,23 @@ async fn main() {
                 resolve_effective_downloads_dir(initial_config.downloads_directory.as_deref());
             let skills_dir = resolve_skills_dir(&initial_downloads);
             let app_handle = app.handle().clone();
+            chat::init_app_events(app.handle().clone());
 
             // Create app state
             let app_state: AppState = Arc::new(tokio::sync::Mutex::new(AppStateInner::new()));
             app.manage(app_state.clone());
 
             // Create MCP manager
+            let mcp_host = Arc::new(chat::McpHost::new(app.handle().clone()));
+            let mut mcp_manager = McpManager::with_message_handler(mcp_host);
+            let status_app_handle = app.handle().clone();
+            mcp_manager.set_status_listener(Arc::new(move |status| {
+                let _ = status_app_handle.emit("mcp-server-status", status);
+            }));
             let mcp_manager_state: Arc<tokio::sync::Mutex<McpManager>> =
-                Arc::new(tokio::sync::Mutex::new(McpManager::new()));
+                Arc::new(tokio::sync::Mutex::new(mcp_manager));
             app.manage(mcp_manager_state.clone());
+            spawn_mcp_supervisor(mcp_manager_state.clone());
 
             // Create skill manager
             let skill_manager = SkillManager::new(skills_dir)?;
@@ -154,6 +162,7 @@ async fn main() {
             config::get_config,
             config::set_config,
             config::validate_api_key,
+            config::discover_local_models,
             config::open_browser_profile_dir,
             config::reset_browser_profile,
             config::submit_feedback,
@@ -161,16 +170,28 @@ async fn main() {
             // Chat commands
             chat::commands::send_message,
             chat::commands::stream_message,
+            chat::commands::edit_and_resend_message,
+            chat::commands::regenerate_message,
             chat::commands::stop_stream,
             chat::commands::generate_image,
             chat::commands::resolve_tool_approval,
             chat::commands::get_conversations,
             chat::commands::get_messages,
             chat::commands::get_conversation_timeline,
+            chat::commands::get_conversation_plan,
             chat::commands::create_conversation,
             chat::commands::delete_conversation,
             chat::commands::rename_conversation,
             chat::commands::update_conversation_model,
+            chat::commands::set_conversation_fallback_models,
+            chat::commands::set_conversation_tool_profile,
+            chat::commands::set_conversation_sandbox,
+            chat::commands::list_tool_profiles,
+            chat::branches::switch_conversation_branch,
+            chat::branches::fork_conversation,
+            chat::checkpoints::list_workspace_checkpoints,
+            chat::checkpoints::diff_workspace_checkpoint,
+            chat::checkpoints::restore_workspace_checkpoint,
             // File system commands
             fs::select_folder,
             fs::scan_directory,
@@ -185,9 +206,17 @@ async fn main() {
             mcp::call_tool,
             mcp::list_prompts,
             mcp::list_resources,
+            mcp::list_resource_templates,
             mcp::list_servers,
+            mcp::list_server_statuses,
+            mcp::restart_server,
             mcp::disconnect_all_servers,
             mcp::read_resource,
+            mcp::get_prompt,
+            mcp::import_mcp_servers,
+            mcp::set_mcp_secret,
+            mcp::delete_mcp_secret,
+            mcp::has_mcp_secret,
             // Skills commands
             skills::list_skills,
             skills::discover_skills,
@@ -207,6 +236,10 @@ async fn main() {
             scheduler::scheduler_run_heartbeat_now,
             scheduler::scheduler_list_runs,
             scheduler::scheduler_get_run,
+            // Usage commands
+            usage::get_usage_by_conversation,
+            usage::get_usage_by_model,
+            usage::get_usage_by_day,
             // Petool 账户命令
             petool_account::petool_login,
             petool_account::petool_register,
@@ -219,6 +252,11 @@ async fn main() {
             petool_account::petool_create_order,
             petool_account::petool_query_order,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            if let RunEvent::Exit = event {
+                chat::terminate_all_workspace_processes();
+            }
+        });
 }
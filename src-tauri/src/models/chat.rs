@@ -6,6 +6,10 @@ pub struct Conversation {
     pub id: String,
     pub title: String,
     pub model: String,
+    #[serde(default)]
+    pub tool_profile: Option<String>,
+    #[serde(default)]
+    pub sandboxed: Option<bool>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -14,6 +18,8 @@ pub struct Conversation {
 pub struct Message {
     pub id: String,
     pub conversation_id: String,
+    #[serde(default)]
+    pub parent_id: Option<String>,
     pub role: MessageRole,
     pub content: String,
     pub reasoning: Option<String>,
@@ -45,6 +51,10 @@ pub enum TimelineEventType {
     AssistantText,
     AssistantToolCall,
     AssistantToolResult,
+    ModelFallback,
+    CheckpointRestored,
+    PlanUpdated,
+    SubAgentStep,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,4 +73,14 @@ pub struct TimelineEvent {
 pub struct ConversationTimeline {
     pub events: Vec<TimelineEvent>,
     pub legacy: bool,
+    /// User messages on the active branch that have edited or regenerated alternatives.
+    #[serde(default)]
+    pub branches: Vec<TimelineBranch>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineBranch {
+    pub message_id: String,
+    /// Every version of the message, oldest first, including `message_id` itself.
+    pub sibling_ids: Vec<String>,
 }
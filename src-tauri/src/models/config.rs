@@ -68,12 +68,95 @@ fn default_heartbeat_tool_whitelist() -> Vec<String> {
         "sessions_history".to_string(),
         "sessions_send".to_string(),
         "sessions_spawn".to_string(),
+        "sessions_run_status".to_string(),
         "workspace_write_file".to_string(),
         "workspace_edit_file".to_string(),
         "workspace_apply_patch".to_string(),
     ]
 }
 
+fn default_llm_provider_enabled() -> bool {
+    true
+}
+
+fn default_llm_provider_tools_enabled() -> bool {
+    true
+}
+
+fn default_context_compaction_enabled() -> bool {
+    true
+}
+
+fn default_context_compaction_trigger_ratio() -> f64 {
+    0.75
+}
+
+fn default_context_compaction_keep_recent_ratio() -> f64 {
+    0.35
+}
+
+fn default_context_compaction_reserved_output_tokens() -> usize {
+    8_192
+}
+
+fn default_context_compaction_stale_tool_result_chars() -> usize {
+    2_000
+}
+
+fn default_llm_retry_max_retries() -> u32 {
+    3
+}
+
+fn default_llm_retry_initial_backoff_ms() -> u64 {
+    1_000
+}
+
+fn default_llm_retry_max_backoff_ms() -> u64 {
+    20_000
+}
+
+fn default_llm_retry_max_retry_after_ms() -> u64 {
+    60_000
+}
+
+fn default_max_parallel_tool_calls() -> usize {
+    4
+}
+
+fn default_tool_selection_max_tools() -> usize {
+    24
+}
+
+fn default_agent_max_rounds() -> u32 {
+    12
+}
+
+fn default_sandbox_max_memory_mb() -> u64 {
+    2048
+}
+
+fn default_sandbox_max_cpu_seconds() -> u64 {
+    300
+}
+
+fn default_sandbox_max_process_seconds() -> u64 {
+    1800
+}
+
+fn default_sandbox_hidden_paths() -> Vec<String> {
+    [
+        "~/.ssh",
+        "~/.gnupg",
+        "~/.aws",
+        "~/.kube",
+        "~/.docker",
+        "~/.netrc",
+    ]
+    .iter()
+    .map(|path| path.to_string())
+    .collect()
+}
+
 fn default_browser_enabled() -> bool {
     true
 }
@@ -207,6 +290,9 @@ pub struct Config {
     pub tool_path_permissions: Vec<ToolPathPermissionRule>,
     #[serde(default)]
     pub auto_approve_tool_requests: bool,
+    /// 同一轮中只读工具的最大并发数（1 表示逐个执行）
+    #[serde(default = "default_max_parallel_tool_calls")]
+    pub max_parallel_tool_calls: usize,
     #[serde(default = "default_autostart_enabled")]
     pub autostart_enabled: bool,
     #[serde(default = "default_downloads_directory_option")]
@@ -219,6 +305,32 @@ pub struct Config {
     pub desktop: DesktopConfig,
     #[serde(default)]
     pub automation: AutomationConfig,
+    #[serde(default)]
+    pub context_compaction: ContextCompactionConfig,
+    /// 按模型名（或前缀）配置单价，用于估算 token 费用
+    #[serde(default)]
+    pub model_pricing: HashMap<String, ModelPricingConfig>,
+    /// LLM 请求失败时的重试退避与模型回退策略
+    #[serde(default)]
+    pub llm_retry: LlmRetryConfig,
+    /// 自定义模型供应商，按名称覆盖内置的 GLM / Doubao / MiniMax / OpenAI
+    #[serde(default)]
+    pub llm_providers: Vec<LlmProviderConfig>,
+    /// 模型未匹配任何供应商时使用的供应商名称（默认 GLM）
+    #[serde(default)]
+    pub default_llm_provider: Option<String>,
+    /// 从本地服务（Ollama / llama.cpp）发现的模型，按供应商名称保存；优先级低于自定义供应商与 Petool 代理
+    #[serde(default)]
+    pub discovered_llm_models: HashMap<String, Vec<String>>,
+    /// 工具配置档与按消息预选，减少每轮发送给模型的工具定义
+    #[serde(default)]
+    pub tool_selection: ToolSelectionConfig,
+    /// bash 与后台进程的沙箱（仅 Linux，基于 bubblewrap）
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+    /// 可通过 core_task 委派的子代理，按 id 覆盖内置的 default / planner / analyst
+    #[serde(default)]
+    pub agents: Vec<AgentDefinitionConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -310,6 +422,281 @@ impl Default for AutomationConfig {
     }
 }
 
+/// 长会话上下文压缩：超过模型窗口阈值时，把较早的轮次总结为持久化摘要
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextCompactionConfig {
+    #[serde(default = "default_context_compaction_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_context_compaction_trigger_ratio")]
+    pub trigger_ratio: f64,
+    #[serde(default = "default_context_compaction_keep_recent_ratio")]
+    pub keep_recent_ratio: f64,
+    #[serde(default = "default_context_compaction_reserved_output_tokens")]
+    pub reserved_output_tokens: usize,
+    #[serde(default = "default_context_compaction_stale_tool_result_chars")]
+    pub stale_tool_result_chars: usize,
+    /// 按模型名（或前缀）覆盖内置的上下文窗口大小
+    #[serde(default)]
+    pub model_context_windows: HashMap<String, usize>,
+}
+
+impl Default for ContextCompactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_context_compaction_enabled(),
+            trigger_ratio: default_context_compaction_trigger_ratio(),
+            keep_recent_ratio: default_context_compaction_keep_recent_ratio(),
+            reserved_output_tokens: default_context_compaction_reserved_output_tokens(),
+            stale_tool_result_chars: default_context_compaction_stale_tool_result_chars(),
+            model_context_windows: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LlmRetryConfig {
+    /// 429 / 5xx / 网络错误的重试次数（不含首次请求）
+    #[serde(default = "default_llm_retry_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_llm_retry_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_llm_retry_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Retry-After 超过该值时不再等待，直接切换到回退模型
+    #[serde(default = "default_llm_retry_max_retry_after_ms")]
+    pub max_retry_after_ms: u64,
+    /// 默认回退模型链，按顺序尝试；会话可单独覆盖
+    #[serde(default)]
+    pub fallback_models: Vec<String>,
+}
+
+impl Default for LlmRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: default_llm_retry_max_retries(),
+            initial_backoff_ms: default_llm_retry_initial_backoff_ms(),
+            max_backoff_ms: default_llm_retry_max_backoff_ms(),
+            max_retry_after_ms: default_llm_retry_max_retry_after_ms(),
+            fallback_models: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AgentDefinitionConfig {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub system_prompt: String,
+    /// 为空时沿用调用方的模型
+    #[serde(default)]
+    pub model: Option<String>,
+    /// 子代理可用的工具配置档（为空表示全部工具）
+    #[serde(default)]
+    pub tool_profile: Option<String>,
+    /// 最多调用模型的轮数，最后一轮不再提供工具
+    #[serde(default = "default_agent_max_rounds")]
+    pub max_rounds: u32,
+    /// 工作目录，相对路径基于调用方的工作区；为空时与调用方相同
+    #[serde(default)]
+    pub workspace_directory: Option<String>,
+}
+
+impl Default for AgentDefinitionConfig {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            name: String::new(),
+            description: String::new(),
+            system_prompt: String::new(),
+            model: None,
+            tool_profile: None,
+            max_rounds: default_agent_max_rounds(),
+            workspace_directory: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ToolProfileConfig {
+    pub name: String,
+    /// 工具名或通配符，如 "workspace_*"、"mcp__github__*"
+    #[serde(default)]
+    pub tools: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ToolSelectionConfig {
+    /// 会话与定时任务未指定时使用的配置档（为空表示发送全部工具）
+    #[serde(default)]
+    pub default_profile: Option<String>,
+    /// 自定义配置档，按名称覆盖内置的 coding / research / office
+    #[serde(default)]
+    pub profiles: Vec<ToolProfileConfig>,
+    /// 按当前用户消息预选相关工具，其余工具由模型通过 tool_search 按需加载
+    #[serde(default)]
+    pub relevance_enabled: bool,
+    /// 预选时每轮最多发送的工具数
+    #[serde(default = "default_tool_selection_max_tools")]
+    pub max_tools: usize,
+}
+
+impl Default for ToolSelectionConfig {
+    fn default() -> Self {
+        Self {
+            default_profile: None,
+            profiles: Vec::new(),
+            relevance_enabled: false,
+            max_tools: default_tool_selection_max_tools(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SandboxConfig {
+    /// 会话未单独设置时是否在沙箱中执行命令（定时任务与心跳始终使用沙箱）
+    #[serde(default)]
+    pub enabled: bool,
+    /// 沙箱内是否允许访问网络
+    #[serde(default)]
+    pub allow_network: bool,
+    /// 虚拟内存上限（MB，0 表示不限制）
+    #[serde(default = "default_sandbox_max_memory_mb")]
+    pub max_memory_mb: u64,
+    /// 单个命令的 CPU 时间上限（秒，0 表示不限制）
+    #[serde(default = "default_sandbox_max_cpu_seconds")]
+    pub max_cpu_seconds: u64,
+    /// 后台进程最长运行时间（秒，0 表示不限制）
+    #[serde(default = "default_sandbox_max_process_seconds")]
+    pub max_process_seconds: u64,
+    /// 除工作区外允许写入的目录，支持 ~ 开头
+    #[serde(default)]
+    pub writable_paths: Vec<String>,
+    /// 在沙箱中隐藏的目录或文件（如 ~/.ssh），应用配置与数据目录始终隐藏
+    #[serde(default = "default_sandbox_hidden_paths")]
+    pub hidden_paths: Vec<String>,
+    /// 名称像密钥但仍需传入沙箱的环境变量
+    #[serde(default)]
+    pub env_allowlist: Vec<String>,
+    /// bwrap 可执行文件路径（为空时从 PATH 查找）
+    #[serde(default)]
+    pub bwrap_path: Option<String>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allow_network: false,
+            max_memory_mb: default_sandbox_max_memory_mb(),
+            max_cpu_seconds: default_sandbox_max_cpu_seconds(),
+            max_process_seconds: default_sandbox_max_process_seconds(),
+            writable_paths: Vec::new(),
+            hidden_paths: default_sandbox_hidden_paths(),
+            env_allowlist: Vec::new(),
+            bwrap_path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelPricingConfig {
+    pub prompt_per_million: f64,
+    pub completion_per_million: f64,
+    #[serde(default)]
+    pub cached_prompt_per_million: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LlmWireProtocol {
+    #[serde(alias = "openai")]
+    OpenaiChat,
+    #[serde(alias = "anthropic")]
+    AnthropicMessages,
+}
+
+impl Default for LlmWireProtocol {
+    fn default() -> Self {
+        Self::OpenaiChat
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LocalLlmBackend {
+    Ollama,
+    #[serde(alias = "llama.cpp")]
+    LlamaCpp,
+}
+
+/// Where a provider's API key comes from; sources are tried in order.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LlmKeySource {
+    Inline {
+        value: String,
+    },
+    Env {
+        name: String,
+    },
+    /// One of the top-level key fields: `api_key`, `ark_api_key`, `minimax_api_key`.
+    Config {
+        field: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LlmProviderCapabilities {
+    #[serde(default = "default_llm_provider_tools_enabled")]
+    pub tools: bool,
+    #[serde(default)]
+    pub vision: bool,
+    #[serde(default)]
+    pub reasoning: bool,
+    #[serde(default)]
+    pub image_generation: bool,
+}
+
+impl Default for LlmProviderCapabilities {
+    fn default() -> Self {
+        Self {
+            tools: default_llm_provider_tools_enabled(),
+            vision: false,
+            reasoning: false,
+            image_generation: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmProviderConfig {
+    pub name: String,
+    pub api_base: String,
+    /// 为空表示无需鉴权（如本地 Ollama / llama.cpp）
+    #[serde(default)]
+    pub api_key_sources: Vec<LlmKeySource>,
+    #[serde(default)]
+    pub protocol: LlmWireProtocol,
+    /// 模型名，支持 `prefix*` 通配；匹配时忽略大小写
+    #[serde(default)]
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub capabilities: LlmProviderCapabilities,
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    #[serde(default)]
+    pub embedding_dimensions: Option<usize>,
+    /// 本地推理服务类型；设置后支持模型发现，并按本地服务的流式格式解析
+    #[serde(default)]
+    pub local_backend: Option<LocalLlmBackend>,
+    #[serde(default = "default_llm_provider_enabled")]
+    pub enabled: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum ToolPermissionAction {
@@ -335,8 +722,45 @@ pub struct McpServerConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum McpTransport {
-    Stdio { command: String, args: Vec<String> },
-    Http { url: String },
+    #[serde(alias = "stdio")]
+    Stdio {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        /// 传给服务进程的环境变量，值中可用 `${secret:名称}` 引用系统钥匙串中的密钥
+        #[serde(default)]
+        env: HashMap<String, String>,
+        /// 服务进程的工作目录
+        #[serde(default)]
+        cwd: Option<String>,
+    },
+    #[serde(alias = "http")]
+    Http {
+        url: String,
+        /// 每个请求附带的自定义请求头，值中可用 `${secret:名称}` 引用密钥
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        /// 以 `Authorization: Bearer` 发送的令牌
+        #[serde(default)]
+        bearer_token: Option<String>,
+        #[serde(default)]
+        protocol: McpHttpProtocol,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum McpHttpProtocol {
+    /// 先尝试 Streamable HTTP，服务端返回 4xx 时回退到旧版 SSE
+    Auto,
+    Streamable,
+    Sse,
+}
+
+impl Default for McpHttpProtocol {
+    fn default() -> Self {
+        Self::Auto
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -514,12 +938,22 @@ impl Default for Config {
             tool_permissions: HashMap::new(),
             tool_path_permissions: Vec::new(),
             auto_approve_tool_requests: false,
+            max_parallel_tool_calls: default_max_parallel_tool_calls(),
             autostart_enabled: default_autostart_enabled(),
             downloads_directory: default_downloads_directory_option(),
             notifications: NotificationSettingsConfig::default(),
             browser: BrowserConfig::default(),
             desktop: DesktopConfig::default(),
             automation: AutomationConfig::default(),
+            context_compaction: ContextCompactionConfig::default(),
+            llm_retry: LlmRetryConfig::default(),
+            model_pricing: HashMap::new(),
+            llm_providers: Vec::new(),
+            default_llm_provider: None,
+            discovered_llm_models: HashMap::new(),
+            tool_selection: ToolSelectionConfig::default(),
+            sandbox: SandboxConfig::default(),
+            agents: Vec::new(),
         }
     }
 }
@@ -7,6 +7,8 @@ pub struct McpServer {
     pub tools: Vec<Tool>,
     pub prompts: Vec<Prompt>,
     pub resources: Vec<Resource>,
+    #[serde(default)]
+    pub resource_templates: Vec<ResourceTemplate>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,26 +16,34 @@ pub struct ServerCapabilities {
     pub tools: Option<bool>,
     pub prompts: Option<bool>,
     pub resources: Option<bool>,
+    #[serde(default)]
+    pub resource_subscribe: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tool {
     pub name: String,
+    #[serde(default)]
     pub description: String,
+    #[serde(alias = "inputSchema", default)]
     pub input_schema: serde_json::Value,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Prompt {
     pub name: String,
+    #[serde(default)]
     pub description: String,
+    #[serde(default)]
     pub arguments: Vec<PromptArgument>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PromptArgument {
     pub name: String,
+    #[serde(default)]
     pub description: String,
+    #[serde(default)]
     pub required: bool,
 }
 
@@ -41,6 +51,41 @@ pub struct PromptArgument {
 pub struct Resource {
     pub uri: String,
     pub name: String,
+    #[serde(default)]
     pub description: String,
+    #[serde(alias = "mimeType", default)]
     pub mime_type: Option<String>,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceTemplate {
+    #[serde(alias = "uriTemplate")]
+    pub uri_template: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(alias = "mimeType", default)]
+    pub mime_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum McpServerState {
+    Connected,
+    Restarting,
+    Failed,
+    Stopped,
+}
+
+/// Health of one configured MCP server as seen by the supervisor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpServerStatus {
+    pub name: String,
+    pub state: McpServerState,
+    pub last_error: Option<String>,
+    pub stderr: Option<String>,
+    pub restart_attempts: u32,
+    pub tool_count: usize,
+    pub updated_at: String,
+}
@@ -0,0 +1,212 @@
+pub mod search;
+pub mod semantic;
+pub mod symbols;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ignore::gitignore::Gitignore;
+use ignore::{Match, WalkBuilder};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::utils::get_app_data_dir;
+
+/// Dependency, build output and VCS directories that are never worth indexing, even when
+/// no `.gitignore` says so.
+const SKIPPED_DIRECTORIES: &[&str] = &[
+    ".git",
+    "node_modules",
+    "target",
+    "dist",
+    "build",
+    "__pycache__",
+    ".venv",
+    "venv",
+    ".next",
+    "coverage",
+];
+
+/// File names whose changes alter which files `collect_indexable_files` returns.
+pub(crate) const IGNORE_FILE_NAMES: &[&str] = &[".ignore", ".gitignore"];
+
+fn is_skipped_directory(name: &str) -> bool {
+    SKIPPED_DIRECTORIES.contains(&name)
+}
+
+/// Files of the workspace that are worth indexing, honouring `.gitignore`, `.ignore` and
+/// the global git excludes.
+pub fn collect_indexable_files(workspace_root: &Path, max_files: usize) -> Vec<PathBuf> {
+    WalkBuilder::new(workspace_root)
+        .hidden(false)
+        .follow_links(false)
+        .require_git(false)
+        .filter_entry(|entry| {
+            entry.depth() == 0
+                || !entry.file_type().is_some_and(|kind| kind.is_dir())
+                || !entry.file_name().to_str().is_some_and(is_skipped_directory)
+        })
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+        .map(|entry| entry.into_path())
+        .take(max_files)
+        .collect()
+}
+
+pub(crate) fn is_in_skipped_directory(workspace_root: &Path, path: &Path) -> bool {
+    path.strip_prefix(workspace_root)
+        .map(|relative| {
+            relative.components().any(|component| {
+                component
+                    .as_os_str()
+                    .to_str()
+                    .is_some_and(is_skipped_directory)
+            })
+        })
+        .unwrap_or(true)
+}
+
+/// Applies the same ignore files as `collect_indexable_files` to single paths, e.g. those
+/// reported by a file watcher. Matchers are cached per directory for the matcher's lifetime.
+pub(crate) struct IgnoreRules {
+    root: PathBuf,
+    matchers: HashMap<PathBuf, Vec<Gitignore>>,
+    global: Gitignore,
+}
+
+impl IgnoreRules {
+    pub fn new(workspace_root: &Path) -> Self {
+        Self {
+            root: workspace_root.to_path_buf(),
+            matchers: HashMap::new(),
+            global: Gitignore::global().0,
+        }
+    }
+
+    pub fn is_ignored(&mut self, path: &Path) -> bool {
+        if is_in_skipped_directory(&self.root, path) {
+            return true;
+        }
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return true;
+        };
+        let is_dir = path.is_dir();
+        let mut directories = vec![self.root.clone()];
+        if let Some(parent) = relative.parent() {
+            let mut directory = self.root.clone();
+            for component in parent.components() {
+                directory.push(component);
+                directories.push(directory.clone());
+            }
+        }
+
+        // The nearest ignore file decides, and `.ignore` wins over `.gitignore`.
+        for directory in directories.into_iter().rev() {
+            let matchers = self
+                .matchers
+                .entry(directory)
+                .or_insert_with_key(|directory| {
+                    IGNORE_FILE_NAMES
+                        .iter()
+                        .map(|name| directory.join(name))
+                        .filter(|ignore_file| ignore_file.is_file())
+                        .map(|ignore_file| Gitignore::new(ignore_file).0)
+                        .collect()
+                });
+            for matcher in matchers.iter() {
+                match matcher.matched_path_or_any_parents(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+        }
+        self.global
+            .matched_path_or_any_parents(relative, is_dir)
+            .is_ignore()
+    }
+}
+
+pub(crate) fn relative_display_path(workspace_root: &Path, path: &Path) -> String {
+    path.strip_prefix(workspace_root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Per-workspace file under the app data dir, e.g. `code-index/<name>-<hash>.bin.gz`.
+/// `discriminator` separates stores of the same workspace, such as per embedding model.
+pub(crate) fn workspace_storage_path(
+    workspace_root: &Path,
+    directory: &str,
+    discriminator: &str,
+) -> Result<PathBuf, String> {
+    let name = workspace_root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "workspace".to_string())
+        .chars()
+        .map(|ch| if ch.is_alphanumeric() { ch } else { '_' })
+        .collect::<String>();
+    let directory = get_app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join(directory);
+    fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+    let hash = stable_hash(&[
+        workspace_root.to_string_lossy().as_bytes(),
+        discriminator.as_bytes(),
+    ]);
+    Ok(directory.join(format!("{}-{:016x}.bin.gz", name, hash)))
+}
+
+/// Hash for persisted keys and file names; unlike `DefaultHasher` it does not change
+/// between Rust releases.
+pub(crate) fn stable_hash(parts: &[&[u8]]) -> u64 {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&hasher.finalize()[..8]);
+    u64::from_le_bytes(prefix)
+}
+
+pub(crate) fn read_compressed<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = fs::File::open(path).map_err(|e| e.to_string())?;
+    bincode::deserialize_from(GzDecoder::new(BufReader::new(file)))
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+/// Writes through a temporary file so a crash never leaves a truncated store behind.
+pub(crate) fn write_compressed<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let temporary = path.with_extension("tmp");
+    let file = fs::File::create(&temporary).map_err(|e| e.to_string())?;
+    let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::fast());
+    bincode::serialize_into(&mut encoder, value).map_err(|e| e.to_string())?;
+    encoder.finish().map_err(|e| e.to_string())?;
+    fs::rename(&temporary, path).map_err(|e| e.to_string())
+}
+
+/// Serializes in memory so callers can release their locks before `write_compressed_bytes`.
+pub(crate) fn encode_compressed<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    bincode::serialize_into(&mut encoder, value).map_err(|e| e.to_string())?;
+    encoder.finish().map_err(|e| e.to_string())
+}
+
+pub(crate) fn write_compressed_bytes(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, bytes).map_err(|e| e.to_string())?;
+    fs::rename(&temporary, path).map_err(|e| e.to_string())
+}
@@ -0,0 +1,868 @@
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::{
+    collect_indexable_files, is_in_skipped_directory, read_compressed, relative_display_path,
+    workspace_storage_path, write_compressed, IgnoreRules, IGNORE_FILE_NAMES,
+};
+
+const CODE_INDEX_DIRECTORY: &str = "code-index";
+const INDEX_FORMAT_VERSION: u32 = 1;
+const MAX_INDEXED_FILES: usize = 200_000;
+/// Larger files are kept out of the trigram index and always searched directly.
+const MAX_TRIGRAM_FILE_BYTES: u64 = 4_000_000;
+const BINARY_SNIFF_BYTES: usize = 8192;
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// Without a working watcher the index is reconciled by a stat-only walk at most this often.
+const UNWATCHED_RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+/// Safety net against missed watcher events.
+const WATCHED_RESCAN_INTERVAL: Duration = Duration::from_secs(600);
+const RECENCY_HALF_LIFE_HOURS: f64 = 24.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedEntry {
+    relative_path: String,
+    modified_ms: u64,
+    len: u64,
+    binary: bool,
+    /// Sorted, ASCII case-folded byte trigrams; `None` when the file was too large.
+    trigrams: Option<Vec<u32>>,
+}
+
+#[derive(Serialize)]
+struct PersistedIndexRef<'a> {
+    version: u32,
+    root: &'a Path,
+    entries: Vec<&'a IndexedEntry>,
+}
+
+#[derive(Deserialize)]
+struct PersistedIndex {
+    version: u32,
+    root: PathBuf,
+    entries: Vec<IndexedEntry>,
+}
+
+#[derive(Default)]
+struct PendingChanges {
+    paths: HashSet<PathBuf>,
+    rescan: bool,
+}
+
+/// What a content search looks for; used to narrow the files worth reading.
+pub struct CodeSearchQuery<'a> {
+    pub pattern: &'a str,
+    pub regex: bool,
+    pub case_sensitive: bool,
+}
+
+/// Trigram index of one workspace, persisted under the app data dir and kept fresh by a
+/// file watcher.
+pub struct CodeSearchIndex {
+    root: PathBuf,
+    loaded: bool,
+    entries: Vec<Option<IndexedEntry>>,
+    ids: HashMap<String, u32>,
+    postings: HashMap<u32, Vec<u32>>,
+    untrigrammed: HashSet<u32>,
+    pending: Arc<Mutex<PendingChanges>>,
+    watcher: Option<RecommendedWatcher>,
+    last_scan: Option<Instant>,
+    last_saved: Option<Instant>,
+    unsaved_changes: bool,
+}
+
+const INDEX_BUILDING: u8 = 0;
+const INDEX_READY: u8 = 1;
+const INDEX_FAILED: u8 = 2;
+
+/// An index is only queried once its first load and scan, which can walk up to
+/// `MAX_INDEXED_FILES` files, has finished on a background thread.
+#[derive(Clone)]
+struct IndexSlot {
+    index: Arc<Mutex<CodeSearchIndex>>,
+    state: Arc<AtomicU8>,
+}
+
+/// Marks the slot failed if the build thread panics, so searches keep walking the
+/// workspace instead of waiting for an index that never becomes ready.
+struct BuildGuard(Arc<AtomicU8>);
+
+impl Drop for BuildGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.store(INDEX_FAILED, Ordering::Release);
+        }
+    }
+}
+
+static CODE_SEARCH_INDEXES: OnceLock<Mutex<HashMap<PathBuf, IndexSlot>>> = OnceLock::new();
+
+fn start_initial_build(root: PathBuf) -> Result<IndexSlot, String> {
+    let slot = IndexSlot {
+        index: Arc::new(Mutex::new(CodeSearchIndex::new(root.clone()))),
+        state: Arc::new(AtomicU8::new(INDEX_BUILDING)),
+    };
+    let build = slot.clone();
+    std::thread::Builder::new()
+        .name("code-index".to_string())
+        .spawn(move || {
+            let _guard = BuildGuard(build.state.clone());
+            let started = Instant::now();
+            if let Ok(mut index) = build.index.lock() {
+                index.refresh();
+            }
+            build.state.store(INDEX_READY, Ordering::Release);
+            eprintln!(
+                "[code_index] index for {} ready in {:?}",
+                root.display(),
+                started.elapsed()
+            );
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(slot)
+}
+
+fn with_code_search_index<T>(
+    workspace_root: &Path,
+    query: impl FnOnce(&CodeSearchIndex) -> T,
+) -> Result<T, String> {
+    let root = workspace_root.canonicalize().map_err(|e| e.to_string())?;
+    let slot = {
+        let mut indexes = CODE_SEARCH_INDEXES
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .map_err(|_| "Code search index is unavailable".to_string())?;
+        match indexes.get(&root) {
+            Some(slot) => slot.clone(),
+            None => {
+                let slot = start_initial_build(root.clone())?;
+                indexes.insert(root.clone(), slot.clone());
+                slot
+            }
+        }
+    };
+    match slot.state.load(Ordering::Acquire) {
+        INDEX_READY => {}
+        INDEX_FAILED => {
+            return Err(format!(
+                "Code search index for {} failed to build",
+                root.display()
+            ))
+        }
+        _ => {
+            return Err(format!(
+                "Code search index for {} is still being built",
+                root.display()
+            ))
+        }
+    }
+    let mut index = slot
+        .index
+        .lock()
+        .map_err(|_| "Code search index is unavailable".to_string())?;
+    index.refresh();
+    Ok(query(&index))
+}
+
+/// Files that may contain `query`, most relevant first: path matches, then recently
+/// modified files.
+pub fn search_candidate_files(
+    workspace_root: &Path,
+    query: &CodeSearchQuery,
+) -> Result<Vec<PathBuf>, String> {
+    with_code_search_index(workspace_root, |index| index.candidates(query))
+}
+
+/// Every indexed file, most recently modified first.
+pub fn indexed_workspace_files(workspace_root: &Path) -> Result<Vec<PathBuf>, String> {
+    with_code_search_index(workspace_root, |index| index.recent_files())
+}
+
+impl CodeSearchIndex {
+    fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            loaded: false,
+            entries: Vec::new(),
+            ids: HashMap::new(),
+            postings: HashMap::new(),
+            untrigrammed: HashSet::new(),
+            pending: Arc::new(Mutex::new(PendingChanges::default())),
+            watcher: None,
+            last_scan: None,
+            last_saved: None,
+            unsaved_changes: false,
+        }
+    }
+
+    fn refresh(&mut self) {
+        if !self.loaded {
+            self.loaded = true;
+            if let Err(error) = self.load() {
+                eprintln!(
+                    "[code_index] rebuilding index for {}: {}",
+                    self.root.display(),
+                    error
+                );
+            }
+            self.watcher = self.start_watcher();
+        }
+
+        let pending = self
+            .pending
+            .lock()
+            .map(|mut pending| std::mem::take(&mut *pending))
+            .unwrap_or_default();
+        let rescan_interval = if self.watcher.is_some() {
+            WATCHED_RESCAN_INTERVAL
+        } else {
+            UNWATCHED_RESCAN_INTERVAL
+        };
+        let stale = self
+            .last_scan
+            .map_or(true, |last_scan| last_scan.elapsed() >= rescan_interval);
+
+        if pending.rescan || stale || !self.index_changed_paths(&pending.paths) {
+            self.full_scan();
+        }
+        self.compact();
+
+        if self.unsaved_changes
+            && self
+                .last_saved
+                .map_or(true, |last_saved| last_saved.elapsed() >= SAVE_INTERVAL)
+        {
+            match self.save() {
+                Ok(()) => self.unsaved_changes = false,
+                Err(error) => eprintln!("[code_index] failed to save index: {}", error),
+            }
+            self.last_saved = Some(Instant::now());
+        }
+    }
+
+    fn start_watcher(&self) -> Option<RecommendedWatcher> {
+        let pending = self.pending.clone();
+        let root = self.root.clone();
+        let watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+            let Ok(mut pending) = pending.lock() else {
+                return;
+            };
+            match result {
+                Ok(event) => {
+                    if event.need_rescan() {
+                        pending.rescan = true;
+                    }
+                    pending.paths.extend(
+                        event
+                            .paths
+                            .into_iter()
+                            .filter(|path| !is_in_skipped_directory(&root, path)),
+                    );
+                }
+                Err(_) => pending.rescan = true,
+            }
+        });
+        match watcher.and_then(|mut watcher| {
+            watcher.watch(&self.root, RecursiveMode::Recursive)?;
+            Ok(watcher)
+        }) {
+            Ok(watcher) => Some(watcher),
+            Err(error) => {
+                eprintln!(
+                    "[code_index] cannot watch {}, falling back to periodic rescans: {}",
+                    self.root.display(),
+                    error
+                );
+                None
+            }
+        }
+    }
+
+    /// Indexes the paths a watcher reported, new files and directories included, skipping
+    /// ignored ones. Returns false when an ignore file changed and only a full scan can tell
+    /// which files are in scope now.
+    fn index_changed_paths(&mut self, paths: &HashSet<PathBuf>) -> bool {
+        if paths.is_empty() {
+            return true;
+        }
+        let mut rules = IgnoreRules::new(&self.root);
+        let mut changed = Vec::new();
+        for path in paths {
+            let is_ignore_file = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| IGNORE_FILE_NAMES.contains(&name));
+            if is_ignore_file {
+                return false;
+            }
+            if !rules.is_ignored(path) {
+                changed.push(path);
+            }
+        }
+
+        for path in changed {
+            if path.is_dir() {
+                for file in collect_indexable_files(path, MAX_INDEXED_FILES) {
+                    self.index_path(&file);
+                }
+            } else {
+                self.index_path(path);
+            }
+        }
+        true
+    }
+
+    fn full_scan(&mut self) {
+        let files = collect_indexable_files(&self.root, MAX_INDEXED_FILES);
+        let mut seen = HashSet::new();
+        for path in &files {
+            seen.insert(relative_display_path(&self.root, path));
+            self.index_path(path);
+        }
+        let removed = self
+            .ids
+            .keys()
+            .filter(|relative_path| !seen.contains(*relative_path))
+            .cloned()
+            .collect::<Vec<_>>();
+        for relative_path in removed {
+            self.remove(&relative_path);
+        }
+        self.last_scan = Some(Instant::now());
+    }
+
+    /// Re-indexes `path` if its size or mtime changed, or drops it when it is gone.
+    fn index_path(&mut self, path: &Path) {
+        let relative_path = relative_display_path(&self.root, path);
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => return,
+            Err(_) => {
+                self.remove(&relative_path);
+                self.remove_under(&relative_path);
+                return;
+            }
+        };
+        let modified_ms = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+        let unchanged = self
+            .ids
+            .get(&relative_path)
+            .and_then(|id| self.entries[*id as usize].as_ref())
+            .is_some_and(|entry| entry.modified_ms == modified_ms && entry.len == metadata.len());
+        if unchanged {
+            return;
+        }
+
+        match build_entry(path, relative_path.clone(), modified_ms, metadata.len()) {
+            Ok(entry) => self.insert(entry),
+            Err(error) => {
+                eprintln!("[code_index] failed to index {}: {}", relative_path, error);
+                self.remove(&relative_path);
+            }
+        }
+    }
+
+    fn insert(&mut self, entry: IndexedEntry) {
+        self.remove(&entry.relative_path);
+        let id = self.entries.len() as u32;
+        if let Some(trigrams) = &entry.trigrams {
+            for trigram in trigrams {
+                // Ids only grow, so pushing keeps every posting list sorted.
+                self.postings.entry(*trigram).or_default().push(id);
+            }
+        } else {
+            self.untrigrammed.insert(id);
+        }
+        self.ids.insert(entry.relative_path.clone(), id);
+        self.entries.push(Some(entry));
+        self.unsaved_changes = true;
+    }
+
+    fn remove(&mut self, relative_path: &str) {
+        let Some(id) = self.ids.remove(relative_path) else {
+            return;
+        };
+        if let Some(entry) = self.entries[id as usize].take() {
+            for trigram in entry.trigrams.iter().flatten() {
+                if let Some(posting) = self.postings.get_mut(trigram) {
+                    if let Ok(position) = posting.binary_search(&id) {
+                        posting.remove(position);
+                    }
+                    if posting.is_empty() {
+                        self.postings.remove(trigram);
+                    }
+                }
+            }
+        }
+        self.untrigrammed.remove(&id);
+        self.unsaved_changes = true;
+    }
+
+    fn remove_under(&mut self, relative_directory: &str) {
+        let prefix = format!("{}/", relative_directory.trim_end_matches('/'));
+        let removed = self
+            .ids
+            .keys()
+            .filter(|relative_path| relative_path.starts_with(&prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        for relative_path in removed {
+            self.remove(&relative_path);
+        }
+    }
+
+    /// Re-packs ids once removed slots outnumber live ones.
+    fn compact(&mut self) {
+        let live = self.ids.len();
+        if self.entries.len() - live <= live.max(10_000) {
+            return;
+        }
+        let entries = std::mem::take(&mut self.entries)
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        self.ids.clear();
+        self.postings.clear();
+        self.untrigrammed.clear();
+        let unsaved_changes = self.unsaved_changes;
+        for entry in entries {
+            self.insert(entry);
+        }
+        self.unsaved_changes = unsaved_changes;
+    }
+
+    fn load(&mut self) -> Result<(), String> {
+        let path = workspace_storage_path(&self.root, CODE_INDEX_DIRECTORY, "")?;
+        let Some(persisted) = read_compressed::<PersistedIndex>(&path)? else {
+            return Ok(());
+        };
+        if persisted.version != INDEX_FORMAT_VERSION || persisted.root != self.root {
+            return Err("stale index format".to_string());
+        }
+        for entry in persisted.entries {
+            self.insert(entry);
+        }
+        self.unsaved_changes = false;
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = workspace_storage_path(&self.root, CODE_INDEX_DIRECTORY, "")?;
+        write_compressed(
+            &path,
+            &PersistedIndexRef {
+                version: INDEX_FORMAT_VERSION,
+                root: &self.root,
+                entries: self.entries.iter().flatten().collect(),
+            },
+        )
+    }
+
+    fn live_entries(&self) -> impl Iterator<Item = (u32, &IndexedEntry)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(id, entry)| entry.as_ref().map(|entry| (id as u32, entry)))
+    }
+
+    fn candidates(&self, query: &CodeSearchQuery) -> Vec<PathBuf> {
+        let literals = required_literals(query);
+        let mut trigrams = literals
+            .iter()
+            .flat_map(|literal| literal_trigrams(literal))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let ids = if trigrams.is_empty() {
+            self.live_entries().map(|(id, _)| id).collect::<Vec<_>>()
+        } else {
+            let empty = Vec::new();
+            trigrams.sort_by_key(|trigram| self.postings.get(trigram).map_or(0, Vec::len));
+            let mut postings = trigrams
+                .iter()
+                .map(|trigram| self.postings.get(trigram).unwrap_or(&empty));
+            let mut ids = postings.next().cloned().unwrap_or_default();
+            for posting in postings {
+                ids.retain(|id| posting.binary_search(id).is_ok());
+            }
+            ids.extend(self.untrigrammed.iter().copied());
+            ids
+        };
+
+        let terms = path_terms(&literals);
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+        let mut ranked = ids
+            .into_iter()
+            .filter_map(|id| self.entries[id as usize].as_ref())
+            .filter(|entry| !entry.binary)
+            .map(|entry| (rank_score(entry, &terms, now_ms), entry))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|left, right| {
+            right
+                .0
+                .total_cmp(&left.0)
+                .then_with(|| left.1.relative_path.cmp(&right.1.relative_path))
+        });
+        ranked
+            .into_iter()
+            .map(|(_, entry)| self.root.join(&entry.relative_path))
+            .collect()
+    }
+
+    fn recent_files(&self) -> Vec<PathBuf> {
+        let mut entries = self
+            .live_entries()
+            .map(|(_, entry)| entry)
+            .collect::<Vec<_>>();
+        entries.sort_by(|left, right| {
+            right
+                .modified_ms
+                .cmp(&left.modified_ms)
+                .then_with(|| left.relative_path.cmp(&right.relative_path))
+        });
+        entries
+            .into_iter()
+            .map(|entry| self.root.join(&entry.relative_path))
+            .collect()
+    }
+}
+
+fn build_entry(
+    path: &Path,
+    relative_path: String,
+    modified_ms: u64,
+    len: u64,
+) -> Result<IndexedEntry, String> {
+    if len > MAX_TRIGRAM_FILE_BYTES {
+        let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+        fs::File::open(path)
+            .and_then(|file| file.take(BINARY_SNIFF_BYTES as u64).read_to_end(&mut head))
+            .map_err(|e| e.to_string())?;
+        return Ok(IndexedEntry {
+            relative_path,
+            modified_ms,
+            len,
+            binary: is_binary(&head),
+            trigrams: None,
+        });
+    }
+
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let binary = is_binary(&bytes);
+    Ok(IndexedEntry {
+        relative_path,
+        modified_ms,
+        len,
+        binary,
+        trigrams: Some(if binary {
+            Vec::new()
+        } else {
+            literal_trigrams(&bytes)
+        }),
+    })
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(BINARY_SNIFF_BYTES).any(|byte| *byte == 0)
+}
+
+/// Sorted, deduplicated trigrams of `bytes` after ASCII case folding.
+fn literal_trigrams(bytes: &[u8]) -> Vec<u32> {
+    let mut trigrams = bytes
+        .windows(3)
+        .map(|window| {
+            (u32::from(window[0].to_ascii_lowercase()) << 16)
+                | (u32::from(window[1].to_ascii_lowercase()) << 8)
+                | u32::from(window[2].to_ascii_lowercase())
+        })
+        .collect::<Vec<_>>();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+/// Literal byte strings every match must contain. Empty when nothing can be said, in
+/// which case every file is a candidate.
+fn required_literals(query: &CodeSearchQuery) -> Vec<Vec<u8>> {
+    let literals = if query.regex {
+        regex_required_literals(query.pattern)
+    } else {
+        vec![query.pattern.to_string()]
+    };
+    literals
+        .iter()
+        .flat_map(|literal| {
+            // Index trigrams only fold ASCII case; other cased letters cannot be relied on
+            // in a case-insensitive search.
+            literal.split(|ch: char| {
+                !query.case_sensitive && !ch.is_ascii() && (ch.is_lowercase() || ch.is_uppercase())
+            })
+        })
+        .filter(|literal| literal.len() >= 3)
+        .map(|literal| literal.as_bytes().to_vec())
+        .collect()
+}
+
+/// Conservative literal extraction: only runs outside groups and classes count, an
+/// optional character ends the run before it, and top-level alternation gives up.
+fn regex_required_literals(pattern: &str) -> Vec<String> {
+    let mut literals = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut chars = pattern.chars().peekable();
+
+    fn flush(current: &mut String, literals: &mut Vec<String>) {
+        if !current.is_empty() {
+            literals.push(std::mem::take(current));
+        }
+    }
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(escaped) if escaped.is_ascii_punctuation() => {
+                    if depth == 0 {
+                        current.push(escaped);
+                    }
+                }
+                Some(escaped) => {
+                    flush(&mut current, &mut literals);
+                    if matches!(escaped, 'x' | 'u' | 'U' | 'p' | 'P') {
+                        if chars.peek() == Some(&'{') {
+                            for next in chars.by_ref() {
+                                if next == '}' {
+                                    break;
+                                }
+                            }
+                        } else if matches!(escaped, 'p' | 'P') {
+                            chars.next();
+                        } else {
+                            let digits = match escaped {
+                                'x' => 2,
+                                'u' => 4,
+                                _ => 8,
+                            };
+                            for _ in 0..digits {
+                                if chars.next_if(|next| next.is_ascii_hexdigit()).is_none() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+                None => break,
+            },
+            '[' => {
+                flush(&mut current, &mut literals);
+                let mut first = true;
+                while let Some(next) = chars.next() {
+                    match next {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '^' if first => continue,
+                        ']' if !first => break,
+                        _ => {}
+                    }
+                    first = false;
+                }
+            }
+            '(' => {
+                flush(&mut current, &mut literals);
+                depth += 1;
+            }
+            ')' => {
+                flush(&mut current, &mut literals);
+                depth = depth.saturating_sub(1);
+            }
+            '|' if depth == 0 => return Vec::new(),
+            '?' | '*' => {
+                current.pop();
+                flush(&mut current, &mut literals);
+            }
+            '{' => {
+                current.pop();
+                flush(&mut current, &mut literals);
+                for next in chars.by_ref() {
+                    if next == '}' {
+                        break;
+                    }
+                }
+            }
+            '+' | '.' | '^' | '$' | '|' => flush(&mut current, &mut literals),
+            _ if depth == 0 => current.push(ch),
+            _ => {}
+        }
+    }
+    flush(&mut current, &mut literals);
+    literals
+}
+
+fn path_terms(literals: &[Vec<u8>]) -> Vec<String> {
+    literals
+        .iter()
+        .flat_map(|literal| {
+            String::from_utf8_lossy(literal)
+                .split(|ch: char| !ch.is_alphanumeric())
+                .filter(|term| term.chars().count() >= 3)
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn rank_score(entry: &IndexedEntry, terms: &[String], now_ms: u64) -> f64 {
+    let path = entry.relative_path.to_lowercase();
+    let file_name = path.rsplit('/').next().unwrap_or(&path);
+    let mut score = 0.0;
+    for term in terms {
+        if file_name.contains(term.as_str()) {
+            score += 4.0;
+        } else if path.contains(term.as_str()) {
+            score += 2.0;
+        }
+    }
+    let age_hours = now_ms.saturating_sub(entry.modified_ms) as f64 / 3_600_000.0;
+    score += 2.0 * 0.5f64.powf(age_hours / RECENCY_HALF_LIFE_HOURS);
+    score - 0.05 * path.matches('/').count() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literals(pattern: &str, regex: bool, case_sensitive: bool) -> Vec<String> {
+        required_literals(&CodeSearchQuery {
+            pattern,
+            regex,
+            case_sensitive,
+        })
+        .into_iter()
+        .map(|literal| String::from_utf8(literal).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn extracts_literals_and_filters_by_trigrams() {
+        assert_eq!(literals("fn main()", false, false), vec!["fn main()"]);
+        assert_eq!(
+            literals(r"colou?r_picker\(\w+", true, false),
+            vec!["colo", "r_picker("]
+        );
+        assert_eq!(
+            literals(r"async fn (foo|bar)", true, true),
+            vec!["async fn "]
+        );
+        assert!(literals("load|save", true, false).is_empty());
+        assert_eq!(
+            literals(r"\x41bcd[a-z]{2}Def", true, false),
+            vec!["bcd", "Def"]
+        );
+        assert_eq!(literals("ÉcoleNormale", false, false), vec!["coleNormale"]);
+
+        let mut index = CodeSearchIndex::new(PathBuf::from("/workspace"));
+        let entry = |path: &str, text: &str, modified_ms: u64| IndexedEntry {
+            relative_path: path.to_string(),
+            modified_ms,
+            len: text.len() as u64,
+            binary: false,
+            trigrams: Some(literal_trigrams(text.as_bytes())),
+        };
+        index.insert(entry("src/a.rs", "fn load_config() {}", 1));
+        index.insert(entry("src/config.rs", "pub fn LOAD_CONFIG() {}", 2));
+        index.insert(entry("README.md", "nothing here", 3));
+        let query = CodeSearchQuery {
+            pattern: "load_config",
+            regex: false,
+            case_sensitive: false,
+        };
+        assert_eq!(
+            index.candidates(&query),
+            vec![
+                PathBuf::from("/workspace/src/config.rs"),
+                PathBuf::from("/workspace/src/a.rs")
+            ]
+        );
+
+        index.insert(entry("src/config.rs", "pub fn other() {}", 4));
+        index.remove("src/a.rs");
+        assert!(index.candidates(&query).is_empty());
+        assert_eq!(
+            index.recent_files()[0],
+            PathBuf::from("/workspace/src/config.rs")
+        );
+    }
+
+    #[test]
+    fn watcher_paths_are_indexed_one_by_one_unless_ignored() {
+        let root = std::env::temp_dir().join(format!("petool-code-index-{}", uuid::Uuid::new_v4()));
+        let write = |relative: &str, text: &str| {
+            let path = root.join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, text).unwrap();
+            path
+        };
+        write(".gitignore", "*.log\n");
+        write("logs/.gitignore", "!keep.log\n");
+        let changed = [
+            write("src/new.rs", "fn new() {}"),
+            write("debug.log", "noise"),
+            write("logs/keep.log", "kept"),
+            write("coverage/lcov.info", "coverage"),
+            write(".next/server.js", "build output"),
+        ];
+        write("added/inner.rs", "fn inner() {}");
+        write("added/trace.log", "noise");
+
+        let mut index = CodeSearchIndex::new(root.clone());
+        let mut paths = changed.into_iter().collect::<HashSet<_>>();
+        paths.insert(root.join("added"));
+        assert!(index.index_changed_paths(&paths));
+        let mut indexed = index.ids.keys().cloned().collect::<Vec<_>>();
+        indexed.sort();
+        assert_eq!(
+            indexed,
+            vec!["added/inner.rs", "logs/keep.log", "src/new.rs"]
+        );
+        assert!(index.last_scan.is_none());
+
+        fs::remove_file(root.join("src/new.rs")).unwrap();
+        assert!(index.index_changed_paths(&HashSet::from([root.join("src/new.rs")])));
+        assert!(!index.ids.contains_key("src/new.rs"));
+
+        let rules_changed = HashSet::from([write(".gitignore", "")]);
+        assert!(!index.index_changed_paths(&rules_changed));
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn a_panicking_build_marks_the_index_failed() {
+        let state = Arc::new(AtomicU8::new(INDEX_BUILDING));
+        let build_state = state.clone();
+        let result = std::thread::spawn(move || {
+            let _guard = BuildGuard(build_state);
+            panic!("walk failed");
+        })
+        .join();
+        assert!(result.is_err());
+        assert_eq!(state.load(Ordering::Acquire), INDEX_FAILED);
+    }
+}
@@ -0,0 +1,718 @@
+use mem0_rust::embeddings::Embedder;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::UNIX_EPOCH;
+
+use super::search::indexed_workspace_files;
+use super::{
+    collect_indexable_files, encode_compressed, read_compressed, relative_display_path,
+    stable_hash, workspace_storage_path, write_compressed_bytes,
+};
+
+const SEMANTIC_INDEX_DIRECTORY: &str = "semantic-index";
+const STORE_FORMAT_VERSION: u32 = 2;
+const CHUNK_LINES: usize = 40;
+const CHUNK_OVERLAP_LINES: usize = 10;
+const MAX_CHUNK_CHARS: usize = 2_400;
+const MAX_CHUNKS_PER_FILE: usize = 48;
+const MAX_SEMANTIC_FILE_BYTES: u64 = 512_000;
+const MAX_SEMANTIC_FILES: usize = 5_000;
+const EMBED_BATCH_SIZE: usize = 32;
+/// New chunks embedded per search; the first index of a big workspace fills in over
+/// several searches, most recently modified files first.
+const MAX_EMBEDDED_CHUNKS_PER_SEARCH: usize = 1_500;
+const MAX_SNIPPET_CHARS: usize = 1_200;
+const MOCK_EMBEDDER_MODEL: &str = "mock-hash-embedder";
+
+const SEMANTIC_EXTENSIONS: &[&str] = &[
+    "rs", "ts", "tsx", "js", "jsx", "mjs", "cjs", "vue", "svelte", "py", "go", "java", "kt", "c",
+    "h", "cc", "cpp", "hpp", "cs", "swift", "rb", "php", "scala", "lua", "sh", "ps1", "sql", "md",
+    "mdx", "txt", "rst", "toml", "yaml", "yml", "json", "html", "css", "scss",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EmbeddedChunk {
+    start_line: usize,
+    end_line: usize,
+    hash: u64,
+    vector: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EmbeddedFile {
+    modified_ms: u64,
+    len: u64,
+    chunks: Vec<EmbeddedChunk>,
+}
+
+/// Chunk vectors of one workspace for one embedding model.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VectorStore {
+    version: u32,
+    model: String,
+    dimensions: usize,
+    files: HashMap<String, EmbeddedFile>,
+}
+
+struct PendingChunk {
+    start_line: usize,
+    end_line: usize,
+    hash: u64,
+    text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SemanticHit {
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub score: f32,
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SemanticSearchOutcome {
+    pub hits: Vec<SemanticHit>,
+    pub model: String,
+    /// True when no embedding model is configured and vectors are lexical hashes.
+    pub lexical_fallback: bool,
+    pub indexed_files: usize,
+    pub embedded_chunks: usize,
+    /// Changed files left for a later search because of the per-search embedding budget.
+    pub pending_files: usize,
+}
+
+/// A file whose chunks changed since the store last saw it.
+struct FileUpdate {
+    relative_path: String,
+    modified_ms: u64,
+    len: u64,
+    chunks: Vec<PendingChunk>,
+    vectors: HashMap<u64, Vec<f32>>,
+}
+
+#[derive(Default)]
+struct SyncPlan {
+    seen: HashSet<String>,
+    updates: Vec<FileUpdate>,
+    pending_files: usize,
+}
+
+struct FileStamp {
+    path: PathBuf,
+    relative_path: String,
+    modified_ms: u64,
+    len: u64,
+}
+
+/// A file whose stamp differs from the store, with the vectors it had last time.
+struct ChangedFile {
+    stamp: FileStamp,
+    previous: Option<(u64, u64)>,
+    vectors: HashMap<u64, Vec<f32>>,
+}
+
+#[derive(Default)]
+struct StoreState {
+    store: Option<VectorStore>,
+    generation: u64,
+}
+
+/// Per workspace and model. The store lock only covers in-memory work; loading, reading
+/// files and saving happen outside it, and `saved_generation` keeps saves in order.
+#[derive(Default)]
+struct SemanticSlot {
+    state: Mutex<StoreState>,
+    saved_generation: Mutex<u64>,
+}
+
+type StoreSlot = Arc<SemanticSlot>;
+
+static SEMANTIC_STORES: OnceLock<Mutex<HashMap<(PathBuf, String), StoreSlot>>> = OnceLock::new();
+
+fn store_slot(root: &Path, model: &str) -> Result<StoreSlot, String> {
+    Ok(SEMANTIC_STORES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .map_err(|_| "Semantic index is unavailable".to_string())?
+        .entry((root.to_path_buf(), model.to_string()))
+        .or_default()
+        .clone())
+}
+
+fn with_vector_store<T>(
+    slot: &SemanticSlot,
+    root: &Path,
+    model: &str,
+    dimensions: usize,
+    apply: impl FnOnce(&mut StoreState) -> T,
+) -> Result<T, String> {
+    let lock = || {
+        slot.state
+            .lock()
+            .map_err(|_| "Semantic index is unavailable".to_string())
+    };
+    if lock()?.store.is_none() {
+        let loaded = load_store(root, model);
+        lock()?.store.get_or_insert(loaded);
+    }
+    let mut state = lock()?;
+    let store = state.store.get_or_insert_with(VectorStore::default);
+    if store.model != model || store.dimensions != dimensions {
+        *store = VectorStore {
+            version: STORE_FORMAT_VERSION,
+            model: model.to_string(),
+            dimensions,
+            files: HashMap::new(),
+        };
+    }
+    Ok(apply(&mut state))
+}
+
+/// Brings the workspace's vectors up to date (re-embedding only changed chunks) and
+/// returns the `limit` chunks closest to `query` among files accepted by `include`.
+pub async fn semantic_search(
+    workspace_root: &Path,
+    embedder: &dyn Embedder,
+    query: &str,
+    include: impl Fn(&Path) -> bool + Send + 'static,
+    limit: usize,
+) -> Result<SemanticSearchOutcome, String> {
+    let root = workspace_root.canonicalize().map_err(|e| e.to_string())?;
+    let model = embedder.model_name().to_string();
+    let dimensions = embedder.dimensions();
+    let slot = store_slot(&root, &model)?;
+
+    let (sync_root, sync_model, sync_slot) = (root.clone(), model.clone(), slot.clone());
+    let mut plan = tokio::task::spawn_blocking(move || {
+        let files = indexed_workspace_files(&sync_root)
+            .unwrap_or_else(|_| collect_indexable_files(&sync_root, 40_000))
+            .into_iter()
+            .filter(|path| is_semantic_source(path))
+            .take(MAX_SEMANTIC_FILES)
+            .collect::<Vec<_>>();
+        let stamps = stamp_files(&sync_root, &files);
+        let (seen, changed) =
+            with_vector_store(&sync_slot, &sync_root, &sync_model, dimensions, |state| {
+                changed_files(state.store.as_ref().expect("store is loaded"), stamps)
+            })?;
+        Ok::<_, String>(plan_sync(seen, changed, MAX_EMBEDDED_CHUNKS_PER_SEARCH))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    let embedded_chunks = embed_updates(&mut plan.updates, embedder).await?;
+    let query_vector = normalized(
+        embedder
+            .embed(query)
+            .await
+            .map_err(|e| format!("Failed to embed query: {}", e))?,
+    );
+
+    let pending_files = plan.pending_files;
+    let (hits, indexed_files) = {
+        let (root, model) = (root.clone(), model.clone());
+        tokio::task::spawn_blocking(move || {
+            let (ranked, indexed_files, save) =
+                with_vector_store(&slot, &root, &model, dimensions, |state| {
+                    let store = state.store.as_mut().expect("store is loaded");
+                    let save = if apply_sync(store, plan) {
+                        state.generation += 1;
+                        Some((state.generation, encode_compressed(&*store)))
+                    } else {
+                        None
+                    };
+                    let store = state.store.as_ref().expect("store is loaded");
+                    let ranked = rank_chunks(
+                        store,
+                        &query_vector,
+                        |path| include(&root.join(path)),
+                        limit,
+                    )
+                    .into_iter()
+                    .map(|(path, chunk, score)| {
+                        (path.to_string(), chunk.start_line, chunk.end_line, score)
+                    })
+                    .collect::<Vec<_>>();
+                    (ranked, store.files.len(), save)
+                })?;
+            if let Some((generation, encoded)) = save {
+                if let Err(error) = save_store(&slot, &root, &model, generation, encoded) {
+                    eprintln!("[code_index] failed to save semantic index: {}", error);
+                }
+            }
+            let hits = ranked
+                .into_iter()
+                .map(|(path, start_line, end_line, score)| SemanticHit {
+                    snippet: read_snippet(&root.join(&path), start_line, end_line),
+                    path,
+                    start_line,
+                    end_line,
+                    score,
+                })
+                .collect::<Vec<_>>();
+            Ok::<_, String>((hits, indexed_files))
+        })
+        .await
+        .map_err(|e| e.to_string())??
+    };
+
+    Ok(SemanticSearchOutcome {
+        hits,
+        lexical_fallback: model == MOCK_EMBEDDER_MODEL,
+        model,
+        indexed_files,
+        embedded_chunks,
+        pending_files,
+    })
+}
+
+/// Writes an encoded snapshot unless a newer one has been written already.
+fn save_store(
+    slot: &SemanticSlot,
+    root: &Path,
+    model: &str,
+    generation: u64,
+    encoded: Result<Vec<u8>, String>,
+) -> Result<(), String> {
+    let mut saved_generation = slot
+        .saved_generation
+        .lock()
+        .map_err(|_| "Semantic index is unavailable".to_string())?;
+    if *saved_generation >= generation {
+        return Ok(());
+    }
+    let path = workspace_storage_path(root, SEMANTIC_INDEX_DIRECTORY, model)?;
+    write_compressed_bytes(&path, &encoded?)?;
+    *saved_generation = generation;
+    Ok(())
+}
+
+fn is_semantic_source(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            SEMANTIC_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+        })
+}
+
+fn load_store(root: &Path, model: &str) -> VectorStore {
+    let loaded = workspace_storage_path(root, SEMANTIC_INDEX_DIRECTORY, model)
+        .and_then(|path| read_compressed::<VectorStore>(&path));
+    match loaded {
+        Ok(Some(store)) if store.version == STORE_FORMAT_VERSION => store,
+        Ok(_) => VectorStore::default(),
+        Err(error) => {
+            eprintln!("[code_index] rebuilding semantic index: {}", error);
+            VectorStore::default()
+        }
+    }
+}
+
+fn stamp_files(root: &Path, files: &[PathBuf]) -> Vec<FileStamp> {
+    files
+        .iter()
+        .filter_map(|path| {
+            let metadata = fs::metadata(path).ok()?;
+            if metadata.len() > MAX_SEMANTIC_FILE_BYTES {
+                return None;
+            }
+            let modified_ms = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_millis() as u64)
+                .unwrap_or_default();
+            Some(FileStamp {
+                path: path.clone(),
+                relative_path: relative_display_path(root, path),
+                modified_ms,
+                len: metadata.len(),
+            })
+        })
+        .collect()
+}
+
+/// Compares stamps with the store without touching the disk, so it can run under the lock.
+fn changed_files(
+    store: &VectorStore,
+    stamps: Vec<FileStamp>,
+) -> (HashSet<String>, Vec<ChangedFile>) {
+    let mut seen = HashSet::new();
+    let mut changed = Vec::new();
+    for stamp in stamps {
+        seen.insert(stamp.relative_path.clone());
+        let previous = store.files.get(&stamp.relative_path);
+        if previous
+            .is_some_and(|file| file.modified_ms == stamp.modified_ms && file.len == stamp.len)
+        {
+            continue;
+        }
+        changed.push(ChangedFile {
+            previous: previous.map(|file| (file.modified_ms, file.len)),
+            vectors: previous
+                .map(|file| {
+                    file.chunks
+                        .iter()
+                        .map(|chunk| (chunk.hash, chunk.vector.clone()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            stamp,
+        });
+    }
+    (seen, changed)
+}
+
+/// Reads the changed files and keeps the vectors of chunks that did not change. Files
+/// past `max_new_chunks` wait for a later search; until then they keep only the vectors
+/// of chunks still in the file, under their old stamp so they are planned again.
+fn plan_sync(seen: HashSet<String>, changed: Vec<ChangedFile>, max_new_chunks: usize) -> SyncPlan {
+    let mut plan = SyncPlan {
+        seen,
+        ..SyncPlan::default()
+    };
+    let mut planned_chunks = 0;
+
+    for file in changed {
+        let Ok(bytes) = fs::read(&file.stamp.path) else {
+            continue;
+        };
+        if bytes.iter().take(8192).any(|byte| *byte == 0) {
+            continue;
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        let mut chunks = chunk_text(&file.stamp.relative_path, &text);
+        let missing = chunks
+            .iter()
+            .filter(|chunk| !file.vectors.contains_key(&chunk.hash))
+            .count();
+        let (modified_ms, len) = if planned_chunks > 0 && planned_chunks + missing > max_new_chunks
+        {
+            plan.pending_files += 1;
+            let Some(previous) = file.previous else {
+                continue;
+            };
+            chunks.retain(|chunk| file.vectors.contains_key(&chunk.hash));
+            previous
+        } else {
+            planned_chunks += missing;
+            (file.stamp.modified_ms, file.stamp.len)
+        };
+        plan.updates.push(FileUpdate {
+            relative_path: file.stamp.relative_path,
+            modified_ms,
+            len,
+            chunks,
+            vectors: file.vectors,
+        });
+    }
+    plan
+}
+
+/// Embeds the chunks of `updates` that have no vector yet; returns how many were embedded.
+async fn embed_updates(
+    updates: &mut [FileUpdate],
+    embedder: &dyn Embedder,
+) -> Result<usize, String> {
+    let mut embedded_chunks = 0;
+    for update in updates {
+        let missing = update
+            .chunks
+            .iter()
+            .filter(|chunk| !update.vectors.contains_key(&chunk.hash))
+            .collect::<Vec<_>>();
+        for batch in missing.chunks(EMBED_BATCH_SIZE) {
+            let texts = batch
+                .iter()
+                .map(|chunk| chunk.text.as_str())
+                .collect::<Vec<_>>();
+            let vectors = embedder
+                .embed_batch(&texts)
+                .await
+                .map_err(|e| format!("Failed to embed {}: {}", update.relative_path, e))?;
+            for (chunk, vector) in batch.iter().zip(vectors) {
+                update.vectors.insert(chunk.hash, normalized(vector));
+            }
+            embedded_chunks += batch.len();
+        }
+    }
+    Ok(embedded_chunks)
+}
+
+/// Writes the embedded files into the store and drops files that are gone; returns
+/// whether the store changed.
+fn apply_sync(store: &mut VectorStore, plan: SyncPlan) -> bool {
+    let changed = !plan.updates.is_empty();
+    for update in plan.updates {
+        let chunks = update
+            .chunks
+            .into_iter()
+            .filter_map(|chunk| {
+                Some(EmbeddedChunk {
+                    vector: update.vectors.get(&chunk.hash)?.clone(),
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                    hash: chunk.hash,
+                })
+            })
+            .collect();
+        store.files.insert(
+            update.relative_path,
+            EmbeddedFile {
+                modified_ms: update.modified_ms,
+                len: update.len,
+                chunks,
+            },
+        );
+    }
+
+    let before = store.files.len();
+    store.files.retain(|path, _| plan.seen.contains(path));
+    changed || store.files.len() != before
+}
+
+/// Overlapping line windows; the path is embedded with each chunk so file names carry
+/// meaning too.
+fn chunk_text(relative_path: &str, text: &str) -> Vec<PendingChunk> {
+    let lines = text.lines().collect::<Vec<_>>();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < lines.len() && chunks.len() < MAX_CHUNKS_PER_FILE {
+        let end = (start + CHUNK_LINES).min(lines.len());
+        let body = lines[start..end].join("\n");
+        if !body.trim().is_empty() {
+            let body = match body.char_indices().nth(MAX_CHUNK_CHARS) {
+                Some((index, _)) => &body[..index],
+                None => body.as_str(),
+            };
+            let text = format!("{}\n{}", relative_path, body);
+            chunks.push(PendingChunk {
+                start_line: start + 1,
+                end_line: end,
+                hash: stable_hash(&[text.as_bytes()]),
+                text,
+            });
+        }
+        if end == lines.len() {
+            break;
+        }
+        start = end - CHUNK_OVERLAP_LINES;
+    }
+    chunks
+}
+
+fn normalized(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for value in &mut vector {
+            *value /= norm;
+        }
+    }
+    vector
+}
+
+fn rank_chunks<'a>(
+    store: &'a VectorStore,
+    query_vector: &[f32],
+    include: impl Fn(&str) -> bool,
+    limit: usize,
+) -> Vec<(&'a str, &'a EmbeddedChunk, f32)> {
+    let mut scored = store
+        .files
+        .iter()
+        .filter(|(path, _)| include(path))
+        .flat_map(|(path, file)| {
+            file.chunks.iter().map(move |chunk| {
+                let score = chunk
+                    .vector
+                    .iter()
+                    .zip(query_vector)
+                    .map(|(left, right)| left * right)
+                    .sum::<f32>();
+                (path.as_str(), chunk, score)
+            })
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by(|left, right| right.2.total_cmp(&left.2));
+    scored.truncate(limit);
+    scored
+}
+
+fn read_snippet(path: &Path, start_line: usize, end_line: usize) -> String {
+    let text = fs::read(path)
+        .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+        .unwrap_or_default();
+    let snippet = text
+        .lines()
+        .enumerate()
+        .skip(start_line.saturating_sub(1))
+        .take(end_line + 1 - start_line)
+        .map(|(index, line)| format!("{}: {}", index + 1, line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    match snippet.char_indices().nth(MAX_SNIPPET_CHARS) {
+        Some((index, _)) => format!("{}…", &snippet[..index]),
+        None => snippet,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_overlap_and_rank_by_cosine() {
+        let text = (1..=75)
+            .map(|line| format!("line {}", line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let chunks = chunk_text("src/auth.rs", &text);
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| (chunk.start_line, chunk.end_line))
+                .collect::<Vec<_>>(),
+            vec![(1, 40), (31, 70), (61, 75)]
+        );
+        assert!(chunks[0].text.starts_with("src/auth.rs\nline 1\n"));
+
+        let chunk = |start_line: usize, vector: Vec<f32>| EmbeddedChunk {
+            start_line,
+            end_line: start_line,
+            hash: start_line as u64,
+            vector: normalized(vector),
+        };
+        let mut store = VectorStore::default();
+        store.files.insert(
+            "src/auth.rs".to_string(),
+            EmbeddedFile {
+                modified_ms: 0,
+                len: 0,
+                chunks: vec![chunk(1, vec![1.0, 0.0]), chunk(2, vec![0.6, 0.8])],
+            },
+        );
+        store.files.insert(
+            "docs/login.md".to_string(),
+            EmbeddedFile {
+                modified_ms: 0,
+                len: 0,
+                chunks: vec![chunk(3, vec![0.0, 1.0])],
+            },
+        );
+
+        let query = normalized(vec![0.0, 2.0]);
+        let ranked = rank_chunks(&store, &query, |_| true, 2);
+        assert_eq!(
+            ranked
+                .iter()
+                .map(|(path, chunk, _)| (*path, chunk.start_line))
+                .collect::<Vec<_>>(),
+            vec![("docs/login.md", 3), ("src/auth.rs", 2)]
+        );
+        let scoped = rank_chunks(&store, &query, |path| path.starts_with("src/"), 5);
+        assert_eq!(scoped.len(), 2);
+        assert!((scoped[0].2 - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn chunk_hashes_are_stable_and_sync_keeps_unchanged_vectors() {
+        let chunks = chunk_text("src/auth.rs", "fn login() {}");
+        // Persisted stores key vectors by this hash, so it must never change.
+        assert_eq!(chunks[0].hash, 0xb6e6b6d1a81053a9);
+
+        let mut store = VectorStore::default();
+        store.files.insert(
+            "src/gone.rs".to_string(),
+            EmbeddedFile {
+                modified_ms: 0,
+                len: 0,
+                chunks: Vec::new(),
+            },
+        );
+        let hash = chunks[0].hash;
+        let plan = SyncPlan {
+            seen: HashSet::from(["src/auth.rs".to_string()]),
+            updates: vec![FileUpdate {
+                relative_path: "src/auth.rs".to_string(),
+                modified_ms: 1,
+                len: 13,
+                chunks,
+                vectors: HashMap::from([(hash, vec![1.0, 0.0])]),
+            }],
+            pending_files: 0,
+        };
+
+        assert!(apply_sync(&mut store, plan));
+        assert_eq!(store.files.len(), 1);
+        assert_eq!(store.files["src/auth.rs"].chunks[0].vector, vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn deferred_files_drop_stale_vectors_and_keep_their_old_stamp() {
+        let root = std::env::temp_dir().join(format!("petool-semantic-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let source = |name: &str, lines: usize| {
+            let path = root.join(name);
+            let text = (1..=lines)
+                .map(|line| format!("{} line {}", name, line))
+                .collect::<Vec<_>>()
+                .join("\n");
+            fs::write(&path, &text).unwrap();
+            (path, chunk_text(name, &text))
+        };
+        let (new_path, _) = source("new.rs", 5);
+        let (edited_path, edited_chunks) = source("edited.rs", 50);
+        let stamp = |path: PathBuf, relative_path: &str| FileStamp {
+            path,
+            relative_path: relative_path.to_string(),
+            modified_ms: 2,
+            len: 2,
+        };
+        let changed = vec![
+            ChangedFile {
+                stamp: stamp(new_path, "new.rs"),
+                previous: None,
+                vectors: HashMap::new(),
+            },
+            ChangedFile {
+                stamp: stamp(edited_path, "edited.rs"),
+                previous: Some((1, 1)),
+                vectors: HashMap::from([
+                    (edited_chunks[0].hash, vec![1.0, 0.0]),
+                    (7, vec![0.0, 1.0]),
+                ]),
+            },
+        ];
+
+        let plan = plan_sync(HashSet::new(), changed, 1);
+        assert_eq!(plan.pending_files, 1);
+        let mut store = VectorStore::default();
+        let mut updates = plan.updates;
+        let new_hash = updates[0].chunks[0].hash;
+        updates[0].vectors.insert(new_hash, vec![0.0, 1.0]);
+        apply_sync(
+            &mut store,
+            SyncPlan {
+                seen: HashSet::from(["new.rs".to_string(), "edited.rs".to_string()]),
+                updates,
+                pending_files: 1,
+            },
+        );
+
+        assert_eq!(store.files["new.rs"].modified_ms, 2);
+        let edited = &store.files["edited.rs"];
+        assert_eq!((edited.modified_ms, edited.len), (1, 1));
+        assert_eq!(
+            edited
+                .chunks
+                .iter()
+                .map(|chunk| chunk.hash)
+                .collect::<Vec<_>>(),
+            vec![edited_chunks[0].hash]
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
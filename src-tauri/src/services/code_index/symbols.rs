@@ -0,0 +1,769 @@
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use tree_sitter::{Language, Node, Parser};
+
+use super::relative_display_path;
+
+const MAX_INDEXED_FILE_BYTES: u64 = 2_000_000;
+const MAX_SIGNATURE_CHARS: usize = 160;
+const MAX_REFERENCE_LINE_CHARS: usize = 200;
+
+/// Leaf node kinds that count as a use of a name for find-references.
+const IDENTIFIER_KINDS: &[&str] = &[
+    "identifier",
+    "type_identifier",
+    "field_identifier",
+    "property_identifier",
+    "shorthand_property_identifier",
+    "namespace_identifier",
+    "package_identifier",
+];
+
+/// Containers whose nested functions are reported as methods.
+const METHOD_CONTAINER_KINDS: &[&str] = &["impl", "trait", "class", "interface", "struct"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SymbolLanguage {
+    Rust,
+    TypeScript,
+    Tsx,
+    JavaScript,
+    Python,
+    Go,
+    Java,
+    C,
+    Cpp,
+}
+
+impl SymbolLanguage {
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "rs" => Some(Self::Rust),
+            "ts" | "mts" | "cts" => Some(Self::TypeScript),
+            "tsx" => Some(Self::Tsx),
+            "js" | "jsx" | "mjs" | "cjs" => Some(Self::JavaScript),
+            "py" | "pyi" => Some(Self::Python),
+            "go" => Some(Self::Go),
+            "java" => Some(Self::Java),
+            "c" => Some(Self::C),
+            "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => Some(Self::Cpp),
+            _ => None,
+        }
+    }
+
+    fn grammar(self) -> Language {
+        match self {
+            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Self::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Self::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Self::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Self::Python => tree_sitter_python::LANGUAGE.into(),
+            Self::Go => tree_sitter_go::LANGUAGE.into(),
+            Self::Java => tree_sitter_java::LANGUAGE.into(),
+            Self::C => tree_sitter_c::LANGUAGE.into(),
+            Self::Cpp => tree_sitter_cpp::LANGUAGE.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SourceKind {
+    Plain(SymbolLanguage),
+    Vue,
+}
+
+fn source_kind(path: &Path) -> Option<SourceKind> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    if extension == "vue" {
+        return Some(SourceKind::Vue);
+    }
+    SymbolLanguage::from_extension(&extension).map(SourceKind::Plain)
+}
+
+pub fn is_symbol_source(path: &Path) -> bool {
+    source_kind(path).is_some()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolInfo {
+    pub name: String,
+    pub kind: &'static str,
+    pub container: Option<String>,
+    pub path: String,
+    pub line_number: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub signature: String,
+    #[serde(skip)]
+    parent: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolReference {
+    pub path: String,
+    pub line_number: usize,
+    pub column: usize,
+    pub line: String,
+    pub is_definition: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlineNode {
+    pub name: String,
+    pub kind: &'static str,
+    pub line_number: usize,
+    pub end_line: usize,
+    pub children: Vec<OutlineNode>,
+}
+
+#[derive(Debug, Default)]
+struct ParsedSource {
+    symbols: Vec<SymbolInfo>,
+    /// Name -> 1-based (line, column) of every identifier with that text.
+    occurrences: HashMap<String, Vec<(usize, usize)>>,
+}
+
+struct IndexedFile {
+    relative_path: String,
+    modified: Option<SystemTime>,
+    len: u64,
+    parsed: ParsedSource,
+}
+
+/// Parsed symbols of one workspace, refreshed lazily by file mtime and size.
+#[derive(Default)]
+pub struct SymbolIndex {
+    files: HashMap<PathBuf, IndexedFile>,
+}
+
+static SYMBOL_INDEXES: OnceLock<Mutex<HashMap<PathBuf, SymbolIndex>>> = OnceLock::new();
+
+/// Brings the cached index of `workspace_root` in line with `files` (re-parsing only
+/// changed files and dropping deleted ones) and runs `query` against it.
+pub fn with_symbol_index<T>(
+    workspace_root: &Path,
+    files: &[PathBuf],
+    query: impl FnOnce(&SymbolIndex) -> T,
+) -> Result<T, String> {
+    let mut indexes = SYMBOL_INDEXES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .map_err(|_| "Symbol index is unavailable".to_string())?;
+    let index = indexes.entry(workspace_root.to_path_buf()).or_default();
+    index.refresh(workspace_root, files);
+    Ok(query(index))
+}
+
+impl SymbolIndex {
+    pub fn refresh(&mut self, workspace_root: &Path, files: &[PathBuf]) -> usize {
+        let mut seen = HashSet::new();
+        let mut reparsed = 0;
+        for path in files {
+            let Some(kind) = source_kind(path) else {
+                continue;
+            };
+            let Ok(metadata) = fs::metadata(path) else {
+                continue;
+            };
+            if metadata.len() > MAX_INDEXED_FILE_BYTES {
+                continue;
+            }
+            seen.insert(path.clone());
+            let modified = metadata.modified().ok();
+            if self
+                .files
+                .get(path)
+                .is_some_and(|file| file.modified == modified && file.len == metadata.len())
+            {
+                continue;
+            }
+
+            let relative_path = relative_display_path(workspace_root, path);
+            let parsed = index_file(path, &relative_path, kind).unwrap_or_else(|error| {
+                eprintln!("[code_index] failed to index {}: {}", relative_path, error);
+                ParsedSource::default()
+            });
+            self.files.insert(
+                path.clone(),
+                IndexedFile {
+                    relative_path,
+                    modified,
+                    len: metadata.len(),
+                    parsed,
+                },
+            );
+            reparsed += 1;
+        }
+        self.files.retain(|path, _| seen.contains(path));
+        reparsed
+    }
+
+    fn files_in_scope<'a>(&'a self, scope: &Path) -> Vec<(&'a PathBuf, &'a IndexedFile)> {
+        let mut files = self
+            .files
+            .iter()
+            .filter(|(path, _)| path.starts_with(scope))
+            .collect::<Vec<_>>();
+        files.sort_by(|left, right| left.0.cmp(right.0));
+        files
+    }
+
+    /// Symbols whose name matches `query`: exact matches first, then prefixes, then
+    /// substrings. An empty query lists every symbol in scope.
+    pub fn search(&self, query: &str, scope: &Path, limit: usize) -> (Vec<SymbolInfo>, bool) {
+        let needle = query.trim().to_lowercase();
+        let mut ranked = Vec::new();
+        for (_, file) in self.files_in_scope(scope) {
+            for symbol in &file.parsed.symbols {
+                let name = symbol.name.to_lowercase();
+                let rank = if needle.is_empty() {
+                    2
+                } else if name == needle {
+                    0
+                } else if name.starts_with(&needle) {
+                    1
+                } else if name.contains(&needle) {
+                    2
+                } else {
+                    continue;
+                };
+                ranked.push((rank, symbol));
+            }
+        }
+        ranked.sort_by_key(|(rank, _)| *rank);
+        let truncated = ranked.len() > limit;
+        let symbols = ranked
+            .into_iter()
+            .take(limit)
+            .map(|(_, symbol)| symbol.clone())
+            .collect();
+        (symbols, truncated)
+    }
+
+    /// Definitions of `symbol`, which may be qualified as `Container.name` or
+    /// `Container::name`.
+    pub fn definitions(&self, symbol: &str, scope: &Path) -> Vec<SymbolInfo> {
+        let (container, name) = split_qualified_name(symbol);
+        self.files_in_scope(scope)
+            .into_iter()
+            .flat_map(|(_, file)| file.parsed.symbols.iter())
+            .filter(|candidate| candidate.name == name)
+            .filter(|candidate| match container {
+                Some(container) => candidate
+                    .container
+                    .as_deref()
+                    .is_some_and(|value| split_qualified_name(value).1 == container),
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn references(
+        &self,
+        symbol: &str,
+        scope: &Path,
+        limit: usize,
+    ) -> (Vec<SymbolReference>, bool) {
+        let (_, name) = split_qualified_name(symbol);
+        let mut references = Vec::new();
+        for (path, file) in self.files_in_scope(scope) {
+            let Some(positions) = file.parsed.occurrences.get(name) else {
+                continue;
+            };
+            let content = fs::read(path)
+                .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+                .unwrap_or_default();
+            let lines = content.lines().collect::<Vec<_>>();
+            for &(line_number, column) in positions {
+                if references.len() >= limit {
+                    return (references, true);
+                }
+                let line = lines
+                    .get(line_number - 1)
+                    .map(|line| {
+                        line.trim()
+                            .chars()
+                            .take(MAX_REFERENCE_LINE_CHARS)
+                            .collect::<String>()
+                    })
+                    .unwrap_or_default();
+                let is_definition = file.parsed.symbols.iter().any(|symbol| {
+                    symbol.name == name
+                        && symbol.line_number == line_number
+                        && symbol.column == column
+                });
+                references.push(SymbolReference {
+                    path: file.relative_path.clone(),
+                    line_number,
+                    column,
+                    line,
+                    is_definition,
+                });
+            }
+        }
+        (references, false)
+    }
+
+    /// Nested symbol tree of one file, or `None` when the file is not indexed.
+    pub fn outline(&self, path: &Path) -> Option<Vec<OutlineNode>> {
+        self.files
+            .get(path)
+            .map(|file| outline_nodes(&file.parsed.symbols, None))
+    }
+}
+
+fn outline_nodes(symbols: &[SymbolInfo], parent: Option<usize>) -> Vec<OutlineNode> {
+    symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, symbol)| symbol.parent == parent)
+        .map(|(index, symbol)| OutlineNode {
+            name: symbol.name.clone(),
+            kind: symbol.kind,
+            line_number: symbol.line_number,
+            end_line: symbol.end_line,
+            children: outline_nodes(symbols, Some(index)),
+        })
+        .collect()
+}
+
+fn split_qualified_name(symbol: &str) -> (Option<&str>, &str) {
+    let symbol = symbol.trim();
+    let split = symbol
+        .rfind("::")
+        .map(|index| (index, index + 2))
+        .or_else(|| symbol.rfind('.').map(|index| (index, index + 1)));
+    match split {
+        Some((end, start)) if start < symbol.len() => {
+            let container = split_qualified_name(&symbol[..end]).1;
+            (
+                Some(container).filter(|value| !value.is_empty()),
+                &symbol[start..],
+            )
+        }
+        _ => (None, symbol),
+    }
+}
+
+fn index_file(path: &Path, relative_path: &str, kind: SourceKind) -> Result<ParsedSource, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let source = String::from_utf8_lossy(&bytes);
+    let mut parsed = ParsedSource::default();
+    match kind {
+        SourceKind::Plain(language) => {
+            parse_source(language, &source, relative_path, 0, &mut parsed)?
+        }
+        SourceKind::Vue => {
+            for (language, line_offset, script) in vue_script_blocks(&source) {
+                parse_source(language, script, relative_path, line_offset, &mut parsed)?;
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+fn vue_script_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(r"(?is)<script\b([^>]*)>(.*?)</script>").expect("valid vue script pattern")
+    })
+}
+
+/// `<script>` blocks of a single-file component with their language and the number of
+/// lines that precede the block content.
+fn vue_script_blocks(source: &str) -> Vec<(SymbolLanguage, usize, &str)> {
+    vue_script_pattern()
+        .captures_iter(source)
+        .filter_map(|captures| {
+            let attributes = captures.get(1)?.as_str().to_ascii_lowercase();
+            let content = captures.get(2)?;
+            let language = if attributes.contains("\"tsx\"") || attributes.contains("'tsx'") {
+                SymbolLanguage::Tsx
+            } else if attributes.contains("\"ts\"") || attributes.contains("'ts'") {
+                SymbolLanguage::TypeScript
+            } else {
+                SymbolLanguage::JavaScript
+            };
+            let line_offset = source[..content.start()].matches('\n').count();
+            Some((language, line_offset, content.as_str()))
+        })
+        .collect()
+}
+
+fn parse_source(
+    language: SymbolLanguage,
+    source: &str,
+    relative_path: &str,
+    line_offset: usize,
+    parsed: &mut ParsedSource,
+) -> Result<(), String> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(&language.grammar())
+        .map_err(|e| e.to_string())?;
+    let tree = parser
+        .parse(source, None)
+        .ok_or_else(|| "Parser returned no tree".to_string())?;
+    let bytes = source.as_bytes();
+    let lines = source.lines().collect::<Vec<_>>();
+
+    let mut stack = vec![(tree.root_node(), None::<usize>)];
+    while let Some((node, parent)) = stack.pop() {
+        let mut scope = parent;
+        if let Some(definition) = classify_definition(language, node, bytes) {
+            let parent_kind = parent.map(|index| parsed.symbols[index].kind);
+            let kind = if definition.kind == "function"
+                && parent_kind.is_some_and(|kind| METHOD_CONTAINER_KINDS.contains(&kind))
+            {
+                "method"
+            } else {
+                definition.kind
+            };
+            let container = definition
+                .container
+                .or_else(|| parent.map(|index| parsed.symbols[index].name.clone()));
+            let position = definition.name_node.start_position();
+            parsed.symbols.push(SymbolInfo {
+                name: definition.name,
+                kind,
+                container,
+                path: relative_path.to_string(),
+                line_number: line_offset + position.row + 1,
+                column: position.column + 1,
+                end_line: line_offset + node.end_position().row + 1,
+                signature: lines
+                    .get(node.start_position().row)
+                    .map(|line| {
+                        line.trim()
+                            .chars()
+                            .take(MAX_SIGNATURE_CHARS)
+                            .collect::<String>()
+                    })
+                    .unwrap_or_default(),
+                parent,
+            });
+            scope = Some(parsed.symbols.len() - 1);
+        } else if IDENTIFIER_KINDS.contains(&node.kind()) {
+            if let Ok(text) = node.utf8_text(bytes) {
+                let position = node.start_position();
+                parsed
+                    .occurrences
+                    .entry(text.to_string())
+                    .or_default()
+                    .push((line_offset + position.row + 1, position.column + 1));
+            }
+        }
+
+        let mut cursor = node.walk();
+        let children = node.named_children(&mut cursor).collect::<Vec<_>>();
+        stack.extend(children.into_iter().rev().map(|child| (child, scope)));
+    }
+    Ok(())
+}
+
+struct Definition<'tree> {
+    name: String,
+    name_node: Node<'tree>,
+    kind: &'static str,
+    container: Option<String>,
+}
+
+fn named_definition<'tree>(
+    node: Node<'tree>,
+    field: &str,
+    kind: &'static str,
+    source: &[u8],
+) -> Option<Definition<'tree>> {
+    let name_node = node.child_by_field_name(field)?;
+    let name = name_node.utf8_text(source).ok()?.trim().to_string();
+    if name.is_empty() {
+        return None;
+    }
+    Some(Definition {
+        name,
+        name_node,
+        kind,
+        container: None,
+    })
+}
+
+/// Drops generic arguments and pointer markers from a type used as a container name.
+fn bare_type_name(text: &str) -> String {
+    text.trim_start_matches(['*', '&'])
+        .split(['<', '['])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+fn classify_definition<'tree>(
+    language: SymbolLanguage,
+    node: Node<'tree>,
+    source: &[u8],
+) -> Option<Definition<'tree>> {
+    let kind = match language {
+        SymbolLanguage::Rust => match node.kind() {
+            "function_item" | "function_signature_item" => "function",
+            "struct_item" => "struct",
+            "enum_item" => "enum",
+            "union_item" => "union",
+            "trait_item" => "trait",
+            "type_item" => "type",
+            "const_item" => "constant",
+            "static_item" => "static",
+            "mod_item" => "module",
+            "macro_definition" => "macro",
+            "enum_variant" => "variant",
+            "field_declaration" => "field",
+            "impl_item" => {
+                let mut definition = named_definition(node, "type", "impl", source)?;
+                definition.name = bare_type_name(&definition.name);
+                return Some(definition);
+            }
+            _ => return None,
+        },
+        SymbolLanguage::TypeScript | SymbolLanguage::Tsx | SymbolLanguage::JavaScript => {
+            match node.kind() {
+                "function_declaration"
+                | "generator_function_declaration"
+                | "function_signature" => "function",
+                "class_declaration" | "abstract_class_declaration" | "class" => "class",
+                "method_definition" | "method_signature" | "abstract_method_signature" => "method",
+                "interface_declaration" => "interface",
+                "type_alias_declaration" => "type",
+                "enum_declaration" => "enum",
+                "internal_module" | "module" => "module",
+                "public_field_definition" | "property_signature" => "field",
+                "field_definition" => return named_definition(node, "property", "field", source),
+                "variable_declarator" => return classify_js_variable(node, source),
+                _ => return None,
+            }
+        }
+        SymbolLanguage::Python => match node.kind() {
+            "function_definition" => "function",
+            "class_definition" => "class",
+            _ => return None,
+        },
+        SymbolLanguage::Go => match node.kind() {
+            "function_declaration" => "function",
+            "method_declaration" => {
+                let mut definition = named_definition(node, "name", "method", source)?;
+                definition.container = go_receiver_type(node, source);
+                return Some(definition);
+            }
+            "type_spec" => match node.child_by_field_name("type").map(|value| value.kind()) {
+                Some("struct_type") => "struct",
+                Some("interface_type") => "interface",
+                _ => "type",
+            },
+            "const_spec" => "constant",
+            "field_declaration" => "field",
+            "method_spec" | "method_elem" => "method",
+            _ => return None,
+        },
+        SymbolLanguage::Java => match node.kind() {
+            "class_declaration" => "class",
+            "interface_declaration" | "annotation_type_declaration" => "interface",
+            "enum_declaration" => "enum",
+            "record_declaration" => "record",
+            "method_declaration" => "method",
+            "constructor_declaration" => "constructor",
+            "enum_constant" => "variant",
+            "field_declaration" => {
+                let declarator = node.child_by_field_name("declarator")?;
+                return named_definition(declarator, "name", "field", source);
+            }
+            _ => return None,
+        },
+        SymbolLanguage::C | SymbolLanguage::Cpp => match node.kind() {
+            "function_definition" => return c_function(node, source),
+            "field_declaration"
+                if node
+                    .child_by_field_name("declarator")
+                    .is_some_and(|declarator| declarator.kind() == "function_declarator") =>
+            {
+                return c_function(node, source);
+            }
+            "struct_specifier" | "class_specifier" | "union_specifier" | "enum_specifier"
+                if node.child_by_field_name("body").is_some() =>
+            {
+                match node.kind() {
+                    "class_specifier" => "class",
+                    "union_specifier" => "union",
+                    "enum_specifier" => "enum",
+                    _ => "struct",
+                }
+            }
+            "type_definition" => {
+                let name_node = c_declarator_name(node.child_by_field_name("declarator")?)?;
+                let name = name_node.utf8_text(source).ok()?.to_string();
+                return Some(Definition {
+                    name,
+                    name_node,
+                    kind: "type",
+                    container: None,
+                });
+            }
+            "namespace_definition" => "namespace",
+            "enumerator" => "variant",
+            _ => return None,
+        },
+    };
+    named_definition(node, "name", kind, source)
+}
+
+/// `const handler = () => {}` style bindings; other variables only at the top level.
+fn classify_js_variable<'tree>(node: Node<'tree>, source: &[u8]) -> Option<Definition<'tree>> {
+    if node.child_by_field_name("name")?.kind() != "identifier" {
+        return None;
+    }
+    let kind = match node.child_by_field_name("value").map(|value| value.kind()) {
+        Some("arrow_function" | "function_expression" | "function" | "generator_function") => {
+            "function"
+        }
+        Some("class") => "class",
+        _ => {
+            let top_level = node
+                .parent()
+                .and_then(|declaration| declaration.parent())
+                .is_some_and(|scope| matches!(scope.kind(), "program" | "export_statement"));
+            if !top_level {
+                return None;
+            }
+            "variable"
+        }
+    };
+    named_definition(node, "name", kind, source)
+}
+
+fn go_receiver_type(node: Node, source: &[u8]) -> Option<String> {
+    let receiver = node.child_by_field_name("receiver")?;
+    let mut cursor = receiver.walk();
+    let parameter = receiver
+        .named_children(&mut cursor)
+        .find(|child| child.kind() == "parameter_declaration")?;
+    let receiver_type = parameter
+        .child_by_field_name("type")?
+        .utf8_text(source)
+        .ok()?;
+    Some(bare_type_name(receiver_type))
+}
+
+/// Follows nested pointer/function/reference declarators down to the declared name.
+fn c_declarator_name(mut node: Node) -> Option<Node> {
+    loop {
+        match node.kind() {
+            "identifier"
+            | "field_identifier"
+            | "type_identifier"
+            | "qualified_identifier"
+            | "destructor_name"
+            | "operator_name" => return Some(node),
+            _ => {
+                node = node
+                    .child_by_field_name("declarator")
+                    .or_else(|| node.named_child(0))?;
+            }
+        }
+    }
+}
+
+fn c_function<'tree>(node: Node<'tree>, source: &[u8]) -> Option<Definition<'tree>> {
+    let mut name_node = c_declarator_name(node.child_by_field_name("declarator")?)?;
+    let mut container = None;
+    if name_node.kind() == "qualified_identifier" {
+        container = name_node
+            .child_by_field_name("scope")
+            .and_then(|scope| scope.utf8_text(source).ok())
+            .map(bare_type_name);
+        while name_node.kind() == "qualified_identifier" {
+            name_node = name_node.child_by_field_name("name")?;
+        }
+    }
+    let name = name_node.utf8_text(source).ok()?.to_string();
+    Some(Definition {
+        name,
+        name_node,
+        kind: if container.is_some() {
+            "method"
+        } else {
+            "function"
+        },
+        container,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(parsed: &ParsedSource) -> Vec<(&str, &str, Option<&str>, usize)> {
+        parsed
+            .symbols
+            .iter()
+            .map(|symbol| {
+                (
+                    symbol.name.as_str(),
+                    symbol.kind,
+                    symbol.container.as_deref(),
+                    symbol.line_number,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn indexes_definitions_containers_and_references() {
+        let mut rust = ParsedSource::default();
+        parse_source(
+            SymbolLanguage::Rust,
+            "struct Cache;\n\nimpl Cache {\n    fn get(&self) -> u8 {\n        helper()\n    }\n}\n\nfn helper() -> u8 {\n    1\n}\n",
+            "src/lib.rs",
+            0,
+            &mut rust,
+        )
+        .unwrap();
+        assert_eq!(
+            summary(&rust),
+            vec![
+                ("Cache", "struct", None, 1),
+                ("Cache", "impl", None, 3),
+                ("get", "method", Some("Cache"), 4),
+                ("helper", "function", None, 9),
+            ]
+        );
+        assert_eq!(rust.occurrences["helper"], vec![(5, 9), (9, 4)]);
+        assert_eq!(
+            outline_nodes(&rust.symbols, None)[1].children[0].name,
+            "get"
+        );
+
+        let component = "<template>\n  <div />\n</template>\n<script setup lang=\"ts\">\nclass Login {\n  retry() {}\n}\n</script>\n";
+        let blocks = vue_script_blocks(component);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].0, SymbolLanguage::TypeScript);
+        let mut vue = ParsedSource::default();
+        parse_source(blocks[0].0, blocks[0].2, "Login.vue", blocks[0].1, &mut vue).unwrap();
+        assert_eq!(
+            summary(&vue),
+            vec![
+                ("Login", "class", None, 5),
+                ("retry", "method", Some("Login"), 6)
+            ]
+        );
+
+        assert_eq!(split_qualified_name("Cache::get"), (Some("Cache"), "get"));
+        assert_eq!(
+            split_qualified_name("a.b.Login.retry"),
+            (Some("Login"), "retry")
+        );
+        assert_eq!(split_qualified_name("helper"), (None, "helper"));
+    }
+}
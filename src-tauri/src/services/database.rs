@@ -40,6 +40,18 @@ impl Database {
                 FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
             );
 
+            CREATE TABLE IF NOT EXISTS conversation_summaries (
+                id TEXT PRIMARY KEY,
+                conversation_id TEXT NOT NULL,
+                summary TEXT NOT NULL,
+                boundary_message_id TEXT NOT NULL,
+                boundary_created_at TEXT NOT NULL,
+                summarized_messages INTEGER NOT NULL,
+                model TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+            );
+
             CREATE TABLE IF NOT EXISTS skills (
                 id TEXT PRIMARY KEY,
                 name TEXT NOT NULL,
@@ -108,6 +120,44 @@ impl Database {
                 FOREIGN KEY (job_id) REFERENCES scheduler_jobs(id) ON DELETE SET NULL
             );
 
+            CREATE TABLE IF NOT EXISTS llm_usage (
+                id TEXT PRIMARY KEY,
+                conversation_id TEXT,
+                turn_id TEXT,
+                source TEXT NOT NULL,
+                model TEXT NOT NULL,
+                prompt_tokens INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL,
+                reasoning_tokens INTEGER NOT NULL,
+                cached_tokens INTEGER NOT NULL,
+                total_tokens INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE SET NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS workspace_checkpoints (
+                id TEXT PRIMARY KEY,
+                conversation_id TEXT NOT NULL,
+                turn_id TEXT NOT NULL,
+                workspace_root TEXT NOT NULL,
+                path TEXT NOT NULL,
+                existed INTEGER NOT NULL,
+                content BLOB,
+                tool_name TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                restored_at TEXT,
+                UNIQUE (conversation_id, turn_id, path),
+                FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS todo_plans (
+                conversation_id TEXT PRIMARY KEY,
+                version INTEGER NOT NULL,
+                items TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+            );
+
             CREATE TABLE IF NOT EXISTS memory_snapshots (
                 id TEXT PRIMARY KEY,
                 user_id TEXT NOT NULL,
@@ -118,28 +168,50 @@ impl Database {
             );
 
             CREATE INDEX IF NOT EXISTS idx_messages_conversation ON messages(conversation_id);
+            CREATE INDEX IF NOT EXISTS idx_conversation_summaries_conversation_created ON conversation_summaries(conversation_id, created_at);
             CREATE INDEX IF NOT EXISTS idx_message_events_conversation_turn_seq ON message_events(conversation_id, turn_id, seq);
             CREATE INDEX IF NOT EXISTS idx_message_events_conversation_created ON message_events(conversation_id, created_at);
             CREATE INDEX IF NOT EXISTS idx_scheduler_jobs_enabled_next_run ON scheduler_jobs(enabled, next_run_at);
             CREATE INDEX IF NOT EXISTS idx_scheduler_runs_job_created ON scheduler_runs(job_id, created_at);
             CREATE INDEX IF NOT EXISTS idx_scheduler_runs_source_created ON scheduler_runs(source, created_at);
+            CREATE INDEX IF NOT EXISTS idx_llm_usage_created ON llm_usage(created_at);
+            CREATE INDEX IF NOT EXISTS idx_llm_usage_conversation_turn ON llm_usage(conversation_id, turn_id);
             CREATE INDEX IF NOT EXISTS idx_memory_snapshots_user_updated ON memory_snapshots(user_id, updated_at);
+            CREATE INDEX IF NOT EXISTS idx_workspace_checkpoints_conversation_created ON workspace_checkpoints(conversation_id, created_at);
             "#,
         )
         .execute(&pool)
         .await?;
 
-        let has_reasoning_column = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM pragma_table_info('messages') WHERE name = 'reasoning'",
+        ensure_column(&pool, "messages", "reasoning", "TEXT").await?;
+        // JSON array of fallback models tried in order when the conversation model fails.
+        ensure_column(&pool, "conversations", "fallback_models", "TEXT").await?;
+        // Named tool profile that limits which tools are sent to the model.
+        ensure_column(&pool, "conversations", "tool_profile", "TEXT").await?;
+        ensure_column(&pool, "scheduler_jobs", "tool_profile", "TEXT").await?;
+        // NULL follows `sandbox.enabled`; 0/1 overrides it for this conversation.
+        ensure_column(&pool, "conversations", "sandboxed", "INTEGER").await?;
+        // Rendered MCP resources attached to a user message, replayed with it on later turns.
+        ensure_column(&pool, "messages", "attachment_context", "TEXT").await?;
+        // Messages form a tree: edits and regenerations add siblings under the same parent,
+        // and `active_leaf_id` picks the branch that is shown and replayed.
+        let added_parents = ensure_column(&pool, "messages", "parent_id", "TEXT").await?;
+        let added_leaves = ensure_column(&pool, "conversations", "active_leaf_id", "TEXT").await?;
+        if added_parents || added_leaves {
+            backfill_linear_branches(&pool).await?;
+        }
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_messages_conversation_parent ON messages(conversation_id, parent_id)",
         )
-        .fetch_one(&pool)
-        .await?
-            > 0;
-
-        if !has_reasoning_column {
-            sqlx::query("ALTER TABLE messages ADD COLUMN reasoning TEXT")
-                .execute(&pool)
-                .await?;
+        .execute(&pool)
+        .await?;
+        for column in [
+            "prompt_tokens",
+            "completion_tokens",
+            "reasoning_tokens",
+            "cached_tokens",
+        ] {
+            ensure_column(&pool, "scheduler_runs", column, "INTEGER NOT NULL DEFAULT 0").await?;
         }
 
         Ok(Self { pool })
@@ -149,3 +221,171 @@ impl Database {
         &self.pool
     }
 }
+
+async fn ensure_column(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<bool> {
+    let has_column = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?",
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_one(pool)
+    .await?
+        > 0;
+
+    if !has_column {
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ))
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(!has_column)
+}
+
+/// Chains the existing flat history so every conversation starts on a single branch.
+async fn backfill_linear_branches(pool: &SqlitePool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        UPDATE messages SET parent_id = (
+            SELECT prev.id FROM messages prev
+            WHERE prev.conversation_id = messages.conversation_id
+              AND (prev.created_at < messages.created_at
+                   OR (prev.created_at = messages.created_at AND prev.rowid < messages.rowid))
+            ORDER BY prev.created_at DESC, prev.rowid DESC
+            LIMIT 1
+        )
+        WHERE parent_id IS NULL;
+
+        UPDATE conversations SET active_leaf_id = (
+            SELECT id FROM messages
+            WHERE conversation_id = conversations.id
+            ORDER BY created_at DESC, rowid DESC
+            LIMIT 1
+        )
+        WHERE active_leaf_id IS NULL;
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Older user_message events carry no message id. A turn inserts its user message right
+    // before the event, so each event takes the nearest preceding unclaimed message with the
+    // same content; events without such a message are left alone.
+    let events = sqlx::query_as::<_, (String, String, String, String)>(
+        r#"
+        SELECT id, conversation_id, json_extract(payload, '$.content'), created_at
+        FROM message_events
+        WHERE event_type = 'user_message'
+          AND json_valid(payload)
+          AND json_extract(payload, '$.messageId') IS NULL
+          AND json_type(payload, '$.content') = 'text'
+        ORDER BY conversation_id, created_at DESC, seq DESC
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let messages = sqlx::query_as::<_, (String, String, String, String)>(
+        "SELECT id, conversation_id, content, created_at FROM messages WHERE role = 'user' ORDER BY created_at DESC, rowid DESC",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut claimed = std::collections::HashSet::new();
+    for (event_id, conversation_id, content, event_created_at) in events {
+        let matched = messages.iter().find(|(id, message_conversation, message_content, created_at)| {
+            message_conversation == &conversation_id
+                && message_content == &content
+                && created_at <= &event_created_at
+                && !claimed.contains(id)
+        });
+        let Some((message_id, ..)) = matched else {
+            continue;
+        };
+        claimed.insert(message_id.clone());
+        sqlx::query("UPDATE message_events SET payload = json_set(payload, '$.messageId', ?) WHERE id = ?")
+            .bind(message_id)
+            .bind(&event_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn backfill_links_each_user_event_to_its_nearest_preceding_message() {
+        let root = std::env::temp_dir().join(format!("petool-db-test-{}", uuid::Uuid::new_v4()));
+        let path = root.join("petool.db");
+        let pool = Database::new(path.clone()).await.unwrap().pool().clone();
+        sqlx::query(
+            r#"
+            INSERT INTO conversations (id, title, model, created_at, updated_at) VALUES ('c1', 'First', 'm', '', '');
+            INSERT INTO messages (id, conversation_id, role, content, created_at) VALUES
+                ('m1', 'c1', 'user', 'again', '2024-01-01T00:00:00Z'),
+                ('m2', 'c1', 'assistant', 'ok', '2024-01-01T00:00:02Z'),
+                ('m3', 'c1', 'user', 'again', '2024-01-01T00:00:05Z');
+            INSERT INTO message_events (id, conversation_id, turn_id, seq, event_type, payload, created_at) VALUES
+                ('e1', 'c1', 't1', 1, 'user_message', '{"content":"again"}', '2024-01-01T00:00:01Z'),
+                ('e2', 'c1', 't2', 1, 'user_message', '{"content":"again"}', '2024-01-01T00:00:06Z'),
+                ('e3', 'c1', 't3', 1, 'user_message', '{"content":"never stored"}', '2024-01-01T00:00:07Z');
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        // Simulate a database from before branching so the migration runs again.
+        sqlx::query(
+            "DROP INDEX idx_messages_conversation_parent; ALTER TABLE messages DROP COLUMN parent_id; ALTER TABLE conversations DROP COLUMN active_leaf_id;",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool.close().await;
+
+        let pool = Database::new(path).await.unwrap().pool().clone();
+        let linked = sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT id, json_extract(payload, '$.messageId') FROM message_events ORDER BY id",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            linked,
+            vec![
+                ("e1".to_string(), Some("m1".to_string())),
+                ("e2".to_string(), Some("m3".to_string())),
+                ("e3".to_string(), None),
+            ]
+        );
+        let parents = sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT id, parent_id FROM messages ORDER BY id",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            parents,
+            vec![
+                ("m1".to_string(), None),
+                ("m2".to_string(), Some("m1".to_string())),
+                ("m3".to_string(), Some("m2".to_string())),
+            ]
+        );
+        pool.close().await;
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -1,12 +1,22 @@
 use anyhow::{anyhow, Result};
-use async_openai::{config::OpenAIConfig, traits::RequestOptionsBuilder, Client as OpenAiClient};
-use futures_util::StreamExt;
+use async_openai::{config::OpenAIConfig, Client as OpenAiClient};
+use futures_util::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use crate::models::config::{LlmRetryConfig, LlmWireProtocol, LocalLlmBackend};
+use crate::services::local_llm::{split_think_tags, ThinkSplit, ThinkTagSplitter};
+use crate::services::sse::{SseDecoder, SseEvent};
 
-const DEFAULT_API_BASE: &str = "https://open.bigmodel.cn/api/paas/v4";
 const ANTHROPIC_API_VERSION: &str = "2023-06-01";
+const LLM_CONNECT_TIMEOUT_SECS: u64 = 15;
+/// Bounds a non-streaming completion, and the wait for a stream's response headers.
+const LLM_RESPONSE_TIMEOUT_SECS: u64 = 300;
+/// A stream that stays silent this long is treated as dropped.
+const LLM_STREAM_IDLE_TIMEOUT_SECS: u64 = 120;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
@@ -76,23 +86,32 @@ pub struct ChatRequest {
     pub tool_choice: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub extra_body: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<Value>,
     // #[serde(skip_serializing_if = "Option::is_none")]
     // pub tool_stream: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatResponse {
+    #[serde(default)]
     pub id: String,
+    #[serde(default)]
     pub object: String,
+    #[serde(default)]
     pub created: u64,
     pub model: String,
     pub choices: Vec<Choice>,
+    #[serde(default)]
+    pub usage: Option<Value>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct OpenAiCompatStreamResponse {
     #[serde(default)]
     choices: Vec<OpenAiCompatStreamChoice>,
+    #[serde(default)]
+    usage: Option<Value>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -107,6 +126,9 @@ struct OpenAiCompatStreamDelta {
     content: Option<String>,
     #[serde(default)]
     reasoning_content: Option<String>,
+    /// Ollama reports thinking output under `reasoning`.
+    #[serde(default)]
+    reasoning: Option<String>,
     #[serde(default)]
     reasoning_details: Option<Vec<ChatReasoningDetail>>,
     #[serde(default)]
@@ -115,8 +137,9 @@ struct OpenAiCompatStreamDelta {
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct OpenAiCompatToolCallChunk {
+    /// Missing on some local servers, which send each call whole in a single chunk.
     #[serde(default)]
-    index: usize,
+    index: Option<usize>,
     #[serde(default)]
     id: Option<String>,
     #[serde(default)]
@@ -127,8 +150,19 @@ struct OpenAiCompatToolCallChunk {
 struct OpenAiCompatFunctionCallChunk {
     #[serde(default)]
     name: Option<String>,
+    /// Normally a JSON string; Ollama may send the arguments as an object.
     #[serde(default)]
-    arguments: Option<String>,
+    arguments: Option<Value>,
+}
+
+impl OpenAiCompatFunctionCallChunk {
+    fn arguments_text(&self) -> Option<String> {
+        match self.arguments.as_ref()? {
+            Value::Null => None,
+            Value::String(text) => Some(text.clone()),
+            other => Some(other.to_string()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -158,6 +192,16 @@ pub enum LlmStreamEvent {
     Content(String),
     Reasoning(String),
     ToolCallDelta(ToolCallDelta),
+    /// Emitted by the failover chain before output from a fallback route starts.
+    ModelFallback(ModelFallback),
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelFallback {
+    pub from_model: String,
+    pub to_model: String,
+    pub provider: String,
+    pub error: String,
 }
 
 #[derive(Debug, Clone)]
@@ -167,6 +211,90 @@ pub struct LlmStreamResult {
     pub reasoning_details: Option<Vec<ChatReasoningDetail>>,
     pub tool_calls: Vec<ChatToolCall>,
     pub cancelled: bool,
+    pub usage: Option<LlmUsage>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LlmChatOutput {
+    pub content: String,
+    pub usage: Option<LlmUsage>,
+}
+
+/// Token counts reported by the provider for one request (or a sum of requests).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub reasoning_tokens: u64,
+    pub cached_tokens: u64,
+}
+
+impl LlmUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prompt_tokens == 0
+            && self.completion_tokens == 0
+            && self.reasoning_tokens == 0
+            && self.cached_tokens == 0
+    }
+
+    pub fn add(&mut self, other: &LlmUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+        self.cached_tokens += other.cached_tokens;
+    }
+
+    /// Parses an OpenAI-compatible `usage` block (GLM, Ark, MiniMax follow the same shape).
+    pub fn from_openai_value(value: &Value) -> Option<Self> {
+        let usage = Self {
+            prompt_tokens: read_usage_count(value, &["prompt_tokens"]),
+            completion_tokens: read_usage_count(value, &["completion_tokens"]),
+            reasoning_tokens: read_usage_count(
+                value,
+                &["completion_tokens_details", "reasoning_tokens"],
+            ),
+            cached_tokens: read_usage_count(value, &["prompt_tokens_details", "cached_tokens"]),
+        };
+        if usage.is_empty() {
+            None
+        } else {
+            Some(usage)
+        }
+    }
+
+    /// Parses an Anthropic `usage` block. Cache reads and writes count as prompt tokens.
+    pub fn from_anthropic_value(value: &Value) -> Option<Self> {
+        let cache_read = read_usage_count(value, &["cache_read_input_tokens"]);
+        let usage = Self {
+            prompt_tokens: read_usage_count(value, &["input_tokens"])
+                + cache_read
+                + read_usage_count(value, &["cache_creation_input_tokens"]),
+            completion_tokens: read_usage_count(value, &["output_tokens"]),
+            reasoning_tokens: 0,
+            cached_tokens: cache_read,
+        };
+        if usage.is_empty() {
+            None
+        } else {
+            Some(usage)
+        }
+    }
+}
+
+fn read_usage_count(value: &Value, path: &[&str]) -> u64 {
+    let mut current = value;
+    for key in path {
+        let Some(next) = current.get(*key) else {
+            return 0;
+        };
+        current = next;
+    }
+    current.as_u64().unwrap_or(0)
 }
 
 #[derive(Default)]
@@ -176,6 +304,42 @@ struct ToolCallBuilder {
     arguments: String,
 }
 
+/// Maps a streamed tool call chunk onto a builder slot. Chunks without an index continue the
+/// latest call unless they carry a new id, and a new id on an occupied index (seen when local
+/// servers emit several complete calls as index 0) opens a fresh slot.
+fn resolve_tool_call_slot(
+    builders: &BTreeMap<usize, ToolCallBuilder>,
+    remapped: &mut BTreeMap<usize, usize>,
+    index: Option<usize>,
+    id: Option<&str>,
+) -> usize {
+    let next_free = builders
+        .keys()
+        .next_back()
+        .map(|last| last + 1)
+        .unwrap_or(0);
+    let Some(index) = index else {
+        return match id {
+            Some(id) => builders
+                .iter()
+                .find(|(_, builder)| builder.id.as_deref() == Some(id))
+                .map(|(slot, _)| *slot)
+                .unwrap_or(next_free),
+            None => builders.keys().next_back().copied().unwrap_or(0),
+        };
+    };
+    let slot = remapped.get(&index).copied().unwrap_or(index);
+    let conflicts = match (id, builders.get(&slot)) {
+        (Some(id), Some(existing)) => existing.id.as_deref().is_some_and(|known| known != id),
+        _ => false,
+    };
+    if conflicts {
+        remapped.insert(index, next_free);
+        return next_free;
+    }
+    slot
+}
+
 fn append_tool_arguments_chunk(base: &mut String, chunk: &str) {
     if chunk.is_empty() {
         return;
@@ -225,6 +389,23 @@ fn append_tool_arguments_chunk(base: &mut String, chunk: &str) {
     base.push_str(chunk);
 }
 
+fn emit_think_split(
+    split: ThinkSplit,
+    content: &mut String,
+    reasoning: &mut String,
+    callback: &mut impl FnMut(LlmStreamEvent),
+) {
+    if !split.reasoning.is_empty() {
+        if let Some(delta_reasoning) = append_reasoning_chunk(reasoning, &split.reasoning) {
+            callback(LlmStreamEvent::Reasoning(delta_reasoning));
+        }
+    }
+    if !split.content.is_empty() {
+        content.push_str(&split.content);
+        callback(LlmStreamEvent::Content(split.content));
+    }
+}
+
 fn append_reasoning_chunk(base: &mut String, chunk: &str) -> Option<String> {
     if chunk.is_empty() {
         return None;
@@ -355,45 +536,432 @@ fn merge_leading_system_messages(messages: Vec<ChatMessage>) -> Vec<ChatMessage>
     normalized
 }
 
+/// Non-2xx response from a provider, kept typed so retries can honour `Retry-After`.
+#[derive(Debug, Clone)]
+pub struct LlmHttpError {
+    pub status: u16,
+    pub retry_after: Option<Duration>,
+    pub message: String,
+}
+
+impl std::fmt::Display for LlmHttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "API error: HTTP {}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for LlmHttpError {}
+
+/// Network failure or timeout before a provider finished answering, kept typed so retries
+/// can tell dropped connections from malformed requests.
+#[derive(Debug, Clone)]
+pub struct LlmTransportError {
+    pub transient: bool,
+    pub message: String,
+}
+
+impl LlmTransportError {
+    fn from_reqwest(stage: &str, error: reqwest::Error) -> Self {
+        Self {
+            transient: error.is_timeout()
+                || error.is_connect()
+                || error.is_request()
+                || error.is_body(),
+            message: format!("{}: {}", stage, error),
+        }
+    }
+
+    fn timed_out(stage: &str) -> Self {
+        Self {
+            transient: true,
+            message: format!("{}: timed out", stage),
+        }
+    }
+}
+
+impl std::fmt::Display for LlmTransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "API error: {}", self.message)
+    }
+}
+
+impl std::error::Error for LlmTransportError {}
+
+fn is_transient_status(status: u16) -> bool {
+    matches!(status, 408 | 409 | 425 | 429 | 500 | 502 | 503 | 504 | 529)
+}
+
+/// Only typed provider failures count: retryable HTTP statuses and dropped or timed-out
+/// connections. Everything else (bad requests, auth, malformed replies) fails immediately.
+pub fn is_transient_llm_error(error: &anyhow::Error) -> bool {
+    if let Some(http_error) = error.downcast_ref::<LlmHttpError>() {
+        return is_transient_status(http_error.status);
+    }
+    error
+        .downcast_ref::<LlmTransportError>()
+        .is_some_and(|transport_error| transport_error.transient)
+}
+
+/// Local servers reject tools outright for models whose chat template lacks them:
+/// Ollama answers "<model> does not support tools", llama.cpp started without `--jinja`
+/// answers "tools param requires --jinja flag".
+fn is_local_tools_unsupported_error(error: &anyhow::Error) -> bool {
+    let Some(http_error) = error.downcast_ref::<LlmHttpError>() else {
+        return false;
+    };
+    let lowered = http_error.message.to_ascii_lowercase();
+    matches!(http_error.status, 400 | 500)
+        && (lowered.contains("does not support tools") || lowered.contains("requires --jinja"))
+}
+
+fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let raw = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(seconds) = raw.parse::<f64>() {
+        return (seconds >= 0.0).then(|| Duration::from_secs_f64(seconds));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(raw).ok()?;
+    let wait_ms = at
+        .with_timezone(&chrono::Utc)
+        .signed_duration_since(chrono::Utc::now())
+        .num_milliseconds();
+    Some(Duration::from_millis(wait_ms.max(0) as u64))
+}
+
+/// Delay before retry number `retry` (1-based), or `None` when the error should not be retried.
+fn retry_delay(policy: &LlmRetryConfig, retry: u32, error: &anyhow::Error) -> Option<Duration> {
+    if retry > policy.max_retries || !is_transient_llm_error(error) {
+        return None;
+    }
+    if let Some(retry_after) = error
+        .downcast_ref::<LlmHttpError>()
+        .and_then(|http_error| http_error.retry_after)
+    {
+        // A long Retry-After is better served by the fallback chain than by waiting.
+        return (retry_after <= Duration::from_millis(policy.max_retry_after_ms))
+            .then_some(retry_after);
+    }
+    let exponent = retry.saturating_sub(1).min(16);
+    let backoff = policy.initial_backoff_ms.saturating_mul(1u64 << exponent);
+    Some(Duration::from_millis(backoff.min(policy.max_backoff_ms)))
+}
+
+/// Returns false if cancelled while waiting.
+async fn sleep_unless_cancelled(
+    delay: Duration,
+    should_cancel: &(impl Fn() -> bool + Sync),
+) -> bool {
+    let deadline = Instant::now() + delay;
+    loop {
+        if should_cancel() {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        tokio::time::sleep((deadline - now).min(Duration::from_millis(250))).await;
+    }
+}
+
+type SseDataStream = Pin<Box<dyn Stream<Item = Result<String>> + Send>>;
+type OpenAiCompatEventStream =
+    Pin<Box<dyn Stream<Item = Result<OpenAiCompatStreamResponse>> + Send>>;
+type AnthropicEventStream = Pin<Box<dyn Stream<Item = Result<Value>> + Send>>;
+
+struct SseReadState<S> {
+    bytes: S,
+    decoder: SseDecoder,
+    pending: VecDeque<String>,
+    finished: bool,
+}
+
+/// Queues the data of decoded events; returns true once the `[DONE]` sentinel arrived.
+fn queue_sse_data(events: Vec<SseEvent>, pending: &mut VecDeque<String>) -> bool {
+    for event in events {
+        let data = event.data.trim();
+        if data == "[DONE]" {
+            return true;
+        }
+        if !data.is_empty() {
+            pending.push_back(data.to_string());
+        }
+    }
+    false
+}
+
+fn parse_openai_compat_stream_chunk(data: &str) -> Result<OpenAiCompatStreamResponse> {
+    let value: Value = serde_json::from_str(data)
+        .map_err(|e| anyhow!("API error: invalid stream chunk: {}", e))?;
+    if let Some(error) = value.get("error").filter(|error| !error.is_null()) {
+        return Err(anyhow!("API error: {}", error));
+    }
+    serde_json::from_value(value).map_err(|e| anyhow!("API error: invalid stream chunk: {}", e))
+}
+
+fn parse_anthropic_stream_event(data: &str) -> Result<Value> {
+    let value: Value = serde_json::from_str(data)
+        .map_err(|e| anyhow!("Anthropic API error: invalid stream event: {}", e))?;
+    if value.get("type").and_then(Value::as_str) == Some("error") {
+        let error = value.get("error").cloned().unwrap_or(Value::Null);
+        // Overload and rate-limit errors can arrive after a 200 as an `error` event.
+        let status = match error.get("type").and_then(Value::as_str) {
+            Some("overloaded_error") => Some(529),
+            Some("rate_limit_error") => Some(429),
+            Some("api_error") => Some(500),
+            _ => None,
+        };
+        let message = error
+            .get("message")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| error.to_string());
+        return Err(match status {
+            Some(status) => LlmHttpError {
+                status,
+                retry_after: None,
+                message,
+            }
+            .into(),
+            None => anyhow!("Anthropic API error: {}", message),
+        });
+    }
+    Ok(value)
+}
+
+/// Yields the `data` of each event until `[DONE]` or the end of the body.
+fn sse_data_stream(response: reqwest::Response) -> SseDataStream {
+    let state = SseReadState {
+        bytes: Box::pin(response.bytes_stream()),
+        decoder: SseDecoder::default(),
+        pending: VecDeque::new(),
+        finished: false,
+    };
+    Box::pin(futures_util::stream::unfold(
+        state,
+        |mut state| async move {
+            loop {
+                if let Some(data) = state.pending.pop_front() {
+                    return Some((Ok(data), state));
+                }
+                if state.finished {
+                    return None;
+                }
+                let next = tokio::time::timeout(
+                    Duration::from_secs(LLM_STREAM_IDLE_TIMEOUT_SECS),
+                    state.bytes.next(),
+                )
+                .await;
+                match next {
+                    Ok(Some(Ok(chunk))) => {
+                        let events = state.decoder.push(&chunk);
+                        state.finished = queue_sse_data(events, &mut state.pending);
+                    }
+                    Ok(Some(Err(error))) => {
+                        state.finished = true;
+                        return Some((
+                            Err(LlmTransportError::from_reqwest("stream interrupted", error)
+                                .into()),
+                            state,
+                        ));
+                    }
+                    Ok(None) => {
+                        // Flush an event whose blank terminator line never arrived.
+                        let events = state.decoder.push(b"\n\n");
+                        queue_sse_data(events, &mut state.pending);
+                        state.finished = true;
+                    }
+                    Err(_) => {
+                        state.finished = true;
+                        return Some((
+                            Err(LlmTransportError::timed_out("stream stalled").into()),
+                            state,
+                        ));
+                    }
+                }
+            }
+        },
+    ))
+}
+
+fn openai_compat_event_stream(response: reqwest::Response) -> OpenAiCompatEventStream {
+    Box::pin(
+        sse_data_stream(response)
+            .map(|data| data.and_then(|data| parse_openai_compat_stream_chunk(&data))),
+    )
+}
+
+fn anthropic_event_stream(response: reqwest::Response) -> AnthropicEventStream {
+    Box::pin(
+        sse_data_stream(response)
+            .map(|data| data.and_then(|data| parse_anthropic_stream_event(&data))),
+    )
+}
+
+/// Extracts the provider's error message from a failed response body.
+fn http_error_message(body: String) -> String {
+    serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|value| {
+            value
+                .pointer("/error/message")
+                .or_else(|| value.get("message"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        })
+        .unwrap_or(body)
+}
+
+fn build_http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(LLM_CONNECT_TIMEOUT_SECS))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+#[derive(Clone)]
 pub struct LlmService {
     client: OpenAiClient<OpenAIConfig>,
+    http: reqwest::Client,
     api_key: String,
     api_base: String,
+    protocol: LlmWireProtocol,
+    local_backend: Option<LocalLlmBackend>,
+    retry_policy: LlmRetryConfig,
 }
 
 impl LlmService {
-    pub fn new(api_key: String, api_base: Option<String>) -> Self {
-        let resolved_api_base = api_base
-            .unwrap_or_else(|| DEFAULT_API_BASE.to_string())
-            .trim_end_matches('/')
-            .to_string();
+    pub fn with_protocol(api_key: String, api_base: String, protocol: LlmWireProtocol) -> Self {
+        let resolved_api_base = api_base.trim_end_matches('/').to_string();
         let config = OpenAIConfig::new()
             .with_api_key(api_key.clone())
             .with_api_base(resolved_api_base.clone());
         Self {
             client: OpenAiClient::with_config(config),
+            http: build_http_client(),
             api_key,
             api_base: resolved_api_base,
+            protocol,
+            local_backend: None,
+            retry_policy: LlmRetryConfig::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: LlmRetryConfig) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn with_local_backend(mut self, local_backend: Option<LocalLlmBackend>) -> Self {
+        self.local_backend = local_backend;
+        self
+    }
+
+    fn openai_request(&self, path: &str) -> reqwest::RequestBuilder {
+        let builder = self.http.post(format!("{}{}", self.api_base, path));
+        if self.api_key.trim().is_empty() {
+            builder
+        } else {
+            builder.bearer_auth(&self.api_key)
         }
     }
 
+    fn anthropic_request(&self) -> reqwest::RequestBuilder {
+        self.http
+            .post(format!("{}/v1/messages", self.api_base))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_API_VERSION)
+    }
+
+    /// Sends a request and turns non-2xx answers into `LlmHttpError`, so every path
+    /// retries on the same statuses and honours `Retry-After`.
+    async fn send(&self, builder: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| LlmTransportError::from_reqwest("error sending request", e))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let retry_after = parse_retry_after(response.headers());
+        let body = response.text().await.unwrap_or_default();
+        Err(LlmHttpError {
+            status: status.as_u16(),
+            retry_after,
+            message: http_error_message(body),
+        }
+        .into())
+    }
+
+    async fn send_for_json<T: serde::de::DeserializeOwned>(
+        &self,
+        builder: reqwest::RequestBuilder,
+    ) -> Result<T> {
+        let timeout = Duration::from_secs(LLM_RESPONSE_TIMEOUT_SECS);
+        let response = self.send(builder.timeout(timeout)).await?;
+        response
+            .json::<T>()
+            .await
+            .map_err(|e| LlmTransportError::from_reqwest("invalid response", e).into())
+    }
+
+    async fn send_for_stream(
+        &self,
+        builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        tokio::time::timeout(
+            Duration::from_secs(LLM_RESPONSE_TIMEOUT_SECS),
+            self.send(builder),
+        )
+        .await
+        .map_err(|_| LlmTransportError::timed_out("waiting for stream"))?
+    }
+
     fn is_anthropic_compatible(&self) -> bool {
-        let base = self.api_base.to_ascii_lowercase();
-        base.contains("/anthropic")
+        self.protocol == LlmWireProtocol::AnthropicMessages
     }
 
-    pub async fn chat(&self, model: &str, messages: Vec<ChatMessage>) -> Result<String> {
-        if self.is_anthropic_compatible() {
-            return self.chat_anthropic(model, messages).await;
+    pub async fn chat_with_usage(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+    ) -> Result<LlmChatOutput> {
+        let mut retry = 0u32;
+        loop {
+            let result = if self.is_anthropic_compatible() {
+                self.chat_anthropic(model, messages.clone()).await
+            } else {
+                self.chat_openai_compatible(model, messages.clone()).await
+            };
+            let error = match result {
+                Ok(output) => return Ok(output),
+                Err(error) => error,
+            };
+            retry += 1;
+            let Some(delay) = retry_delay(&self.retry_policy, retry, &error) else {
+                return Err(error);
+            };
+            eprintln!(
+                "[llm] transient error, retry {}/{} in {}ms: model={}, error={}",
+                retry,
+                self.retry_policy.max_retries,
+                delay.as_millis(),
+                model,
+                error
+            );
+            tokio::time::sleep(delay).await;
         }
-        self.chat_openai_compatible(model, messages).await
     }
 
     async fn chat_openai_compatible(
         &self,
         model: &str,
         messages: Vec<ChatMessage>,
-    ) -> Result<String> {
+    ) -> Result<LlmChatOutput> {
         let messages = if is_minimax_model(model) {
             merge_leading_system_messages(messages)
         } else {
@@ -407,42 +975,47 @@ impl LlmService {
             tools: None,
             tool_choice: None,
             extra_body: minimax_reasoning_split_extra_body(model),
+            stream_options: None,
             // tool_stream: None,
         };
 
-        let chat_response: ChatResponse =
-            self.client.chat().create_byot(request).await.map_err(|e| {
+        let chat_response: ChatResponse = self
+            .send_for_json(self.openai_request("/chat/completions").json(&request))
+            .await
+            .inspect_err(|e| {
                 eprintln!(
                     "[llm] non-stream request failed: model={}, error={}",
                     model, e
-                );
-                anyhow!("API error: {}", e)
+                )
             })?;
         let choice = chat_response
             .choices
             .first()
             .ok_or_else(|| anyhow!("No response from API"))?;
 
-        Ok(choice.message.content.clone().unwrap_or_default())
+        let content = choice.message.content.clone().unwrap_or_default();
+        Ok(LlmChatOutput {
+            content: if self.local_backend.is_some() {
+                split_think_tags(&content).content
+            } else {
+                content
+            },
+            usage: chat_response
+                .usage
+                .as_ref()
+                .and_then(LlmUsage::from_openai_value),
+        })
     }
 
-    async fn chat_anthropic(&self, model: &str, messages: Vec<ChatMessage>) -> Result<String> {
+    async fn chat_anthropic(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+    ) -> Result<LlmChatOutput> {
         let request = self.build_anthropic_request(model, messages, None, false)?;
-
-        let chat_api = self
-            .client
-            .chat()
-            .path("/v1/messages")
-            .map_err(|e| anyhow!("Anthropic API error: {}", e))?
-            .header("x-api-key", &self.api_key)
-            .map_err(|e| anyhow!("Anthropic API error: {}", e))?
-            .header("anthropic-version", ANTHROPIC_API_VERSION)
-            .map_err(|e| anyhow!("Anthropic API error: {}", e))?;
-
-        let response_json: Value = chat_api
-            .create_byot(request)
-            .await
-            .map_err(|e| anyhow!("Anthropic API error: {}", e))?;
+        let response_json: Value = self
+            .send_for_json(self.anthropic_request().json(&request))
+            .await?;
         let content_blocks = response_json
             .get("content")
             .and_then(Value::as_array)
@@ -456,33 +1029,94 @@ impl LlmService {
                 }
             }
         }
-        Ok(text)
+        Ok(LlmChatOutput {
+            content: text,
+            usage: response_json
+                .get("usage")
+                .and_then(LlmUsage::from_anthropic_value),
+        })
     }
 
+    /// Streams one completion, retrying transient failures with backoff as long as nothing
+    /// has been emitted to `callback` yet (a partially streamed reply cannot be replayed).
     pub async fn chat_stream_with_tools<'a>(
         &'a self,
         model: &'a str,
         messages: Vec<ChatMessage>,
         tools: Option<Vec<ChatTool>>,
-        callback: impl FnMut(LlmStreamEvent) + Send + 'a,
+        mut callback: impl FnMut(LlmStreamEvent) + Send + 'a,
         should_cancel: impl Fn() -> bool + Send + Sync + 'a,
     ) -> Result<LlmStreamResult> {
-        if self.is_anthropic_compatible() {
-            return self
-                .chat_stream_with_tools_anthropic(model, messages, tools, callback, should_cancel)
-                .await;
+        let mut retry = 0u32;
+        loop {
+            let mut emitted = false;
+            let forward = |event: LlmStreamEvent| {
+                emitted = true;
+                callback(event);
+            };
+            let result = if self.is_anthropic_compatible() {
+                self.chat_stream_with_tools_anthropic(
+                    model,
+                    messages.clone(),
+                    tools.clone(),
+                    forward,
+                    &should_cancel,
+                )
+                .await
+            } else {
+                self.chat_stream_with_tools_openai(
+                    model,
+                    messages.clone(),
+                    tools.clone(),
+                    forward,
+                    &should_cancel,
+                )
+                .await
+            };
+            let error = match result {
+                Ok(output) => return Ok(output),
+                Err(error) => error,
+            };
+            retry += 1;
+            let delay = if emitted || should_cancel() {
+                None
+            } else {
+                retry_delay(&self.retry_policy, retry, &error)
+            };
+            let Some(delay) = delay else {
+                return Err(error);
+            };
+            eprintln!(
+                "[llm] transient stream error, retry {}/{} in {}ms: model={}, error={}",
+                retry,
+                self.retry_policy.max_retries,
+                delay.as_millis(),
+                model,
+                error
+            );
+            if !sleep_unless_cancelled(delay, &should_cancel).await {
+                return Err(error);
+            }
         }
-        self.chat_stream_with_tools_openai(model, messages, tools, callback, should_cancel)
-            .await
     }
 
-    async fn chat_stream_with_tools_openai<'a>(
-        &'a self,
-        model: &'a str,
+    async fn open_openai_compat_stream(
+        &self,
+        request: &ChatRequest,
+    ) -> Result<OpenAiCompatEventStream> {
+        let response = self
+            .send_for_stream(self.openai_request("/chat/completions").json(request))
+            .await?;
+        Ok(openai_compat_event_stream(response))
+    }
+
+    async fn chat_stream_with_tools_openai(
+        &self,
+        model: &str,
         messages: Vec<ChatMessage>,
         tools: Option<Vec<ChatTool>>,
-        mut callback: impl FnMut(LlmStreamEvent) + Send + 'a,
-        should_cancel: impl Fn() -> bool + Send + Sync + 'a,
+        mut callback: impl FnMut(LlmStreamEvent) + Send,
+        should_cancel: impl Fn() -> bool + Send + Sync,
     ) -> Result<LlmStreamResult> {
         let messages = if is_minimax_model(model) {
             merge_leading_system_messages(messages)
@@ -500,10 +1134,16 @@ impl LlmService {
             },
             tools: tools.clone(),
             extra_body: minimax_reasoning_split_extra_body(model),
+            // Ask for a trailing usage chunk; MiniMax reports usage on the last chunk unprompted.
+            stream_options: if is_minimax_model(model) {
+                None
+            } else {
+                Some(json!({ "include_usage": true }))
+            },
             // Some OpenAI-compatible providers (including MiniMax /v1) reject tool_stream.
             // tool_stream: None,
         };
-        let primary_stream_result = self.client.chat().create_stream_byot(request.clone()).await;
+        let primary_stream_result = self.open_openai_compat_stream(&request).await;
         let mut stream = match primary_stream_result {
             Ok(stream) => stream,
             Err(primary_error) => {
@@ -513,20 +1153,18 @@ impl LlmService {
                     model, error_text
                 );
                 let should_retry_without_tools = request.tools.is_some()
-                    && error_text
-                        .to_ascii_lowercase()
-                        .contains("invalid chat setting");
+                    && (error_text.to_ascii_lowercase().contains("invalid chat setting")
+                        || (self.local_backend.is_some()
+                            && is_local_tools_unsupported_error(&primary_error)));
 
                 if !should_retry_without_tools {
-                    return Err(anyhow!("API error: {}", primary_error));
+                    return Err(primary_error);
                 }
 
                 let mut fallback_request = request;
                 fallback_request.tools = None;
                 fallback_request.tool_choice = None;
-                self.client
-                    .chat()
-                    .create_stream_byot(fallback_request)
+                self.open_openai_compat_stream(&fallback_request)
                     .await
                     .map_err(|fallback_error| {
                         eprintln!(
@@ -545,15 +1183,21 @@ impl LlmService {
         let mut content = String::new();
         let mut reasoning = String::new();
         let mut tool_call_builders: BTreeMap<usize, ToolCallBuilder> = BTreeMap::new();
+        let mut remapped_tool_slots: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut think_splitter = self.local_backend.map(|_| ThinkTagSplitter::default());
         let mut cancelled = false;
+        let mut usage: Option<LlmUsage> = None;
 
         while let Some(item) = stream.next().await {
             if should_cancel() {
                 cancelled = true;
                 break;
             }
-            let value: OpenAiCompatStreamResponse =
-                item.map_err(|e| anyhow!("API error: {}", e))?;
+            let value = item?;
+
+            if let Some(chunk_usage) = value.usage.as_ref().and_then(LlmUsage::from_openai_value) {
+                usage = Some(chunk_usage);
+            }
 
             for choice in value.choices {
                 if should_cancel() {
@@ -563,13 +1207,20 @@ impl LlmService {
 
                 if let Some(chunk_text) = choice.delta.content {
                     if !chunk_text.is_empty() {
-                        let part = chunk_text.to_string();
-                        content.push_str(&part);
-                        callback(LlmStreamEvent::Content(part));
+                        let split = match think_splitter.as_mut() {
+                            Some(splitter) => splitter.push(&chunk_text),
+                            None => ThinkSplit {
+                                content: chunk_text,
+                                reasoning: String::new(),
+                            },
+                        };
+                        emit_think_split(split, &mut content, &mut reasoning, &mut callback);
                     }
                 }
 
-                if let Some(reasoning_text) = choice.delta.reasoning_content {
+                if let Some(reasoning_text) =
+                    choice.delta.reasoning_content.or(choice.delta.reasoning)
+                {
                     if let Some(delta_reasoning) =
                         append_reasoning_chunk(&mut reasoning, &reasoning_text)
                     {
@@ -591,7 +1242,12 @@ impl LlmService {
 
                 if let Some(tool_calls) = choice.delta.tool_calls {
                     for item in tool_calls {
-                        let index = item.index;
+                        let index = resolve_tool_call_slot(
+                            &tool_call_builders,
+                            &mut remapped_tool_slots,
+                            item.index,
+                            item.id.as_deref(),
+                        );
                         let entry = tool_call_builders.entry(index).or_default();
 
                         let id = item.id;
@@ -604,7 +1260,10 @@ impl LlmService {
                             entry.name = Some(value.clone());
                         }
 
-                        let arguments_chunk = item.function.and_then(|v| v.arguments);
+                        let arguments_chunk = item
+                            .function
+                            .as_ref()
+                            .and_then(OpenAiCompatFunctionCallChunk::arguments_text);
                         if let Some(ref value) = arguments_chunk {
                             append_tool_arguments_chunk(&mut entry.arguments, value);
                         }
@@ -622,6 +1281,14 @@ impl LlmService {
                 break;
             }
         }
+        if let Some(splitter) = think_splitter.as_mut() {
+            emit_think_split(
+                splitter.finish(),
+                &mut content,
+                &mut reasoning,
+                &mut callback,
+            );
+        }
 
         let tool_calls = tool_call_builders
             .into_iter()
@@ -649,37 +1316,28 @@ impl LlmService {
             reasoning_details,
             tool_calls,
             cancelled,
+            usage,
         })
     }
 
-    async fn chat_stream_with_tools_anthropic<'a>(
-        &'a self,
-        model: &'a str,
+    async fn chat_stream_with_tools_anthropic(
+        &self,
+        model: &str,
         messages: Vec<ChatMessage>,
         tools: Option<Vec<ChatTool>>,
-        mut callback: impl FnMut(LlmStreamEvent) + Send + 'a,
-        should_cancel: impl Fn() -> bool + Send + Sync + 'a,
+        mut callback: impl FnMut(LlmStreamEvent) + Send,
+        should_cancel: impl Fn() -> bool + Send + Sync,
     ) -> Result<LlmStreamResult> {
         let request = self.build_anthropic_request(model, messages, tools.clone(), true)?;
-
-        let chat_api = self
-            .client
-            .chat()
-            .path("/v1/messages")
-            .map_err(|e| anyhow!("Anthropic API error: {}", e))?
-            .header("x-api-key", &self.api_key)
-            .map_err(|e| anyhow!("Anthropic API error: {}", e))?
-            .header("anthropic-version", ANTHROPIC_API_VERSION)
-            .map_err(|e| anyhow!("Anthropic API error: {}", e))?;
-
-        let mut stream = chat_api
-            .create_stream_byot(request)
-            .await
-            .map_err(|e| anyhow!("Anthropic API error: {}", e))?;
+        let response = self
+            .send_for_stream(self.anthropic_request().json(&request))
+            .await?;
+        let mut stream = anthropic_event_stream(response);
         let mut content = String::new();
         let mut reasoning = String::new();
         let mut tool_call_builders: BTreeMap<usize, ToolCallBuilder> = BTreeMap::new();
         let mut cancelled = false;
+        let mut usage: Option<LlmUsage> = None;
 
         while let Some(item) = stream.next().await {
             if should_cancel() {
@@ -687,13 +1345,35 @@ impl LlmService {
                 break;
             }
 
-            let value: Value = item.map_err(|e| anyhow!("Anthropic API error: {}", e))?;
+            let value = item?;
 
             let event_type = value
                 .get("type")
                 .and_then(Value::as_str)
                 .unwrap_or_default();
 
+            if event_type == "message_start" {
+                usage = value
+                    .get("message")
+                    .and_then(|message| message.get("usage"))
+                    .and_then(LlmUsage::from_anthropic_value);
+                continue;
+            }
+
+            if event_type == "message_delta" {
+                // output_tokens in message_delta is cumulative for the whole message.
+                if let Some(output_tokens) = value
+                    .get("usage")
+                    .and_then(|item| item.get("output_tokens"))
+                    .and_then(Value::as_u64)
+                {
+                    usage
+                        .get_or_insert_with(LlmUsage::default)
+                        .completion_tokens = output_tokens;
+                }
+                continue;
+            }
+
             if event_type == "content_block_start" {
                 let index = value.get("index").and_then(Value::as_u64).unwrap_or(0) as usize;
                 if let Some(block) = value.get("content_block") {
@@ -808,6 +1488,7 @@ impl LlmService {
             reasoning_details,
             tool_calls,
             cancelled,
+            usage,
         })
     }
 
@@ -1056,3 +1737,422 @@ impl LlmService {
         Ok(image_url.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_openai_usage_with_details() {
+        let usage = LlmUsage::from_openai_value(&json!({
+            "prompt_tokens": 120,
+            "completion_tokens": 30,
+            "total_tokens": 150,
+            "prompt_tokens_details": { "cached_tokens": 100 },
+            "completion_tokens_details": { "reasoning_tokens": 12 }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            usage,
+            LlmUsage {
+                prompt_tokens: 120,
+                completion_tokens: 30,
+                reasoning_tokens: 12,
+                cached_tokens: 100,
+            }
+        );
+        assert_eq!(usage.total_tokens(), 150);
+    }
+
+    #[test]
+    fn parses_openai_usage_without_details() {
+        let usage = LlmUsage::from_openai_value(&json!({
+            "prompt_tokens": 8,
+            "completion_tokens": 2,
+            "prompt_tokens_details": null
+        }))
+        .unwrap();
+
+        assert_eq!(usage.prompt_tokens, 8);
+        assert_eq!(usage.completion_tokens, 2);
+        assert_eq!(usage.reasoning_tokens, 0);
+        assert_eq!(usage.cached_tokens, 0);
+        assert!(LlmUsage::from_openai_value(&json!({})).is_none());
+        assert!(LlmUsage::from_openai_value(&json!({ "prompt_tokens": 0 })).is_none());
+    }
+
+    #[test]
+    fn parses_anthropic_usage_with_cache() {
+        let usage = LlmUsage::from_anthropic_value(&json!({
+            "input_tokens": 10,
+            "cache_read_input_tokens": 200,
+            "cache_creation_input_tokens": 50,
+            "output_tokens": 40
+        }))
+        .unwrap();
+
+        assert_eq!(
+            usage,
+            LlmUsage {
+                prompt_tokens: 260,
+                completion_tokens: 40,
+                reasoning_tokens: 0,
+                cached_tokens: 200,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_anthropic_usage_without_cache() {
+        let usage = LlmUsage::from_anthropic_value(&json!({
+            "input_tokens": 15,
+            "output_tokens": 5
+        }))
+        .unwrap();
+
+        assert_eq!(usage.prompt_tokens, 15);
+        assert_eq!(usage.completion_tokens, 5);
+        assert_eq!(usage.cached_tokens, 0);
+        // message_delta events carry only output_tokens.
+        let delta = LlmUsage::from_anthropic_value(&json!({ "output_tokens": 7 })).unwrap();
+        assert_eq!(delta.prompt_tokens, 0);
+        assert_eq!(delta.completion_tokens, 7);
+        assert!(LlmUsage::from_anthropic_value(&json!({})).is_none());
+    }
+
+    fn builder_with_id(id: &str) -> ToolCallBuilder {
+        ToolCallBuilder {
+            id: Some(id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tool_call_slots_follow_indices_and_ids() {
+        let mut builders = BTreeMap::new();
+        let mut remapped = BTreeMap::new();
+
+        let slot = resolve_tool_call_slot(&builders, &mut remapped, Some(0), Some("call_a"));
+        assert_eq!(slot, 0);
+        builders.insert(slot, builder_with_id("call_a"));
+        // Argument chunks repeat the index without an id.
+        assert_eq!(resolve_tool_call_slot(&builders, &mut remapped, Some(0), None), 0);
+        assert_eq!(
+            resolve_tool_call_slot(&builders, &mut remapped, Some(0), Some("call_a")),
+            0
+        );
+
+        let slot = resolve_tool_call_slot(&builders, &mut remapped, Some(1), Some("call_b"));
+        assert_eq!(slot, 1);
+        builders.insert(slot, builder_with_id("call_b"));
+        assert!(remapped.is_empty());
+    }
+
+    #[test]
+    fn new_id_on_occupied_index_opens_a_fresh_slot() {
+        let mut builders = BTreeMap::new();
+        let mut remapped = BTreeMap::new();
+        builders.insert(0, builder_with_id("call_a"));
+
+        let slot = resolve_tool_call_slot(&builders, &mut remapped, Some(0), Some("call_b"));
+        assert_eq!(slot, 1);
+        builders.insert(slot, builder_with_id("call_b"));
+        // Later chunks for index 0 stay with the remapped call.
+        assert_eq!(resolve_tool_call_slot(&builders, &mut remapped, Some(0), None), 1);
+
+        let slot = resolve_tool_call_slot(&builders, &mut remapped, Some(0), Some("call_c"));
+        assert_eq!(slot, 2);
+    }
+
+    #[test]
+    fn chunks_without_index_continue_the_latest_or_matching_call() {
+        let mut remapped = BTreeMap::new();
+        let empty = BTreeMap::new();
+        assert_eq!(resolve_tool_call_slot(&empty, &mut remapped, None, None), 0);
+        assert_eq!(resolve_tool_call_slot(&empty, &mut remapped, None, Some("call_a")), 0);
+
+        let mut builders = BTreeMap::new();
+        builders.insert(0, builder_with_id("call_a"));
+        builders.insert(1, builder_with_id("call_b"));
+        assert_eq!(resolve_tool_call_slot(&builders, &mut remapped, None, None), 1);
+        assert_eq!(
+            resolve_tool_call_slot(&builders, &mut remapped, None, Some("call_a")),
+            0
+        );
+        assert_eq!(
+            resolve_tool_call_slot(&builders, &mut remapped, None, Some("call_c")),
+            2
+        );
+    }
+
+    #[test]
+    fn only_unsupported_tools_errors_trigger_the_local_retry() {
+        let http_error = |status: u16, message: &str| -> anyhow::Error {
+            LlmHttpError {
+                status,
+                retry_after: None,
+                message: message.to_string(),
+            }
+            .into()
+        };
+        assert!(is_local_tools_unsupported_error(&http_error(
+            400,
+            "registry.ollama.ai/library/gemma:2b does not support tools"
+        )));
+        assert!(is_local_tools_unsupported_error(&http_error(
+            500,
+            "tools param requires --jinja flag"
+        )));
+        assert!(!is_local_tools_unsupported_error(&http_error(
+            400,
+            "invalid tool_call_id in messages"
+        )));
+        assert!(!is_local_tools_unsupported_error(&http_error(
+            503,
+            "model does not support tools right now"
+        )));
+        assert!(!is_local_tools_unsupported_error(&anyhow!(
+            "API error: stream interrupted while reading tool call"
+        )));
+    }
+
+    #[test]
+    fn queues_multi_line_stream_data_until_done() {
+        let mut decoder = SseDecoder::default();
+        let mut pending = VecDeque::new();
+        let events = decoder.push(b": ping\n\ndata: {\"id\":\ndata: \"a\"}\n\ndata: [DO");
+        assert!(!queue_sse_data(events, &mut pending));
+        assert_eq!(pending.pop_front().as_deref(), Some("{\"id\":\n\"a\"}"));
+        let events = decoder.push(b"NE]\n\ndata: {}\n\n");
+        assert!(queue_sse_data(events, &mut pending));
+        assert!(pending.is_empty());
+    }
+
+    fn fast_retry_policy(max_retries: u32) -> LlmRetryConfig {
+        LlmRetryConfig {
+            max_retries,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            max_retry_after_ms: 1_000,
+            fallback_models: Vec::new(),
+        }
+    }
+
+    fn user_message(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: "user".to_string(),
+            content: Some(content.to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            reasoning_details: None,
+            reasoning: None,
+        }
+    }
+
+    fn completion(content: &str) -> Value {
+        json!({
+            "model": "test-model",
+            "choices": [{ "index": 0, "message": { "role": "assistant", "content": content } }]
+        })
+    }
+
+    #[test]
+    fn only_typed_transient_errors_are_retryable() {
+        let http_error = |status: u16| -> anyhow::Error {
+            LlmHttpError {
+                status,
+                retry_after: None,
+                message: "failed".to_string(),
+            }
+            .into()
+        };
+        for status in [408, 429, 500, 502, 503, 504, 529] {
+            assert!(is_transient_llm_error(&http_error(status)), "{}", status);
+        }
+        for status in [400, 401, 403, 404, 422] {
+            assert!(!is_transient_llm_error(&http_error(status)), "{}", status);
+        }
+        assert!(is_transient_llm_error(
+            &LlmTransportError::timed_out("stream stalled").into()
+        ));
+        assert!(!is_transient_llm_error(
+            &LlmTransportError {
+                transient: false,
+                message: "builder error".to_string(),
+            }
+            .into()
+        ));
+        // Untyped errors never retry, even when the text looks like a rate limit.
+        assert!(!is_transient_llm_error(&anyhow!("HTTP 429 Too Many Requests")));
+    }
+
+    #[test]
+    fn retry_delay_backs_off_exponentially_up_to_the_cap() {
+        let policy = LlmRetryConfig {
+            max_retries: 5,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 350,
+            max_retry_after_ms: 10_000,
+            fallback_models: Vec::new(),
+        };
+        let error: anyhow::Error = LlmHttpError {
+            status: 503,
+            retry_after: None,
+            message: "unavailable".to_string(),
+        }
+        .into();
+        let delays: Vec<_> = (1..=6)
+            .map(|retry| retry_delay(&policy, retry, &error))
+            .collect();
+        assert_eq!(
+            delays,
+            vec![
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(350)),
+                Some(Duration::from_millis(350)),
+                Some(Duration::from_millis(350)),
+                None,
+            ]
+        );
+        let bad_request: anyhow::Error = LlmHttpError {
+            status: 400,
+            retry_after: None,
+            message: "bad".to_string(),
+        }
+        .into();
+        assert_eq!(retry_delay(&policy, 1, &bad_request), None);
+    }
+
+    #[test]
+    fn retry_delay_honours_retry_after_within_the_limit() {
+        let policy = LlmRetryConfig {
+            max_retries: 3,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1_000,
+            max_retry_after_ms: 5_000,
+            fallback_models: Vec::new(),
+        };
+        let rate_limited = |retry_after: Duration| -> anyhow::Error {
+            LlmHttpError {
+                status: 429,
+                retry_after: Some(retry_after),
+                message: "slow down".to_string(),
+            }
+            .into()
+        };
+        assert_eq!(
+            retry_delay(&policy, 1, &rate_limited(Duration::from_secs(3))),
+            Some(Duration::from_secs(3))
+        );
+        // Longer waits are left to the fallback chain.
+        assert_eq!(
+            retry_delay(&policy, 1, &rate_limited(Duration::from_secs(30))),
+            None
+        );
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(reqwest::header::RETRY_AFTER, "2".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(2)));
+        headers.insert(
+            reqwest::header::RETRY_AFTER,
+            "Thu, 01 Jan 1970 00:00:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(parse_retry_after(&headers), Some(Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn chat_retries_transient_statuses_with_retry_after() {
+        let server = crate::services::test_http::TestServer::spawn({
+            let counter = std::sync::atomic::AtomicUsize::new(0);
+            move |_| {
+                use crate::services::test_http::TestResponse;
+                match counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                    0 => TestResponse::json(429, json!({ "error": { "message": "slow down" } }))
+                        .with_header("Retry-After", "0"),
+                    1 => TestResponse::json(503, json!({ "error": { "message": "busy" } })),
+                    _ => TestResponse::json(200, completion("hello")),
+                }
+            }
+        })
+        .await;
+        let service = LlmService::with_protocol(
+            "sk-test".to_string(),
+            server.base_url.clone(),
+            LlmWireProtocol::OpenaiChat,
+        )
+        .with_retry_policy(fast_retry_policy(3));
+
+        let output = service
+            .chat_with_usage("test-model", vec![user_message("hi")])
+            .await
+            .unwrap();
+
+        assert_eq!(output.content, "hello");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|request| request.method == "POST"
+            && request.path == "/chat/completions"
+            && request.header("authorization") == Some("Bearer sk-test")
+            && request.body.contains("\"model\":\"test-model\"")));
+    }
+
+    #[tokio::test]
+    async fn chat_gives_up_after_max_retries_and_skips_non_transient_errors() {
+        let server = crate::services::test_http::TestServer::scripted(vec![(
+            503,
+            json!({ "error": { "message": "busy" } }),
+        )])
+        .await;
+        let service = LlmService::with_protocol(
+            String::new(),
+            server.base_url.clone(),
+            LlmWireProtocol::OpenaiChat,
+        )
+        .with_retry_policy(fast_retry_policy(2));
+        let error = service
+            .chat_with_usage("test-model", vec![user_message("hi")])
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref::<LlmHttpError>().unwrap().status, 503);
+        assert_eq!(server.requests().len(), 3);
+
+        let server = crate::services::test_http::TestServer::scripted(vec![(
+            400,
+            json!({ "error": { "message": "unknown model" } }),
+        )])
+        .await;
+        let service = LlmService::with_protocol(
+            String::new(),
+            server.base_url.clone(),
+            LlmWireProtocol::OpenaiChat,
+        )
+        .with_retry_policy(fast_retry_policy(2));
+        let error = service
+            .chat_with_usage("test-model", vec![user_message("hi")])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("unknown model"));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn unreachable_servers_fail_as_transient_transport_errors() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let api_base = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let service = LlmService::with_protocol(
+            String::new(),
+            api_base,
+            LlmWireProtocol::OpenaiChat,
+        )
+        .with_retry_policy(fast_retry_policy(0));
+        let error = service
+            .chat_with_usage("test-model", vec![user_message("hi")])
+            .await
+            .unwrap_err();
+        assert!(error.downcast_ref::<LlmTransportError>().is_some());
+        assert!(is_transient_llm_error(&error));
+    }
+}
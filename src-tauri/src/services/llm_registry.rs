@@ -0,0 +1,503 @@
+use crate::models::config::{
+    Config, LlmKeySource, LlmProviderCapabilities, LlmProviderConfig, LlmRetryConfig,
+    LlmWireProtocol, LocalLlmBackend,
+};
+use crate::services::llm::LlmService;
+
+pub const DEFAULT_GLM_API_BASE: &str = "https://open.bigmodel.cn/api/paas/v4";
+pub const DEFAULT_ARK_API_BASE: &str = "https://ark.cn-beijing.volces.com/api/v3";
+pub const DEFAULT_MINIMAX_OPENAI_API_BASE: &str = "https://api.minimaxi.com/v1";
+pub const DEFAULT_OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+pub const DEFAULT_OLLAMA_API_BASE: &str = "http://localhost:11434/v1";
+pub const DEFAULT_LLAMA_CPP_API_BASE: &str = "http://localhost:8080/v1";
+const DEFAULT_PETOOL_API_BASE: &str = "http://localhost:8000";
+const FALLBACK_PROVIDER_NAME: &str = "GLM";
+
+#[derive(Debug, Clone)]
+pub struct ResolvedLlmProvider {
+    pub name: String,
+    pub api_base: String,
+    /// Empty when the provider needs no authentication.
+    pub api_key: String,
+    pub protocol: LlmWireProtocol,
+    pub capabilities: LlmProviderCapabilities,
+    pub embedding_model: Option<String>,
+    pub embedding_dimensions: Option<usize>,
+    pub local_backend: Option<LocalLlmBackend>,
+    pub retry: LlmRetryConfig,
+}
+
+impl ResolvedLlmProvider {
+    pub fn build_service(&self) -> LlmService {
+        LlmService::with_protocol(self.api_key.clone(), self.api_base.clone(), self.protocol)
+            .with_local_backend(self.local_backend)
+            .with_retry_policy(self.retry.clone())
+    }
+}
+
+pub fn env_value(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+pub fn first_non_empty(values: Vec<Option<String>>) -> Option<String> {
+    values
+        .into_iter()
+        .flatten()
+        .find(|value| !value.trim().is_empty())
+}
+
+fn config_key(field: &str) -> LlmKeySource {
+    LlmKeySource::Config {
+        field: field.to_string(),
+    }
+}
+
+fn env_key(name: &str) -> LlmKeySource {
+    LlmKeySource::Env {
+        name: name.to_string(),
+    }
+}
+
+fn non_empty_option(value: Option<&String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Providers that ship with the app; entries in `Config::llm_providers` replace them by name.
+pub fn builtin_llm_providers(config: &Config) -> Vec<LlmProviderConfig> {
+    vec![
+        LlmProviderConfig {
+            name: "GLM".to_string(),
+            api_base: non_empty_option(config.api_base.as_ref())
+                .unwrap_or_else(|| DEFAULT_GLM_API_BASE.to_string()),
+            api_key_sources: vec![
+                config_key("api_key"),
+                env_key("GLM_API_KEY"),
+                env_key("OPENAI_API_KEY"),
+            ],
+            protocol: LlmWireProtocol::OpenaiChat,
+            models: vec!["glm-*".to_string()],
+            capabilities: LlmProviderCapabilities {
+                tools: true,
+                vision: true,
+                reasoning: true,
+                image_generation: false,
+            },
+            embedding_model: None,
+            embedding_dimensions: None,
+            local_backend: None,
+            enabled: true,
+        },
+        LlmProviderConfig {
+            name: "Doubao".to_string(),
+            api_base: non_empty_option(config.ark_api_base.as_ref())
+                .unwrap_or_else(|| DEFAULT_ARK_API_BASE.to_string()),
+            api_key_sources: vec![
+                config_key("ark_api_key"),
+                env_key("ARK_API_KEY"),
+                env_key("DOUBAO_API_KEY"),
+                config_key("api_key"),
+            ],
+            protocol: LlmWireProtocol::OpenaiChat,
+            models: vec!["doubao-*".to_string(), "ep-*".to_string()],
+            capabilities: LlmProviderCapabilities {
+                tools: true,
+                vision: true,
+                reasoning: true,
+                image_generation: true,
+            },
+            embedding_model: None,
+            embedding_dimensions: None,
+            local_backend: None,
+            enabled: true,
+        },
+        LlmProviderConfig {
+            name: "MiniMax".to_string(),
+            api_base: DEFAULT_MINIMAX_OPENAI_API_BASE.to_string(),
+            api_key_sources: vec![
+                config_key("minimax_api_key"),
+                env_key("MINIMAX_API_KEY"),
+                env_key("OPENAI_API_KEY"),
+                env_key("ANTHROPIC_API_KEY"),
+            ],
+            protocol: LlmWireProtocol::OpenaiChat,
+            models: vec!["minimax-*".to_string(), "abab*".to_string()],
+            capabilities: LlmProviderCapabilities {
+                tools: true,
+                vision: false,
+                reasoning: true,
+                image_generation: false,
+            },
+            embedding_model: None,
+            embedding_dimensions: None,
+            local_backend: None,
+            enabled: true,
+        },
+        LlmProviderConfig {
+            name: "OpenAI".to_string(),
+            api_base: env_value("OPENAI_API_BASE")
+                .unwrap_or_else(|| DEFAULT_OPENAI_API_BASE.to_string()),
+            api_key_sources: vec![env_key("OPENAI_API_KEY"), config_key("api_key")],
+            protocol: LlmWireProtocol::OpenaiChat,
+            models: vec!["gpt-*".to_string()],
+            capabilities: LlmProviderCapabilities {
+                tools: true,
+                vision: true,
+                reasoning: false,
+                image_generation: false,
+            },
+            embedding_model: Some("text-embedding-3-small".to_string()),
+            embedding_dimensions: Some(1536),
+            local_backend: None,
+            enabled: true,
+        },
+        // Local servers need no key; their model lists come from discovery.
+        LlmProviderConfig {
+            name: "Ollama".to_string(),
+            api_base: DEFAULT_OLLAMA_API_BASE.to_string(),
+            api_key_sources: Vec::new(),
+            protocol: LlmWireProtocol::OpenaiChat,
+            models: Vec::new(),
+            capabilities: LlmProviderCapabilities::default(),
+            embedding_model: None,
+            embedding_dimensions: None,
+            local_backend: Some(LocalLlmBackend::Ollama),
+            enabled: true,
+        },
+        LlmProviderConfig {
+            name: "llama.cpp".to_string(),
+            api_base: DEFAULT_LLAMA_CPP_API_BASE.to_string(),
+            api_key_sources: Vec::new(),
+            protocol: LlmWireProtocol::OpenaiChat,
+            models: Vec::new(),
+            capabilities: LlmProviderCapabilities::default(),
+            embedding_model: None,
+            embedding_dimensions: None,
+            local_backend: Some(LocalLlmBackend::LlamaCpp),
+            enabled: true,
+        },
+    ]
+}
+
+/// User-configured providers first, followed by the builtins they do not override.
+/// Discovered models are appended to their provider's list.
+pub fn effective_llm_providers(config: &Config) -> Vec<LlmProviderConfig> {
+    let mut providers = config.llm_providers.clone();
+    for builtin in builtin_llm_providers(config) {
+        if !providers
+            .iter()
+            .any(|provider| provider.name.eq_ignore_ascii_case(&builtin.name))
+        {
+            providers.push(builtin);
+        }
+    }
+    for provider in &mut providers {
+        for id in discovered_models_for(config, &provider.name) {
+            if !provider
+                .models
+                .iter()
+                .any(|existing| existing.trim().eq_ignore_ascii_case(id))
+            {
+                provider.models.push(id.clone());
+            }
+        }
+    }
+    providers
+}
+
+fn discovered_models_for<'a>(config: &'a Config, provider_name: &str) -> &'a [String] {
+    config
+        .discovered_llm_models
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(provider_name))
+        .map(|(_, models)| models.as_slice())
+        .unwrap_or_default()
+}
+
+pub fn find_llm_provider(config: &Config, name: &str) -> Option<LlmProviderConfig> {
+    let name = name.trim();
+    effective_llm_providers(config)
+        .into_iter()
+        .find(|provider| provider.name.eq_ignore_ascii_case(name))
+}
+
+/// Records model names discovered on a provider. They are kept apart from
+/// `Config::llm_providers`, so they route like builtin models and never outrank
+/// user-configured providers or the Petool proxy.
+pub fn merge_discovered_models(config: &mut Config, provider_name: &str, model_ids: &[String]) {
+    let key = config
+        .discovered_llm_models
+        .keys()
+        .find(|name| name.eq_ignore_ascii_case(provider_name))
+        .cloned()
+        .unwrap_or_else(|| provider_name.to_string());
+    let models = config.discovered_llm_models.entry(key).or_default();
+    for id in model_ids {
+        let id = id.trim();
+        if !id.is_empty()
+            && !models
+                .iter()
+                .any(|existing| existing.trim().eq_ignore_ascii_case(id))
+        {
+            models.push(id.to_string());
+        }
+    }
+}
+
+fn model_match_score(pattern: &str, normalized_model: &str) -> Option<usize> {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    if pattern.is_empty() {
+        return None;
+    }
+    if let Some(prefix) = pattern.strip_suffix('*') {
+        return normalized_model.starts_with(prefix).then_some(prefix.len());
+    }
+    (pattern == normalized_model).then_some(usize::MAX)
+}
+
+/// Exact model names win over `prefix*` patterns, longer prefixes over shorter ones,
+/// and earlier providers over later ones on a tie.
+fn best_matching_provider<'a>(
+    providers: impl IntoIterator<Item = &'a LlmProviderConfig>,
+    model: &str,
+) -> Option<&'a LlmProviderConfig> {
+    let normalized = model.trim().to_ascii_lowercase();
+    let mut best: Option<(usize, &LlmProviderConfig)> = None;
+    for provider in providers {
+        let Some(score) = provider
+            .models
+            .iter()
+            .filter_map(|pattern| model_match_score(pattern, &normalized))
+            .max()
+        else {
+            continue;
+        };
+        if best
+            .map(|(best_score, _)| score > best_score)
+            .unwrap_or(true)
+        {
+            best = Some((score, provider));
+        }
+    }
+    best.map(|(_, provider)| provider)
+}
+
+fn key_source_value(config: &Config, source: &LlmKeySource) -> Option<String> {
+    match source {
+        LlmKeySource::Inline { value } => non_empty_option(Some(value)),
+        LlmKeySource::Env { name } => env_value(name),
+        LlmKeySource::Config { field } => match field.trim() {
+            "api_key" => non_empty_option(config.api_key.as_ref()),
+            "ark_api_key" => non_empty_option(config.ark_api_key.as_ref()),
+            "minimax_api_key" => non_empty_option(config.minimax_api_key.as_ref()),
+            _ => None,
+        },
+    }
+}
+
+fn resolve_provider_api_key(config: &Config, provider: &LlmProviderConfig) -> Option<String> {
+    if provider.api_key_sources.is_empty() {
+        return Some(String::new());
+    }
+    first_non_empty(
+        provider
+            .api_key_sources
+            .iter()
+            .map(|source| key_source_value(config, source))
+            .collect(),
+    )
+}
+
+pub fn resolve_configured_provider(
+    config: &Config,
+    provider: &LlmProviderConfig,
+) -> Result<ResolvedLlmProvider, String> {
+    let api_key = resolve_provider_api_key(config, provider)
+        .ok_or_else(|| format!("请先登录账号，或在设置中填写 {} API Key", provider.name))?;
+    Ok(ResolvedLlmProvider {
+        name: provider.name.clone(),
+        api_base: provider.api_base.trim().to_string(),
+        api_key,
+        protocol: provider.protocol,
+        capabilities: provider.capabilities.clone(),
+        embedding_model: provider.embedding_model.clone(),
+        embedding_dimensions: provider.embedding_dimensions,
+        local_backend: provider.local_backend,
+        retry: config.llm_retry.clone(),
+    })
+}
+
+fn petool_proxy_credentials(config: &Config) -> Option<(String, String)> {
+    let token = config
+        .petool_token
+        .as_deref()
+        .map(str::trim)
+        .filter(|token| !token.is_empty())?;
+    let base = config
+        .petool_api_base
+        .clone()
+        .unwrap_or_else(|| DEFAULT_PETOOL_API_BASE.to_string());
+    Some((
+        format!("{}/v1", base.trim_end_matches('/')),
+        token.to_string(),
+    ))
+}
+
+/// Resolves the provider serving `model` for text chat.
+///
+/// Order: a user-configured provider listing the model, then the Petool proxy when logged in,
+/// then builtin providers and discovered models, then `default_llm_provider`.
+pub fn resolve_llm_provider(config: &Config, model: &str) -> Result<ResolvedLlmProvider, String> {
+    if let Some(provider) =
+        best_matching_provider(config.llm_providers.iter().filter(|p| p.enabled), model)
+    {
+        return resolve_configured_provider(config, provider);
+    }
+
+    let providers = effective_llm_providers(config);
+    let enabled = providers.iter().filter(|provider| provider.enabled);
+    let default_name = config
+        .default_llm_provider
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or(FALLBACK_PROVIDER_NAME);
+    let matched = best_matching_provider(enabled.clone(), model).or_else(|| {
+        enabled
+            .clone()
+            .find(|provider| provider.name.eq_ignore_ascii_case(default_name))
+    });
+
+    if let Some((api_base, token)) = petool_proxy_credentials(config) {
+        return Ok(ResolvedLlmProvider {
+            name: "Petool".to_string(),
+            api_base,
+            api_key: token,
+            protocol: LlmWireProtocol::OpenaiChat,
+            capabilities: matched
+                .map(|provider| provider.capabilities.clone())
+                .unwrap_or_default(),
+            embedding_model: matched.and_then(|provider| provider.embedding_model.clone()),
+            embedding_dimensions: matched.and_then(|provider| provider.embedding_dimensions),
+            local_backend: None,
+            retry: config.llm_retry.clone(),
+        });
+    }
+
+    let provider =
+        matched.ok_or_else(|| format!("No LLM provider configured for model {}", model))?;
+    resolve_configured_provider(config, provider)
+}
+
+/// Image generation never goes through the Petool proxy; it picks the provider that lists
+/// `config.image_model`, or the first one flagged for image generation.
+pub fn resolve_image_generation_provider(config: &Config) -> Result<ResolvedLlmProvider, String> {
+    let providers = effective_llm_providers(config);
+    let candidates = providers
+        .iter()
+        .filter(|provider| provider.enabled && provider.capabilities.image_generation);
+    let provider = best_matching_provider(candidates.clone(), &config.image_model)
+        .or_else(|| candidates.clone().next())
+        .ok_or_else(|| "No image generation provider configured".to_string())?;
+    resolve_configured_provider(config, provider).map_err(|_| "Image API key not set".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inline_provider(name: &str, models: &[&str], key: &str) -> LlmProviderConfig {
+        LlmProviderConfig {
+            name: name.to_string(),
+            api_base: format!("http://{}.local/v1", name.to_ascii_lowercase()),
+            api_key_sources: if key.is_empty() {
+                Vec::new()
+            } else {
+                vec![LlmKeySource::Inline {
+                    value: key.to_string(),
+                }]
+            },
+            protocol: LlmWireProtocol::OpenaiChat,
+            models: models.iter().map(|model| model.to_string()).collect(),
+            capabilities: LlmProviderCapabilities::default(),
+            embedding_model: None,
+            embedding_dimensions: None,
+            local_backend: None,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn exact_model_beats_prefix_pattern() {
+        let wide = inline_provider("Wide", &["deepseek-*"], "a");
+        let exact = inline_provider("Exact", &["deepseek-chat"], "b");
+        let providers = [wide, exact];
+        let matched = best_matching_provider(providers.iter(), "DeepSeek-Chat").unwrap();
+        assert_eq!(matched.name, "Exact");
+        let matched = best_matching_provider(providers.iter(), "deepseek-coder").unwrap();
+        assert_eq!(matched.name, "Wide");
+    }
+
+    #[test]
+    fn user_provider_takes_precedence_over_proxy() {
+        let config = Config {
+            petool_token: Some("token".to_string()),
+            llm_providers: vec![inline_provider("Ollama", &["qwen2.5*"], "")],
+            ..Config::default()
+        };
+
+        let resolved = resolve_llm_provider(&config, "qwen2.5:7b").unwrap();
+        assert_eq!(resolved.name, "Ollama");
+        assert!(resolved.api_key.is_empty());
+
+        let resolved = resolve_llm_provider(&config, "glm-5").unwrap();
+        assert_eq!(resolved.name, "Petool");
+        assert!(resolved.capabilities.vision);
+    }
+
+    #[test]
+    fn openai_builtin_falls_back_to_configured_api_key() {
+        let openai = find_llm_provider(&Config::default(), "OpenAI").unwrap();
+        assert_eq!(
+            openai.api_key_sources,
+            vec![env_key("OPENAI_API_KEY"), config_key("api_key")]
+        );
+    }
+
+    #[test]
+    fn discovered_models_rank_below_configured_providers_and_proxy() {
+        let mut config = Config::default();
+        merge_discovered_models(&mut config, "ollama", &["qwen2.5:7b".to_string()]);
+        merge_discovered_models(&mut config, "Ollama", &[" qwen2.5:7b ".to_string()]);
+        assert!(config.llm_providers.is_empty());
+        assert_eq!(config.discovered_llm_models["ollama"], vec!["qwen2.5:7b"]);
+
+        let resolved = resolve_llm_provider(&config, "qwen2.5:7b").unwrap();
+        assert_eq!(resolved.name, "Ollama");
+
+        config.petool_token = Some("token".to_string());
+        let resolved = resolve_llm_provider(&config, "qwen2.5:7b").unwrap();
+        assert_eq!(resolved.name, "Petool");
+
+        config.llm_providers = vec![inline_provider("Local", &["qwen2.5:7b"], "")];
+        let resolved = resolve_llm_provider(&config, "qwen2.5:7b").unwrap();
+        assert_eq!(resolved.name, "Local");
+    }
+
+    #[test]
+    fn unmatched_model_falls_back_to_default_provider() {
+        let config = Config {
+            llm_providers: vec![inline_provider("Local", &[], "local-key")],
+            default_llm_provider: Some("local".to_string()),
+            ..Config::default()
+        };
+
+        let resolved = resolve_llm_provider(&config, "mystery-model").unwrap();
+        assert_eq!(resolved.name, "Local");
+        assert_eq!(resolved.api_key, "local-key");
+    }
+}
@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::models::config::LocalLlmBackend;
+
+const LOCAL_DISCOVERY_TIMEOUT_SECS: u64 = 5;
+const THINK_OPEN_TAG: &str = "<think>";
+const THINK_CLOSE_TAG: &str = "</think>";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalModelInfo {
+    pub id: String,
+    pub size_bytes: Option<u64>,
+    pub family: Option<String>,
+}
+
+/// Ollama serves its native API next to the OpenAI-compatible `/v1` routes.
+pub fn ollama_native_root(api_base: &str) -> String {
+    let trimmed = api_base.trim().trim_end_matches('/');
+    trimmed.strip_suffix("/v1").unwrap_or(trimmed).to_string()
+}
+
+async fn fetch_json(url: &str, api_key: &str) -> Result<Value, String> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(LOCAL_DISCOVERY_TIMEOUT_SECS))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut request = client.get(url);
+    if !api_key.trim().is_empty() {
+        request = request.bearer_auth(api_key.trim());
+    }
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("GET {} failed: HTTP {}", url, status));
+    }
+    response.json::<Value>().await.map_err(|e| e.to_string())
+}
+
+fn parse_ollama_tags(value: &Value) -> Vec<LocalModelInfo> {
+    value
+        .get("models")
+        .and_then(Value::as_array)
+        .map(|models| {
+            models
+                .iter()
+                .filter_map(|item| {
+                    let id = item
+                        .get("model")
+                        .or_else(|| item.get("name"))
+                        .and_then(Value::as_str)?
+                        .trim()
+                        .to_string();
+                    (!id.is_empty()).then(|| LocalModelInfo {
+                        id,
+                        size_bytes: item.get("size").and_then(Value::as_u64),
+                        family: item
+                            .pointer("/details/family")
+                            .and_then(Value::as_str)
+                            .map(str::to_string),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parse_openai_models(value: &Value) -> Vec<LocalModelInfo> {
+    value
+        .get("data")
+        .and_then(Value::as_array)
+        .map(|models| {
+            models
+                .iter()
+                .filter_map(|item| item.get("id").and_then(Value::as_str))
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(|id| LocalModelInfo {
+                    id: id.to_string(),
+                    size_bytes: None,
+                    family: None,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn fetch_openai_models(api_base: &str, api_key: &str) -> Result<Vec<LocalModelInfo>, String> {
+    let url = format!("{}/models", api_base.trim().trim_end_matches('/'));
+    fetch_json(&url, api_key)
+        .await
+        .map(|value| parse_openai_models(&value))
+}
+
+/// Lists the models a local inference server currently has available.
+///
+/// Ollama is queried through `/api/tags` (which includes models that are pulled but not loaded),
+/// falling back to the OpenAI-compatible `/models` route used by llama.cpp and other servers.
+pub async fn discover_local_models(
+    api_base: &str,
+    api_key: &str,
+    backend: Option<LocalLlmBackend>,
+) -> Result<Vec<LocalModelInfo>, String> {
+    if backend == Some(LocalLlmBackend::Ollama) {
+        let url = format!("{}/api/tags", ollama_native_root(api_base));
+        match fetch_json(&url, api_key).await {
+            Ok(value) => return Ok(parse_ollama_tags(&value)),
+            Err(error) => {
+                return fetch_openai_models(api_base, api_key)
+                    .await
+                    .map_err(|fallback| format!("{}; {}", error, fallback));
+            }
+        }
+    }
+    fetch_openai_models(api_base, api_key).await
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ThinkSplit {
+    pub content: String,
+    pub reasoning: String,
+}
+
+/// Separates inline `<think>...</think>` blocks that local reasoning models emit in the
+/// content stream. Tags may be split across chunks, so a possible partial tag is held back
+/// until the next chunk arrives.
+#[derive(Debug, Default)]
+pub struct ThinkTagSplitter {
+    in_think: bool,
+    pending: String,
+}
+
+fn partial_tag_suffix_len(text: &str, tag: &str) -> usize {
+    (1..tag.len())
+        .rev()
+        .find(|len| text.ends_with(&tag[..*len]))
+        .unwrap_or(0)
+}
+
+impl ThinkTagSplitter {
+    fn emit(&self, text: &str, out: &mut ThinkSplit) {
+        if self.in_think {
+            out.reasoning.push_str(text);
+        } else {
+            out.content.push_str(text);
+        }
+    }
+
+    pub fn push(&mut self, chunk: &str) -> ThinkSplit {
+        self.pending.push_str(chunk);
+        let mut out = ThinkSplit::default();
+        loop {
+            let tag = if self.in_think {
+                THINK_CLOSE_TAG
+            } else {
+                THINK_OPEN_TAG
+            };
+            if let Some(position) = self.pending.find(tag) {
+                let before = self.pending[..position].to_string();
+                self.emit(&before, &mut out);
+                self.pending.drain(..position + tag.len());
+                self.in_think = !self.in_think;
+                continue;
+            }
+            let ready_len = self.pending.len() - partial_tag_suffix_len(&self.pending, tag);
+            let ready: String = self.pending.drain(..ready_len).collect();
+            self.emit(&ready, &mut out);
+            return out;
+        }
+    }
+
+    pub fn finish(&mut self) -> ThinkSplit {
+        let mut out = ThinkSplit::default();
+        let rest = std::mem::take(&mut self.pending);
+        self.emit(&rest, &mut out);
+        out
+    }
+}
+
+/// Strips `<think>` blocks from a complete (non-streamed) reply.
+pub fn split_think_tags(text: &str) -> ThinkSplit {
+    let mut splitter = ThinkTagSplitter::default();
+    let mut out = splitter.push(text);
+    let rest = splitter.finish();
+    out.content.push_str(&rest.content);
+    out.reasoning.push_str(&rest.reasoning);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn think_tags_split_across_chunks() {
+        let mut splitter = ThinkTagSplitter::default();
+        let mut content = String::new();
+        let mut reasoning = String::new();
+        for chunk in ["<thi", "nk>plan the ", "call</th", "ink>\n\nAnswer", " <"] {
+            let split = splitter.push(chunk);
+            content.push_str(&split.content);
+            reasoning.push_str(&split.reasoning);
+        }
+        let rest = splitter.finish();
+        content.push_str(&rest.content);
+        reasoning.push_str(&rest.reasoning);
+
+        assert_eq!(reasoning, "plan the call");
+        assert_eq!(content, "\n\nAnswer <");
+    }
+
+    #[test]
+    fn parses_ollama_and_openai_model_lists() {
+        let tags = json!({
+            "models": [
+                { "name": "qwen2.5:7b", "model": "qwen2.5:7b", "size": 4683087332u64,
+                  "details": { "family": "qwen2" } },
+                { "name": "" }
+            ]
+        });
+        let models = parse_ollama_tags(&tags);
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].id, "qwen2.5:7b");
+        assert_eq!(models[0].family.as_deref(), Some("qwen2"));
+
+        let listed = json!({ "object": "list", "data": [{ "id": "model.gguf" }] });
+        assert_eq!(parse_openai_models(&listed)[0].id, "model.gguf");
+        assert_eq!(
+            ollama_native_root("http://localhost:11434/v1/"),
+            "http://localhost:11434"
+        );
+    }
+}
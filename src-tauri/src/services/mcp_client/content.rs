@@ -0,0 +1,157 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// One entry of a `resources/read` result, with text cut to the caller's budget and
+/// binary blobs reduced to their size.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedResource {
+    pub uri: String,
+    pub mime_type: Option<String>,
+    pub text: Option<String>,
+    pub blob_bytes: Option<usize>,
+    pub truncated: bool,
+}
+
+pub fn render_resource_contents(result: &Value, max_chars: usize) -> Vec<RenderedResource> {
+    let Some(contents) = result.get("contents").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+
+    contents
+        .iter()
+        .map(|item| {
+            let uri = item
+                .get("uri")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            let mime_type = item
+                .get("mimeType")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            let mut truncated = false;
+            let text = item.get("text").and_then(Value::as_str).map(|text| {
+                if text.chars().count() > max_chars {
+                    truncated = true;
+                    text.chars().take(max_chars).collect()
+                } else {
+                    text.to_string()
+                }
+            });
+            let blob_bytes = item
+                .get("blob")
+                .and_then(Value::as_str)
+                .map(|blob| blob.trim_end_matches('=').len() * 3 / 4);
+            RenderedResource {
+                uri,
+                mime_type,
+                text,
+                blob_bytes,
+                truncated,
+            }
+        })
+        .collect()
+}
+
+fn prompt_content_text(content: &Value) -> String {
+    match content.get("type").and_then(Value::as_str) {
+        Some("text") => content
+            .get("text")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        Some("resource") => {
+            let resource = content.get("resource").cloned().unwrap_or(Value::Null);
+            match resource.get("text").and_then(Value::as_str) {
+                Some(text) => text.to_string(),
+                None => format!(
+                    "[resource {}]",
+                    resource
+                        .get("uri")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                ),
+            }
+        }
+        Some(other) => format!("[{} content omitted]", other),
+        None => String::new(),
+    }
+}
+
+/// Flattens a `prompts/get` result into text that can seed the composer. Role labels are
+/// only added when the prompt contains more than user messages.
+pub fn render_prompt_messages(result: &Value) -> String {
+    let messages = result
+        .get("messages")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let user_only = messages
+        .iter()
+        .all(|message| message.get("role").and_then(Value::as_str) != Some("assistant"));
+
+    messages
+        .iter()
+        .filter_map(|message| {
+            let text = message
+                .get("content")
+                .map(prompt_content_text)
+                .unwrap_or_default();
+            if text.trim().is_empty() {
+                return None;
+            }
+            if user_only {
+                return Some(text);
+            }
+            let role = message
+                .get("role")
+                .and_then(Value::as_str)
+                .unwrap_or("user");
+            Some(format!("{}: {}", role, text))
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn renders_resources_and_prompts() {
+        let resources = render_resource_contents(
+            &json!({
+                "contents": [
+                    { "uri": "file:///a.txt", "mimeType": "text/plain", "text": "abcdef" },
+                    { "uri": "file:///b.png", "mimeType": "image/png", "blob": "AAAA" }
+                ]
+            }),
+            4,
+        );
+        assert_eq!(resources[0].text.as_deref(), Some("abcd"));
+        assert!(resources[0].truncated);
+        assert_eq!(resources[1].text, None);
+        assert_eq!(resources[1].blob_bytes, Some(3));
+
+        let single = json!({
+            "messages": [
+                { "role": "user", "content": { "type": "text", "text": "Review this diff" } },
+                { "role": "user", "content": { "type": "resource", "resource": { "uri": "x", "text": "+line" } } }
+            ]
+        });
+        assert_eq!(render_prompt_messages(&single), "Review this diff\n\n+line");
+
+        let dialogue = json!({
+            "messages": [
+                { "role": "user", "content": { "type": "text", "text": "Hi" } },
+                { "role": "assistant", "content": { "type": "image", "data": "" } }
+            ]
+        });
+        assert_eq!(
+            render_prompt_messages(&dialogue),
+            "user: Hi\n\nassistant: [image content omitted]"
+        );
+    }
+}
@@ -0,0 +1,973 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{StatusCode, Url};
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use super::rpc::{
+    request_timeout_for, McpMessageHandler, RpcSession, DEFAULT_REQUEST_TIMEOUT_SECS,
+};
+use super::{
+    initialize_request, parse_initialize_response, JsonRpcRequest, JsonRpcResponse, McpRequester,
+    McpTransport,
+};
+use crate::models::config::McpHttpProtocol;
+use crate::models::mcp::ServerCapabilities;
+use crate::services::sse::read_sse_events;
+
+const SESSION_ID_HEADER: &str = "mcp-session-id";
+const PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+const CONNECT_TIMEOUT_SECS: u64 = 15;
+const SSE_ENDPOINT_TIMEOUT_SECS: u64 = 30;
+const SESSION_DELETE_TIMEOUT_SECS: u64 = 5;
+const STREAM_RECONNECT_INITIAL_MS: u64 = 1_000;
+const STREAM_RECONNECT_MAX_MS: u64 = 30_000;
+
+#[derive(Debug)]
+pub struct McpHttpStatusError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl fmt::Display for McpHttpStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "HTTP error: {}", self.status)
+        } else {
+            write!(f, "HTTP error: {}: {}", self.status, self.message)
+        }
+    }
+}
+
+impl std::error::Error for McpHttpStatusError {}
+
+fn http_status_of(error: &anyhow::Error) -> Option<StatusCode> {
+    error
+        .downcast_ref::<McpHttpStatusError>()
+        .map(|error| error.status)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HttpMode {
+    Streamable,
+    LegacySse,
+}
+
+#[derive(Default)]
+struct HttpSessionState {
+    post_url: Option<Url>,
+    session_id: Option<String>,
+    protocol_version: Option<String>,
+}
+
+/// What a background task needs to talk to the server: the client, the configured headers
+/// and the per-connection endpoint / session id.
+#[derive(Clone)]
+struct HttpChannel {
+    client: reqwest::Client,
+    headers: HeaderMap,
+    state: Arc<Mutex<HttpSessionState>>,
+}
+
+impl HttpChannel {
+    fn post_url(&self) -> Result<Url> {
+        self.state
+            .lock()
+            .ok()
+            .and_then(|state| state.post_url.clone())
+            .ok_or_else(|| anyhow!("MCP endpoint is not known yet"))
+    }
+
+    fn has_session(&self) -> bool {
+        self.state
+            .lock()
+            .map(|state| state.session_id.is_some())
+            .unwrap_or(false)
+    }
+
+    fn session_headers(&self) -> HeaderMap {
+        let mut headers = self.headers.clone();
+        if let Ok(state) = self.state.lock() {
+            if let Some(value) = state
+                .session_id
+                .as_deref()
+                .and_then(|value| HeaderValue::from_str(value).ok())
+            {
+                headers.insert(SESSION_ID_HEADER, value);
+            }
+            if let Some(value) = state
+                .protocol_version
+                .as_deref()
+                .and_then(|value| HeaderValue::from_str(value).ok())
+            {
+                headers.insert(PROTOCOL_VERSION_HEADER, value);
+            }
+        }
+        headers
+    }
+
+    async fn post(&self, payload: String, timeout: Duration) -> Result<reqwest::Response> {
+        let response = self
+            .client
+            .post(self.post_url()?)
+            .headers(self.session_headers())
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json, text/event-stream")
+            .body(payload)
+            .timeout(timeout)
+            .send()
+            .await?;
+
+        if let Some(session_id) = response
+            .headers()
+            .get(SESSION_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            if let Ok(mut state) = self.state.lock() {
+                state.session_id = Some(session_id.to_string());
+            }
+        }
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(McpHttpStatusError {
+                status,
+                message: body.trim().chars().take(300).collect(),
+            }
+            .into());
+        }
+        Ok(response)
+    }
+}
+
+fn build_headers(
+    headers: &HashMap<String, String>,
+    bearer_token: Option<&str>,
+) -> Result<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let header_name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| anyhow!("Invalid MCP header name: {}", name))?;
+        let header_value = HeaderValue::from_str(value.trim())
+            .map_err(|_| anyhow!("Invalid value for MCP header {}", name))?;
+        map.insert(header_name, header_value);
+    }
+    if let Some(token) = bearer_token
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+    {
+        if !map.contains_key(AUTHORIZATION) {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|_| anyhow!("Invalid MCP bearer token"))?;
+            map.insert(AUTHORIZATION, value);
+        }
+    }
+    Ok(map)
+}
+
+fn content_type_of(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Streamable HTTP answers a POSTed request either inline as JSON or as an SSE stream that
+/// may carry server requests/notifications before the response. Legacy SSE servers reply
+/// `202 Accepted` and deliver the response on the long-lived GET stream instead.
+///
+/// When a JSON body or response stream ends without the reply to `key`, the pending slot is
+/// dropped so the caller fails right away instead of waiting for the request timeout.
+async fn consume_post_response(
+    session: &Arc<RpcSession>,
+    outgoing: &mpsc::UnboundedSender<String>,
+    key: &str,
+    response: reqwest::Response,
+) -> Result<()> {
+    let content_type = content_type_of(&response);
+    if content_type.starts_with("text/event-stream") {
+        let session = session.clone();
+        let outgoing = outgoing.clone();
+        let key = key.to_string();
+        tokio::spawn(async move {
+            let result = read_sse_events(response, |event| {
+                if event.is_message() {
+                    session.dispatch_payload(&event.data, &outgoing);
+                }
+            })
+            .await;
+            if let Err(error) = result {
+                eprintln!("[mcp] {} response stream: {}", session.server_name(), error);
+            }
+            session.forget(&key);
+        });
+    } else if content_type.contains("json") {
+        let body = response.text().await?;
+        let body = body.trim();
+        if !body.is_empty() {
+            session.dispatch_payload(body, outgoing);
+        }
+        session.forget(key);
+    }
+    Ok(())
+}
+
+/// Delivers our replies to server requests and cancellation notices, in order.
+async fn run_outgoing_writer(channel: HttpChannel, mut outgoing: mpsc::UnboundedReceiver<String>) {
+    while let Some(payload) = outgoing.recv().await {
+        if let Err(error) = channel
+            .post(payload, Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS))
+            .await
+        {
+            eprintln!("[mcp] failed to deliver message to server: {}", error);
+        }
+    }
+}
+
+async fn run_legacy_sse_listener(
+    response: reqwest::Response,
+    base_url: Url,
+    session: Arc<RpcSession>,
+    outgoing: mpsc::UnboundedSender<String>,
+    endpoint_tx: oneshot::Sender<Url>,
+    stream_open: Arc<AtomicBool>,
+    connected: Arc<AtomicBool>,
+) {
+    let mut endpoint_tx = Some(endpoint_tx);
+    let result = read_sse_events(response, |event| {
+        if event.event == "endpoint" {
+            match base_url.join(event.data.trim()) {
+                Ok(endpoint) => {
+                    if let Some(sender) = endpoint_tx.take() {
+                        let _ = sender.send(endpoint);
+                    }
+                }
+                Err(error) => {
+                    eprintln!(
+                        "[mcp] {} announced an invalid endpoint: {}",
+                        session.server_name(),
+                        error
+                    );
+                }
+            }
+        } else if event.is_message() {
+            session.dispatch_payload(&event.data, &outgoing);
+        }
+    })
+    .await;
+    if let Err(error) = result {
+        eprintln!("[mcp] {} SSE stream: {}", session.server_name(), error);
+    }
+    stream_open.store(false, Ordering::SeqCst);
+    connected.store(false, Ordering::SeqCst);
+    session.fail_all();
+}
+
+/// Optional server-to-client stream of a Streamable HTTP session. Servers that do not offer
+/// one answer 405; dropped streams are resumed with `Last-Event-ID` after a backoff.
+async fn run_streamable_listener(
+    channel: HttpChannel,
+    url: Url,
+    session: Arc<RpcSession>,
+    outgoing: mpsc::UnboundedSender<String>,
+) {
+    let mut backoff_ms = STREAM_RECONNECT_INITIAL_MS;
+    let mut last_event_id: Option<String> = None;
+    loop {
+        let mut request = channel
+            .client
+            .get(url.clone())
+            .headers(channel.session_headers())
+            .header(ACCEPT, "text/event-stream");
+        if let Some(event_id) = last_event_id.as_deref() {
+            request = request.header(LAST_EVENT_ID_HEADER, event_id);
+        }
+
+        match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                backoff_ms = STREAM_RECONNECT_INITIAL_MS;
+                let result = read_sse_events(response, |event| {
+                    if event.is_message() {
+                        session.dispatch_payload(&event.data, &outgoing);
+                    }
+                })
+                .await;
+                match result {
+                    Ok(Some(event_id)) => last_event_id = Some(event_id),
+                    Ok(None) => {}
+                    Err(error) => {
+                        eprintln!("[mcp] {} event stream: {}", session.server_name(), error);
+                    }
+                }
+            }
+            Ok(response)
+                if matches!(
+                    response.status(),
+                    StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_FOUND
+                ) =>
+            {
+                return;
+            }
+            Ok(response) => {
+                eprintln!(
+                    "[mcp] {} event stream rejected: HTTP {}",
+                    session.server_name(),
+                    response.status()
+                );
+            }
+            Err(error) => {
+                eprintln!("[mcp] {} event stream: {}", session.server_name(), error);
+            }
+        }
+
+        tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
+        backoff_ms = (backoff_ms * 2).min(STREAM_RECONNECT_MAX_MS);
+    }
+}
+
+async fn post_request(
+    channel: &HttpChannel,
+    session: &Arc<RpcSession>,
+    outgoing: &mpsc::UnboundedSender<String>,
+    mut request: JsonRpcRequest,
+) -> Result<JsonRpcResponse> {
+    let (pending, receiver) = session.register(&mut request);
+    let payload = serde_json::to_string(&request)?;
+    let timeout = request_timeout_for(&request.method);
+    let response = channel.post(payload, timeout).await?;
+    consume_post_response(session, outgoing, pending.key(), response).await?;
+    session
+        .wait(pending, &request.method, receiver, outgoing)
+        .await
+}
+
+/// Requests over an established connection, shared with `McpClientHandle`s. It cannot
+/// reconnect itself; a lost connection or expired session is left for the next `prepare`.
+struct HttpLink {
+    channel: HttpChannel,
+    session: Arc<RpcSession>,
+    outgoing: mpsc::UnboundedSender<String>,
+    connected: Arc<AtomicBool>,
+    mode: Option<HttpMode>,
+}
+
+#[async_trait]
+impl McpRequester for HttpLink {
+    async fn send(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        if !self.connected.load(Ordering::SeqCst) {
+            return Err(anyhow!(
+                "MCP server '{}' is not connected",
+                self.session.server_name()
+            ));
+        }
+        let result = post_request(&self.channel, &self.session, &self.outgoing, request).await;
+        if let Err(error) = result.as_ref() {
+            if is_session_expired(self.mode, &self.channel, error) {
+                self.connected.store(false, Ordering::SeqCst);
+            }
+        }
+        result
+    }
+}
+
+fn is_session_expired(
+    mode: Option<HttpMode>,
+    channel: &HttpChannel,
+    error: &anyhow::Error,
+) -> bool {
+    mode == Some(HttpMode::Streamable)
+        && http_status_of(error) == Some(StatusCode::NOT_FOUND)
+        && channel.has_session()
+}
+
+/// MCP over HTTP: the Streamable HTTP transport with `Mcp-Session-Id` handling, or the
+/// legacy HTTP+SSE transport. Lost sessions and dropped SSE streams are re-established
+/// (including a fresh `initialize`) on the next request.
+pub struct HttpTransport {
+    url: Url,
+    protocol: McpHttpProtocol,
+    mode: Option<HttpMode>,
+    channel: HttpChannel,
+    session: Arc<RpcSession>,
+    outgoing: mpsc::UnboundedSender<String>,
+    connected: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+    writer: JoinHandle<()>,
+}
+
+impl HttpTransport {
+    pub fn new(
+        server_name: &str,
+        url: &str,
+        headers: &HashMap<String, String>,
+        bearer_token: Option<&str>,
+        protocol: McpHttpProtocol,
+        handler: Arc<dyn McpMessageHandler>,
+    ) -> Result<Self> {
+        let url = Url::parse(url.trim())
+            .map_err(|e| anyhow!("Invalid MCP server URL '{}': {}", url, e))?;
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+            .build()?;
+        let channel = HttpChannel {
+            client,
+            headers: build_headers(headers, bearer_token)?,
+            state: Arc::new(Mutex::new(HttpSessionState::default())),
+        };
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(run_outgoing_writer(channel.clone(), outgoing_rx));
+
+        Ok(Self {
+            url,
+            protocol,
+            mode: None,
+            channel,
+            session: RpcSession::new(server_name, handler),
+            outgoing,
+            connected: Arc::new(AtomicBool::new(false)),
+            listener: None,
+            writer,
+        })
+    }
+
+    fn set_session_state(&self, state: HttpSessionState) {
+        if let Ok(mut current) = self.channel.state.lock() {
+            *current = state;
+        }
+    }
+
+    fn reset_connection(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            listener.abort();
+        }
+        self.mode = None;
+        self.connected.store(false, Ordering::SeqCst);
+        self.set_session_state(HttpSessionState::default());
+        self.session.fail_all();
+    }
+
+    async fn connect(&mut self) -> Result<ServerCapabilities> {
+        self.reset_connection();
+        match self.protocol {
+            McpHttpProtocol::Streamable => self.connect_streamable().await,
+            McpHttpProtocol::Sse => self.connect_legacy_sse().await,
+            McpHttpProtocol::Auto => match self.connect_streamable().await {
+                Err(error) if Self::should_fall_back_to_sse(&error) => {
+                    eprintln!(
+                        "[mcp] {} rejected Streamable HTTP ({}), trying legacy SSE",
+                        self.session.server_name(),
+                        error
+                    );
+                    self.reset_connection();
+                    self.connect_legacy_sse().await
+                }
+                result => result,
+            },
+        }
+    }
+
+    /// Per the MCP backwards-compatibility rules a 4xx on the initialize POST means the
+    /// server only speaks the older HTTP+SSE transport. Auth failures are reported as-is.
+    fn should_fall_back_to_sse(error: &anyhow::Error) -> bool {
+        http_status_of(error)
+            .map(|status| {
+                status.is_client_error()
+                    && status != StatusCode::UNAUTHORIZED
+                    && status != StatusCode::FORBIDDEN
+            })
+            .unwrap_or(false)
+    }
+
+    async fn connect_streamable(&mut self) -> Result<ServerCapabilities> {
+        self.set_session_state(HttpSessionState {
+            post_url: Some(self.url.clone()),
+            ..HttpSessionState::default()
+        });
+        let capabilities = self.handshake().await?;
+        self.mode = Some(HttpMode::Streamable);
+        self.connected.store(true, Ordering::SeqCst);
+        self.listener = Some(tokio::spawn(run_streamable_listener(
+            self.channel.clone(),
+            self.url.clone(),
+            self.session.clone(),
+            self.outgoing.clone(),
+        )));
+        Ok(capabilities)
+    }
+
+    async fn connect_legacy_sse(&mut self) -> Result<ServerCapabilities> {
+        let response = self
+            .channel
+            .client
+            .get(self.url.clone())
+            .headers(self.channel.headers.clone())
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(McpHttpStatusError {
+                status,
+                message: body.trim().chars().take(300).collect(),
+            }
+            .into());
+        }
+
+        let (endpoint_tx, endpoint_rx) = oneshot::channel();
+        let stream_open = Arc::new(AtomicBool::new(true));
+        self.listener = Some(tokio::spawn(run_legacy_sse_listener(
+            response,
+            self.url.clone(),
+            self.session.clone(),
+            self.outgoing.clone(),
+            endpoint_tx,
+            stream_open.clone(),
+            self.connected.clone(),
+        )));
+
+        let server_name = self.session.server_name().to_string();
+        let endpoint =
+            tokio::time::timeout(Duration::from_secs(SSE_ENDPOINT_TIMEOUT_SECS), endpoint_rx)
+                .await
+                .map_err(|_| {
+                    anyhow!(
+                        "MCP server '{}' did not announce an SSE endpoint",
+                        server_name
+                    )
+                })?
+                .map_err(|_| {
+                    anyhow!(
+                        "MCP server '{}' closed the SSE stream before announcing an endpoint",
+                        server_name
+                    )
+                })?;
+        if endpoint.origin() != self.url.origin() {
+            return Err(anyhow!(
+                "MCP server '{}' announced an endpoint on another origin: {}",
+                server_name,
+                endpoint
+            ));
+        }
+
+        self.set_session_state(HttpSessionState {
+            post_url: Some(endpoint),
+            ..HttpSessionState::default()
+        });
+        let capabilities = self.handshake().await?;
+        self.mode = Some(HttpMode::LegacySse);
+        self.connected.store(true, Ordering::SeqCst);
+        // The listener clears `connected` when the stream ends; if that happened during the
+        // handshake the store above must not revive the connection.
+        if !stream_open.load(Ordering::SeqCst) {
+            self.connected.store(false, Ordering::SeqCst);
+            return Err(anyhow!(
+                "MCP server '{}' closed the SSE stream during initialization",
+                server_name
+            ));
+        }
+        Ok(capabilities)
+    }
+
+    async fn handshake(&mut self) -> Result<ServerCapabilities> {
+        let response = self
+            .request(initialize_request(self.session.client_capabilities()))
+            .await?;
+        let capabilities = parse_initialize_response(&response)?;
+        let protocol_version = response
+            .result
+            .as_ref()
+            .and_then(|result| result.get("protocolVersion"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        if let Ok(mut state) = self.channel.state.lock() {
+            state.protocol_version = protocol_version;
+        }
+        self.post_notification(JsonRpcRequest::notification(
+            "notifications/initialized",
+            None,
+        ))
+        .await?;
+        Ok(capabilities)
+    }
+
+    async fn request(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        post_request(&self.channel, &self.session, &self.outgoing, request).await
+    }
+
+    async fn post_notification(&self, request: JsonRpcRequest) -> Result<()> {
+        let payload = serde_json::to_string(&request)?;
+        let timeout = request_timeout_for(&request.method);
+        self.channel.post(payload, timeout).await?;
+        Ok(())
+    }
+
+    fn is_session_expired(&self, error: &anyhow::Error) -> bool {
+        is_session_expired(self.mode, &self.channel, error)
+    }
+
+    async fn ensure_connected(&mut self) -> Result<()> {
+        if self.mode.is_some() && self.connected.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        eprintln!("[mcp] reconnecting to {}", self.session.server_name());
+        self.connect().await.map(|_| ())
+    }
+}
+
+#[async_trait]
+impl McpTransport for HttpTransport {
+    async fn send(&mut self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        self.ensure_connected().await?;
+        match self.request(request.clone()).await {
+            Err(error) if self.is_session_expired(&error) => {
+                eprintln!(
+                    "[mcp] {} session expired, re-initializing",
+                    self.session.server_name()
+                );
+                self.connect().await?;
+                self.request(request).await
+            }
+            result => result,
+        }
+    }
+
+    async fn notify(&mut self, request: JsonRpcRequest) -> Result<()> {
+        self.ensure_connected().await?;
+        self.post_notification(request).await
+    }
+
+    async fn initialize(&mut self) -> Result<ServerCapabilities> {
+        self.connect().await
+    }
+
+    async fn prepare(&mut self) -> Result<()> {
+        self.ensure_connected().await
+    }
+
+    fn requester(&self) -> Arc<dyn McpRequester> {
+        Arc::new(HttpLink {
+            channel: self.channel.clone(),
+            session: self.session.clone(),
+            outgoing: self.outgoing.clone(),
+            connected: self.connected.clone(),
+            mode: self.mode,
+        })
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        if self.mode == Some(HttpMode::Streamable) && self.channel.has_session() {
+            let _ = self
+                .channel
+                .client
+                .delete(self.url.clone())
+                .headers(self.channel.session_headers())
+                .timeout(Duration::from_secs(SESSION_DELETE_TIMEOUT_SECS))
+                .send()
+                .await;
+        }
+        self.reset_connection();
+        self.writer.abort();
+        Ok(())
+    }
+}
+
+impl Drop for HttpTransport {
+    fn drop(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            listener.abort();
+        }
+        self.writer.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::mcp_client::DefaultMessageHandler;
+    use crate::services::test_http::{TestResponse, TestServer};
+    use serde_json::json;
+    use std::sync::atomic::AtomicUsize;
+
+    /// The reply a minimal server gives to `body`, or `None` for notifications.
+    fn rpc_reply(body: &str) -> Option<Value> {
+        let message: Value = serde_json::from_str(body).ok()?;
+        let id = message.get("id")?.clone();
+        let result = match message.get("method").and_then(Value::as_str) {
+            Some("initialize") => json!({
+                "protocolVersion": "2025-03-26",
+                "capabilities": { "tools": {} }
+            }),
+            Some("tools/list") => json!({ "tools": [] }),
+            _ => json!({}),
+        };
+        Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+    }
+
+    fn rpc_method(body: &str) -> String {
+        serde_json::from_str::<Value>(body)
+            .ok()
+            .and_then(|message| {
+                message
+                    .get("method")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            })
+            .unwrap_or_default()
+    }
+
+    fn transport(server: &TestServer, protocol: McpHttpProtocol) -> HttpTransport {
+        HttpTransport::new(
+            "test",
+            &format!("{}/mcp", server.base_url),
+            &HashMap::new(),
+            None,
+            protocol,
+            Arc::new(DefaultMessageHandler),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn legacy_sse_discovers_the_endpoint_and_reads_replies_from_the_stream() {
+        let stream: Arc<Mutex<Option<mpsc::UnboundedSender<String>>>> = Arc::default();
+        let server = TestServer::spawn({
+            let stream = stream.clone();
+            move |request| {
+                if request.method == "GET" {
+                    let (sender, receiver) = mpsc::unbounded_channel();
+                    let _ = sender.send("event: endpoint\ndata: /messages?session=abc\n\n".into());
+                    *stream.lock().unwrap() = Some(sender);
+                    return TestResponse::stream(receiver);
+                }
+                if request.path == "/mcp" {
+                    return TestResponse::new(405, "text/plain", "");
+                }
+                if let Some(reply) = rpc_reply(&request.body) {
+                    if let Some(sender) = stream.lock().unwrap().as_ref() {
+                        let _ = sender.send(format!("event: message\ndata: {}\n\n", reply));
+                    }
+                }
+                TestResponse::new(202, "text/plain", "")
+            }
+        })
+        .await;
+        let mut transport = transport(&server, McpHttpProtocol::Auto);
+
+        let capabilities = transport.initialize().await.unwrap();
+        assert_eq!(capabilities.tools, Some(true));
+        assert_eq!(transport.mode, Some(HttpMode::LegacySse));
+        assert!(transport.connected.load(Ordering::SeqCst));
+
+        let response = transport
+            .send(JsonRpcRequest::new("tools/list", None))
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(json!({ "tools": [] })));
+
+        let requests = server.requests();
+        assert_eq!(
+            (requests[0].method.as_str(), requests[0].path.as_str()),
+            ("POST", "/mcp")
+        );
+        assert_eq!(
+            (requests[1].method.as_str(), requests[1].path.as_str()),
+            ("GET", "/mcp")
+        );
+        let posts: Vec<(String, String)> = requests[2..]
+            .iter()
+            .map(|request| (request.path.clone(), rpc_method(&request.body)))
+            .collect();
+        assert_eq!(
+            posts,
+            vec![
+                (
+                    "/messages?session=abc".to_string(),
+                    "initialize".to_string()
+                ),
+                (
+                    "/messages?session=abc".to_string(),
+                    "notifications/initialized".to_string()
+                ),
+                (
+                    "/messages?session=abc".to_string(),
+                    "tools/list".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn legacy_sse_stays_disconnected_when_the_handshake_fails() {
+        let server = TestServer::spawn(|request| {
+            if request.method == "GET" {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                let _ = sender.send("event: endpoint\ndata: /messages\n\n".into());
+                std::mem::forget(sender);
+                return TestResponse::stream(receiver);
+            }
+            TestResponse::new(500, "text/plain", "boom")
+        })
+        .await;
+        let mut transport = transport(&server, McpHttpProtocol::Sse);
+
+        assert!(transport.initialize().await.is_err());
+        assert_eq!(transport.mode, None);
+        assert!(!transport.connected.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn streamable_sessions_are_sent_back_and_renewed_when_they_expire() {
+        let sessions = Arc::new(AtomicUsize::new(0));
+        let expired = Arc::new(AtomicBool::new(false));
+        let server = TestServer::spawn({
+            let sessions = sessions.clone();
+            let expired = expired.clone();
+            move |request| {
+                if request.method == "GET" {
+                    return TestResponse::new(405, "text/plain", "");
+                }
+                if rpc_method(&request.body) == "initialize" {
+                    expired.store(false, Ordering::SeqCst);
+                    let session = sessions.fetch_add(1, Ordering::SeqCst) + 1;
+                    return TestResponse::json(200, rpc_reply(&request.body).unwrap())
+                        .with_header("Mcp-Session-Id", &format!("s{}", session));
+                }
+                let current = format!("s{}", sessions.load(Ordering::SeqCst));
+                if expired.load(Ordering::SeqCst)
+                    || request.header(SESSION_ID_HEADER) != Some(&current)
+                {
+                    return TestResponse::new(404, "text/plain", "unknown session");
+                }
+                match rpc_reply(&request.body) {
+                    Some(reply) => TestResponse::json(200, reply),
+                    None => TestResponse::new(202, "text/plain", ""),
+                }
+            }
+        })
+        .await;
+        let mut transport = transport(&server, McpHttpProtocol::Streamable);
+
+        transport.initialize().await.unwrap();
+        transport
+            .send(JsonRpcRequest::new("tools/list", None))
+            .await
+            .unwrap();
+        expired.store(true, Ordering::SeqCst);
+        let response = transport
+            .send(JsonRpcRequest::new("tools/list", None))
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(json!({ "tools": [] })));
+
+        let posts: Vec<(String, Option<String>, Option<String>)> = server
+            .requests()
+            .iter()
+            .filter(|request| request.method == "POST")
+            .map(|request| {
+                (
+                    rpc_method(&request.body),
+                    request.header(SESSION_ID_HEADER).map(str::to_string),
+                    request.header(PROTOCOL_VERSION_HEADER).map(str::to_string),
+                )
+            })
+            .collect();
+        let version = Some("2025-03-26".to_string());
+        assert_eq!(
+            posts,
+            vec![
+                ("initialize".to_string(), None, None),
+                (
+                    "notifications/initialized".to_string(),
+                    Some("s1".to_string()),
+                    version.clone()
+                ),
+                (
+                    "tools/list".to_string(),
+                    Some("s1".to_string()),
+                    version.clone()
+                ),
+                (
+                    "tools/list".to_string(),
+                    Some("s1".to_string()),
+                    version.clone()
+                ),
+                ("initialize".to_string(), None, None),
+                (
+                    "notifications/initialized".to_string(),
+                    Some("s2".to_string()),
+                    version.clone()
+                ),
+                ("tools/list".to_string(), Some("s2".to_string()), version),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn post_response_streams_are_demultiplexed_and_fail_fast_without_a_reply() {
+        let server = TestServer::spawn(|request| {
+            if request.method == "GET" {
+                return TestResponse::new(405, "text/plain", "");
+            }
+            let notice = json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/message",
+                "params": { "level": "info", "data": "working" }
+            });
+            match rpc_method(&request.body).as_str() {
+                "initialize" => TestResponse::json(200, rpc_reply(&request.body).unwrap()),
+                "tools/list" => TestResponse::new(
+                    200,
+                    "text/event-stream",
+                    format!(
+                        "data: {}\n\ndata: {}\n\n",
+                        notice,
+                        rpc_reply(&request.body).unwrap()
+                    ),
+                ),
+                // The stream closes after a notification without ever answering.
+                "tools/call" => {
+                    TestResponse::new(200, "text/event-stream", format!("data: {}\n\n", notice))
+                }
+                _ => TestResponse::new(202, "text/plain", ""),
+            }
+        })
+        .await;
+        let mut transport = transport(&server, McpHttpProtocol::Streamable);
+        transport.initialize().await.unwrap();
+
+        let response = transport
+            .send(JsonRpcRequest::new("tools/list", None))
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(json!({ "tools": [] })));
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            transport.send(JsonRpcRequest::new(
+                "tools/call",
+                Some(json!({ "name": "slow", "arguments": {} })),
+            )),
+        )
+        .await
+        .expect("a stream that ends without the reply must fail immediately");
+        let error = result.unwrap_err().to_string();
+        assert!(
+            error.contains("closed the connection during tools/call"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use crate::models::config::{McpHttpProtocol, McpServerConfig, McpTransport};
+
+fn string_field(entry: &Map<String, Value>, key: &str) -> Option<String> {
+    entry
+        .get(key)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+fn string_map_field(entry: &Map<String, Value>, key: &str) -> HashMap<String, String> {
+    entry
+        .get(key)
+        .and_then(Value::as_object)
+        .map(|values| {
+            values
+                .iter()
+                .map(|(name, value)| {
+                    let value = match value {
+                        Value::String(text) => text.clone(),
+                        other => other.to_string(),
+                    };
+                    (name.clone(), value)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn http_protocol(entry: &Map<String, Value>) -> McpHttpProtocol {
+    let kind = string_field(entry, "type")
+        .or_else(|| string_field(entry, "transport"))
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match kind.as_str() {
+        "sse" => McpHttpProtocol::Sse,
+        "http" | "streamable-http" | "streamable_http" | "streamablehttp" => {
+            McpHttpProtocol::Streamable
+        }
+        _ => McpHttpProtocol::Auto,
+    }
+}
+
+fn parse_server_entry(name: &str, entry: &Value) -> Result<McpServerConfig> {
+    let entry = entry
+        .as_object()
+        .ok_or_else(|| anyhow!("MCP server '{}' must be an object", name))?;
+    let enabled = !entry
+        .get("disabled")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    let transport = if let Some(command) = string_field(entry, "command") {
+        let args = entry
+            .get("args")
+            .and_then(Value::as_array)
+            .map(|args| {
+                args.iter()
+                    .filter_map(|arg| arg.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        McpTransport::Stdio {
+            command,
+            args,
+            env: string_map_field(entry, "env"),
+            cwd: string_field(entry, "cwd"),
+        }
+    } else if let Some(url) =
+        string_field(entry, "url").or_else(|| string_field(entry, "serverUrl"))
+    {
+        McpTransport::Http {
+            url,
+            headers: string_map_field(entry, "headers"),
+            bearer_token: None,
+            protocol: http_protocol(entry),
+        }
+    } else {
+        return Err(anyhow!(
+            "MCP server '{}' has neither a command nor a url",
+            name
+        ));
+    };
+
+    Ok(McpServerConfig {
+        name: name.to_string(),
+        transport,
+        enabled,
+    })
+}
+
+/// Parses the `mcpServers` JSON used by other MCP clients (Claude Desktop, Cursor, ...).
+/// Accepts the full document, VS Code's `servers` key, or the bare server map.
+pub fn parse_mcp_servers_json(raw: &str) -> Result<Vec<McpServerConfig>> {
+    let document: Value =
+        serde_json::from_str(raw.trim()).map_err(|e| anyhow!("Invalid MCP config JSON: {}", e))?;
+    let servers = document
+        .get("mcpServers")
+        .or_else(|| document.get("servers"))
+        .unwrap_or(&document)
+        .as_object()
+        .ok_or_else(|| anyhow!("Expected an object of MCP servers"))?;
+
+    servers
+        .iter()
+        .map(|(name, entry)| parse_server_entry(name, entry))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stdio_and_remote_servers() {
+        let servers = parse_mcp_servers_json(
+            r#"{
+                "mcpServers": {
+                    "github": {
+                        "command": "npx",
+                        "args": ["-y", "@modelcontextprotocol/server-github"],
+                        "env": { "GITHUB_TOKEN": "${secret:github}" },
+                        "cwd": "/tmp"
+                    },
+                    "remote": {
+                        "type": "sse",
+                        "url": "https://example.com/sse",
+                        "headers": { "X-Api-Key": "abc" },
+                        "disabled": true
+                    }
+                }
+            }"#,
+        )
+        .expect("valid config");
+
+        assert_eq!(servers.len(), 2);
+        let github = servers
+            .iter()
+            .find(|server| server.name == "github")
+            .unwrap();
+        assert!(github.enabled);
+        match &github.transport {
+            McpTransport::Stdio {
+                command,
+                args,
+                env,
+                cwd,
+            } => {
+                assert_eq!(command, "npx");
+                assert_eq!(args.len(), 2);
+                assert_eq!(env.get("GITHUB_TOKEN").unwrap(), "${secret:github}");
+                assert_eq!(cwd.as_deref(), Some("/tmp"));
+            }
+            other => panic!("unexpected transport: {:?}", other),
+        }
+
+        let remote = servers
+            .iter()
+            .find(|server| server.name == "remote")
+            .unwrap();
+        assert!(!remote.enabled);
+        match &remote.transport {
+            McpTransport::Http {
+                url,
+                headers,
+                protocol,
+                ..
+            } => {
+                assert_eq!(url, "https://example.com/sse");
+                assert_eq!(headers.get("X-Api-Key").unwrap(), "abc");
+                assert_eq!(*protocol, McpHttpProtocol::Sse);
+            }
+            other => panic!("unexpected transport: {:?}", other),
+        }
+
+        assert!(parse_mcp_servers_json(r#"{"broken": {"args": []}}"#).is_err());
+    }
+}
@@ -0,0 +1,798 @@
+use crate::models::config::McpTransport as McpTransportConfig;
+use crate::models::mcp::*;
+use crate::services::secret_store::resolve_secret_refs;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+mod content;
+mod http;
+mod import;
+mod rpc;
+mod stdio;
+mod supervisor;
+
+pub use content::{render_prompt_messages, render_resource_contents, RenderedResource};
+pub use http::HttpTransport;
+pub use import::parse_mcp_servers_json;
+pub use rpc::{DefaultMessageHandler, McpMessageHandler};
+pub use stdio::StdioTransport;
+pub use supervisor::{spawn_mcp_supervisor, McpChangeQueue};
+
+pub(crate) const MCP_PROTOCOL_VERSION: &str = "2025-03-26";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JsonRpcRequest {
+    jsonrpc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<Value>,
+    method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params: Option<Value>,
+}
+
+impl JsonRpcRequest {
+    /// The transport assigns the id when the request is sent.
+    pub(crate) fn new(method: &str, params: Option<Value>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: method.to_string(),
+            params,
+        }
+    }
+
+    pub(crate) fn notification(method: &str, params: Option<Value>) -> Self {
+        Self::new(method, params)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JsonRpcResponse {
+    jsonrpc: String,
+    id: Option<Value>,
+    result: Option<Value>,
+    error: Option<JsonRpcError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    code: i32,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl JsonRpcError {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+/// Sends requests over an established connection without exclusive access to the
+/// transport, so a long tool call blocks neither the manager nor other requests.
+#[async_trait]
+pub trait McpRequester: Send + Sync {
+    async fn send(&self, request: JsonRpcRequest) -> Result<JsonRpcResponse>;
+}
+
+#[async_trait]
+pub trait McpTransport: Send + Sync {
+    async fn send(&mut self, request: JsonRpcRequest) -> Result<JsonRpcResponse>;
+    async fn notify(&mut self, request: JsonRpcRequest) -> Result<()>;
+    async fn initialize(&mut self) -> Result<ServerCapabilities>;
+    async fn shutdown(&mut self) -> Result<()>;
+
+    /// Re-establishes a dropped connection so `requester` hands out a usable link.
+    async fn prepare(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn requester(&self) -> Arc<dyn McpRequester>;
+
+    /// False once the transport knows the server is gone (e.g. the stdio child exited).
+    fn is_alive(&self) -> bool {
+        true
+    }
+
+    /// Recent diagnostic output from the server, if the transport captures any.
+    fn stderr_excerpt(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Servers advertise capabilities as objects (`"tools": { "listChanged": true }`);
+/// presence of the key is what matters here.
+fn parse_server_capabilities(value: Option<&Value>) -> ServerCapabilities {
+    let has = |key: &str| value.map(|caps| caps.get(key).is_some()).unwrap_or(false);
+    ServerCapabilities {
+        tools: Some(has("tools")),
+        prompts: Some(has("prompts")),
+        resources: Some(has("resources")),
+        resource_subscribe: Some(
+            value
+                .and_then(|caps| caps.get("resources"))
+                .and_then(|resources| resources.get("subscribe"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+        ),
+    }
+}
+
+pub(crate) fn initialize_request(client_capabilities: Value) -> JsonRpcRequest {
+    JsonRpcRequest::new(
+        "initialize",
+        Some(json!({
+            "protocolVersion": MCP_PROTOCOL_VERSION,
+            "capabilities": client_capabilities,
+            "clientInfo": {
+                "name": "petool",
+                "version": env!("CARGO_PKG_VERSION")
+            }
+        })),
+    )
+}
+
+pub(crate) fn parse_initialize_response(response: &JsonRpcResponse) -> Result<ServerCapabilities> {
+    if let Some(error) = response.error.as_ref() {
+        return Err(anyhow!("MCP initialization error: {}", error.message));
+    }
+    Ok(parse_server_capabilities(
+        response
+            .result
+            .as_ref()
+            .and_then(|value| value.get("capabilities")),
+    ))
+}
+
+pub(crate) async fn initialize_handshake<T: McpTransport + ?Sized>(
+    transport: &mut T,
+    client_capabilities: Value,
+) -> Result<ServerCapabilities> {
+    let response = transport
+        .send(initialize_request(client_capabilities))
+        .await?;
+    let capabilities = parse_initialize_response(&response)?;
+    transport
+        .notify(JsonRpcRequest::notification(
+            "notifications/initialized",
+            None,
+        ))
+        .await?;
+    Ok(capabilities)
+}
+
+fn resolve_secret_map(values: &HashMap<String, String>) -> Result<HashMap<String, String>> {
+    values
+        .iter()
+        .map(|(key, value)| {
+            resolve_secret_refs(value)
+                .map(|value| (key.clone(), value))
+                .map_err(|e| anyhow!(e))
+        })
+        .collect()
+}
+
+/// Builds the transport described by a server entry in the config, resolving
+/// `${secret:NAME}` references in env values, headers and the bearer token.
+pub fn build_transport(
+    server_name: &str,
+    config: &McpTransportConfig,
+    handler: Arc<dyn McpMessageHandler>,
+) -> Result<Box<dyn McpTransport>> {
+    match config {
+        McpTransportConfig::Stdio {
+            command,
+            args,
+            env,
+            cwd,
+        } => {
+            let env = resolve_secret_map(env)?;
+            let cwd = cwd
+                .as_deref()
+                .map(str::trim)
+                .filter(|cwd| !cwd.is_empty())
+                .map(PathBuf::from);
+            Ok(Box::new(StdioTransport::spawn(
+                server_name,
+                command,
+                args,
+                &env,
+                cwd.as_deref(),
+                handler,
+            )?))
+        }
+        McpTransportConfig::Http {
+            url,
+            headers,
+            bearer_token,
+            protocol,
+        } => {
+            let headers = resolve_secret_map(headers)?;
+            let bearer_token = bearer_token
+                .as_deref()
+                .map(resolve_secret_refs)
+                .transpose()
+                .map_err(|e| anyhow!(e))?;
+            Ok(Box::new(HttpTransport::new(
+                server_name,
+                url,
+                &headers,
+                bearer_token.as_deref(),
+                *protocol,
+                handler,
+            )?))
+        }
+    }
+}
+
+/// Builds the transport for `config` and performs the MCP handshake.
+pub async fn connect_client(
+    server_name: &str,
+    config: &McpTransportConfig,
+    handler: Arc<dyn McpMessageHandler>,
+) -> Result<McpClient> {
+    let transport = build_transport(server_name, config, handler)?;
+    McpClient::new(server_name.to_string(), transport).await
+}
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+pub struct McpClient {
+    pub name: String,
+    connection_id: u64,
+    transport: Option<Box<dyn McpTransport>>,
+    roots_root: Option<PathBuf>,
+    pub capabilities: ServerCapabilities,
+    pub tools: HashMap<String, Tool>,
+    pub prompts: HashMap<String, Prompt>,
+    pub resources: HashMap<String, Resource>,
+    pub resource_templates: HashMap<String, ResourceTemplate>,
+    subscriptions: HashSet<String>,
+}
+
+impl McpClient {
+    pub async fn new(name: String, mut transport: Box<dyn McpTransport>) -> Result<Self> {
+        let capabilities = transport.initialize().await?;
+
+        Ok(Self {
+            name,
+            connection_id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            transport: Some(transport),
+            roots_root: None,
+            capabilities,
+            tools: HashMap::new(),
+            prompts: HashMap::new(),
+            resources: HashMap::new(),
+            resource_templates: HashMap::new(),
+            subscriptions: HashSet::new(),
+        })
+    }
+
+    pub async fn refresh_tools(&mut self) -> Result<()> {
+        if self.capabilities.tools != Some(true) {
+            return Ok(());
+        }
+
+        let transport = self
+            .transport
+            .as_mut()
+            .ok_or_else(|| anyhow!("Transport not available"))?;
+        let response = transport
+            .send(JsonRpcRequest::new("tools/list", None))
+            .await?;
+
+        if let Some(error) = response.error {
+            return Err(anyhow!("MCP tools/list error: {}", error.message));
+        }
+
+        if let Some(result) = response.result {
+            if let Some(tools) = result
+                .get("tools")
+                .and_then(|v| serde_json::from_value::<Vec<Tool>>(v.clone()).ok())
+            {
+                self.tools = tools
+                    .into_iter()
+                    .map(|tool| (tool.name.clone(), tool))
+                    .collect();
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn refresh_prompts(&mut self) -> Result<()> {
+        if self.capabilities.prompts != Some(true) {
+            return Ok(());
+        }
+
+        let transport = self
+            .transport
+            .as_mut()
+            .ok_or_else(|| anyhow!("Transport not available"))?;
+        let response = transport
+            .send(JsonRpcRequest::new("prompts/list", None))
+            .await?;
+
+        if let Some(error) = response.error {
+            return Err(anyhow!("MCP prompts/list error: {}", error.message));
+        }
+
+        if let Some(result) = response.result {
+            if let Some(prompts) = result
+                .get("prompts")
+                .and_then(|v| serde_json::from_value::<Vec<Prompt>>(v.clone()).ok())
+            {
+                self.prompts = prompts
+                    .into_iter()
+                    .map(|prompt| (prompt.name.clone(), prompt))
+                    .collect();
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn refresh_resources(&mut self) -> Result<()> {
+        if self.capabilities.resources != Some(true) {
+            return Ok(());
+        }
+
+        let transport = self
+            .transport
+            .as_mut()
+            .ok_or_else(|| anyhow!("Transport not available"))?;
+        let response = transport
+            .send(JsonRpcRequest::new("resources/list", None))
+            .await?;
+
+        if let Some(error) = response.error {
+            return Err(anyhow!("MCP resources/list error: {}", error.message));
+        }
+
+        if let Some(result) = response.result {
+            if let Some(resources) = result
+                .get("resources")
+                .and_then(|v| serde_json::from_value::<Vec<Resource>>(v.clone()).ok())
+            {
+                self.resources = resources
+                    .into_iter()
+                    .map(|resource| (resource.uri.clone(), resource))
+                    .collect();
+            }
+        }
+
+        // Templates are optional; servers without them answer with "method not found".
+        match self.request("resources/templates/list", None).await {
+            Ok(result) => {
+                if let Some(templates) = result
+                    .get("resourceTemplates")
+                    .and_then(|v| serde_json::from_value::<Vec<ResourceTemplate>>(v.clone()).ok())
+                {
+                    self.resource_templates = templates
+                        .into_iter()
+                        .map(|template| (template.uri_template.clone(), template))
+                        .collect();
+                }
+            }
+            Err(error) => {
+                eprintln!(
+                    "[mcp] {} resources/templates/list skipped: {}",
+                    self.name, error
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn refresh_lists(&mut self) -> Result<()> {
+        self.refresh_tools().await?;
+        self.refresh_prompts().await?;
+        self.refresh_resources().await
+    }
+
+    /// Any response counts: a server that answers `ping` with an error is still alive.
+    /// Distinguishes this connection from a later reconnect of the same server.
+    pub fn connection_id(&self) -> u64 {
+        self.connection_id
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.transport
+            .as_ref()
+            .map(|transport| transport.is_alive())
+            .unwrap_or(false)
+    }
+
+    pub fn stderr_excerpt(&self) -> Option<String> {
+        self.transport
+            .as_ref()
+            .and_then(|transport| transport.stderr_excerpt())
+    }
+
+    async fn request(&mut self, method: &str, params: Option<Value>) -> Result<Value> {
+        let transport = self
+            .transport
+            .as_mut()
+            .ok_or_else(|| anyhow!("Transport not available"))?;
+        let response = transport.send(JsonRpcRequest::new(method, params)).await?;
+
+        if let Some(error) = response.error {
+            return Err(anyhow!("MCP {} error: {}", method, error.message));
+        }
+
+        Ok(response.result.unwrap_or_else(|| json!({})))
+    }
+
+    /// Reconnects if needed and returns a handle that keeps working after the manager lock
+    /// is released.
+    pub async fn handle(&mut self) -> Result<McpClientHandle> {
+        let transport = self
+            .transport
+            .as_mut()
+            .ok_or_else(|| anyhow!("Transport not available"))?;
+        transport.prepare().await?;
+        Ok(McpClientHandle {
+            requester: transport.requester(),
+        })
+    }
+
+    pub async fn read_resource(&mut self, uri: &str) -> Result<Value> {
+        let transport = self
+            .transport
+            .as_mut()
+            .ok_or_else(|| anyhow!("Transport not available"))?;
+        let response = transport
+            .send(JsonRpcRequest::new(
+                "resources/read",
+                Some(json!({
+                    "uri": uri
+                })),
+            ))
+            .await?;
+
+        if let Some(error) = response.error {
+            return Err(anyhow!("MCP resources/read error: {}", error.message));
+        }
+
+        Ok(response.result.unwrap_or_else(|| json!({})))
+    }
+
+    pub async fn get_prompt(&mut self, name: &str, arguments: Value) -> Result<Value> {
+        self.request(
+            "prompts/get",
+            Some(json!({
+                "name": name,
+                "arguments": arguments
+            })),
+        )
+        .await
+    }
+
+    /// Asks the server to send `notifications/resources/updated` whenever `uri` changes.
+    pub async fn subscribe_resource(&mut self, uri: &str) -> Result<()> {
+        if self.capabilities.resource_subscribe != Some(true) {
+            return Err(anyhow!(
+                "MCP server '{}' does not support resource subscriptions",
+                self.name
+            ));
+        }
+        self.request("resources/subscribe", Some(json!({ "uri": uri })))
+            .await?;
+        self.subscriptions.insert(uri.to_string());
+        Ok(())
+    }
+
+    pub async fn unsubscribe_resource(&mut self, uri: &str) -> Result<()> {
+        if !self.subscriptions.remove(uri) {
+            return Ok(());
+        }
+        self.request("resources/unsubscribe", Some(json!({ "uri": uri })))
+            .await?;
+        Ok(())
+    }
+
+    pub fn is_subscribed(&self, uri: &str) -> bool {
+        self.subscriptions.contains(uri)
+    }
+
+    /// Tells the server its roots changed when a call runs under a different workspace
+    /// than the one it last saw.
+    pub async fn sync_roots(&mut self, workspace_root: &Path) -> Result<()> {
+        if self.roots_root.as_deref() == Some(workspace_root) {
+            return Ok(());
+        }
+        self.roots_root = Some(workspace_root.to_path_buf());
+        let transport = self
+            .transport
+            .as_mut()
+            .ok_or_else(|| anyhow!("Transport not available"))?;
+        transport
+            .notify(JsonRpcRequest::notification(
+                "notifications/roots/list_changed",
+                None,
+            ))
+            .await
+    }
+
+    pub fn list_tools(&self) -> Vec<Tool> {
+        self.tools.values().cloned().collect()
+    }
+
+    pub fn list_prompts(&self) -> Vec<Prompt> {
+        self.prompts.values().cloned().collect()
+    }
+
+    pub fn list_resources(&self) -> Vec<Resource> {
+        self.resources.values().cloned().collect()
+    }
+
+    pub fn list_resource_templates(&self) -> Vec<ResourceTemplate> {
+        self.resource_templates.values().cloned().collect()
+    }
+
+    pub async fn shutdown(mut self) -> Result<()> {
+        if let Some(mut transport) = self.transport.take() {
+            transport.shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Cloned out of the manager for requests that may run long, such as `tools/call`.
+#[derive(Clone)]
+pub struct McpClientHandle {
+    requester: Arc<dyn McpRequester>,
+}
+
+impl McpClientHandle {
+    async fn request(&self, method: &str, params: Option<Value>) -> Result<Value> {
+        let response = self
+            .requester
+            .send(JsonRpcRequest::new(method, params))
+            .await?;
+        if let Some(error) = response.error {
+            return Err(anyhow!("MCP {} error: {}", method, error.message));
+        }
+        Ok(response.result.unwrap_or_else(|| json!({})))
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        self.requester
+            .send(JsonRpcRequest::new("ping", None))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<Value> {
+        self.request(
+            "tools/call",
+            Some(json!({
+                "name": name,
+                "arguments": arguments
+            })),
+        )
+        .await
+    }
+}
+
+pub type McpStatusListener = Arc<dyn Fn(&McpServerStatus) + Send + Sync>;
+
+pub struct McpManager {
+    clients: HashMap<String, McpClient>,
+    message_handler: Arc<dyn McpMessageHandler>,
+    changes: Arc<McpChangeQueue>,
+    configs: HashMap<String, McpTransportConfig>,
+    statuses: HashMap<String, McpServerStatus>,
+    status_listener: Option<McpStatusListener>,
+}
+
+impl McpManager {
+    pub fn new() -> Self {
+        Self::with_message_handler(Arc::new(DefaultMessageHandler))
+    }
+
+    /// `handler` serves the requests servers send to us (sampling, roots, elicitation).
+    pub fn with_message_handler(message_handler: Arc<dyn McpMessageHandler>) -> Self {
+        let changes = Arc::new(McpChangeQueue::default());
+        Self {
+            clients: HashMap::new(),
+            message_handler: Arc::new(supervisor::ChangeTrackingHandler::new(
+                message_handler,
+                changes.clone(),
+            )),
+            changes,
+            configs: HashMap::new(),
+            statuses: HashMap::new(),
+            status_listener: None,
+        }
+    }
+
+    pub fn message_handler(&self) -> Arc<dyn McpMessageHandler> {
+        self.message_handler.clone()
+    }
+
+    pub fn changes(&self) -> Arc<McpChangeQueue> {
+        self.changes.clone()
+    }
+
+    /// Called with every status change, e.g. to forward it to the frontend.
+    pub fn set_status_listener(&mut self, listener: McpStatusListener) {
+        self.status_listener = Some(listener);
+    }
+
+    /// Adds a freshly connected client and remembers its config so the supervisor can
+    /// restart it.
+    pub async fn attach_client(
+        &mut self,
+        name: String,
+        config: McpTransportConfig,
+        client: McpClient,
+    ) -> Result<()> {
+        self.add_client(name.clone(), client).await?;
+        self.configs.insert(name.clone(), config);
+        self.record_status(&name, McpServerState::Connected, None, None, 0);
+        Ok(())
+    }
+
+    /// Remembers a server that failed to connect and hands it to the supervisor.
+    pub fn track_failed_connect(
+        &mut self,
+        name: &str,
+        config: McpTransportConfig,
+        error: &anyhow::Error,
+    ) {
+        let attempts = self
+            .statuses
+            .get(name)
+            .map(|status| status.restart_attempts + 1)
+            .unwrap_or(1);
+        self.configs.insert(name.to_string(), config);
+        self.record_status(
+            name,
+            McpServerState::Restarting,
+            Some(error.to_string()),
+            None,
+            attempts,
+        );
+        self.changes.wake();
+    }
+
+    /// Drops a connected server for a manual restart; the supervisor reconnects it.
+    pub async fn request_restart(&mut self, name: &str) -> Result<()> {
+        if !self.configs.contains_key(name) {
+            return Err(anyhow!("MCP server '{}' is not known", name));
+        }
+        if let Some(client) = self.clients.remove(name) {
+            client.shutdown().await?;
+        }
+        self.record_status(name, McpServerState::Restarting, None, None, 0);
+        self.changes.wake();
+        Ok(())
+    }
+
+    /// Disconnects a server for good: no restarts, status becomes `stopped`.
+    pub fn forget_server(&mut self, name: &str) -> Option<McpClient> {
+        self.configs.remove(name);
+        if self.statuses.contains_key(name) {
+            self.record_status(name, McpServerState::Stopped, None, None, 0);
+        }
+        self.clients.remove(name)
+    }
+
+    pub fn server_config(&self, name: &str) -> Option<McpTransportConfig> {
+        self.configs.get(name).cloned()
+    }
+
+    pub fn server_status(&self, name: &str) -> Option<&McpServerStatus> {
+        self.statuses.get(name)
+    }
+
+    pub fn server_statuses(&self) -> Vec<McpServerStatus> {
+        let mut statuses = self.statuses.values().cloned().collect::<Vec<_>>();
+        statuses.sort_by(|left, right| left.name.cmp(&right.name));
+        statuses
+    }
+
+    pub fn record_status(
+        &mut self,
+        name: &str,
+        state: McpServerState,
+        last_error: Option<String>,
+        stderr: Option<String>,
+        restart_attempts: u32,
+    ) {
+        let status = McpServerStatus {
+            name: name.to_string(),
+            state,
+            last_error,
+            stderr,
+            restart_attempts,
+            tool_count: self
+                .clients
+                .get(name)
+                .map(|client| client.tools.len())
+                .unwrap_or(0),
+            updated_at: chrono::Utc::now().to_rfc3339(),
+        };
+        if let Some(listener) = self.status_listener.as_ref() {
+            listener(&status);
+        }
+        self.statuses.insert(name.to_string(), status);
+    }
+
+    /// Re-sends the current status with an updated tool count after a list refresh.
+    pub fn republish_status(&mut self, name: &str) {
+        let Some(status) = self.statuses.get(name).cloned() else {
+            return;
+        };
+        self.record_status(
+            name,
+            status.state,
+            status.last_error,
+            status.stderr,
+            status.restart_attempts,
+        );
+    }
+
+    pub async fn add_client(&mut self, name: String, mut client: McpClient) -> Result<()> {
+        client.refresh_tools().await?;
+        client.refresh_prompts().await?;
+        client.refresh_resources().await?;
+        self.clients.insert(name, client);
+        Ok(())
+    }
+
+    pub fn get_client(&self, name: &str) -> Option<&McpClient> {
+        self.clients.get(name)
+    }
+
+    pub fn get_client_mut(&mut self, name: &str) -> Option<&mut McpClient> {
+        self.clients.get_mut(name)
+    }
+
+    pub fn remove_client(&mut self, name: &str) -> Option<McpClient> {
+        self.clients.remove(name)
+    }
+
+    pub fn list_all_tools(&self) -> Vec<(String, Tool)> {
+        let mut all_tools = Vec::new();
+        for client in self.clients.values() {
+            for tool in client.list_tools() {
+                all_tools.push((client.name.clone(), tool));
+            }
+        }
+        all_tools
+    }
+
+    pub fn list_clients(&self) -> Vec<(String, &McpClient)> {
+        self.clients
+            .values()
+            .map(|client| (client.name.clone(), client))
+            .collect()
+    }
+
+    pub async fn shutdown_all(&mut self) -> Result<()> {
+        let names = self.configs.keys().cloned().collect::<Vec<_>>();
+        for name in names {
+            self.forget_server(&name);
+        }
+        for (_name, client) in self.clients.drain() {
+            client.shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for McpManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
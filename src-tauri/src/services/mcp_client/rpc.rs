@@ -0,0 +1,344 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use super::{JsonRpcError, JsonRpcRequest, JsonRpcResponse};
+
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 60;
+pub const TOOL_CALL_TIMEOUT_SECS: u64 = 600;
+
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// Tool calls may legitimately run for minutes; everything else should answer quickly.
+pub fn request_timeout_for(method: &str) -> Duration {
+    match method {
+        "tools/call" => Duration::from_secs(TOOL_CALL_TIMEOUT_SECS),
+        _ => Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+    }
+}
+
+/// Answers requests and consumes notifications that the server sends to us.
+#[async_trait]
+pub trait McpMessageHandler: Send + Sync {
+    async fn handle_request(
+        &self,
+        server_name: &str,
+        method: &str,
+        params: Option<Value>,
+    ) -> std::result::Result<Value, JsonRpcError>;
+
+    async fn handle_notification(&self, server_name: &str, method: &str, params: Option<Value>);
+
+    /// Capabilities advertised in `initialize`; must match the requests this handler serves.
+    fn client_capabilities(&self) -> Value {
+        json!({})
+    }
+}
+
+/// Fallback handler: answers `ping`, rejects every other server request and logs notifications.
+pub struct DefaultMessageHandler;
+
+#[async_trait]
+impl McpMessageHandler for DefaultMessageHandler {
+    async fn handle_request(
+        &self,
+        _server_name: &str,
+        method: &str,
+        _params: Option<Value>,
+    ) -> std::result::Result<Value, JsonRpcError> {
+        match method {
+            "ping" => Ok(json!({})),
+            _ => Err(JsonRpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Method not supported by client: {}", method),
+            )),
+        }
+    }
+
+    async fn handle_notification(&self, server_name: &str, method: &str, _params: Option<Value>) {
+        eprintln!("[mcp] {} notification: {}", server_name, method);
+    }
+}
+
+/// Response slot of an in-flight request; dropping it forgets the slot.
+pub struct PendingRequest<'a> {
+    session: &'a RpcSession,
+    key: String,
+}
+
+impl PendingRequest<'_> {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+impl Drop for PendingRequest<'_> {
+    fn drop(&mut self) {
+        self.session.forget(&self.key);
+    }
+}
+
+fn id_key(id: &Value) -> String {
+    match id {
+        Value::String(value) => value.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// JSON-RPC session state shared by a transport and its reader task: assigns request ids,
+/// routes responses back to the waiting caller and dispatches server-initiated messages.
+pub struct RpcSession {
+    server_name: String,
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<String, oneshot::Sender<JsonRpcResponse>>>,
+    handler: Arc<dyn McpMessageHandler>,
+}
+
+impl RpcSession {
+    pub fn new(server_name: &str, handler: Arc<dyn McpMessageHandler>) -> Arc<Self> {
+        Arc::new(Self {
+            server_name: server_name.to_string(),
+            next_id: AtomicU64::new(1),
+            pending: Mutex::new(HashMap::new()),
+            handler,
+        })
+    }
+
+    pub fn server_name(&self) -> &str {
+        &self.server_name
+    }
+
+    pub fn client_capabilities(&self) -> Value {
+        self.handler.client_capabilities()
+    }
+
+    /// Gives the request a fresh numeric id and registers a slot for its response. The slot
+    /// is freed when the returned guard drops, including when the caller is cancelled.
+    pub fn register(
+        &self,
+        request: &mut JsonRpcRequest,
+    ) -> (PendingRequest<'_>, oneshot::Receiver<JsonRpcResponse>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        request.id = Some(json!(id));
+        let key = id.to_string();
+        let (sender, receiver) = oneshot::channel();
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(key.clone(), sender);
+        }
+        (PendingRequest { session: self, key }, receiver)
+    }
+
+    pub fn forget(&self, key: &str) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(key);
+        }
+    }
+
+    /// Drops every waiting request so callers fail fast once the connection is gone.
+    pub fn fail_all(&self) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.clear();
+        }
+    }
+
+    /// Waits for the response to `pending`, sending `notifications/cancelled` through
+    /// `outgoing` if the server does not answer in time.
+    pub async fn wait(
+        &self,
+        pending: PendingRequest<'_>,
+        method: &str,
+        receiver: oneshot::Receiver<JsonRpcResponse>,
+        outgoing: &mpsc::UnboundedSender<String>,
+    ) -> Result<JsonRpcResponse> {
+        let key = pending.key();
+        let timeout = request_timeout_for(method);
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err(anyhow!(
+                "MCP server '{}' closed the connection during {}",
+                self.server_name,
+                method
+            )),
+            Err(_) => {
+                self.forget(key);
+                let cancel = JsonRpcRequest::notification(
+                    "notifications/cancelled",
+                    Some(json!({
+                        "requestId": key.parse::<u64>().map(Value::from).unwrap_or(json!(key)),
+                        "reason": "timeout"
+                    })),
+                );
+                if let Ok(payload) = serde_json::to_string(&cancel) {
+                    let _ = outgoing.send(payload);
+                }
+                Err(anyhow!(
+                    "MCP server '{}' did not answer {} within {}s",
+                    self.server_name,
+                    method,
+                    timeout.as_secs()
+                ))
+            }
+        }
+    }
+
+    /// Routes one incoming message. Server-initiated requests are answered from a separate
+    /// task so a slow handler (e.g. one waiting for user approval) never stalls the reader.
+    pub fn dispatch(self: &Arc<Self>, message: Value, outgoing: &mpsc::UnboundedSender<String>) {
+        let method = message
+            .get("method")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let id = message.get("id").filter(|id| !id.is_null()).cloned();
+        let params = message.get("params").cloned();
+
+        match (method, id) {
+            (Some(method), Some(id)) => {
+                let session = self.clone();
+                let outgoing = outgoing.clone();
+                tokio::spawn(async move {
+                    let reply = match session
+                        .handler
+                        .handle_request(&session.server_name, &method, params)
+                        .await
+                    {
+                        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+                    };
+                    if let Ok(payload) = serde_json::to_string(&reply) {
+                        let _ = outgoing.send(payload);
+                    }
+                });
+            }
+            (Some(method), None) => {
+                let session = self.clone();
+                tokio::spawn(async move {
+                    session
+                        .handler
+                        .handle_notification(&session.server_name, &method, params)
+                        .await;
+                });
+            }
+            (None, Some(id)) => {
+                let sender = self
+                    .pending
+                    .lock()
+                    .ok()
+                    .and_then(|mut pending| pending.remove(&id_key(&id)));
+                match (sender, serde_json::from_value::<JsonRpcResponse>(message)) {
+                    (Some(sender), Ok(response)) => {
+                        let _ = sender.send(response);
+                    }
+                    (None, _) => {
+                        eprintln!(
+                            "[mcp] {} sent a response for unknown request {}",
+                            self.server_name, id
+                        );
+                    }
+                    (Some(_), Err(error)) => {
+                        eprintln!(
+                            "[mcp] {} sent an invalid response: {}",
+                            self.server_name, error
+                        );
+                    }
+                }
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Dispatches a raw payload, which may be a single message or a JSON-RPC batch.
+    pub fn dispatch_payload(
+        self: &Arc<Self>,
+        payload: &str,
+        outgoing: &mpsc::UnboundedSender<String>,
+    ) {
+        let message = match serde_json::from_str::<Value>(payload) {
+            Ok(message) => message,
+            Err(error) => {
+                eprintln!(
+                    "[mcp] {} sent a non-JSON message ({}): {}",
+                    self.server_name,
+                    error,
+                    payload.chars().take(200).collect::<String>()
+                );
+                return;
+            }
+        };
+        match message {
+            Value::Array(items) => {
+                for item in items {
+                    self.dispatch(item, outgoing);
+                }
+            }
+            single => self.dispatch(single, outgoing),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn routes_responses_by_id_and_answers_server_ping() {
+        let session = RpcSession::new("test", Arc::new(DefaultMessageHandler));
+        let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel();
+
+        let mut first = JsonRpcRequest::new("tools/list", None);
+        let mut second = JsonRpcRequest::new("prompts/list", None);
+        let (first_pending, first_rx) = session.register(&mut first);
+        let (second_pending, second_rx) = session.register(&mut second);
+        let (first_key, second_key) = (first_pending.key(), second_pending.key());
+        assert_ne!(first_key, second_key);
+
+        session.dispatch_payload(
+            &format!(
+                r#"[{{"jsonrpc":"2.0","id":{},"result":{{"prompts":[]}}}},{{"jsonrpc":"2.0","id":{},"result":{{"tools":[]}}}}]"#,
+                second_key, first_key
+            ),
+            &outgoing,
+        );
+        let first_response = first_rx.await.expect("first response");
+        let second_response = second_rx.await.expect("second response");
+        assert!(first_response.result.unwrap().get("tools").is_some());
+        assert!(second_response.result.unwrap().get("prompts").is_some());
+
+        session.dispatch_payload(
+            r#"{"jsonrpc":"2.0","id":"srv-1","method":"ping"}"#,
+            &outgoing,
+        );
+        let reply: Value = serde_json::from_str(&outgoing_rx.recv().await.expect("ping reply"))
+            .expect("valid json");
+        assert_eq!(reply["id"], json!("srv-1"));
+        assert_eq!(reply["result"], json!({}));
+    }
+
+    #[tokio::test]
+    async fn abandoned_requests_free_their_slot() {
+        let session = RpcSession::new("test", Arc::new(DefaultMessageHandler));
+        let (outgoing, _outgoing_rx) = mpsc::unbounded_channel();
+        let pending_count = || session.pending.lock().unwrap().len();
+
+        let mut request = JsonRpcRequest::new("ping", None);
+        let (pending, receiver) = session.register(&mut request);
+        assert_eq!(pending_count(), 1);
+        // The caller gives up (e.g. a health-check timeout) while the wait is in flight.
+        let waited = tokio::time::timeout(
+            Duration::from_millis(20),
+            session.wait(pending, "ping", receiver, &outgoing),
+        )
+        .await;
+        assert!(waited.is_err());
+        assert_eq!(pending_count(), 0);
+
+        let mut request = JsonRpcRequest::new("ping", None);
+        let (pending, _receiver) = session.register(&mut request);
+        drop(pending);
+        assert_eq!(pending_count(), 0);
+    }
+}
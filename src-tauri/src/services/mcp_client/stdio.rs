@@ -0,0 +1,296 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::sync::mpsc;
+
+use super::rpc::{McpMessageHandler, RpcSession};
+use super::{initialize_handshake, JsonRpcRequest, JsonRpcResponse, McpRequester, McpTransport};
+use crate::models::mcp::ServerCapabilities;
+use crate::utils::get_app_log_dir;
+
+const STDERR_TAIL_LINES: usize = 50;
+
+/// Recent stderr output kept in memory so connection errors can say why a server died.
+pub type StderrTail = Arc<Mutex<VecDeque<String>>>;
+
+pub fn stderr_log_path(server_name: &str) -> Result<PathBuf> {
+    let safe_name: String = server_name
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    Ok(get_app_log_dir()?.join(format!("mcp-{}.log", safe_name)))
+}
+
+fn sanitize_stderr_line(line: &str) -> String {
+    let lower = line.to_ascii_lowercase();
+    for marker in [
+        "token",
+        "authorization",
+        "api_key",
+        "apikey",
+        "password",
+        "cookie",
+    ] {
+        if lower.contains(marker) {
+            return "[redacted]".to_string();
+        }
+    }
+    line.to_string()
+}
+
+async fn run_stdout_reader(
+    stdout: ChildStdout,
+    session: Arc<RpcSession>,
+    outgoing: mpsc::UnboundedSender<String>,
+    alive: Arc<AtomicBool>,
+) {
+    // MCP stdio framing: one JSON-RPC message per line, no embedded newlines.
+    let mut lines = BufReader::new(stdout).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                let line = line.trim();
+                if !line.is_empty() {
+                    session.dispatch_payload(line, &outgoing);
+                }
+            }
+            Ok(None) => break,
+            Err(error) => {
+                eprintln!(
+                    "[mcp] {} stdout read failed: {}",
+                    session.server_name(),
+                    error
+                );
+                break;
+            }
+        }
+    }
+    alive.store(false, Ordering::SeqCst);
+    session.fail_all();
+}
+
+async fn run_stdin_writer(mut stdin: ChildStdin, mut outgoing: mpsc::UnboundedReceiver<String>) {
+    while let Some(payload) = outgoing.recv().await {
+        if stdin.write_all(payload.as_bytes()).await.is_err()
+            || stdin.write_all(b"\n").await.is_err()
+            || stdin.flush().await.is_err()
+        {
+            break;
+        }
+    }
+}
+
+async fn run_stderr_reader(stderr: ChildStderr, server_name: String, tail: StderrTail) {
+    let log_path = stderr_log_path(&server_name).ok();
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let line = sanitize_stderr_line(&line);
+        if let Some(path) = log_path.as_ref() {
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+        if let Ok(mut tail) = tail.lock() {
+            if tail.len() >= STDERR_TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+    }
+}
+
+/// The part of a stdio connection that requests go through; shared with `McpClientHandle`s.
+struct StdioLink {
+    session: Arc<RpcSession>,
+    outgoing: mpsc::UnboundedSender<String>,
+    alive: Arc<AtomicBool>,
+    stderr_tail: StderrTail,
+}
+
+impl StdioLink {
+    fn stderr_tail_text(&self) -> String {
+        self.stderr_tail
+            .lock()
+            .map(|tail| tail.iter().cloned().collect::<Vec<_>>().join("\n"))
+            .unwrap_or_default()
+    }
+
+    fn ensure_alive(&self) -> Result<()> {
+        if self.alive.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let excerpt = self.stderr_tail_text();
+        if excerpt.is_empty() {
+            Err(anyhow!(
+                "MCP server '{}' is not running",
+                self.session.server_name()
+            ))
+        } else {
+            Err(anyhow!(
+                "MCP server '{}' is not running. Last stderr:\n{}",
+                self.session.server_name(),
+                excerpt
+            ))
+        }
+    }
+
+    fn post(&self, payload: String) -> Result<()> {
+        self.outgoing.send(payload).map_err(|_| {
+            anyhow!(
+                "MCP server '{}' stdin is closed",
+                self.session.server_name()
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl McpRequester for StdioLink {
+    async fn send(&self, mut request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        self.ensure_alive()?;
+        let (pending, receiver) = self.session.register(&mut request);
+        let payload = serde_json::to_string(&request)?;
+        self.post(payload)?;
+        let result = self
+            .session
+            .wait(pending, &request.method, receiver, &self.outgoing)
+            .await;
+        if result.is_err() {
+            self.ensure_alive()?;
+        }
+        result
+    }
+}
+
+/// MCP stdio transport: newline-delimited JSON-RPC over the child's stdin/stdout, with a
+/// reader task that demultiplexes responses by id and serves server-initiated messages.
+pub struct StdioTransport {
+    child: Option<Child>,
+    link: Arc<StdioLink>,
+}
+
+impl StdioTransport {
+    pub fn spawn(
+        server_name: &str,
+        command: &str,
+        args: &[String],
+        env: &HashMap<String, String>,
+        cwd: Option<&Path>,
+        handler: Arc<dyn McpMessageHandler>,
+    ) -> Result<Self> {
+        let mut command_builder = Command::new(command);
+        command_builder.args(args).envs(env);
+        if let Some(cwd) = cwd {
+            if !cwd.is_dir() {
+                return Err(anyhow!(
+                    "Working directory for MCP server '{}' does not exist: {}",
+                    server_name,
+                    cwd.display()
+                ));
+            }
+            command_builder.current_dir(cwd);
+        }
+        let mut child = command_builder
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start MCP server '{}': {}", server_name, e))?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("Stdin not available"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("Stdout not available"))?;
+        let stderr = child
+            .stderr
+            .take()
+            .ok_or_else(|| anyhow!("Stderr not available"))?;
+
+        let session = RpcSession::new(server_name, handler);
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        let alive = Arc::new(AtomicBool::new(true));
+        let stderr_tail: StderrTail = Arc::new(Mutex::new(VecDeque::new()));
+
+        tokio::spawn(run_stdin_writer(stdin, outgoing_rx));
+        tokio::spawn(run_stdout_reader(
+            stdout,
+            session.clone(),
+            outgoing.clone(),
+            alive.clone(),
+        ));
+        tokio::spawn(run_stderr_reader(
+            stderr,
+            server_name.to_string(),
+            stderr_tail.clone(),
+        ));
+
+        Ok(Self {
+            child: Some(child),
+            link: Arc::new(StdioLink {
+                session,
+                outgoing,
+                alive,
+                stderr_tail,
+            }),
+        })
+    }
+}
+
+#[async_trait]
+impl McpTransport for StdioTransport {
+    async fn send(&mut self, request: JsonRpcRequest) -> Result<JsonRpcResponse> {
+        self.link.send(request).await
+    }
+
+    async fn notify(&mut self, request: JsonRpcRequest) -> Result<()> {
+        self.link.ensure_alive()?;
+        let payload = serde_json::to_string(&request)?;
+        self.link.post(payload)
+    }
+
+    async fn initialize(&mut self) -> Result<ServerCapabilities> {
+        let client_capabilities = self.link.session.client_capabilities();
+        initialize_handshake(self, client_capabilities).await
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.link.alive.store(false, Ordering::SeqCst);
+        self.link.session.fail_all();
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill().await;
+            let _ = child.wait().await;
+        }
+        Ok(())
+    }
+
+    fn requester(&self) -> Arc<dyn McpRequester> {
+        self.link.clone()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.link.alive.load(Ordering::SeqCst)
+    }
+
+    fn stderr_excerpt(&self) -> Option<String> {
+        Some(self.link.stderr_tail_text()).filter(|excerpt| !excerpt.is_empty())
+    }
+}
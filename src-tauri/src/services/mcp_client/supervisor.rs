@@ -0,0 +1,481 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::Notify;
+
+use super::rpc::McpMessageHandler;
+use super::{connect_client, JsonRpcError, McpManager};
+use crate::models::mcp::McpServerState;
+
+const HEALTH_CHECK_INTERVAL_SECS: u64 = 30;
+const PING_TIMEOUT_SECS: u64 = 10;
+const RESTART_BASE_DELAY_SECS: u64 = 2;
+const RESTART_MAX_DELAY_SECS: u64 = 300;
+const MAX_RESTART_ATTEMPTS: u32 = 8;
+
+/// Servers whose tool/prompt/resource lists went stale, plus a wake-up signal for the
+/// supervisor loop.
+#[derive(Default)]
+pub struct McpChangeQueue {
+    stale: Mutex<HashSet<String>>,
+    wake: Notify,
+}
+
+impl McpChangeQueue {
+    pub fn mark_stale(&self, server_name: &str) {
+        if let Ok(mut stale) = self.stale.lock() {
+            stale.insert(server_name.to_string());
+        }
+        self.wake.notify_one();
+    }
+
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    fn drain(&self) -> Vec<String> {
+        self.stale
+            .lock()
+            .map(|mut stale| stale.drain().collect())
+            .unwrap_or_default()
+    }
+
+    async fn wait(&self, timeout: Duration) {
+        let _ = tokio::time::timeout(timeout, self.wake.notified()).await;
+    }
+}
+
+/// Wraps the app's handler so `*/list_changed` notifications queue a refresh.
+pub(crate) struct ChangeTrackingHandler {
+    inner: Arc<dyn McpMessageHandler>,
+    changes: Arc<McpChangeQueue>,
+}
+
+impl ChangeTrackingHandler {
+    pub fn new(inner: Arc<dyn McpMessageHandler>, changes: Arc<McpChangeQueue>) -> Self {
+        Self { inner, changes }
+    }
+}
+
+#[async_trait]
+impl McpMessageHandler for ChangeTrackingHandler {
+    async fn handle_request(
+        &self,
+        server_name: &str,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<Value, JsonRpcError> {
+        self.inner.handle_request(server_name, method, params).await
+    }
+
+    async fn handle_notification(&self, server_name: &str, method: &str, params: Option<Value>) {
+        if matches!(
+            method,
+            "notifications/tools/list_changed"
+                | "notifications/prompts/list_changed"
+                | "notifications/resources/list_changed"
+        ) {
+            self.changes.mark_stale(server_name);
+        }
+        self.inner
+            .handle_notification(server_name, method, params)
+            .await
+    }
+
+    fn client_capabilities(&self) -> Value {
+        self.inner.client_capabilities()
+    }
+}
+
+fn restart_delay(attempts: u32) -> Duration {
+    let exponent = attempts.saturating_sub(1).min(16);
+    Duration::from_secs(
+        RESTART_BASE_DELAY_SECS
+            .saturating_mul(1u64 << exponent)
+            .min(RESTART_MAX_DELAY_SECS),
+    )
+}
+
+/// Starts the background loop that pings connected servers, restarts dead ones with
+/// exponential backoff and reloads lists after `list_changed` notifications.
+pub fn spawn_mcp_supervisor(state: Arc<tokio::sync::Mutex<McpManager>>) {
+    tauri::async_runtime::spawn(async move {
+        let changes = state.lock().await.changes();
+        let mut next_restart = HashMap::<String, Instant>::new();
+        loop {
+            let now = Instant::now();
+            let wait = next_restart
+                .values()
+                .map(|due| due.saturating_duration_since(now))
+                .min()
+                .unwrap_or(Duration::MAX)
+                .min(Duration::from_secs(HEALTH_CHECK_INTERVAL_SECS));
+            changes.wait(wait).await;
+
+            refresh_stale_servers(&state, changes.drain()).await;
+            check_health(&state).await;
+            restart_due_servers(&state, &mut next_restart).await;
+        }
+    });
+}
+
+async fn refresh_stale_servers(state: &tokio::sync::Mutex<McpManager>, servers: Vec<String>) {
+    for server_name in servers {
+        let mut manager = state.lock().await;
+        let Some(client) = manager.get_client_mut(&server_name) else {
+            continue;
+        };
+        if let Err(error) = client.refresh_lists().await {
+            eprintln!("[mcp] {} list refresh failed: {}", server_name, error);
+        }
+        manager.republish_status(&server_name);
+    }
+}
+
+async fn check_health(state: &tokio::sync::Mutex<McpManager>) {
+    // Pings go through cloned handles so a slow server never holds the manager lock.
+    let probes = {
+        let mut manager = state.lock().await;
+        let server_names = manager
+            .list_clients()
+            .into_iter()
+            .map(|(server_name, _)| server_name)
+            .collect::<Vec<_>>();
+        let mut probes = Vec::new();
+        for server_name in server_names {
+            let Some(client) = manager.get_client_mut(&server_name) else {
+                continue;
+            };
+            let connection_id = client.connection_id();
+            let handle = if client.is_alive() {
+                client
+                    .handle()
+                    .await
+                    .map_err(|error| format!("Reconnect failed: {}", error))
+            } else {
+                Err("Server process exited".to_string())
+            };
+            probes.push((server_name, connection_id, handle));
+        }
+        probes
+    };
+    if probes.is_empty() {
+        return;
+    }
+
+    let results = futures_util::future::join_all(probes.into_iter().map(
+        |(server_name, connection_id, handle)| async move {
+            let failure = match handle {
+                Err(reason) => Some(reason),
+                Ok(handle) => {
+                    match tokio::time::timeout(
+                        Duration::from_secs(PING_TIMEOUT_SECS),
+                        handle.ping(),
+                    )
+                    .await
+                    {
+                        Ok(Ok(())) => None,
+                        Ok(Err(error)) => Some(format!("Ping failed: {}", error)),
+                        Err(_) => Some(format!("Ping timed out after {}s", PING_TIMEOUT_SECS)),
+                    }
+                }
+            };
+            (server_name, connection_id, failure)
+        },
+    ))
+    .await;
+
+    let mut manager = state.lock().await;
+    for (server_name, connection_id, failure) in results {
+        let Some(reason) = failure else {
+            continue;
+        };
+        // Restarted or disconnected while the ping was in flight.
+        let current = manager
+            .get_client(&server_name)
+            .map(|client| client.connection_id());
+        if current != Some(connection_id) {
+            continue;
+        }
+        eprintln!("[mcp] {} is unhealthy: {}", server_name, reason);
+        let mut stderr = None;
+        if let Some(client) = manager.remove_client(&server_name) {
+            stderr = client.stderr_excerpt();
+            let _ = client.shutdown().await;
+        }
+        manager.record_status(
+            &server_name,
+            McpServerState::Restarting,
+            Some(reason),
+            stderr,
+            0,
+        );
+    }
+}
+
+async fn restart_due_servers(
+    state: &tokio::sync::Mutex<McpManager>,
+    next_restart: &mut HashMap<String, Instant>,
+) {
+    let (candidates, handler) = {
+        let manager = state.lock().await;
+        let candidates = manager
+            .server_statuses()
+            .into_iter()
+            .filter(|status| status.state == McpServerState::Restarting)
+            .filter_map(|status| {
+                manager
+                    .server_config(&status.name)
+                    .map(|config| (status, config))
+            })
+            .collect::<Vec<_>>();
+        (candidates, manager.message_handler())
+    };
+    next_restart.retain(|name, _| candidates.iter().any(|(status, _)| &status.name == name));
+
+    for (status, config) in candidates {
+        let due = next_restart
+            .get(&status.name)
+            .map(|due| *due <= Instant::now())
+            .unwrap_or(true);
+        // attempts == 0 means a fresh failure or a manual restart: go right away.
+        if !due && status.restart_attempts > 0 {
+            continue;
+        }
+
+        let attempts = status.restart_attempts + 1;
+        let result = connect_client(&status.name, &config, handler.clone()).await;
+        let mut manager = state.lock().await;
+        if manager.server_config(&status.name).is_none() {
+            // Disconnected while we were reconnecting.
+            next_restart.remove(&status.name);
+            continue;
+        }
+        match result {
+            Ok(client) => match manager
+                .attach_client(status.name.clone(), config, client)
+                .await
+            {
+                Ok(()) => {
+                    eprintln!(
+                        "[mcp] {} restarted after {} attempt(s)",
+                        status.name, attempts
+                    );
+                    next_restart.remove(&status.name);
+                }
+                Err(error) => {
+                    schedule_retry(&mut manager, next_restart, &status.name, attempts, error);
+                }
+            },
+            Err(error) => {
+                schedule_retry(&mut manager, next_restart, &status.name, attempts, error);
+            }
+        }
+    }
+}
+
+fn schedule_retry(
+    manager: &mut McpManager,
+    next_restart: &mut HashMap<String, Instant>,
+    server_name: &str,
+    attempts: u32,
+    error: anyhow::Error,
+) {
+    let state = if attempts >= MAX_RESTART_ATTEMPTS {
+        next_restart.remove(server_name);
+        McpServerState::Failed
+    } else {
+        next_restart.insert(
+            server_name.to_string(),
+            Instant::now() + restart_delay(attempts),
+        );
+        McpServerState::Restarting
+    };
+    let stderr = manager
+        .server_status(server_name)
+        .and_then(|status| status.stderr.clone());
+    manager.record_status(
+        server_name,
+        state,
+        Some(error.to_string()),
+        stderr,
+        attempts,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::mcp::ServerCapabilities;
+    use crate::services::mcp_client::{
+        JsonRpcRequest, JsonRpcResponse, McpClient, McpRequester, McpTransport,
+    };
+    use anyhow::anyhow;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers pings only once `gate` is notified, failing them unless `healthy`.
+    struct GatedLink {
+        healthy: bool,
+        gate: Notify,
+        pings: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl McpRequester for GatedLink {
+        async fn send(&self, _request: JsonRpcRequest) -> anyhow::Result<JsonRpcResponse> {
+            self.pings.fetch_add(1, Ordering::SeqCst);
+            self.gate.notified().await;
+            if !self.healthy {
+                return Err(anyhow!("broken pipe"));
+            }
+            Ok(serde_json::from_value(
+                json!({ "jsonrpc": "2.0", "id": 1, "result": {} }),
+            )?)
+        }
+    }
+
+    struct GatedTransport {
+        link: Arc<GatedLink>,
+    }
+
+    #[async_trait]
+    impl McpTransport for GatedTransport {
+        async fn send(&mut self, request: JsonRpcRequest) -> anyhow::Result<JsonRpcResponse> {
+            self.link.send(request).await
+        }
+
+        async fn notify(&mut self, _request: JsonRpcRequest) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn initialize(&mut self) -> anyhow::Result<ServerCapabilities> {
+            Ok(ServerCapabilities {
+                tools: Some(false),
+                prompts: Some(false),
+                resources: Some(false),
+                resource_subscribe: Some(false),
+            })
+        }
+
+        async fn shutdown(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn requester(&self) -> Arc<dyn McpRequester> {
+            self.link.clone()
+        }
+    }
+
+    async fn add_gated_client(
+        manager: &mut McpManager,
+        name: &str,
+        healthy: bool,
+    ) -> Arc<GatedLink> {
+        let link = Arc::new(GatedLink {
+            healthy,
+            gate: Notify::new(),
+            pings: AtomicUsize::new(0),
+        });
+        let transport = Box::new(GatedTransport { link: link.clone() });
+        let client = McpClient::new(name.to_string(), transport).await.unwrap();
+        manager.add_client(name.to_string(), client).await.unwrap();
+        link
+    }
+
+    async fn wait_for_pings(links: &[&Arc<GatedLink>]) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while links
+                .iter()
+                .any(|link| link.pings.load(Ordering::SeqCst) == 0)
+            {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("health check should ping every server");
+    }
+
+    #[tokio::test]
+    async fn health_checks_ping_without_holding_the_manager_lock() {
+        let state = Arc::new(tokio::sync::Mutex::new(McpManager::new()));
+        let (healthy, broken) = {
+            let mut manager = state.lock().await;
+            (
+                add_gated_client(&mut manager, "healthy", true).await,
+                add_gated_client(&mut manager, "broken", false).await,
+            )
+        };
+
+        let check = tokio::spawn({
+            let state = state.clone();
+            async move { check_health(&state).await }
+        });
+        wait_for_pings(&[&healthy, &broken]).await;
+        // Both pings are still waiting, yet tool calls and the UI can use the manager.
+        drop(
+            tokio::time::timeout(Duration::from_secs(1), state.lock())
+                .await
+                .expect("manager lock should be free while pings are in flight"),
+        );
+
+        healthy.gate.notify_one();
+        broken.gate.notify_one();
+        check.await.unwrap();
+
+        let manager = state.lock().await;
+        assert!(manager.get_client("healthy").is_some());
+        assert!(manager.get_client("broken").is_none());
+        let status = manager.server_status("broken").unwrap();
+        assert_eq!(status.state, McpServerState::Restarting);
+        assert!(status
+            .last_error
+            .as_deref()
+            .unwrap()
+            .contains("broken pipe"));
+    }
+
+    #[tokio::test]
+    async fn a_stale_ping_failure_leaves_a_reconnected_server_alone() {
+        let state = Arc::new(tokio::sync::Mutex::new(McpManager::new()));
+        let broken = add_gated_client(&mut *state.lock().await, "server", false).await;
+
+        let check = tokio::spawn({
+            let state = state.clone();
+            async move { check_health(&state).await }
+        });
+        wait_for_pings(&[&broken]).await;
+        let reconnected = {
+            let mut manager = state.lock().await;
+            manager.remove_client("server");
+            add_gated_client(&mut manager, "server", true).await;
+            manager.get_client("server").unwrap().connection_id()
+        };
+
+        broken.gate.notify_one();
+        check.await.unwrap();
+
+        let manager = state.lock().await;
+        assert_eq!(
+            manager
+                .get_client("server")
+                .map(|client| client.connection_id()),
+            Some(reconnected)
+        );
+        assert!(manager.server_status("server").is_none());
+    }
+
+    #[test]
+    fn restart_delay_doubles_up_to_the_cap() {
+        assert_eq!(restart_delay(1), Duration::from_secs(2));
+        assert_eq!(restart_delay(2), Duration::from_secs(4));
+        assert_eq!(restart_delay(5), Duration::from_secs(32));
+        assert_eq!(
+            restart_delay(30),
+            Duration::from_secs(RESTART_MAX_DELAY_SECS)
+        );
+    }
+}
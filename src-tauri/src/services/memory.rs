@@ -1,6 +1,8 @@
-use crate::models::config::Config;
+use crate::models::config::{Config, LlmWireProtocol};
+use crate::services::llm_registry::{env_value, first_non_empty, resolve_llm_provider};
 use chrono::Utc;
 use mem0_rust::config::{OpenAIEmbedderConfig, OpenAILLMConfig};
+use mem0_rust::embeddings::{create_embedder, Embedder};
 use mem0_rust::{
     AddOptions, EmbedderConfig, GetAllOptions, LLMConfig, Memory, MemoryConfig, Message,
     MockEmbedderConfig, SearchOptions,
@@ -8,12 +10,8 @@ use mem0_rust::{
 use serde_json::{json, Value};
 use sqlx::SqlitePool;
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
-const DEFAULT_MEMORY_API_BASE: &str = "http://localhost:8000";
-const DEFAULT_GLM_API_BASE: &str = "https://open.bigmodel.cn/api/paas/v4";
-const DEFAULT_ARK_API_BASE: &str = "https://ark.cn-beijing.volces.com/api/v3";
-const DEFAULT_MINIMAX_API_BASE: &str = "https://api.minimaxi.com/v1";
-const DEFAULT_OPENAI_API_BASE: &str = "https://api.openai.com/v1";
 const MEMORY_COLLECTION_NAME: &str = "petool_memory";
 const MEMORY_SEARCH_LIMIT: usize = 8;
 const MEMORY_SEARCH_THRESHOLD: f32 = 0.38;
@@ -26,14 +24,6 @@ struct MemoryHit {
     score: f32,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum TextModelProvider {
-    Glm,
-    Doubao,
-    MiniMax,
-    OpenAi,
-}
-
 pub async fn prepare_memory_prompt_and_remember_turn(
     pool: &SqlitePool,
     config: &Config,
@@ -263,7 +253,7 @@ async fn build_memory(config: &Config, model: &str) -> Result<Memory, String> {
         })
     });
 
-    let embedder_config = resolve_embedder_config(model, credentials.as_ref());
+    let embedder_config = resolve_embedder_config(credentials.as_ref());
 
     let memory_config = MemoryConfig {
         embedder: embedder_config,
@@ -275,129 +265,55 @@ async fn build_memory(config: &Config, model: &str) -> Result<Memory, String> {
     Memory::new(memory_config).await.map_err(|e| e.to_string())
 }
 
-fn resolve_embedder_config(model: &str, credentials: Option<&LlmCredentials>) -> EmbedderConfig {
-    let embedding_model = env_value(MEMORY_EMBEDDING_MODEL_ENV);
-    if let (Some(embed_model), Some(credential)) = (embedding_model, credentials) {
-        return EmbedderConfig::OpenAI(OpenAIEmbedderConfig {
+/// The embedder memory uses for `model`, shared with workspace semantic search.
+pub fn build_embedder(config: &Config, model: &str) -> Result<Arc<dyn Embedder>, String> {
+    let credentials = resolve_llm_credentials(config, model);
+    create_embedder(&resolve_embedder_config(credentials.as_ref())).map_err(|e| e.to_string())
+}
+
+fn resolve_embedder_config(credentials: Option<&LlmCredentials>) -> EmbedderConfig {
+    let Some(credential) = credentials else {
+        return EmbedderConfig::Mock(MockEmbedderConfig { dimensions: 384 });
+    };
+    // Local providers (Ollama, llama.cpp) serve `/v1/embeddings` on the same base as chat.
+    let (embedding_model, dimensions) = match env_value(MEMORY_EMBEDDING_MODEL_ENV) {
+        Some(embed_model) => (Some(embed_model), None),
+        None => (
+            credential.embedding_model.clone(),
+            credential.embedding_dimensions,
+        ),
+    };
+    match embedding_model {
+        Some(embed_model) => EmbedderConfig::OpenAI(OpenAIEmbedderConfig {
             api_key: Some(credential.api_key.clone()),
             model: embed_model,
-            dimensions: None,
+            dimensions,
             base_url: Some(credential.api_base.clone()),
-        });
-    }
-
-    if detect_text_model_provider(model) == TextModelProvider::OpenAi {
-        if let Some(credential) = credentials {
-            return EmbedderConfig::OpenAI(OpenAIEmbedderConfig {
-                api_key: Some(credential.api_key.clone()),
-                model: "text-embedding-3-small".to_string(),
-                dimensions: Some(1536),
-                base_url: Some(credential.api_base.clone()),
-            });
-        }
+        }),
+        None => EmbedderConfig::Mock(MockEmbedderConfig { dimensions: 384 }),
     }
-
-    EmbedderConfig::Mock(MockEmbedderConfig { dimensions: 384 })
 }
 
 #[derive(Debug, Clone)]
 struct LlmCredentials {
     api_key: String,
     api_base: String,
+    embedding_model: Option<String>,
+    embedding_dimensions: Option<usize>,
 }
 
+/// mem0 only speaks the OpenAI chat protocol, so Anthropic-style providers run without an LLM.
 fn resolve_llm_credentials(config: &Config, model: &str) -> Option<LlmCredentials> {
-    if let Some(token) = config
-        .petool_token
-        .as_deref()
-        .filter(|value| !value.trim().is_empty())
-    {
-        let base = config
-            .petool_api_base
-            .clone()
-            .unwrap_or_else(|| DEFAULT_MEMORY_API_BASE.to_string())
-            .trim_end_matches('/')
-            .to_string();
-        return Some(LlmCredentials {
-            api_key: token.to_string(),
-            api_base: format!("{}/v1", base),
-        });
+    let provider = resolve_llm_provider(config, model).ok()?;
+    if provider.protocol != LlmWireProtocol::OpenaiChat {
+        return None;
     }
-
-    match detect_text_model_provider(model) {
-        TextModelProvider::Glm => first_non_empty(vec![
-            config.api_key.clone(),
-            env_value("GLM_API_KEY"),
-            env_value("OPENAI_API_KEY"),
-        ])
-        .map(|api_key| LlmCredentials {
-            api_key,
-            api_base: config
-                .api_base
-                .clone()
-                .unwrap_or_else(|| DEFAULT_GLM_API_BASE.to_string()),
-        }),
-        TextModelProvider::Doubao => first_non_empty(vec![
-            config.ark_api_key.clone(),
-            env_value("ARK_API_KEY"),
-            env_value("DOUBAO_API_KEY"),
-            config.api_key.clone(),
-        ])
-        .map(|api_key| LlmCredentials {
-            api_key,
-            api_base: config
-                .ark_api_base
-                .clone()
-                .unwrap_or_else(|| DEFAULT_ARK_API_BASE.to_string()),
-        }),
-        TextModelProvider::MiniMax => first_non_empty(vec![
-            config.minimax_api_key.clone(),
-            env_value("MINIMAX_API_KEY"),
-            env_value("OPENAI_API_KEY"),
-            env_value("ANTHROPIC_API_KEY"),
-        ])
-        .map(|api_key| LlmCredentials {
-            api_key,
-            api_base: DEFAULT_MINIMAX_API_BASE.to_string(),
-        }),
-        TextModelProvider::OpenAi => {
-            first_non_empty(vec![env_value("OPENAI_API_KEY"), config.api_key.clone()]).map(
-                |api_key| LlmCredentials {
-                    api_key,
-                    api_base: env_value("OPENAI_API_BASE")
-                        .unwrap_or_else(|| DEFAULT_OPENAI_API_BASE.to_string()),
-                },
-            )
-        }
-    }
-}
-
-fn detect_text_model_provider(model: &str) -> TextModelProvider {
-    let normalized = model.trim().to_ascii_lowercase();
-    if normalized.starts_with("gpt-") {
-        return TextModelProvider::OpenAi;
-    }
-    if normalized.starts_with("minimax-") || normalized.starts_with("abab") {
-        return TextModelProvider::MiniMax;
-    }
-    if normalized.starts_with("doubao-") || normalized.starts_with("ep-") {
-        return TextModelProvider::Doubao;
-    }
-    TextModelProvider::Glm
-}
-
-fn env_value(name: &str) -> Option<String> {
-    std::env::var(name)
-        .ok()
-        .map(|value| value.trim().to_string())
-        .filter(|value| !value.is_empty())
-}
-
-fn first_non_empty(values: Vec<Option<String>>) -> Option<String> {
-    values
-        .into_iter()
-        .flatten()
-        .find(|value| !value.trim().is_empty())
+    Some(LlmCredentials {
+        api_key: provider.api_key,
+        api_base: provider.api_base,
+        embedding_model: provider.embedding_model,
+        embedding_dimensions: provider.embedding_dimensions,
+    })
 }
 
 fn resolve_memory_user_id(config: &Config) -> String {
@@ -1,10 +1,19 @@
 pub mod browser;
+pub mod code_index;
 pub mod database;
 pub mod desktop;
 pub mod llm;
+pub mod llm_registry;
+pub mod local_llm;
 pub mod mcp_client;
 pub mod memory;
 pub mod node_runtime;
 pub mod pdf_parse;
+pub mod sandbox;
 pub mod scheduler;
+pub mod secret_store;
 pub mod skill_manager;
+pub mod sse;
+#[cfg(test)]
+pub(crate) mod test_http;
+pub mod usage;
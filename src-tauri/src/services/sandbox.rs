@@ -0,0 +1,399 @@
+use crate::models::config::SandboxConfig;
+use crate::utils::{get_app_config_dir, get_app_data_dir};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Environment variables whose names contain one of these are treated as credentials and
+/// kept out of sandboxed commands unless allowlisted.
+const SECRET_ENV_MARKERS: &[&str] = &[
+    "KEY",
+    "TOKEN",
+    "SECRET",
+    "PASSWORD",
+    "PASSWD",
+    "CREDENTIAL",
+    "AUTH",
+    "COOKIE",
+    "SESSION",
+    "PRIVATE",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxReason {
+    /// The conversation (or the global default) opted in.
+    Conversation,
+    /// Scheduler and heartbeat runs have nobody approving commands, so they always are.
+    BackgroundRun,
+}
+
+impl SandboxReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Conversation => "conversation",
+            Self::BackgroundRun => "background_run",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SandboxPolicy {
+    pub reason: SandboxReason,
+    pub allow_network: bool,
+    pub max_memory_mb: u64,
+    pub max_cpu_seconds: u64,
+    pub max_process_seconds: u64,
+    pub writable_paths: Vec<PathBuf>,
+    pub hidden_paths: Vec<PathBuf>,
+    pub env_allowlist: Vec<String>,
+    pub bwrap_path: Option<String>,
+}
+
+impl SandboxPolicy {
+    pub fn from_config(config: &SandboxConfig, reason: SandboxReason) -> Self {
+        Self {
+            reason,
+            allow_network: config.allow_network,
+            max_memory_mb: config.max_memory_mb,
+            max_cpu_seconds: config.max_cpu_seconds,
+            max_process_seconds: config.max_process_seconds,
+            writable_paths: config
+                .writable_paths
+                .iter()
+                .map(|path| expand_home(path))
+                .collect(),
+            hidden_paths: config
+                .hidden_paths
+                .iter()
+                .map(|path| expand_home(path))
+                .chain(app_private_paths())
+                .collect(),
+            env_allowlist: config.env_allowlist.clone(),
+            bwrap_path: config
+                .bwrap_path
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string),
+        }
+    }
+
+    /// Attached to tool results so the model can tell sandbox denials from real failures.
+    pub fn describe(&self, workspace_root: &Path) -> Value {
+        let writable = std::iter::once(workspace_root)
+            .chain(self.writable_paths.iter().map(PathBuf::as_path))
+            .map(|path| path.to_string_lossy().to_string())
+            .chain(std::iter::once("/tmp (private)".to_string()))
+            .collect::<Vec<_>>();
+        json!({
+            "reason": self.reason.as_str(),
+            "network": self.allow_network,
+            "writable_paths": writable,
+            "max_memory_mb": self.max_memory_mb,
+            "max_cpu_seconds": self.max_cpu_seconds
+        })
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    let path = path.trim();
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest.trim_start_matches(['/', '\\'])),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// The config dir holds API keys and the data dir the conversation database; neither is
+/// ever visible to sandboxed commands, whatever `hidden_paths` says.
+fn app_private_paths() -> Vec<PathBuf> {
+    [get_app_config_dir(), get_app_data_dir()]
+        .into_iter()
+        .filter_map(Result::ok)
+        .collect()
+}
+
+tokio::task_local! {
+    static BACKGROUND_SANDBOX: bool;
+}
+
+/// Runs `run` (a scheduler or heartbeat run, or one started by such a run) so that its
+/// commands run sandboxed whatever the conversation setting says. Other runs on the same
+/// conversation are unaffected, and a run inside a sandboxed run cannot leave it.
+pub async fn with_background_sandbox<F: Future>(sandboxed: bool, run: F) -> F::Output {
+    let sandboxed = sandboxed || background_sandbox_active();
+    BACKGROUND_SANDBOX.scope(sandboxed, run).await
+}
+
+pub fn background_sandbox_active() -> bool {
+    BACKGROUND_SANDBOX
+        .try_with(|sandboxed| *sandboxed)
+        .unwrap_or(false)
+}
+
+/// The shell invocation for `command` in `cwd`: `sh -lc` (PowerShell on Windows), or
+/// `sh -c` inside bubblewrap when a policy applies, so no login profile runs in the sandbox.
+pub fn shell_command(
+    command: &str,
+    cwd: &Path,
+    workspace_root: &Path,
+    policy: Option<&SandboxPolicy>,
+) -> Result<Command, String> {
+    match policy {
+        Some(policy) => sandboxed_shell_command(command, cwd, workspace_root, policy),
+        None => Ok(plain_shell_command(command, cwd)),
+    }
+}
+
+fn plain_shell_command(command: &str, cwd: &Path) -> Command {
+    let mut process = if cfg!(target_os = "windows") {
+        let wrapped_command = format!(
+            "$OutputEncoding = [Console]::OutputEncoding = [System.Text.UTF8Encoding]::new($false); [Console]::InputEncoding = [System.Text.UTF8Encoding]::new($false); chcp 65001 > $null; {}",
+            command
+        );
+        let mut process = Command::new("powershell");
+        process.args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            &wrapped_command,
+        ]);
+        process
+    } else {
+        let mut process = Command::new("sh");
+        process.args(["-lc", command]);
+        process
+    };
+    process.current_dir(cwd);
+    process
+}
+
+#[cfg(target_os = "linux")]
+fn sandboxed_shell_command(
+    command: &str,
+    cwd: &Path,
+    workspace_root: &Path,
+    policy: &SandboxPolicy,
+) -> Result<Command, String> {
+    let bwrap = find_bwrap(policy).ok_or_else(|| unavailable_message(policy))?;
+    let mut process = Command::new(bwrap);
+    process.args([
+        "--die-with-parent",
+        "--new-session",
+        "--unshare-pid",
+        "--unshare-ipc",
+        "--unshare-uts",
+        "--unshare-cgroup-try",
+    ]);
+    if !policy.allow_network {
+        process.arg("--unshare-net");
+    }
+    process.args([
+        "--ro-bind",
+        "/",
+        "/",
+        "--dev",
+        "/dev",
+        "--proc",
+        "/proc",
+        "--tmpfs",
+        "/tmp",
+    ]);
+    // Later mounts win: writable paths first so hidden paths inside them stay masked, then
+    // writable paths below a hidden path again so the workspace stays reachable.
+    let writable_paths = std::iter::once(workspace_root)
+        .chain(policy.writable_paths.iter().map(PathBuf::as_path))
+        .filter(|path| path.exists())
+        .collect::<Vec<_>>();
+    for writable in &writable_paths {
+        process.arg("--bind").arg(writable).arg(writable);
+    }
+    for hidden in &policy.hidden_paths {
+        if hidden.is_dir() {
+            process.arg("--tmpfs").arg(hidden);
+        } else if hidden.exists() {
+            process.arg("--ro-bind").arg("/dev/null").arg(hidden);
+        }
+    }
+    for writable in writable_paths.iter().filter(|writable| {
+        policy
+            .hidden_paths
+            .iter()
+            .any(|hidden| writable.starts_with(hidden))
+    }) {
+        process.arg("--bind").arg(writable).arg(writable);
+    }
+    process.arg("--chdir").arg(cwd);
+    process.arg("--").args([
+        "sh",
+        "-c",
+        &resource_limit_script(policy),
+        "petool-sandbox",
+        command,
+    ]);
+    process.current_dir(cwd);
+    scrub_secret_env(&mut process, &policy.env_allowlist);
+    Ok(process)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sandboxed_shell_command(
+    _command: &str,
+    _cwd: &Path,
+    _workspace_root: &Path,
+    policy: &SandboxPolicy,
+) -> Result<Command, String> {
+    Err(unavailable_message(policy))
+}
+
+fn unavailable_message(policy: &SandboxPolicy) -> String {
+    let requirement = if cfg!(target_os = "linux") {
+        "Sandboxed command execution needs bubblewrap ('bwrap'); install it or set sandbox.bwrap_path"
+    } else {
+        "Sandboxed command execution is only available on Linux"
+    };
+    match policy.reason {
+        SandboxReason::Conversation => format!(
+            "{}, or turn the sandbox off for this conversation.",
+            requirement
+        ),
+        SandboxReason::BackgroundRun => format!(
+            "{}. Scheduled and heartbeat runs always execute commands in the sandbox.",
+            requirement
+        ),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn find_bwrap(policy: &SandboxPolicy) -> Option<PathBuf> {
+    let name = policy.bwrap_path.as_deref().unwrap_or("bwrap");
+    let candidate = Path::new(name);
+    if candidate.components().count() > 1 {
+        return candidate.is_file().then(|| candidate.to_path_buf());
+    }
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    })
+}
+
+/// `ulimit` inside the sandbox, then the user's command as `$1` so it needs no quoting.
+fn resource_limit_script(policy: &SandboxPolicy) -> String {
+    let mut script = String::new();
+    if policy.max_memory_mb > 0 {
+        script.push_str(&format!(
+            "ulimit -v {} || exit 126; ",
+            policy.max_memory_mb.saturating_mul(1024)
+        ));
+    }
+    if policy.max_cpu_seconds > 0 {
+        script.push_str(&format!(
+            "ulimit -t {} || exit 126; ",
+            policy.max_cpu_seconds
+        ));
+    }
+    script.push_str("exec sh -c \"$1\"");
+    script
+}
+
+fn is_secret_env_name(name: &str, allowlist: &[String]) -> bool {
+    let upper = name.to_ascii_uppercase();
+    SECRET_ENV_MARKERS
+        .iter()
+        .any(|marker| upper.contains(marker))
+        && !allowlist
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(name))
+}
+
+fn scrub_secret_env(process: &mut Command, allowlist: &[String]) {
+    for (name, _) in std::env::vars_os() {
+        if is_secret_env_name(&name.to_string_lossy(), allowlist) {
+            process.env_remove(&name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrubs_secret_names_and_builds_limit_script() {
+        let allowlist = vec!["GIT_ASKPASS_KEY".to_string()];
+        assert!(is_secret_env_name("OPENAI_API_KEY", &allowlist));
+        assert!(is_secret_env_name("github_token", &allowlist));
+        assert!(!is_secret_env_name("git_askpass_key", &allowlist));
+        assert!(!is_secret_env_name("PATH", &allowlist));
+
+        let mut policy =
+            SandboxPolicy::from_config(&SandboxConfig::default(), SandboxReason::BackgroundRun);
+        assert_eq!(
+            resource_limit_script(&policy),
+            "ulimit -v 2097152 || exit 126; ulimit -t 300 || exit 126; exec sh -c \"$1\""
+        );
+        policy.max_memory_mb = 0;
+        policy.max_cpu_seconds = 0;
+        assert_eq!(resource_limit_script(&policy), "exec sh -c \"$1\"");
+    }
+
+    #[tokio::test]
+    async fn background_sandbox_covers_only_its_run_and_nested_runs() {
+        assert!(!background_sandbox_active());
+        let (outer, nested) = with_background_sandbox(true, async {
+            let nested = with_background_sandbox(false, async { background_sandbox_active() });
+            (background_sandbox_active(), nested.await)
+        })
+        .await;
+        assert!(outer && nested);
+        // A concurrent run on the same conversation is a different task.
+        let other = tokio::spawn(async { background_sandbox_active() });
+        assert!(!with_background_sandbox(true, other).await.unwrap());
+        assert!(!with_background_sandbox(false, async { background_sandbox_active() }).await);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn hides_paths_inside_the_workspace_and_rebinds_a_hidden_workspace() {
+        let root =
+            std::env::temp_dir().join(format!("petool-sandbox-test-{}", uuid::Uuid::new_v4()));
+        let workspace = root.join("workspace");
+        let secrets = workspace.join(".secrets");
+        let hidden_parent = root.join("private");
+        let nested_workspace = hidden_parent.join("workspace");
+        std::fs::create_dir_all(&secrets).unwrap();
+        std::fs::create_dir_all(&nested_workspace).unwrap();
+
+        let mut policy =
+            SandboxPolicy::from_config(&SandboxConfig::default(), SandboxReason::Conversation);
+        policy.bwrap_path = Some("/bin/sh".to_string());
+        policy.hidden_paths = vec![secrets.clone(), hidden_parent.clone()];
+        assert!(
+            SandboxPolicy::from_config(&SandboxConfig::default(), SandboxReason::Conversation)
+                .hidden_paths
+                .iter()
+                .any(|path| get_app_config_dir().is_ok_and(|config_dir| &config_dir == path))
+        );
+
+        let mount_position = |process: &Command, flag: &str, target: &Path| {
+            let args = process.get_args().collect::<Vec<_>>();
+            args.windows(2)
+                .rposition(|pair| pair[0] == flag && Path::new(pair[1]) == target)
+                .unwrap()
+        };
+        let process = sandboxed_shell_command("true", &workspace, &workspace, &policy).unwrap();
+        assert!(
+            mount_position(&process, "--tmpfs", &secrets)
+                > mount_position(&process, "--bind", &workspace)
+        );
+        let process =
+            sandboxed_shell_command("true", &nested_workspace, &nested_workspace, &policy).unwrap();
+        assert!(
+            mount_position(&process, "--bind", &nested_workspace)
+                > mount_position(&process, "--tmpfs", &hidden_parent)
+        );
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
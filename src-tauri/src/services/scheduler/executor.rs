@@ -1,15 +1,16 @@
 use crate::commands::chat::{
-    run_agent_turn_background, BackgroundAgentRunRequest, BackgroundAgentRunResult,
+    insert_message, run_agent_turn_background, BackgroundAgentRunRequest,
+    BackgroundAgentRunResult,
 };
 use crate::commands::mcp::McpState;
 use crate::commands::skills::SkillManagerState;
 use std::sync::Arc;
 use super::manager::SchedulerManager;
+use crate::services::llm::LlmUsage;
+use crate::services::usage::UsageSource;
 use crate::state::AppState;
-use chrono::Utc;
 use serde_json::json;
 use sqlx::SqlitePool;
-use uuid::Uuid;
 
 use super::models::{SchedulerJob, SchedulerRunSource, SchedulerRunStatus, SchedulerSessionTarget};
 
@@ -27,6 +28,9 @@ pub struct SchedulerExecutionResult {
     pub summary: Option<String>,
     pub output_text: Option<String>,
     pub detail_json: serde_json::Value,
+    pub usage: LlmUsage,
+    /// Provider outage or rate limit that outlasted retries and failover; not the job's fault.
+    pub transient: bool,
 }
 
 fn summarize_text(raw: &str) -> String {
@@ -49,30 +53,7 @@ async fn insert_summary_message(
     conversation_id: &str,
     summary: &str,
 ) -> Result<(), String> {
-    let message_id = Uuid::new_v4().to_string();
-    let now = Utc::now().to_rfc3339();
-
-    sqlx::query(
-        "INSERT INTO messages (id, conversation_id, role, content, created_at, tool_calls, reasoning) VALUES (?, ?, ?, ?, ?, ?, ?)",
-    )
-    .bind(&message_id)
-    .bind(conversation_id)
-    .bind("assistant")
-    .bind(summary)
-    .bind(&now)
-    .bind(Option::<String>::None)
-    .bind(Option::<String>::None)
-    .execute(pool)
-    .await
-    .map_err(|e| e.to_string())?;
-
-    sqlx::query("UPDATE conversations SET updated_at = ? WHERE id = ?")
-        .bind(&now)
-        .bind(conversation_id)
-        .execute(pool)
-        .await
-        .map_err(|e| e.to_string())?;
-
+    insert_message(pool, conversation_id, "assistant", summary, None, None).await?;
     Ok(())
 }
 
@@ -103,6 +84,16 @@ pub async fn execute_scheduler_job(
             model_override: job.model_override.clone(),
             persist_main_context,
             tool_whitelist: Some(job.tool_whitelist.iter().cloned().collect()),
+            sandboxed: true,
+            tool_profile: job.tool_profile.clone(),
+            usage_source: if matches!(source, SchedulerRunSource::Heartbeat) {
+                UsageSource::Heartbeat
+            } else {
+                UsageSource::Scheduler
+            },
+            sub_agent: None,
+            live_timeline: false,
+            use_memory: false,
         },
     )
     .await;
@@ -115,6 +106,9 @@ pub async fn execute_scheduler_job(
             tool_calls,
             blocked_tools,
             guard_stopped,
+            usage,
+            model,
+            ..
         }) => {
             let summary = summarize_text(&content);
             if matches!(job.session_target, SchedulerSessionTarget::Isolated)
@@ -139,8 +133,12 @@ pub async fn execute_scheduler_job(
                             "toolCalls": tool_calls,
                             "blockedTools": blocked_tools,
                             "guardStopped": guard_stopped,
+                            "usage": usage,
+                            "model": model,
                             "failedToWriteSummary": true
                         }),
+                        usage,
+                        transient: false,
                     };
                 }
             }
@@ -156,21 +154,32 @@ pub async fn execute_scheduler_job(
                     "toolCalls": tool_calls,
                     "blockedTools": blocked_tools,
                     "guardStopped": guard_stopped,
+                    "usage": usage,
+                    "model": model,
                     "sessionTarget": job.session_target.as_str(),
                     "source": source.as_str()
                 }),
+                usage,
+                transient: false,
+            }
+        }
+        Err(error) => {
+            let transient = error.transient;
+            let error = error.message;
+            SchedulerExecutionResult {
+                status: SchedulerRunStatus::Error,
+                error: Some(error.clone()),
+                summary: None,
+                output_text: None,
+                detail_json: json!({
+                    "error": error,
+                    "transient": transient,
+                    "sessionTarget": job.session_target.as_str(),
+                    "source": source.as_str()
+                }),
+                usage: LlmUsage::default(),
+                transient,
             }
         }
-        Err(error) => SchedulerExecutionResult {
-            status: SchedulerRunStatus::Error,
-            error: Some(error.clone()),
-            summary: None,
-            output_text: None,
-            detail_json: json!({
-                "error": error,
-                "sessionTarget": job.session_target.as_str(),
-                "source": source.as_str()
-            }),
-        },
     }
 }
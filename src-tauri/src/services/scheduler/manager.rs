@@ -10,6 +10,7 @@ use tauri::{AppHandle, Emitter};
 use uuid::Uuid;
 
 use crate::models::config::{AutomationConfig, Config};
+use crate::services::llm::LlmUsage;
 use crate::utils::{load_config, save_config};
 
 use super::executor::{execute_scheduler_job, SchedulerExecutionContext};
@@ -19,8 +20,8 @@ use super::models::{
     SchedulerSessionTarget, SchedulerStatus,
 };
 use super::schedule::{
-    clamp_timeout_seconds, compute_next_after_result, compute_next_run_at, to_rfc3339,
-    validate_schedule_fields,
+    clamp_timeout_seconds, compute_next_after_result, compute_next_run_at,
+    compute_transient_at_retry, to_rfc3339, validate_schedule_fields,
 };
 use super::store;
 
@@ -38,6 +39,7 @@ fn default_job_tool_whitelist() -> Vec<String> {
         "sessions_history".to_string(),
         "sessions_send".to_string(),
         "sessions_spawn".to_string(),
+        "sessions_run_status".to_string(),
         "workspace_write_file".to_string(),
         "workspace_edit_file".to_string(),
         "workspace_apply_patch".to_string(),
@@ -232,6 +234,7 @@ impl SchedulerManager {
             } else {
                 config.automation.heartbeat.tool_whitelist.clone()
             },
+            tool_profile: None,
             run_timeout_seconds: 600,
             delete_after_run: false,
             next_run_at: None,
@@ -375,6 +378,7 @@ impl SchedulerManager {
                 summary: Some("session busy".to_string()),
                 output_text: None,
                 detail_json: json!({ "error": "session_busy" }),
+                usage: LlmUsage::default(),
                 created_at: Utc::now().to_rfc3339(),
             };
             store::insert_run(&self.pool, &run).await?;
@@ -401,15 +405,26 @@ impl SchedulerManager {
                 );
                 job.updated_at = ended_at.to_rfc3339();
 
+                // A provider outage that outlasted retries and failover is not the job's fault,
+                // so it neither escalates the error backoff nor burns a one-shot job.
                 if result.status == SchedulerRunStatus::Error {
-                    job.consecutive_errors += 1;
+                    if !result.transient {
+                        job.consecutive_errors += 1;
+                    }
                 } else {
                     job.consecutive_errors = 0;
                 }
 
                 if job.schedule_kind == SchedulerScheduleKind::At {
+                    let transient_retry_at = if result.transient {
+                        compute_transient_at_retry(&job, ended_at)
+                    } else {
+                        None
+                    };
                     if result.status == SchedulerRunStatus::Ok && job.delete_after_run {
                         should_delete_job = true;
+                    } else if let Some(retry_at) = transient_retry_at {
+                        job.next_run_at = Some(retry_at.to_rfc3339());
                     } else {
                         job.enabled = false;
                         job.next_run_at = None;
@@ -447,6 +462,7 @@ impl SchedulerManager {
             summary: result.summary.clone(),
             output_text: result.output_text,
             detail_json: result.detail_json,
+            usage: result.usage,
             created_at: ended_at.to_rfc3339(),
         };
         store::insert_run(&self.pool, &run).await?;
@@ -594,6 +610,12 @@ impl SchedulerManager {
             tool_whitelist: input
                 .tool_whitelist
                 .unwrap_or_else(default_job_tool_whitelist),
+            tool_profile: input
+                .tool_profile
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string),
             run_timeout_seconds: clamp_timeout_seconds(input.run_timeout_seconds),
             delete_after_run: input.delete_after_run.unwrap_or(is_at_schedule),
             next_run_at: None,
@@ -721,6 +743,13 @@ impl SchedulerManager {
         if let Some(tool_whitelist) = patch.tool_whitelist {
             job.tool_whitelist = tool_whitelist;
         }
+        if let Some(tool_profile) = patch.tool_profile {
+            job.tool_profile = tool_profile
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string);
+        }
         if patch.run_timeout_seconds.is_some() {
             job.run_timeout_seconds = clamp_timeout_seconds(patch.run_timeout_seconds);
         }
@@ -813,6 +842,7 @@ impl SchedulerManager {
             } else {
                 config.automation.heartbeat.tool_whitelist.clone()
             },
+            tool_profile: None,
             run_timeout_seconds: 600,
             delete_after_run: false,
             next_run_at: None,
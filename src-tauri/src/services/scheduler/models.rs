@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::services::llm::LlmUsage;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum SchedulerScheduleKind {
@@ -123,6 +125,9 @@ pub struct SchedulerJob {
     pub model_override: Option<String>,
     pub workspace_directory: Option<String>,
     pub tool_whitelist: Vec<String>,
+    /// Tool profile sent to the model; `None` falls back to the default profile.
+    #[serde(default)]
+    pub tool_profile: Option<String>,
     pub run_timeout_seconds: i64,
     pub delete_after_run: bool,
     pub next_run_at: Option<String>,
@@ -153,6 +158,8 @@ pub struct SchedulerRun {
     pub summary: Option<String>,
     pub output_text: Option<String>,
     pub detail_json: serde_json::Value,
+    #[serde(default)]
+    pub usage: LlmUsage,
     pub created_at: String,
 }
 
@@ -182,6 +189,8 @@ pub struct SchedulerJobCreateInput {
     pub model_override: Option<String>,
     pub workspace_directory: Option<String>,
     pub tool_whitelist: Option<Vec<String>>,
+    #[serde(default)]
+    pub tool_profile: Option<String>,
     pub run_timeout_seconds: Option<i64>,
     pub delete_after_run: Option<bool>,
 }
@@ -203,6 +212,8 @@ pub struct SchedulerJobPatchInput {
     pub model_override: Option<Option<String>>,
     pub workspace_directory: Option<Option<String>>,
     pub tool_whitelist: Option<Vec<String>>,
+    #[serde(default)]
+    pub tool_profile: Option<Option<String>>,
     pub run_timeout_seconds: Option<i64>,
     pub delete_after_run: Option<bool>,
 }
@@ -6,6 +6,8 @@ use std::str::FromStr;
 use super::models::{SchedulerJob, SchedulerRunStatus, SchedulerScheduleKind};
 
 pub const DEFAULT_RUN_TIMEOUT_SECONDS: i64 = 600;
+/// How long a one-shot job keeps retrying after its slot when the LLM provider is down.
+pub const TRANSIENT_AT_RETRY_WINDOW_MINUTES: i64 = 60;
 
 pub fn parse_rfc3339(value: &str) -> Option<DateTime<Utc>> {
     DateTime::parse_from_rfc3339(value)
@@ -148,6 +150,24 @@ pub fn compute_next_after_result(
     }
 }
 
+/// Retry time for a one-shot job that failed on a transient provider error, backing off
+/// with the age of the outage. `None` once the retry window has passed.
+pub fn compute_transient_at_retry(
+    job: &SchedulerJob,
+    ended_at: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    if job.schedule_kind != SchedulerScheduleKind::At || !job.enabled {
+        return None;
+    }
+    let scheduled_at = job.schedule_at.as_deref().and_then(parse_rfc3339)?;
+    let elapsed = ended_at.signed_duration_since(scheduled_at);
+    if elapsed > Duration::minutes(TRANSIENT_AT_RETRY_WINDOW_MINUTES) {
+        return None;
+    }
+    let step = 1 + elapsed.num_minutes().max(0) / 5;
+    Some(ended_at + error_backoff_duration(step))
+}
+
 pub fn to_rfc3339(value: Option<DateTime<Utc>>) -> Option<String> {
     value.map(|dt| dt.to_rfc3339())
 }
@@ -174,6 +194,7 @@ mod tests {
             model_override: None,
             workspace_directory: None,
             tool_whitelist: vec![],
+            tool_profile: None,
             run_timeout_seconds: 600,
             delete_after_run: false,
             next_run_at: None,
@@ -235,4 +256,25 @@ mod tests {
         assert_eq!(error_backoff_duration(4), Duration::minutes(15));
         assert_eq!(error_backoff_duration(5), Duration::minutes(60));
     }
+
+    #[test]
+    fn at_schedule_retries_transient_errors_within_window() {
+        let mut job = build_job(SchedulerScheduleKind::At);
+        job.schedule_at = Some("2026-02-19T10:00:00Z".to_string());
+
+        let soon = parse_rfc3339("2026-02-19T10:01:00Z").expect("valid time");
+        assert_eq!(
+            compute_transient_at_retry(&job, soon),
+            Some(soon + Duration::seconds(30))
+        );
+
+        let later = parse_rfc3339("2026-02-19T10:07:00Z").expect("valid time");
+        assert_eq!(
+            compute_transient_at_retry(&job, later),
+            Some(later + Duration::minutes(1))
+        );
+
+        let expired = parse_rfc3339("2026-02-19T11:30:00Z").expect("valid time");
+        assert_eq!(compute_transient_at_retry(&job, expired), None);
+    }
 }
@@ -1,6 +1,8 @@
 use serde_json::Value;
 use sqlx::{Row, SqlitePool};
 
+use crate::services::llm::LlmUsage;
+
 use super::models::{
     SchedulerJob, SchedulerRun, SchedulerRunSource, SchedulerRunStatus, SchedulerScheduleKind,
     SchedulerSessionTarget,
@@ -50,6 +52,7 @@ fn row_to_job(row: &sqlx::sqlite::SqliteRow) -> Result<SchedulerJob, String> {
         model_override: row.get("model_override"),
         workspace_directory: row.get("workspace_directory"),
         tool_whitelist: parse_whitelist(&row.get::<String, _>("tool_whitelist")),
+        tool_profile: row.get("tool_profile"),
         run_timeout_seconds: row.get("run_timeout_seconds"),
         delete_after_run: row.get::<i64, _>("delete_after_run") != 0,
         next_run_at: row.get("next_run_at"),
@@ -91,6 +94,12 @@ fn row_to_run(row: &sqlx::sqlite::SqliteRow) -> Result<SchedulerRun, String> {
         summary: row.get("summary"),
         output_text: row.get("output_text"),
         detail_json,
+        usage: LlmUsage {
+            prompt_tokens: row.get::<i64, _>("prompt_tokens").max(0) as u64,
+            completion_tokens: row.get::<i64, _>("completion_tokens").max(0) as u64,
+            reasoning_tokens: row.get::<i64, _>("reasoning_tokens").max(0) as u64,
+            cached_tokens: row.get::<i64, _>("cached_tokens").max(0) as u64,
+        },
         created_at: row.get("created_at"),
     })
 }
@@ -100,10 +109,10 @@ pub async fn insert_job(pool: &SqlitePool, job: &SchedulerJob) -> Result<(), Str
         "INSERT INTO scheduler_jobs (
             id, name, description, enabled, schedule_kind, schedule_at, every_ms, cron_expr, timezone,
             session_target, target_conversation_id, message, model_override, workspace_directory,
-            tool_whitelist, run_timeout_seconds, delete_after_run, next_run_at, running_at,
-            last_run_at, last_status, last_error, last_duration_ms, consecutive_errors,
+            tool_whitelist, tool_profile, run_timeout_seconds, delete_after_run, next_run_at,
+            running_at, last_run_at, last_status, last_error, last_duration_ms, consecutive_errors,
             created_at, updated_at
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
     )
     .bind(&job.id)
     .bind(&job.name)
@@ -120,6 +129,7 @@ pub async fn insert_job(pool: &SqlitePool, job: &SchedulerJob) -> Result<(), Str
     .bind(&job.model_override)
     .bind(&job.workspace_directory)
     .bind(encode_whitelist(&job.tool_whitelist))
+    .bind(&job.tool_profile)
     .bind(job.run_timeout_seconds)
     .bind(bool_to_i64(job.delete_after_run))
     .bind(&job.next_run_at)
@@ -154,6 +164,7 @@ pub async fn update_job(pool: &SqlitePool, job: &SchedulerJob) -> Result<(), Str
             model_override = ?,
             workspace_directory = ?,
             tool_whitelist = ?,
+            tool_profile = ?,
             run_timeout_seconds = ?,
             delete_after_run = ?,
             next_run_at = ?,
@@ -180,6 +191,7 @@ pub async fn update_job(pool: &SqlitePool, job: &SchedulerJob) -> Result<(), Str
     .bind(&job.model_override)
     .bind(&job.workspace_directory)
     .bind(encode_whitelist(&job.tool_whitelist))
+    .bind(&job.tool_profile)
     .bind(job.run_timeout_seconds)
     .bind(bool_to_i64(job.delete_after_run))
     .bind(&job.next_run_at)
@@ -263,8 +275,9 @@ pub async fn insert_run(pool: &SqlitePool, run: &SchedulerRun) -> Result<(), Str
         "INSERT INTO scheduler_runs (
             id, source, job_id, job_name_snapshot, target_conversation_id, session_target,
             triggered_at, started_at, ended_at, status, error, summary, output_text,
-            detail_json, created_at
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            detail_json, prompt_tokens, completion_tokens, reasoning_tokens, cached_tokens,
+            created_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
     )
     .bind(&run.id)
     .bind(run.source.as_str())
@@ -280,6 +293,10 @@ pub async fn insert_run(pool: &SqlitePool, run: &SchedulerRun) -> Result<(), Str
     .bind(&run.summary)
     .bind(&run.output_text)
     .bind(serde_json::to_string(&run.detail_json).unwrap_or_else(|_| "{}".to_string()))
+    .bind(run.usage.prompt_tokens as i64)
+    .bind(run.usage.completion_tokens as i64)
+    .bind(run.usage.reasoning_tokens as i64)
+    .bind(run.usage.cached_tokens as i64)
     .bind(&run.created_at)
     .execute(pool)
     .await
@@ -0,0 +1,100 @@
+/// Secrets live in the OS keyring (Credential Manager, Keychain, Secret Service) and are
+/// referenced from the config as `${secret:NAME}`, so they never land in config.json.
+const KEYRING_SERVICE: &str = "petool";
+const SECRET_REF_PREFIX: &str = "${secret:";
+
+fn validate_secret_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-' | '.'))
+    {
+        return Err(format!(
+            "Invalid secret name '{}': use letters, digits, '_', '-' or '.'",
+            name
+        ));
+    }
+    Ok(name)
+}
+
+fn entry(name: &str) -> Result<keyring::Entry, String> {
+    let name = validate_secret_name(name)?;
+    keyring::Entry::new(KEYRING_SERVICE, &format!("secret:{}", name)).map_err(|e| e.to_string())
+}
+
+pub fn set_secret(name: &str, value: &str) -> Result<(), String> {
+    entry(name)?.set_password(value).map_err(|e| e.to_string())
+}
+
+pub fn get_secret(name: &str) -> Result<Option<String>, String> {
+    match entry(name)?.get_password() {
+        Ok(value) => Ok(Some(value)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+pub fn delete_secret(name: &str) -> Result<(), String> {
+    match entry(name)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+fn substitute_secret_refs(
+    value: &str,
+    lookup: impl Fn(&str) -> Result<Option<String>, String>,
+) -> Result<String, String> {
+    let mut rendered = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find(SECRET_REF_PREFIX) {
+        let after_prefix = &rest[start + SECRET_REF_PREFIX.len()..];
+        let Some(end) = after_prefix.find('}') else {
+            break;
+        };
+        let name = &after_prefix[..end];
+        let secret = lookup(name)?.ok_or_else(|| format!("Secret '{}' is not set", name))?;
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&secret);
+        rest = &after_prefix[end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Replaces every `${secret:NAME}` in `value` with the stored secret.
+pub fn resolve_secret_refs(value: &str) -> Result<String, String> {
+    if !value.contains(SECRET_REF_PREFIX) {
+        return Ok(value.to_string());
+    }
+    substitute_secret_refs(value, get_secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitutes_embedded_and_whole_value_references() {
+        let lookup = |name: &str| -> Result<Option<String>, String> {
+            Ok(match name {
+                "github" => Some("ghp_123".to_string()),
+                _ => None,
+            })
+        };
+        assert_eq!(
+            substitute_secret_refs("Bearer ${secret:github}", lookup).unwrap(),
+            "Bearer ghp_123"
+        );
+        assert_eq!(
+            substitute_secret_refs("${secret:github}", lookup).unwrap(),
+            "ghp_123"
+        );
+        assert_eq!(
+            substitute_secret_refs("plain ${secret:unterminated", lookup).unwrap(),
+            "plain ${secret:unterminated"
+        );
+        assert!(substitute_secret_refs("${secret:missing}", lookup).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::{anyhow, Result};
+use futures_util::StreamExt;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    pub event: String,
+    pub data: String,
+    pub id: Option<String>,
+}
+
+impl SseEvent {
+    /// Events without an explicit name default to `message`, as in the SSE spec.
+    pub fn is_message(&self) -> bool {
+        self.event.is_empty() || self.event == "message"
+    }
+}
+
+/// Incremental `text/event-stream` decoder. Bytes are buffered until a full line arrived so
+/// multi-byte characters split across network chunks stay intact.
+#[derive(Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    event: String,
+    data: Vec<String>,
+    id: Option<String>,
+}
+
+impl SseDecoder {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(position) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=position).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: std::mem::take(&mut self.event),
+                        data: self.data.join("\n"),
+                        id: self.id.clone(),
+                    });
+                }
+                self.event.clear();
+                self.data.clear();
+                continue;
+            }
+            if line.starts_with(':') {
+                continue;
+            }
+
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event = value.to_string(),
+                "data" => self.data.push(value.to_string()),
+                "id" => self.id = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        events
+    }
+
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+}
+
+/// Feeds every event of an SSE response body to `on_event` until the stream ends.
+/// Returns the last event id seen so a reconnect can resume with `Last-Event-ID`.
+pub async fn read_sse_events(
+    response: reqwest::Response,
+    mut on_event: impl FnMut(SseEvent),
+) -> Result<Option<String>> {
+    let mut decoder = SseDecoder::default();
+    let mut bytes = Box::pin(response.bytes_stream());
+    while let Some(chunk) = bytes.next().await {
+        let chunk = chunk.map_err(|e| anyhow!("SSE stream interrupted: {}", e))?;
+        for event in decoder.push(&chunk) {
+            on_event(event);
+        }
+    }
+    Ok(decoder.last_event_id().map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_named_events_across_chunk_boundaries() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder
+            .push(b"event: endpoint\r\ndata: /messages?session")
+            .is_empty());
+        let events =
+            decoder.push(b"Id=1\r\n\r\n: keep-alive\n\nid: 7\ndata: {\"a\":\ndata: 1}\n\n");
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: "endpoint".to_string(),
+                    data: "/messages?sessionId=1".to_string(),
+                    id: None,
+                },
+                SseEvent {
+                    event: String::new(),
+                    data: "{\"a\":\n1}".to_string(),
+                    id: Some("7".to_string()),
+                },
+            ]
+        );
+        assert!(events[1].is_message());
+        assert_eq!(decoder.last_event_id(), Some("7"));
+    }
+}
@@ -0,0 +1,178 @@
+//! Minimal HTTP/1.1 server for transport tests. Every connection carries one request and is
+//! closed after the response, so a streamed body ends when its sender is dropped.
+
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone)]
+pub(crate) struct TestRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl TestRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub(crate) enum TestBody {
+    Fixed(String),
+    /// Written chunk by chunk as it arrives, for SSE streams that stay open.
+    Stream(mpsc::UnboundedReceiver<String>),
+}
+
+pub(crate) struct TestResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: TestBody,
+}
+
+impl TestResponse {
+    pub fn new(status: u16, content_type: &str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body: TestBody::Fixed(body.into()),
+        }
+    }
+
+    pub fn json(status: u16, body: serde_json::Value) -> Self {
+        Self::new(status, "application/json", body.to_string())
+    }
+
+    pub fn stream(receiver: mpsc::UnboundedReceiver<String>) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), "text/event-stream".to_string())],
+            body: TestBody::Stream(receiver),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+pub(crate) struct TestServer {
+    pub base_url: String,
+    requests: Arc<Mutex<Vec<TestRequest>>>,
+}
+
+impl TestServer {
+    pub async fn spawn<F>(handler: F) -> Self
+    where
+        F: Fn(&TestRequest) -> TestResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let handler = handler.clone();
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let _ = serve_connection(socket, handler.as_ref(), &recorded).await;
+                });
+            }
+        });
+        Self { base_url, requests }
+    }
+
+    /// Answers request `n` with `responses[n]`, repeating the last one afterwards.
+    pub async fn scripted(responses: Vec<(u16, serde_json::Value)>) -> Self {
+        let counter = std::sync::atomic::AtomicUsize::new(0);
+        Self::spawn(move |_| {
+            let index = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let (status, body) = responses[index.min(responses.len() - 1)].clone();
+            TestResponse::json(status, body)
+        })
+        .await
+    }
+
+    pub fn requests(&self) -> Vec<TestRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn serve_connection(
+    mut socket: TcpStream,
+    handler: &(dyn Fn(&TestRequest) -> TestResponse + Send + Sync),
+    recorded: &Mutex<Vec<TestRequest>>,
+) -> std::io::Result<()> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let content_length = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    while buffer.len() < header_end + content_length {
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    let body_end = buffer.len().min(header_end + content_length);
+    let request = TestRequest {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&buffer[header_end..body_end]).to_string(),
+    };
+    recorded.lock().unwrap().push(request.clone());
+
+    let response = handler(&request);
+    let mut head = format!("HTTP/1.1 {} Test\r\nConnection: close\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    match response.body {
+        TestBody::Fixed(body) => {
+            head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+            socket.write_all(head.as_bytes()).await?;
+            socket.write_all(body.as_bytes()).await?;
+        }
+        TestBody::Stream(mut receiver) => {
+            head.push_str("\r\n");
+            socket.write_all(head.as_bytes()).await?;
+            socket.flush().await?;
+            while let Some(chunk) = receiver.recv().await {
+                socket.write_all(chunk.as_bytes()).await?;
+                socket.flush().await?;
+            }
+        }
+    }
+    socket.shutdown().await
+}
@@ -0,0 +1,393 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::models::config::ModelPricingConfig;
+use crate::services::llm::LlmUsage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageSource {
+    Chat,
+    Scheduler,
+    Heartbeat,
+    Compaction,
+    Tool,
+    SubAgent,
+}
+
+impl UsageSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Chat => "chat",
+            Self::Scheduler => "scheduler",
+            Self::Heartbeat => "heartbeat",
+            Self::Compaction => "compaction",
+            Self::Tool => "tool",
+            Self::SubAgent => "sub_agent",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGroupBy {
+    Conversation,
+    Model,
+    Day,
+}
+
+impl UsageGroupBy {
+    fn key_expr(&self) -> &'static str {
+        match self {
+            Self::Conversation => "COALESCE(u.conversation_id, '')",
+            Self::Model => "u.model",
+            // created_at is stored as UTC RFC3339, so this groups by UTC day.
+            Self::Day => "substr(u.created_at, 1, 10)",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageQuery {
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub conversation_id: Option<String>,
+    pub model: Option<String>,
+    pub source: Option<UsageSource>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageAggregate {
+    pub key: String,
+    pub label: Option<String>,
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub reasoning_tokens: i64,
+    pub cached_tokens: i64,
+    pub total_tokens: i64,
+    /// Estimated spend based on `Config::model_pricing`; `None` when no model in the group is priced.
+    pub estimated_cost: Option<f64>,
+}
+
+pub async fn record_usage(
+    pool: &SqlitePool,
+    conversation_id: Option<&str>,
+    turn_id: Option<&str>,
+    source: UsageSource,
+    model: &str,
+    usage: &LlmUsage,
+) -> Result<(), String> {
+    sqlx::query(
+        "INSERT INTO llm_usage (
+            id, conversation_id, turn_id, source, model, prompt_tokens, completion_tokens,
+            reasoning_tokens, cached_tokens, total_tokens, created_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(conversation_id)
+    .bind(turn_id)
+    .bind(source.as_str())
+    .bind(model)
+    .bind(usage.prompt_tokens as i64)
+    .bind(usage.completion_tokens as i64)
+    .bind(usage.reasoning_tokens as i64)
+    .bind(usage.cached_tokens as i64)
+    .bind(usage.total_tokens() as i64)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Best-effort variant used on hot paths: accounting failures are logged, never surfaced.
+pub async fn record_usage_logged(
+    pool: &SqlitePool,
+    conversation_id: Option<&str>,
+    turn_id: Option<&str>,
+    source: UsageSource,
+    model: &str,
+    usage: Option<&LlmUsage>,
+) {
+    let Some(usage) = usage.filter(|usage| !usage.is_empty()) else {
+        return;
+    };
+    if let Err(error) = record_usage(pool, conversation_id, turn_id, source, model, usage).await {
+        eprintln!(
+            "[usage] failed to record {} usage for model {}: {}",
+            source.as_str(),
+            model,
+            error
+        );
+    }
+}
+
+fn resolve_model_pricing<'a>(
+    pricing: &'a HashMap<String, ModelPricingConfig>,
+    model: &str,
+) -> Option<&'a ModelPricingConfig> {
+    let normalized = model.trim().to_ascii_lowercase();
+    pricing
+        .iter()
+        .filter(|(key, _)| {
+            let key = key.trim().to_ascii_lowercase();
+            !key.is_empty() && normalized.starts_with(&key)
+        })
+        .max_by_key(|(key, _)| key.trim().len())
+        .map(|(_, value)| value)
+}
+
+fn estimate_cost(pricing: &ModelPricingConfig, prompt: i64, completion: i64, cached: i64) -> f64 {
+    let cached_rate = pricing
+        .cached_prompt_per_million
+        .unwrap_or(pricing.prompt_per_million);
+    let uncached_prompt = (prompt - cached).max(0) as f64;
+    (uncached_prompt * pricing.prompt_per_million
+        + cached.max(0) as f64 * cached_rate
+        + completion.max(0) as f64 * pricing.completion_per_million)
+        / 1_000_000.0
+}
+
+pub async fn aggregate_usage(
+    pool: &SqlitePool,
+    group_by: UsageGroupBy,
+    query: &UsageQuery,
+    pricing: &HashMap<String, ModelPricingConfig>,
+) -> Result<Vec<UsageAggregate>, String> {
+    let mut filters = Vec::new();
+    let mut binds: Vec<String> = Vec::new();
+    if let Some(since) = query
+        .since
+        .as_deref()
+        .filter(|value| !value.trim().is_empty())
+    {
+        filters.push("u.created_at >= ?");
+        binds.push(since.trim().to_string());
+    }
+    if let Some(until) = query
+        .until
+        .as_deref()
+        .filter(|value| !value.trim().is_empty())
+    {
+        filters.push("u.created_at < ?");
+        binds.push(until.trim().to_string());
+    }
+    if let Some(conversation_id) = query.conversation_id.as_deref() {
+        filters.push("u.conversation_id = ?");
+        binds.push(conversation_id.to_string());
+    }
+    if let Some(model) = query.model.as_deref() {
+        filters.push("u.model = ?");
+        binds.push(model.to_string());
+    }
+    if let Some(source) = query.source {
+        filters.push("u.source = ?");
+        binds.push(source.as_str().to_string());
+    }
+
+    let where_clause = if filters.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", filters.join(" AND "))
+    };
+    // Group by (key, model) so cost can be priced per model before folding into the key.
+    let sql = format!(
+        "SELECT {key} AS group_key, u.model, MAX(c.title), COUNT(*),
+                SUM(u.prompt_tokens), SUM(u.completion_tokens), SUM(u.reasoning_tokens),
+                SUM(u.cached_tokens), SUM(u.total_tokens)
+         FROM llm_usage u
+         LEFT JOIN conversations c ON c.id = u.conversation_id
+         {where_clause}
+         GROUP BY group_key, u.model
+         ORDER BY group_key ASC",
+        key = group_by.key_expr(),
+        where_clause = where_clause
+    );
+
+    let mut statement =
+        sqlx::query_as::<_, (String, String, Option<String>, i64, i64, i64, i64, i64, i64)>(&sql);
+    for value in &binds {
+        statement = statement.bind(value);
+    }
+    let rows = statement.fetch_all(pool).await.map_err(|e| e.to_string())?;
+
+    let mut aggregates: Vec<UsageAggregate> = Vec::new();
+    for (key, model, title, requests, prompt, completion, reasoning, cached, total) in rows {
+        if aggregates
+            .last()
+            .map(|item| item.key != key)
+            .unwrap_or(true)
+        {
+            aggregates.push(UsageAggregate {
+                label: match group_by {
+                    UsageGroupBy::Conversation => title,
+                    UsageGroupBy::Model | UsageGroupBy::Day => None,
+                },
+                key: key.clone(),
+                ..Default::default()
+            });
+        }
+        let Some(entry) = aggregates.last_mut() else {
+            continue;
+        };
+        entry.requests += requests;
+        entry.prompt_tokens += prompt;
+        entry.completion_tokens += completion;
+        entry.reasoning_tokens += reasoning;
+        entry.cached_tokens += cached;
+        entry.total_tokens += total;
+        if let Some(model_pricing) = resolve_model_pricing(pricing, &model) {
+            *entry.estimated_cost.get_or_insert(0.0) +=
+                estimate_cost(model_pricing, prompt, completion, cached);
+        }
+    }
+
+    Ok(aggregates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::Database;
+
+    fn pricing(prompt: f64, completion: f64, cached: Option<f64>) -> ModelPricingConfig {
+        ModelPricingConfig {
+            prompt_per_million: prompt,
+            completion_per_million: completion,
+            cached_prompt_per_million: cached,
+        }
+    }
+
+    fn usage(prompt: u64, completion: u64, cached: u64) -> LlmUsage {
+        LlmUsage {
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            reasoning_tokens: 0,
+            cached_tokens: cached,
+        }
+    }
+
+    #[test]
+    fn estimates_cost_with_cached_rate() {
+        let cost = estimate_cost(&pricing(2.0, 8.0, Some(0.5)), 1_000_000, 500_000, 400_000);
+        assert!((cost - (0.6 * 2.0 + 0.4 * 0.5 + 0.5 * 8.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn estimates_cost_without_cached_rate() {
+        let cost = estimate_cost(&pricing(2.0, 8.0, None), 1_000_000, 0, 400_000);
+        assert!((cost - 2.0).abs() < 1e-9);
+        // Providers occasionally report more cached than prompt tokens.
+        let cost = estimate_cost(&pricing(2.0, 8.0, Some(1.0)), 100, 0, 1_000_000);
+        assert!((cost - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn resolves_longest_pricing_prefix() {
+        let table = HashMap::from([
+            ("gpt-4o".to_string(), pricing(2.5, 10.0, None)),
+            ("gpt-4o-mini".to_string(), pricing(0.15, 0.6, None)),
+        ]);
+        let resolved = resolve_model_pricing(&table, "GPT-4o-mini-2024-07-18").unwrap();
+        assert_eq!(resolved.prompt_per_million, 0.15);
+        assert!(resolve_model_pricing(&table, "glm-4").is_none());
+    }
+
+    #[tokio::test]
+    async fn aggregates_usage_by_model_and_conversation() {
+        let root = std::env::temp_dir().join(format!("petool-usage-test-{}", Uuid::new_v4()));
+        let db = Database::new(root.join("petool.db")).await.unwrap();
+        let pool = db.pool();
+        sqlx::query(
+            "INSERT INTO conversations (id, title, model, created_at, updated_at) VALUES ('c1', 'First', 'gpt-4o', '', '')",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+
+        record_usage(
+            pool,
+            Some("c1"),
+            None,
+            UsageSource::Chat,
+            "gpt-4o",
+            &usage(1_000, 200, 400),
+        )
+        .await
+        .unwrap();
+        record_usage(
+            pool,
+            Some("c1"),
+            None,
+            UsageSource::Tool,
+            "glm-4",
+            &usage(500, 100, 0),
+        )
+        .await
+        .unwrap();
+        record_usage(
+            pool,
+            None,
+            None,
+            UsageSource::Scheduler,
+            "gpt-4o",
+            &usage(3_000, 0, 0),
+        )
+        .await
+        .unwrap();
+
+        let table = HashMap::from([("gpt-4o".to_string(), pricing(1.0, 4.0, Some(0.5)))]);
+
+        let by_model = aggregate_usage(pool, UsageGroupBy::Model, &UsageQuery::default(), &table)
+            .await
+            .unwrap();
+        assert_eq!(by_model.len(), 2);
+        assert_eq!(by_model[0].key, "glm-4");
+        assert_eq!(by_model[0].estimated_cost, None);
+        assert_eq!(by_model[1].key, "gpt-4o");
+        assert_eq!(by_model[1].requests, 2);
+        assert_eq!(by_model[1].prompt_tokens, 4_000);
+        assert_eq!(by_model[1].cached_tokens, 400);
+        assert_eq!(by_model[1].total_tokens, 4_200);
+        let expected = (3_600.0 * 1.0 + 400.0 * 0.5 + 200.0 * 4.0) / 1_000_000.0;
+        assert!((by_model[1].estimated_cost.unwrap() - expected).abs() < 1e-12);
+
+        let by_conversation = aggregate_usage(
+            pool,
+            UsageGroupBy::Conversation,
+            &UsageQuery::default(),
+            &table,
+        )
+        .await
+        .unwrap();
+        assert_eq!(by_conversation.len(), 2);
+        assert_eq!(by_conversation[0].key, "");
+        assert_eq!(by_conversation[1].key, "c1");
+        assert_eq!(by_conversation[1].label.as_deref(), Some("First"));
+        assert_eq!(by_conversation[1].requests, 2);
+        assert_eq!(by_conversation[1].total_tokens, 1_800);
+
+        let tool_only = aggregate_usage(
+            pool,
+            UsageGroupBy::Model,
+            &UsageQuery {
+                source: Some(UsageSource::Tool),
+                ..Default::default()
+            },
+            &table,
+        )
+        .await
+        .unwrap();
+        assert_eq!(tool_only.len(), 1);
+        assert_eq!(tool_only[0].key, "glm-4");
+
+        pool.close().await;
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
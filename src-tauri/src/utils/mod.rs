@@ -107,6 +107,14 @@ pub fn get_app_log_dir() -> Result<PathBuf> {
     Ok(app_log_dir)
 }
 
+pub fn get_app_data_dir() -> Result<PathBuf> {
+    let data_local_dir = dirs::data_local_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not find local data directory"))?;
+    let app_data_dir = data_local_dir.join("petool");
+    fs::create_dir_all(&app_data_dir)?;
+    Ok(app_data_dir)
+}
+
 pub fn get_config_path() -> Result<PathBuf> {
     Ok(get_app_config_dir()?.join("config.json"))
 }
@@ -1,11 +1,40 @@
 import { listen } from '@tauri-apps/api/event'
 
+export interface FileChangeHunk {
+  index: number
+  header: string
+  diff: string
+}
+
+export interface FileChangePreview {
+  path: string
+  operation: 'add' | 'update' | 'delete'
+  move_to?: string | null
+  binary: boolean
+  diff: string
+  hunks: FileChangeHunk[]
+}
+
 export interface ToolApprovalRequest {
   requestId: string
   conversationId: string
   toolCallId: string
   toolName: string
   arguments: string
+  fileChanges: FileChangePreview[]
+}
+
+export interface WorkspaceProcessOutput {
+  conversationId: string
+  processId: string
+  stream: string
+  text: string
+  closed: boolean
+}
+
+export interface McpResourceUpdate {
+  server: string
+  uri: string
 }
 
 export type TimelineEventType =
@@ -14,6 +43,23 @@ export type TimelineEventType =
   | 'assistant_text'
   | 'assistant_tool_call'
   | 'assistant_tool_result'
+  | 'model_fallback'
+  | 'checkpoint_restored'
+  | 'plan_updated'
+  | 'sub_agent_step'
+
+export interface TodoPlanUpdate {
+  conversationId: string
+  version: number
+  items: {
+    id: string
+    text: string
+    status: 'pending' | 'in_progress' | 'completed'
+    createdAt: string
+    updatedAt: string
+  }[]
+  updatedAt?: string | null
+}
 
 export interface TimelineEventInput {
   conversation_id: string
@@ -29,11 +75,15 @@ interface ChatStoreBridge {
   currentConversationId: string | null
   setConversationStreaming: (conversationId: string, isStreaming: boolean) => void
   appendTimelineEvent: (event: TimelineEventInput) => void
+  setPlan: (plan: TodoPlanUpdate) => void
+  loadConversations: () => Promise<void>
 }
 
 interface ChatEventBridgeOptions {
   chatStore: ChatStoreBridge
   onToolApprovalRequest?: (request: ToolApprovalRequest) => void
+  onProcessOutput?: (output: WorkspaceProcessOutput) => void
+  onMcpResourceUpdated?: (update: McpResourceUpdate) => void
   onStreamEnd: (conversationId: string | null) => void
 }
 
@@ -90,7 +140,9 @@ export async function registerChatEventListeners(options: ChatEventBridgeOptions
       const payload = asObjectPayload(event.payload)
       if (!payload) return
       const timelineEvent = buildTimelineEvent(payload, options.chatStore.currentConversationId, 'user_message', {
-        content: readString(payload, 'content')
+        content: readString(payload, 'content'),
+        messageId: readString(payload, 'messageId'),
+        mcpResources: Array.isArray(payload.mcpResources) ? payload.mcpResources : []
       })
       if (!timelineEvent) return
       options.chatStore.appendTimelineEvent(timelineEvent)
@@ -149,6 +201,96 @@ export async function registerChatEventListeners(options: ChatEventBridgeOptions
     })
   )
 
+  // The turn switched to a fallback model after the previous one kept failing.
+  unlistenFns.push(
+    await listen('chat-model-fallback', (event) => {
+      const payload = asObjectPayload(event.payload)
+      if (!payload) return
+      const timelineEvent = buildTimelineEvent(payload, options.chatStore.currentConversationId, 'model_fallback', {
+        fromModel: readString(payload, 'fromModel'),
+        toModel: readString(payload, 'toModel'),
+        provider: readString(payload, 'provider'),
+        error: readString(payload, 'error')
+      })
+      if (!timelineEvent) return
+      options.chatStore.appendTimelineEvent(timelineEvent)
+    })
+  )
+
+  unlistenFns.push(
+    await listen('chat-plan-updated', (event) => {
+      const payload = asObjectPayload(event.payload)
+      if (!payload) return
+      const timelineEvent = buildTimelineEvent(payload, options.chatStore.currentConversationId, 'plan_updated', {
+        version: payload.version,
+        action: payload.action,
+        items: payload.items
+      })
+      if (!timelineEvent) return
+      options.chatStore.appendTimelineEvent(timelineEvent)
+    })
+  )
+
+  // Steps of a core_task sub-agent, nested under the turn that delegated to it.
+  unlistenFns.push(
+    await listen('sub-agent-progress', (event) => {
+      const payload = asObjectPayload(event.payload)
+      if (!payload || !readString(payload, 'turnId')) return
+      const timelineEvent = buildTimelineEvent(
+        payload,
+        options.chatStore.currentConversationId,
+        'sub_agent_step',
+        asObjectPayload(payload.payload) || {}
+      )
+      if (!timelineEvent) return
+      options.chatStore.appendTimelineEvent(timelineEvent)
+    })
+  )
+
+  // Emitted for every plan change, including scheduled runs that have no open stream.
+  unlistenFns.push(
+    await listen('todo-plan-updated', (event) => {
+      const payload = asObjectPayload(event.payload)
+      if (!payload) return
+      const conversationId = readString(payload, 'conversationId')
+      if (!conversationId) return
+      options.chatStore.setPlan({
+        conversationId,
+        version: readNumber(payload, 'version'),
+        items: Array.isArray(payload.items) ? (payload.items as TodoPlanUpdate['items']) : [],
+        updatedAt: readOptionalString(payload, 'updatedAt')
+      })
+    })
+  )
+
+  // Turns the backend starts on its own (sessions_send / sessions_spawn) announce themselves.
+  unlistenFns.push(
+    await listen('chat-start', (event) => {
+      const payload = asObjectPayload(event.payload)
+      const conversationId = payload ? readString(payload, 'conversationId') : ''
+      if (!conversationId) return
+      options.chatStore.setConversationStreaming(conversationId, true)
+    })
+  )
+
+  // A subscribed MCP resource changed on its server.
+  unlistenFns.push(
+    await listen('mcp-resource-updated', (event) => {
+      const payload = asObjectPayload(event.payload)
+      if (!payload || !options.onMcpResourceUpdated) return
+      const server = readString(payload, 'server')
+      const uri = readString(payload, 'uri')
+      if (!server || !uri) return
+      options.onMcpResourceUpdated({ server, uri })
+    })
+  )
+
+  unlistenFns.push(
+    await listen('conversations-changed', () => {
+      void options.chatStore.loadConversations()
+    })
+  )
+
   unlistenFns.push(
     await listen('chat-end', (event) => {
       let conversationId: string | null = options.chatStore.currentConversationId
@@ -181,7 +323,27 @@ export async function registerChatEventListeners(options: ChatEventBridgeOptions
         conversationId,
         toolCallId,
         toolName,
-        arguments: readString(payload, 'arguments')
+        arguments: readString(payload, 'arguments'),
+        fileChanges: Array.isArray(payload.fileChanges) ? (payload.fileChanges as FileChangePreview[]) : []
+      })
+    })
+  )
+
+  unlistenFns.push(
+    await listen('workspace-process-output', (event) => {
+      const payload = asObjectPayload(event.payload)
+      if (!payload || !options.onProcessOutput) return
+
+      const conversationId = readString(payload, 'conversationId')
+      const processId = readString(payload, 'processId')
+      if (!conversationId || !processId) return
+
+      options.onProcessOutput({
+        conversationId,
+        processId,
+        stream: readString(payload, 'stream'),
+        text: readString(payload, 'text'),
+        closed: payload.closed === true
       })
     })
   )
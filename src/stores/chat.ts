@@ -5,6 +5,7 @@ import { invoke } from '@tauri-apps/api/core'
 export interface Message {
   id: string
   conversation_id: string
+  parent_id?: string | null
   role: 'user' | 'assistant' | 'system' | 'tool'
   content: string
   reasoning?: string | null
@@ -16,6 +17,8 @@ export interface Conversation {
   id: string
   title: string
   model: string
+  tool_profile?: string | null
+  sandboxed?: boolean | null
   created_at: string
   updated_at: string
 }
@@ -26,6 +29,10 @@ export type TimelineEventType =
   | 'assistant_text'
   | 'assistant_tool_call'
   | 'assistant_tool_result'
+  | 'model_fallback'
+  | 'checkpoint_restored'
+  | 'plan_updated'
+  | 'sub_agent_step'
 
 export interface TimelineEvent {
   id: string
@@ -38,9 +45,62 @@ export interface TimelineEvent {
   created_at: string
 }
 
+export interface CheckpointFile {
+  path: string
+  existed: boolean
+  captured: boolean
+  tool_name: string
+  created_at: string
+  restored_at?: string | null
+}
+
+export interface TurnCheckpoint {
+  turn_id: string
+  workspace_root: string
+  created_at: string
+  files: CheckpointFile[]
+}
+
+export interface CheckpointRestoreOutcome {
+  turn_id: string
+  restored: string[]
+  removed: string[]
+  skipped: { path: string; reason: string }[]
+  overwritten_later_edits: string[]
+  undo_turn_id: string
+}
+
+export type TodoStatus = 'pending' | 'in_progress' | 'completed'
+
+export interface TodoItem {
+  id: string
+  text: string
+  status: TodoStatus
+  createdAt: string
+  updatedAt: string
+}
+
+export interface TodoPlan {
+  conversationId: string
+  version: number
+  items: TodoItem[]
+  updatedAt?: string | null
+}
+
+export interface TimelineBranch {
+  message_id: string
+  sibling_ids: string[]
+}
+
+interface CheckpointRestoreResponse {
+  outcome: CheckpointRestoreOutcome
+  event: TimelineEvent
+}
+
 interface ConversationTimelineResponse {
   events: TimelineEvent[]
   legacy: boolean
+  branches?: TimelineBranch[]
 }
 
 interface TimelineEventInput {
@@ -65,6 +125,9 @@ export const useChatStore = defineStore('chat', () => {
   const timelineByConversation = ref<Record<string, TimelineEvent[]>>({})
   const timelineLegacyByConversation = ref<Record<string, boolean>>({})
   const timelineLoadedByConversation = ref<Record<string, boolean>>({})
+  const branchesByConversation = ref<Record<string, TimelineBranch[]>>({})
+  const checkpointsByConversation = ref<Record<string, TurnCheckpoint[]>>({})
+  const plansByConversation = ref<Record<string, TodoPlan>>({})
 
   const currentMessages = computed(() => {
     if (!currentConversationId.value) return []
@@ -86,6 +149,21 @@ export const useChatStore = defineStore('chat', () => {
     return Boolean(timelineLegacyByConversation.value[currentConversationId.value])
   })
 
+  const currentBranches = computed(() => {
+    if (!currentConversationId.value) return []
+    return branchesByConversation.value[currentConversationId.value] || []
+  })
+
+  const currentCheckpoints = computed(() => {
+    if (!currentConversationId.value) return []
+    return checkpointsByConversation.value[currentConversationId.value] || []
+  })
+
+  const currentPlan = computed(() => {
+    if (!currentConversationId.value) return null
+    return plansByConversation.value[currentConversationId.value] || null
+  })
+
   async function loadConversations() {
     loading.value = true
     try {
@@ -116,16 +194,88 @@ export const useChatStore = defineStore('chat', () => {
       const result = await invoke<ConversationTimelineResponse>('get_conversation_timeline', { conversationId })
       timelineByConversation.value[conversationId] = normalizeTimelineEvents(result.events || [])
       timelineLegacyByConversation.value[conversationId] = Boolean(result.legacy)
+      branchesByConversation.value[conversationId] = result.branches || []
+      void loadCheckpoints(conversationId)
+      void loadPlan(conversationId)
     } catch (error) {
       console.error('Failed to load timeline:', error)
       timelineByConversation.value[conversationId] = []
       timelineLegacyByConversation.value[conversationId] = false
+      branchesByConversation.value[conversationId] = []
     } finally {
       timelineLoadedByConversation.value[conversationId] = true
       loading.value = false
     }
   }
 
+  async function loadCheckpoints(conversationId: string) {
+    try {
+      checkpointsByConversation.value[conversationId] = await invoke<TurnCheckpoint[]>(
+        'list_workspace_checkpoints',
+        { conversationId }
+      )
+    } catch (error) {
+      console.error('Failed to load checkpoints:', error)
+    }
+  }
+
+  async function loadPlan(conversationId: string) {
+    try {
+      setPlan(await invoke<TodoPlan>('get_conversation_plan', { conversationId }))
+    } catch (error) {
+      console.error('Failed to load plan:', error)
+    }
+  }
+
+  function setPlan(plan: TodoPlan) {
+    const existing = plansByConversation.value[plan.conversationId]
+    // Events can arrive out of order with a reload; never go back to an older version.
+    if (existing && existing.version > plan.version) return
+    plansByConversation.value[plan.conversationId] = plan
+  }
+
+  async function restoreTurnCheckpoint(
+    conversationId: string,
+    turnId: string,
+    paths?: string[],
+    timelineTurnId?: string
+  ) {
+    const result = await invoke<CheckpointRestoreResponse>('restore_workspace_checkpoint', {
+      conversationId,
+      turnId,
+      paths: paths ?? null,
+      timelineTurnId: timelineTurnId ?? null
+    })
+    appendTimelineEvent(result.event)
+    await loadCheckpoints(conversationId)
+    return result.outcome
+  }
+
+  async function switchBranch(conversationId: string, messageId: string) {
+    await invoke('switch_conversation_branch', { conversationId, messageId })
+    await loadTimeline(conversationId)
+  }
+
+  async function forkConversation(conversationId: string, messageId: string, title?: string) {
+    const conversation = await invoke<Conversation>('fork_conversation', {
+      conversationId,
+      messageId,
+      title: title ?? null
+    })
+    conversations.value = [conversation, ...conversations.value.filter(c => c.id !== conversation.id)]
+    return conversation
+  }
+
+  // Edits and regenerations replace a turn and everything after it with a new branch.
+  function truncateTimelineFromTurn(conversationId: string, turnId: string) {
+    const events = timelineByConversation.value[conversationId]
+    if (!events) return
+    const start = events.findIndex((event) => event.turn_id === turnId)
+    if (start >= 0) {
+      timelineByConversation.value[conversationId] = events.slice(0, start)
+    }
+  }
+
   async function createConversation(title: string, model: string) {
     try {
       const conversation = await invoke<Conversation>('create_conversation', { title, model })
@@ -145,6 +295,9 @@ export const useChatStore = defineStore('chat', () => {
       delete timelineByConversation.value[id]
       delete timelineLegacyByConversation.value[id]
       delete timelineLoadedByConversation.value[id]
+      delete branchesByConversation.value[id]
+      delete checkpointsByConversation.value[id]
+      delete plansByConversation.value[id]
       delete streamingByConversation.value[id]
       streaming.value = Object.values(streamingByConversation.value).some(Boolean)
       if (currentConversationId.value === id) {
@@ -194,6 +347,44 @@ export const useChatStore = defineStore('chat', () => {
     }
   }
 
+  async function updateConversationToolProfile(id: string, profile: string | null) {
+    try {
+      await invoke('set_conversation_tool_profile', { id, profile })
+      const now = new Date().toISOString()
+      conversations.value = conversations.value.map((conversation) =>
+        conversation.id === id
+          ? {
+              ...conversation,
+              tool_profile: profile,
+              updated_at: now
+            }
+          : conversation
+      )
+    } catch (error) {
+      console.error('Failed to update conversation tool profile:', error)
+      throw error
+    }
+  }
+
+  async function updateConversationSandbox(id: string, sandboxed: boolean | null) {
+    try {
+      await invoke('set_conversation_sandbox', { id, sandboxed })
+      const now = new Date().toISOString()
+      conversations.value = conversations.value.map((conversation) =>
+        conversation.id === id
+          ? {
+              ...conversation,
+              sandboxed,
+              updated_at: now
+            }
+          : conversation
+      )
+    } catch (error) {
+      console.error('Failed to update conversation sandbox:', error)
+      throw error
+    }
+  }
+
   function setCurrentConversation(id: string | null) {
     currentConversationId.value = id
   }
@@ -233,6 +424,11 @@ export const useChatStore = defineStore('chat', () => {
 
   function appendTimelineEvent(event: TimelineEventInput) {
     const conversationId = event.conversation_id
+    // A background turn in a conversation that was never opened is read from the database
+    // when it is; a partial live timeline would hide its history.
+    if (!timelineLoadedByConversation.value[conversationId] && conversationId !== currentConversationId.value) {
+      return
+    }
     if (!timelineByConversation.value[conversationId]) {
       timelineByConversation.value[conversationId] = []
     }
@@ -405,6 +601,9 @@ export const useChatStore = defineStore('chat', () => {
     timelineByConversation.value = {}
     timelineLegacyByConversation.value = {}
     timelineLoadedByConversation.value = {}
+    branchesByConversation.value = {}
+    checkpointsByConversation.value = {}
+    plansByConversation.value = {}
   }
 
   return {
@@ -414,23 +613,38 @@ export const useChatStore = defineStore('chat', () => {
     timelineByConversation,
     timelineLegacyByConversation,
     timelineLoadedByConversation,
+    branchesByConversation,
+    checkpointsByConversation,
+    plansByConversation,
     currentConversationId,
     currentMessages,
     currentConversation,
     currentTimeline,
     currentTimelineLegacy,
+    currentBranches,
+    currentCheckpoints,
+    currentPlan,
     loading,
     streaming,
     streamingByConversation,
     loadConversations,
     loadMessages,
     loadTimeline,
+    loadCheckpoints,
+    restoreTurnCheckpoint,
+    switchBranch,
+    forkConversation,
+    truncateTimelineFromTurn,
+    loadPlan,
+    setPlan,
     isTimelineLoaded,
     resetState,
     createConversation,
     deleteConversation,
     renameConversation,
     updateConversationModel,
+    updateConversationToolProfile,
+    updateConversationSandbox,
     setCurrentConversation,
     addMessage,
     updateLastMessage,
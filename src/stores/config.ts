@@ -202,6 +202,7 @@ export const useConfigStore = defineStore('config', () => {
           'sessions_history',
           'sessions_send',
           'sessions_spawn',
+          'sessions_run_status',
           'workspace_write_file',
           'workspace_edit_file',
           'workspace_apply_patch'
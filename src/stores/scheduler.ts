@@ -24,6 +24,7 @@ export interface SchedulerJob {
   modelOverride?: string | null
   workspaceDirectory?: string | null
   toolWhitelist: string[]
+  toolProfile?: string | null
   runTimeoutSeconds: number
   deleteAfterRun: boolean
   nextRunAt?: string | null
@@ -82,6 +83,7 @@ export interface SchedulerJobCreateInput {
   modelOverride?: string | null
   workspaceDirectory?: string | null
   toolWhitelist?: string[]
+  toolProfile?: string | null
   runTimeoutSeconds?: number
   deleteAfterRun?: boolean
 }
@@ -101,6 +103,7 @@ export interface SchedulerJobPatchInput {
   modelOverride?: string | null
   workspaceDirectory?: string | null
   toolWhitelist?: string[]
+  toolProfile?: string | null
   runTimeoutSeconds?: number
   deleteAfterRun?: boolean
 }
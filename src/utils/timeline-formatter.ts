@@ -32,12 +32,27 @@ export interface MonitorArtifactItem {
     status: ToolStepStatus
 }
 
+export interface TurnBranchDisplay {
+    index: number
+    total: number
+    siblingIds: string[]
+}
+
+export interface TurnMcpResourceDisplay {
+    server: string
+    uri: string
+    name: string
+}
+
 export interface TimelineTurnDisplay {
     turnId: string
     userText: string
+    userMessageId: string
+    userMcpResources: TurnMcpResourceDisplay[]
     userCreatedAt: string
     assistantCreatedAt: string
     assistantEvents: TimelineEvent[]
+    branch: TurnBranchDisplay | null
 }
 
 export const TOOL_DETAIL_CACHE_MAX_ENTRIES = 800
@@ -103,6 +118,68 @@ export function getTimelineToolResultStatus(event: TimelineEvent): ToolStepStatu
     return typeof error === 'string' && error.trim() ? 'error' : 'done'
 }
 
+export function getCheckpointRestoredSummary(event: TimelineEvent) {
+    const count = (key: string) => {
+        const value = getTimelinePayloadValue(event, key)
+        return Array.isArray(value) ? value.length : 0
+    }
+    const restoredTurnId = getTimelinePayloadValue(event, 'turn_id')
+    const action = typeof restoredTurnId === 'string' && restoredTurnId.startsWith('undo-')
+        ? '已撤销恢复'
+        : '已撤销本轮文件修改'
+    const parts = [`${action}：恢复 ${count('restored')} 个文件`]
+    if (count('removed') > 0) parts.push(`删除新建文件 ${count('removed')} 个`)
+    if (count('skipped') > 0) parts.push(`${count('skipped')} 个文件未能恢复`)
+    if (count('overwritten_later_edits') > 0) {
+        parts.push(`覆盖了后续轮次对 ${count('overwritten_later_edits')} 个文件的修改`)
+    }
+    return parts.join('，')
+}
+
+export function getCheckpointUndoTurnId(event: TimelineEvent) {
+    const value = getTimelinePayloadValue(event, 'undo_turn_id')
+    return typeof value === 'string' ? value : ''
+}
+
+export function getModelFallbackSummary(event: TimelineEvent) {
+    const read = (key: string) => {
+        const value = getTimelinePayloadValue(event, key)
+        return typeof value === 'string' ? value : ''
+    }
+    const provider = read('provider')
+    const target = provider ? `${read('toModel')}（${provider}）` : read('toModel')
+    return `${read('fromModel')} 请求失败，已切换到 ${target}`
+}
+
+export function getPlanUpdatedSummary(event: TimelineEvent) {
+    const version = getTimelinePayloadValue(event, 'version')
+    const items = getTimelinePayloadValue(event, 'items')
+    const list = Array.isArray(items) ? items : []
+    const completed = list.filter(
+        (item) => item && typeof item === 'object' && (item as Record<string, unknown>).status === 'completed'
+    ).length
+    const label = typeof version === 'number' ? `计划已更新（v${version}）` : '计划已更新'
+    return list.length > 0 ? `${label}：${completed}/${list.length} 已完成` : `${label}：已清空`
+}
+
+export function getSubAgentStepSummary(event: TimelineEvent) {
+    const read = (key: string) => {
+        const value = getTimelinePayloadValue(event, key)
+        return typeof value === 'string' ? value : ''
+    }
+    const agent = read('agentName') || read('agentId') || '子代理'
+    switch (read('kind')) {
+        case 'tool_call':
+            return `${agent} · 调用 ${read('name')}`
+        case 'tool_result':
+            return getTimelinePayloadValue(event, 'isError') === true
+                ? `${agent} · ${read('name')} 失败：${read('preview')}`
+                : `${agent} · ${read('name')} 完成`
+        default:
+            return `${agent} · ${read('text')}`
+    }
+}
+
 export function isTimelineToolEvent(event: TimelineEvent) {
     return event.event_type === 'assistant_tool_call' || event.event_type === 'assistant_tool_result'
 }